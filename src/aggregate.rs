@@ -0,0 +1,339 @@
+use std::collections::HashMap;
+use std::thread;
+
+use crate::fd::Dependencies;
+use crate::value::Value;
+use crate::{hash_key, Relation};
+
+// An aggregate function over one column of a group's rows. Partial states
+// built from different rows of the same group can be merged, so a group's
+// rows don't all have to be seen by the same thread.
+pub trait Aggregate: Sync {
+    type State: Send;
+
+    // The column the aggregate reads, or nothing if it doesn't read one.
+    fn input(&self) -> Option<&str>;
+    // The name of the output column.
+    fn name(&self) -> String;
+    fn init(&self) -> Self::State;
+    // Adds a row to the state. Aggregates without an input get a null.
+    fn update(&self, state: &mut Self::State, value: &Value);
+    fn merge(&self, state: &mut Self::State, other: Self::State);
+    fn finish(&self, state: Self::State) -> Value;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Agg {
+    Count,
+    Sum(String),
+    Min(String),
+    Max(String),
+    // Of ints, rounds towards zero like the rest of the integer arithmetic.
+    Avg(String),
+    // The value from any one of the group's rows, for columns that are the
+    // same throughout a group.
+    Any(String),
+}
+
+// The number of rows seen, and their sum, minimum or maximum. Like in SQL,
+// every aggregate but `Count` skips nulls and is null if there was nothing
+// else.
+#[derive(Debug, Clone)]
+pub struct AggState {
+    count: i64,
+    value: Value,
+}
+
+// Sums ints as ints, wrapping on overflow, and anything with a float as a
+// float.
+fn add(a: Value, b: Value) -> Value {
+    match (a, b) {
+        (Value::Null, v) | (v, Value::Null) => v,
+        (Value::Int(a), Value::Int(b)) => Value::Int(a.wrapping_add(b)),
+        (a, b) => match (a.as_float(), b.as_float()) {
+            (Some(a), Some(b)) => Value::Float(a + b),
+            _ => panic!("can't add {} and {}", a, b),
+        },
+    }
+}
+
+impl Aggregate for Agg {
+    type State = AggState;
+
+    fn input(&self) -> Option<&str> {
+        match self {
+            Agg::Count => None,
+            Agg::Sum(c) | Agg::Min(c) | Agg::Max(c) | Agg::Avg(c) | Agg::Any(c) => Some(c),
+        }
+    }
+
+    fn name(&self) -> String {
+        match self {
+            Agg::Count => "count".to_string(),
+            Agg::Sum(c) => format!("sum_{}", c),
+            Agg::Min(c) => format!("min_{}", c),
+            Agg::Max(c) => format!("max_{}", c),
+            Agg::Avg(c) => format!("avg_{}", c),
+            Agg::Any(c) => format!("any_{}", c),
+        }
+    }
+
+    fn init(&self) -> AggState {
+        AggState {
+            count: 0,
+            value: Value::Null,
+        }
+    }
+
+    fn update(&self, state: &mut AggState, value: &Value) {
+        match (self, value) {
+            (Agg::Count, _) => state.count += 1,
+            (Agg::Any(_), _) if state.count > 0 => {}
+            (_, Value::Null) => {}
+            (_, value) => self.merge(
+                state,
+                AggState {
+                    count: 1,
+                    value: value.clone(),
+                },
+            ),
+        }
+    }
+
+    fn merge(&self, state: &mut AggState, other: AggState) {
+        let value = std::mem::take(&mut state.value);
+        state.value = match (self, value, other.value) {
+            (Agg::Count, _, _) => Value::Null,
+            (Agg::Sum(_) | Agg::Avg(_), a, b) => add(a, b),
+            (Agg::Min(_) | Agg::Max(_), Value::Null, v) | (_, v, Value::Null) => v,
+            (Agg::Min(_), a, b) => a.min(b),
+            (Agg::Max(_), a, b) => a.max(b),
+            (Agg::Any(_), Value::Null, b) => b,
+            (Agg::Any(_), a, _) => a,
+        };
+        state.count += other.count;
+    }
+
+    fn finish(&self, state: AggState) -> Value {
+        match (self, state.value) {
+            (Agg::Count, _) => Value::Int(state.count),
+            (Agg::Avg(_), Value::Int(sum)) => Value::Int(sum / state.count),
+            (Agg::Avg(_), Value::Float(sum)) => Value::Float(sum / state.count as f64),
+            (_, value) => value,
+        }
+    }
+}
+
+impl Relation {
+    // Groups the rows by `group_cols` and computes `aggs` for each group, on
+    // this thread. The output has the group columns followed by one column
+    // per aggregate, with the groups in the order their first rows come in.
+    // Like in SQL, with no group columns there's always exactly one group,
+    // even without any rows.
+    pub fn aggregate<A: Aggregate>(&self, group_cols: &[&str], aggs: &[A]) -> Relation {
+        let group_cols: Vec<_> = group_cols.iter().map(|c| c.to_string()).collect();
+        let key = self.positions(&group_cols);
+        let inputs: Vec<_> = aggs
+            .iter()
+            .map(|a| a.input().map(|c| self.positions(&[c.to_string()])[0]))
+            .collect();
+
+        let mut positions: HashMap<Vec<Value>, usize> = HashMap::new();
+        let mut groups: Vec<(Vec<Value>, Vec<A::State>)> = Vec::new();
+        if key.is_empty() {
+            positions.insert(vec![], 0);
+            groups.push((vec![], aggs.iter().map(|a| a.init()).collect()));
+        }
+        for row in self.data.iter() {
+            let group: Vec<_> = key.iter().map(|k| row[*k].clone()).collect();
+            let i = *positions.entry(group.clone()).or_insert_with(|| {
+                groups.push((group, aggs.iter().map(|a| a.init()).collect()));
+                groups.len() - 1
+            });
+            for ((agg, input), state) in aggs.iter().zip(&inputs).zip(&mut groups[i].1) {
+                agg.update(state, input.map_or(&Value::Null, |i| &row[i]));
+            }
+        }
+
+        Relation::new_with_data(
+            group_cols.into_iter().chain(aggs.iter().map(|a| a.name())),
+            groups.into_iter().map(|(mut group, states)| {
+                group.extend(aggs.iter().zip(states).map(|(a, s)| a.finish(s)));
+                group
+            }),
+        )
+    }
+}
+
+// Each group's key and the states of its aggregates.
+type Groups<S> = HashMap<Vec<Value>, Vec<S>>;
+
+// Groups `rel`'s rows by `group_cols` and computes `aggs` for each group,
+// using `threads` threads. Each thread aggregates its own share of the rows,
+// keeping separate partial results for each of `threads` ranges of group key
+// hashes, and then each thread merges everyone's partials for one of the
+// ranges. The output has the group columns followed by one column per
+// aggregate, with no particular order to the groups.
+pub fn parallel_group_by<A: Aggregate>(
+    rel: &Relation,
+    group_cols: &[&str],
+    aggs: &[A],
+    threads: usize,
+) -> Relation {
+    let threads = threads.max(1);
+    let group_cols: Vec<_> = group_cols.iter().map(|c| c.to_string()).collect();
+    let key = rel.positions(&group_cols);
+    let inputs: Vec<_> = aggs
+        .iter()
+        .map(|a| a.input().map(|c| rel.positions(&[c.to_string()])[0]))
+        .collect();
+
+    let chunk = rel.data.len().div_ceil(threads).max(1);
+    let mut partials: Vec<Vec<Groups<A::State>>> = thread::scope(|s| {
+        let handles: Vec<_> = rel
+            .data
+            .chunks(chunk)
+            .map(|rows| {
+                let (key, inputs) = (&key, &inputs);
+                s.spawn(move || {
+                    let mut parts: Vec<Groups<_>> = (0..threads).map(|_| HashMap::new()).collect();
+                    for row in rows {
+                        let part = hash_key(row, key) as usize % threads;
+                        let states = parts[part]
+                            .entry(key.iter().map(|k| row[*k].clone()).collect())
+                            .or_insert_with(|| aggs.iter().map(|a| a.init()).collect());
+                        for ((agg, input), state) in aggs.iter().zip(inputs).zip(states) {
+                            agg.update(state, input.map_or(&Value::Null, |i| &row[i]));
+                        }
+                    }
+                    parts
+                })
+            })
+            .collect();
+        handles.into_iter().map(|h| h.join().unwrap()).collect()
+    });
+
+    // Hand each merging thread the partials for its range from every thread.
+    let mut ranges: Vec<Vec<_>> = (0..threads).map(|_| Vec::new()).collect();
+    for parts in partials.iter_mut() {
+        for (range, part) in ranges.iter_mut().zip(parts.drain(..)) {
+            range.push(part);
+        }
+    }
+    let merged: Vec<Vec<Vec<Value>>> = thread::scope(|s| {
+        let handles: Vec<_> = ranges
+            .into_iter()
+            .map(|parts| {
+                s.spawn(move || {
+                    let mut parts = parts.into_iter();
+                    let mut groups = parts.next().unwrap_or_default();
+                    for part in parts {
+                        for (group, states) in part {
+                            match groups.get_mut(&group) {
+                                Some(existing) => {
+                                    for ((agg, state), other) in
+                                        aggs.iter().zip(existing.iter_mut()).zip(states)
+                                    {
+                                        agg.merge(state, other);
+                                    }
+                                }
+                                None => {
+                                    groups.insert(group, states);
+                                }
+                            }
+                        }
+                    }
+                    groups
+                        .into_iter()
+                        .map(|(mut group, states)| {
+                            group.extend(aggs.iter().zip(states).map(|(a, s)| a.finish(s)));
+                            group
+                        })
+                        .collect()
+                })
+            })
+            .collect();
+        handles.into_iter().map(|h| h.join().unwrap()).collect()
+    });
+
+    let mut rows: Vec<_> = merged.into_iter().flatten().collect();
+    // With no group columns every row is in the one group, and like with
+    // `aggregate` that group is there even if there are no rows.
+    if key.is_empty() && rows.is_empty() {
+        rows.push(aggs.iter().map(|a| a.finish(a.init())).collect());
+    }
+    Relation::new_with_data(
+        group_cols.into_iter().chain(aggs.iter().map(|a| a.name())),
+        rows,
+    )
+}
+
+// Like `parallel_group_by`, but only groups on the group columns that the
+// others don't determine under `deps`, and takes the rest from any row of
+// each group. The groups are the same, with fewer columns to hash and
+// compare.
+pub fn parallel_group_by_with(
+    rel: &Relation,
+    group_cols: &[&str],
+    aggs: &[Agg],
+    threads: usize,
+    deps: &Dependencies,
+) -> Relation {
+    let cols: Vec<_> = group_cols.iter().map(|c| c.to_string()).collect();
+    let kept = deps.minimal(&cols);
+    let dropped: Vec<_> = cols.iter().filter(|c| !kept.contains(c)).collect();
+    let kept_cols: Vec<_> = kept.iter().map(|c| c.as_str()).collect();
+    let carried = dropped.iter().map(|c| Agg::Any(c.to_string()));
+    let all_aggs: Vec<_> = carried.chain(aggs.iter().cloned()).collect();
+    let mut grouped = parallel_group_by(rel, &kept_cols, &all_aggs, threads);
+
+    // Put the columns back the way they were asked for.
+    for (i, col) in dropped.iter().enumerate() {
+        grouped.col_names[kept.len() + i] = col.to_string();
+    }
+    let order: Vec<_> = cols
+        .iter()
+        .cloned()
+        .chain(aggs.iter().map(|a| a.name()))
+        .collect();
+    let positions = grouped.positions(&order);
+    Relation::new_with_data(
+        order,
+        grouped
+            .data
+            .into_iter()
+            .map(|row| {
+                positions
+                    .iter()
+                    .map(|i| row[*i].clone())
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn no_group_columns_make_one_group_even_of_no_rows() {
+        let aggs = [Agg::Count, Agg::Sum("a".into()), Agg::Max("a".into())];
+        let empty = Relation::new(["a", "b"]);
+        let expected = empty.aggregate(&[], &aggs);
+        assert_eq!(
+            expected.data,
+            [vec![Value::from(0), Value::Null, Value::Null]]
+        );
+        for threads in [1, 4] {
+            assert_eq!(parallel_group_by(&empty, &[], &aggs, threads), expected);
+        }
+        assert!(parallel_group_by(&empty, &["b"], &aggs, 4).data.is_empty());
+
+        let rel = Relation::new(["a", "b"]).rows([[1, 2], [3, 2]]);
+        assert_eq!(
+            parallel_group_by(&rel, &[], &aggs, 4),
+            rel.aggregate(&[], &aggs)
+        );
+    }
+}
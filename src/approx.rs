@@ -0,0 +1,122 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::value::Value;
+use crate::{hash_key, Relation};
+
+// How many standard errors either side of an estimate its interval spans,
+// for 95% confidence.
+const Z: f64 = 1.96;
+
+// An estimate along with an interval that the true value is in with 95%
+// confidence.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Estimate {
+    pub value: f64,
+    pub low: f64,
+    pub high: f64,
+}
+
+impl fmt::Display for Estimate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:.1} [{:.1}, {:.1}]", self.value, self.low, self.high)
+    }
+}
+
+// The join of a sample of two relations, from which the size of their
+// whole join and sums over it can be estimated. Both sides are sampled by
+// the hash of their join key, so a key is either kept on both sides with
+// all its rows or on neither, and the sample's join is exactly the part of
+// the whole join for the keys that were kept.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ApproxJoin {
+    sample: Relation,
+    key: Vec<String>,
+    fraction: f64,
+}
+
+impl Relation {
+    // Joins only as many of the rows of `self` and `other` together as
+    // `budget` says, on their common columns. Each row is still hashed to
+    // decide whether it's in the sample, but that's cheap next to joining.
+    pub fn approx_join(&self, other: &Relation, budget: usize) -> ApproxJoin {
+        let key = self.common_cols(other);
+        assert!(
+            !key.is_empty(),
+            "can't sample a join of {:?} and {:?} by key, since they have no columns in common",
+            self.col_names,
+            other.col_names
+        );
+        let total = self.data.len() + other.data.len();
+        let fraction = match total {
+            0 => 1.0,
+            _ => (budget as f64 / total as f64).min(1.0),
+        };
+        let sample = |rel: &Relation| {
+            let positions = rel.positions(&key);
+            Relation::new_with_data(
+                rel.col_names.iter().cloned(),
+                rel.data
+                    .iter()
+                    .filter(|row| (hash_key(row, &positions) as f64 / u64::MAX as f64) < fraction)
+                    .cloned()
+                    .collect::<Vec<_>>(),
+            )
+        };
+        ApproxJoin {
+            sample: sample(self).join(&sample(other)),
+            key,
+            fraction,
+        }
+    }
+}
+
+impl ApproxJoin {
+    // The chance each key had of being in the sample.
+    pub fn fraction(&self) -> f64 {
+        self.fraction
+    }
+
+    // The sample's join, which is a part of the whole join.
+    pub fn sample(&self) -> &Relation {
+        &self.sample
+    }
+
+    // How many rows the whole join has.
+    pub fn count(&self) -> Estimate {
+        let estimate = self.estimate(|_| 1.0);
+        Estimate {
+            low: estimate.low.max(self.sample.data.len() as f64),
+            ..estimate
+        }
+    }
+
+    // The sum of `col` over the whole join, ignoring values that aren't
+    // numbers.
+    pub fn sum(&self, col: &str) -> Estimate {
+        let i = self.sample.positions(&[col.to_string()])[0];
+        self.estimate(|row| row[i].as_float().unwrap_or(0.0))
+    }
+
+    // Each kept key adds what its rows add up to, scaled up by the chance
+    // it had of being kept. Keys are kept independently, so the variance
+    // comes from how much each kept key adds.
+    fn estimate(&self, value: impl Fn(&[Value]) -> f64) -> Estimate {
+        let positions = self.sample.positions(&self.key);
+        let mut by_key: HashMap<Vec<&Value>, f64> = HashMap::new();
+        for row in &self.sample.data {
+            let key = positions.iter().map(|i| &row[*i]).collect();
+            *by_key.entry(key).or_default() += value(row);
+        }
+        let p = self.fraction;
+        let total: f64 = by_key.values().sum();
+        let squares: f64 = by_key.values().map(|v| v * v).sum();
+        let value = total / p;
+        let error = Z * (squares * (1.0 - p) / (p * p)).sqrt();
+        Estimate {
+            value,
+            low: value - error,
+            high: value + error,
+        }
+    }
+}
@@ -0,0 +1,484 @@
+use std::ffi::{c_char, c_void, CStr, CString};
+use std::fmt;
+use std::ptr;
+
+use crate::value::Value;
+use crate::Relation;
+
+// Relations in and out of Arrow through its C data interface, which every
+// Arrow implementation can import and export without either side linking
+// the other. A batch is a struct array with a child array per column, as
+// the interface has record batches. `arrow::ffi::FFI_ArrowArray` and
+// `FFI_ArrowSchema` have the same layouts as `ArrowArray` and
+// `ArrowSchema`, so passing a batch to arrow-rs is a matter of handing
+// over the raw structs.
+
+#[repr(C)]
+pub struct ArrowSchema {
+    pub format: *const c_char,
+    pub name: *const c_char,
+    pub metadata: *const c_char,
+    pub flags: i64,
+    pub n_children: i64,
+    pub children: *mut *mut ArrowSchema,
+    pub dictionary: *mut ArrowSchema,
+    pub release: Option<unsafe extern "C" fn(*mut ArrowSchema)>,
+    pub private_data: *mut c_void,
+}
+
+#[repr(C)]
+pub struct ArrowArray {
+    pub length: i64,
+    pub null_count: i64,
+    pub offset: i64,
+    pub n_buffers: i64,
+    pub n_children: i64,
+    pub buffers: *mut *const c_void,
+    pub children: *mut *mut ArrowArray,
+    pub dictionary: *mut ArrowArray,
+    pub release: Option<unsafe extern "C" fn(*mut ArrowArray)>,
+    pub private_data: *mut c_void,
+}
+
+const NULLABLE: i64 = 2;
+
+// An array and its schema, which are released together when it's dropped.
+pub struct RecordBatch {
+    array: ArrowArray,
+    schema: ArrowSchema,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ArrowError {
+    // The batch isn't a struct array, but has this format.
+    NotAStruct(String),
+    UnsupportedType { column: String, format: String },
+    // A column has more values than fit in a row with an `Int`.
+    OutOfRange { column: String },
+}
+
+impl fmt::Display for ArrowError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArrowError::NotAStruct(format) => {
+                write!(f, "a record batch is a struct array, not {:?}", format)
+            }
+            ArrowError::UnsupportedType { column, format } => {
+                write!(f, "column {} has unsupported type {:?}", column, format)
+            }
+            ArrowError::OutOfRange { column } => {
+                write!(f, "column {} has a value too big for an int", column)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ArrowError {}
+
+impl RecordBatch {
+    // Takes ownership of an array and schema from somewhere else, such as
+    // arrow-rs exporting a batch.
+    //
+    // Safety: both have to be valid and unreleased as the C data interface
+    // says, with the array being one of the schema's type.
+    pub unsafe fn from_raw(array: ArrowArray, schema: ArrowSchema) -> RecordBatch {
+        RecordBatch { array, schema }
+    }
+
+    // Hands the array and schema over to whatever is going to import them,
+    // which is then responsible for releasing them.
+    pub fn into_raw(self) -> (ArrowArray, ArrowSchema) {
+        let batch = std::mem::ManuallyDrop::new(self);
+        unsafe { (ptr::read(&batch.array), ptr::read(&batch.schema)) }
+    }
+}
+
+impl Drop for RecordBatch {
+    fn drop(&mut self) {
+        unsafe {
+            if let Some(release) = self.array.release {
+                release(&mut self.array);
+            }
+            if let Some(release) = self.schema.release {
+                release(&mut self.schema);
+            }
+        }
+    }
+}
+
+impl Relation {
+    // The relation as a batch with a nullable column for each of its
+    // columns. A column is a bool, 64-bit int or 64-bit float column if all
+    // its values are that type, a float column if they're a mix of ints and
+    // floats, a null column if they're all null, and otherwise a string
+    // column of how the values display.
+    pub fn to_arrow(&self) -> RecordBatch {
+        let rows = self.data.len();
+        let mut fields = Vec::new();
+        let mut columns = Vec::new();
+        for (i, name) in self.col_names.iter().enumerate() {
+            let (format, column) = export_column(self.data.iter().map(|row| &row[i]), rows);
+            fields.push(export_schema(format, name, NULLABLE, Vec::new()));
+            columns.push(column);
+        }
+        RecordBatch {
+            array: export_array(rows, 0, vec![None], columns),
+            schema: export_schema("+s", "", 0, fields),
+        }
+    }
+
+    // The rows of an Arrow batch. Ints of every width are `Int`s, floats of
+    // 32 or 64 bits are `Float`s, and strings and binaries are `Str`s, with
+    // bytes that aren't UTF-8 replaced. Dictionary-encoded columns aren't
+    // supported.
+    pub fn from_arrow(batch: RecordBatch) -> Result<Relation, ArrowError> {
+        let (array, schema) = (&batch.array, &batch.schema);
+        unsafe {
+            let format = CStr::from_ptr(schema.format).to_string_lossy();
+            if format != "+s" {
+                return Err(ArrowError::NotAStruct(format.into_owned()));
+            }
+            let rows = array.length as usize;
+            let mut col_names = Vec::new();
+            let mut columns = Vec::new();
+            for c in 0..schema.n_children as usize {
+                let field = &**schema.children.add(c);
+                let child = &**array.children.add(c);
+                let name = CStr::from_ptr(field.name).to_string_lossy().into_owned();
+                let format = CStr::from_ptr(field.format).to_string_lossy();
+                // A dictionary-encoded field's format is that of its indexes,
+                // which would otherwise be read as if they were the values.
+                if !field.dictionary.is_null() {
+                    let values = CStr::from_ptr((*field.dictionary).format).to_string_lossy();
+                    return Err(ArrowError::UnsupportedType {
+                        column: name,
+                        format: format!("dictionary of {}", values),
+                    });
+                }
+                let start = array.offset as usize + child.offset as usize;
+                columns.push(import_column(child, &name, &format, start, rows)?);
+                col_names.push(name);
+            }
+            let valid = |r: usize| is_valid(array, array.offset as usize + r);
+            let data = (0..rows)
+                .map(|r| {
+                    columns
+                        .iter()
+                        .map(|col| match valid(r) {
+                            true => col[r].clone(),
+                            false => Value::Null,
+                        })
+                        .collect::<Vec<_>>()
+                })
+                .collect::<Vec<_>>();
+            Ok(Relation::new_with_data(col_names, data))
+        }
+    }
+}
+
+// What an exported array or schema owns, freed by its release callback.
+// Buffers are kept as words so they're aligned for any of their values.
+struct ExportedArray {
+    buffers: Vec<Option<Vec<u64>>>,
+    pointers: Vec<*const c_void>,
+    children: Vec<*mut ArrowArray>,
+}
+
+struct ExportedSchema {
+    format: CString,
+    name: CString,
+    children: Vec<*mut ArrowSchema>,
+}
+
+fn export_array(
+    length: usize,
+    null_count: usize,
+    buffers: Vec<Option<Vec<u64>>>,
+    children: Vec<ArrowArray>,
+) -> ArrowArray {
+    let mut private = Box::new(ExportedArray {
+        pointers: buffers
+            .iter()
+            .map(|b| {
+                b.as_ref()
+                    .map_or(ptr::null(), |b| b.as_ptr() as *const c_void)
+            })
+            .collect(),
+        buffers,
+        children: children
+            .into_iter()
+            .map(|c| Box::into_raw(Box::new(c)))
+            .collect(),
+    });
+    ArrowArray {
+        length: length as i64,
+        null_count: null_count as i64,
+        offset: 0,
+        n_buffers: private.buffers.len() as i64,
+        n_children: private.children.len() as i64,
+        buffers: private.pointers.as_mut_ptr(),
+        children: private.children.as_mut_ptr(),
+        dictionary: ptr::null_mut(),
+        release: Some(release_array),
+        private_data: Box::into_raw(private) as *mut c_void,
+    }
+}
+
+unsafe extern "C" fn release_array(array: *mut ArrowArray) {
+    let private = Box::from_raw((*array).private_data as *mut ExportedArray);
+    for child in private.children.iter() {
+        if let Some(release) = (**child).release {
+            release(*child);
+        }
+        drop(Box::from_raw(*child));
+    }
+    (*array).release = None;
+}
+
+fn export_schema(format: &str, name: &str, flags: i64, children: Vec<ArrowSchema>) -> ArrowSchema {
+    let mut private = Box::new(ExportedSchema {
+        format: CString::new(format).unwrap(),
+        name: CString::new(name.replace('\0', "")).unwrap(),
+        children: children
+            .into_iter()
+            .map(|c| Box::into_raw(Box::new(c)))
+            .collect(),
+    });
+    ArrowSchema {
+        format: private.format.as_ptr(),
+        name: private.name.as_ptr(),
+        metadata: ptr::null(),
+        flags,
+        n_children: private.children.len() as i64,
+        children: private.children.as_mut_ptr(),
+        dictionary: ptr::null_mut(),
+        release: Some(release_schema),
+        private_data: Box::into_raw(private) as *mut c_void,
+    }
+}
+
+unsafe extern "C" fn release_schema(schema: *mut ArrowSchema) {
+    let private = Box::from_raw((*schema).private_data as *mut ExportedSchema);
+    for child in private.children.iter() {
+        if let Some(release) = (**child).release {
+            release(*child);
+        }
+        drop(Box::from_raw(*child));
+    }
+    (*schema).release = None;
+}
+
+fn export_column<'a>(
+    values: impl Iterator<Item = &'a Value> + Clone,
+    rows: usize,
+) -> (&'static str, ArrowArray) {
+    let nulls = values.clone().filter(|v| **v == Value::Null).count();
+    let validity = (nulls > 0).then(|| bits(values.clone().map(|v| *v != Value::Null), rows));
+    let mut kinds = values
+        .clone()
+        .filter(|v| **v != Value::Null)
+        .map(|v| match v {
+            Value::Int(_) => 1,
+            Value::Float(_) => 2,
+            Value::Bool(_) => 3,
+            _ => 4,
+        });
+    let kind = kinds.next().map(|first| {
+        kinds.fold(first, |a, b| match (a.min(b), a.max(b)) {
+            (x, y) if x == y => x,
+            (1, 2) => 2,
+            _ => 4,
+        })
+    });
+    match kind {
+        None => ("n", export_array(rows, rows, Vec::new(), Vec::new())),
+        Some(1) => {
+            let words = values.map(|v| match v {
+                Value::Int(v) => *v as u64,
+                _ => 0,
+            });
+            (
+                "l",
+                export_array(
+                    rows,
+                    nulls,
+                    vec![validity, Some(words.collect())],
+                    Vec::new(),
+                ),
+            )
+        }
+        Some(2) => {
+            let words = values.map(|v| v.as_float().unwrap_or(0.0).to_bits());
+            (
+                "g",
+                export_array(
+                    rows,
+                    nulls,
+                    vec![validity, Some(words.collect())],
+                    Vec::new(),
+                ),
+            )
+        }
+        Some(3) => {
+            let packed = bits(values.map(|v| *v == Value::Bool(true)), rows);
+            (
+                "b",
+                export_array(rows, nulls, vec![validity, Some(packed)], Vec::new()),
+            )
+        }
+        _ => {
+            let mut offsets = vec![0i64];
+            let mut bytes = Vec::new();
+            for v in values {
+                if *v != Value::Null {
+                    bytes.extend_from_slice(v.to_string().as_bytes());
+                }
+                offsets.push(bytes.len() as i64);
+            }
+            let offsets = offsets.into_iter().map(|o| o as u64).collect();
+            let buffers = vec![validity, Some(offsets), Some(words(&bytes))];
+            ("U", export_array(rows, nulls, buffers, Vec::new()))
+        }
+    }
+}
+
+fn bits(set: impl Iterator<Item = bool>, len: usize) -> Vec<u64> {
+    let mut words = vec![0u64; len.div_ceil(64)];
+    for (i, set) in set.enumerate() {
+        if set {
+            words[i / 64] |= 1 << (i % 64);
+        }
+    }
+    words
+}
+
+fn words(bytes: &[u8]) -> Vec<u64> {
+    bytes
+        .chunks(8)
+        .map(|c| {
+            let mut word = [0; 8];
+            word[..c.len()].copy_from_slice(c);
+            u64::from_le_bytes(word)
+        })
+        .collect()
+}
+
+unsafe fn is_valid(array: &ArrowArray, i: usize) -> bool {
+    let validity = *array.buffers as *const u8;
+    array.null_count == 0 || validity.is_null() || *validity.add(i / 8) >> (i % 8) & 1 == 1
+}
+
+// The values `start` to `start + rows` of a child array, counting its own
+// offset in `start`.
+unsafe fn import_column(
+    array: &ArrowArray,
+    name: &str,
+    format: &str,
+    start: usize,
+    rows: usize,
+) -> Result<Vec<Value>, ArrowError> {
+    let buffer = |i: usize| *array.buffers.add(i);
+    let fixed = |width: usize, convert: &dyn Fn(*const u8) -> Result<Value, ArrowError>| {
+        (start..start + rows)
+            .map(|i| match is_valid(array, i) {
+                true => convert((buffer(1) as *const u8).add(i * width)),
+                false => Ok(Value::Null),
+            })
+            .collect::<Result<Vec<_>, _>>()
+    };
+    let int = |v: i128| {
+        i64::try_from(v)
+            .map(Value::Int)
+            .map_err(|_| ArrowError::OutOfRange {
+                column: name.to_string(),
+            })
+    };
+    match format {
+        "n" => Ok(vec![Value::Null; rows]),
+        "b" => (start..start + rows)
+            .map(|i| match is_valid(array, i) {
+                true => Ok(Value::Bool(
+                    *(buffer(1) as *const u8).add(i / 8) >> (i % 8) & 1 == 1,
+                )),
+                false => Ok(Value::Null),
+            })
+            .collect(),
+        "c" => fixed(1, &|p| int(*(p as *const i8) as i128)),
+        "C" => fixed(1, &|p| int(*p as i128)),
+        "s" => fixed(2, &|p| int((p as *const i16).read_unaligned() as i128)),
+        "S" => fixed(2, &|p| int((p as *const u16).read_unaligned() as i128)),
+        "i" => fixed(4, &|p| int((p as *const i32).read_unaligned() as i128)),
+        "I" => fixed(4, &|p| int((p as *const u32).read_unaligned() as i128)),
+        "l" => fixed(8, &|p| int((p as *const i64).read_unaligned() as i128)),
+        "L" => fixed(8, &|p| int((p as *const u64).read_unaligned() as i128)),
+        "f" => fixed(4, &|p| {
+            Ok(Value::Float((p as *const f32).read_unaligned() as f64))
+        }),
+        "g" => fixed(8, &|p| Ok(Value::Float((p as *const f64).read_unaligned()))),
+        "u" | "z" | "U" | "Z" => {
+            let large = format == "U" || format == "Z";
+            let offset = |i: usize| match large {
+                true => (buffer(1) as *const i64).add(i).read_unaligned() as usize,
+                false => (buffer(1) as *const i32).add(i).read_unaligned() as usize,
+            };
+            (start..start + rows)
+                .map(|i| match is_valid(array, i) {
+                    true => {
+                        let (from, to) = (offset(i), offset(i + 1));
+                        let bytes = std::slice::from_raw_parts(
+                            (buffer(2) as *const u8).add(from),
+                            to - from,
+                        );
+                        Ok(Value::Str(String::from_utf8_lossy(bytes).into()))
+                    }
+                    false => Ok(Value::Null),
+                })
+                .collect()
+        }
+        format => Err(ArrowError::UnsupportedType {
+            column: name.to_string(),
+            format: format.to_string(),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn batches_read_back_as_they_were_written() {
+        let rel = Relation::new(["id", "score", "name", "ok", "none"])
+            .row([
+                Value::Int(1),
+                Value::Float(0.5),
+                Value::Str("one".into()),
+                Value::Bool(true),
+                Value::Null,
+            ])
+            .row([
+                Value::Null,
+                Value::Float(-2.0),
+                Value::Null,
+                Value::Bool(false),
+                Value::Null,
+            ]);
+        assert_eq!(Relation::from_arrow(rel.to_arrow()), Ok(rel));
+    }
+
+    #[test]
+    fn dictionary_fields_are_unsupported() {
+        let (array, schema) = Relation::new(["k"]).rows([[1], [0]]).to_arrow().into_raw();
+        let mut values = export_schema("u", "", 0, Vec::new());
+        unsafe { (**schema.children).dictionary = &mut values };
+        let batch = unsafe { RecordBatch::from_raw(array, schema) };
+        assert_eq!(
+            Relation::from_arrow(batch),
+            Err(ArrowError::UnsupportedType {
+                column: "k".to_string(),
+                format: "dictionary of u".to_string(),
+            })
+        );
+        unsafe { values.release.unwrap()(&mut values) };
+    }
+}
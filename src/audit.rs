@@ -0,0 +1,119 @@
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use crate::catalog::Catalog;
+use crate::query::{Query, QueryError};
+use crate::value::Value;
+use crate::Relation;
+
+// The relation every audited query gets a row in, which can be joined like
+// any other.
+pub const AUDIT_LOG: &str = "audit_log";
+
+// When the query started in seconds since the epoch, who ran it, its
+// fingerprint, the inputs it joined in order, how long it took, the rows it
+// returned, the bytes of inputs it was given, and what went wrong, if
+// anything did. A query that failed has no plan or rows.
+pub const AUDIT_COLS: [&str; 8] = [
+    "started",
+    "user",
+    "fingerprint",
+    "plan",
+    "millis",
+    "rows",
+    "bytes",
+    "error",
+];
+
+impl Catalog {
+    // Runs `query` for `user` like `execute_with`, and appends a row about
+    // it to the audit log, whether it succeeded or not.
+    pub fn execute_audited(&mut self, user: &str, query: Query) -> Result<Relation, QueryError> {
+        let started = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        let fingerprint = format!("{:016x}", query.fingerprint());
+        let bytes = query.memory_estimate();
+        let start = Instant::now();
+        let result = query.execute_with_stats(Some(self));
+        let millis = start.elapsed().as_secs_f64() * 1000.0;
+
+        let (plan, rows, error) = match &result {
+            Ok((rel, stats)) => {
+                let inputs: Vec<_> = stats
+                    .plan()
+                    .steps
+                    .iter()
+                    .map(|s| s.input.as_str())
+                    .collect();
+                (
+                    Value::from(inputs.join(", ")),
+                    Value::Int(rel.data.len() as i64),
+                    Value::Null,
+                )
+            }
+            Err(e) => (Value::Null, Value::Null, Value::from(e.to_string())),
+        };
+        self.append(
+            AUDIT_LOG,
+            &AUDIT_COLS,
+            [vec![
+                Value::Int(started as i64),
+                Value::from(user),
+                Value::from(fingerprint),
+                plan,
+                Value::Float(millis),
+                rows,
+                Value::Int(bytes as i64),
+                error,
+            ]],
+        );
+        result.map(|(rel, _)| rel)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn col<'a>(log: &'a Relation, name: &str) -> Vec<&'a Value> {
+        let i = log.col_names.iter().position(|c| c == name).unwrap();
+        log.data.iter().map(|row| &row[i]).collect()
+    }
+
+    #[test]
+    fn every_audited_query_gets_a_row_whether_it_fails_or_not() {
+        let mut catalog = Catalog::new();
+        catalog.insert(
+            "users",
+            Relation::new(["id", "city"]).rows([[1, 10], [2, 20]]),
+        );
+        catalog.insert(
+            "orders",
+            Relation::new(["id", "total"]).rows([[1, 5], [1, 6]]),
+        );
+
+        let joined = catalog
+            .execute_audited("ana", Query::new().join_named("users").join_named("orders"))
+            .unwrap();
+        assert_eq!(joined.data.len(), 2);
+        assert!(catalog
+            .execute_audited("bo", Query::new().join_named("missing"))
+            .is_err());
+
+        let log = catalog.get(AUDIT_LOG).unwrap().clone();
+        assert_eq!(log.col_names, AUDIT_COLS);
+        assert_eq!(col(&log, "user"), [&Value::from("ana"), &Value::from("bo")]);
+        assert_eq!(col(&log, "rows"), [&Value::Int(2), &Value::Null]);
+        assert_ne!(col(&log, "plan")[0], &Value::Null);
+        assert_eq!(col(&log, "plan")[1], &Value::Null);
+        assert_eq!(col(&log, "error")[0], &Value::Null);
+        assert_ne!(col(&log, "error")[1], &Value::Null);
+
+        // Querying the log is audited too, once it has been read.
+        let read = catalog
+            .execute_audited("ana", Query::new().join_named(AUDIT_LOG))
+            .unwrap();
+        assert_eq!(read.data.len(), 2);
+        assert_eq!(catalog.get(AUDIT_LOG).unwrap().data.len(), 3);
+    }
+}
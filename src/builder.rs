@@ -0,0 +1,91 @@
+use std::fmt;
+
+use crate::value::Value;
+use crate::Relation;
+
+// Builds a relation a column at a time, which is how data usually comes
+// out of other code, and is faster than pushing rows one at a time since
+// every row is allocated once at its final size.
+#[derive(Debug, Default, Clone)]
+pub struct RelationBuilder {
+    columns: Vec<(String, Vec<Value>)>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BuildError {
+    // A column has a different number of values from the first one.
+    Length {
+        column: String,
+        len: usize,
+        expected: usize,
+    },
+    DuplicateColumn(String),
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BuildError::Length {
+                column,
+                len,
+                expected,
+            } => write!(
+                f,
+                "column {:?} has {} values, expected {}",
+                column, len, expected
+            ),
+            BuildError::DuplicateColumn(column) => {
+                write!(f, "column {:?} is given more than once", column)
+            }
+        }
+    }
+}
+
+impl std::error::Error for BuildError {}
+
+impl Relation {
+    pub fn builder() -> RelationBuilder {
+        RelationBuilder::default()
+    }
+}
+
+impl RelationBuilder {
+    pub fn column<V: Into<Value>>(
+        mut self,
+        name: impl Into<String>,
+        values: impl IntoIterator<Item = V>,
+    ) -> Self {
+        self.columns
+            .push((name.into(), values.into_iter().map(Into::into).collect()));
+        self
+    }
+
+    pub fn build(self) -> Result<Relation, BuildError> {
+        let expected = self.columns.first().map_or(0, |(_, values)| values.len());
+        for (i, (name, values)) in self.columns.iter().enumerate() {
+            if values.len() != expected {
+                return Err(BuildError::Length {
+                    column: name.clone(),
+                    len: values.len(),
+                    expected,
+                });
+            }
+            if self.columns[..i].iter().any(|(other, _)| other == name) {
+                return Err(BuildError::DuplicateColumn(name.clone()));
+            }
+        }
+
+        let mut data: Vec<Vec<Value>> = (0..expected)
+            .map(|_| Vec::with_capacity(self.columns.len()))
+            .collect();
+        for (_, values) in self.columns.iter() {
+            for (row, v) in data.iter_mut().zip(values) {
+                row.push(v.clone());
+            }
+        }
+        Ok(Relation::new_with_data(
+            self.columns.into_iter().map(|(name, _)| name),
+            data,
+        ))
+    }
+}
@@ -0,0 +1,87 @@
+use crate::graph::JoinTree;
+use crate::{Planner, Relation};
+
+impl JoinTree {
+    // Joins the relations in this order, with `leaf` giving each relation
+    // and `join` doing each join, so the caller decides how nulls match
+    // and what limits there are.
+    pub fn try_execute<E>(
+        &self,
+        leaf: &mut impl FnMut(usize) -> Result<Relation, E>,
+        join: &mut impl FnMut(&Relation, &Relation) -> Result<Relation, E>,
+    ) -> Result<Relation, E> {
+        match self {
+            JoinTree::Leaf(i) => leaf(*i),
+            JoinTree::Join(left, right) => {
+                let left = left.try_execute(leaf, join)?;
+                let right = right.try_execute(leaf, join)?;
+                join(&left, &right)
+            }
+        }
+    }
+}
+
+impl Planner {
+    // The join tree with the smallest total of estimated intermediate
+    // rows, bushy or not, going by the relations' row counts.
+    pub fn bushy_plan(&self) -> JoinTree {
+        self.bushy_order(|rels| self.estimate(rels))
+    }
+
+    // The cheapest join tree by `estimate`; see `Graph::bushy_order`.
+    pub fn bushy_order(&self, estimate: impl FnMut(&[usize]) -> f64) -> JoinTree {
+        self.query_graph
+            .bushy_order(self.joined_tables.len(), estimate)
+    }
+
+    // The join tree greedy operator ordering finds; see
+    // `Graph::goo_order`.
+    pub fn goo_order(&self, estimate: impl FnMut(&[usize]) -> f64) -> JoinTree {
+        self.query_graph
+            .goo_order(self.joined_tables.len(), estimate)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::MAX_DP_RELATIONS;
+
+    fn join(left: JoinTree, right: JoinTree) -> JoinTree {
+        JoinTree::Join(Box::new(left), Box::new(right))
+    }
+
+    #[test]
+    fn spine_goes_down_the_left() {
+        let [a, b, c, d] = [0, 1, 2, 3].map(JoinTree::Leaf);
+        let tree = join(join(a, b.clone()), join(c, d));
+        let (first, rights) = tree.spine();
+        assert_eq!(first, 0);
+        assert_eq!(rights, [&b, &join(JoinTree::Leaf(2), JoinTree::Leaf(3))]);
+        let mut joins = vec![];
+        let rels = tree
+            .try_execute(
+                &mut |i| Ok::<_, ()>(Relation::new([format!("c{}", i)]).row([i as i64])),
+                &mut |l, r| {
+                    joins.push((l.col_names.clone(), r.col_names.clone()));
+                    Ok(l.join(r))
+                },
+            )
+            .unwrap();
+        assert_eq!(rels.col_names, ["c0", "c1", "c2", "c3"]);
+        assert_eq!(joins.len(), 3);
+        assert_eq!(joins[1], (vec!["c2".to_string()], vec!["c3".to_string()]));
+    }
+
+    #[test]
+    fn big_plans_are_greedy() {
+        let chain = (0..MAX_DP_RELATIONS + 4).fold(Planner::default(), |planner, i| {
+            planner.join(Relation::new([format!("c{}", i), format!("c{}", i + 1)]).row([1, 1]))
+        });
+        let tree = chain.bushy_plan();
+        let mut rels = tree.relations();
+        rels.sort();
+        assert_eq!(rels, (0..MAX_DP_RELATIONS + 4).collect::<Vec<_>>());
+        assert_eq!(tree, chain.goo_order(|rels| chain.estimate(rels)));
+    }
+}
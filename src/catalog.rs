@@ -0,0 +1,535 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+use crate::domain::{Domain, DomainError};
+use crate::expr::Pred;
+use crate::fd::Dependencies;
+use crate::plan::{Lineage, Plan};
+use crate::query::{Intermediate, Limits};
+use crate::suggest::{infer_join_keys, InferredKey};
+use crate::value::Value;
+use crate::whynot::WhyNot;
+use crate::Relation;
+
+// Named relations that queries can join by name, along with statistics
+// about them. Each relation's keys of up to `KEY_COLS` columns and its
+// functional dependencies are found when it's added. A single column key has
+// as many distinct values as there are rows, and a column it determines has
+// at most that many. Until a query has joined on any other column, its
+// values are assumed to be unique as well. Running a query against the
+// catalog records the distinct values it saw in the columns it joined on,
+// and how many rows each join of catalog relations actually produced, so
+// later queries over the same relations are planned with what was observed
+// rather than the guesses.
+//
+// A catalog can hold other catalogs as namespaces, whose relations are
+// named from it as `namespace.relation`. Giving each user or dataset a
+// namespace of its own and running their queries against it keeps them
+// from seeing each other's relations, and each namespace can have limits
+// that every query using its relations is held to.
+#[derive(Default, Debug)]
+pub struct Catalog {
+    relations: HashMap<String, Relation>,
+    stats: HashMap<String, Stats>,
+    // The rows produced by joining each set of relations, by their sorted
+    // names.
+    joins: HashMap<Vec<String>, usize>,
+    namespaces: HashMap<String, Catalog>,
+    limits: Limits,
+    // Conditions every row of a relation that a query sees must meet.
+    filters: HashMap<String, Vec<Pred>>,
+    // The values each relation's columns are declared to have.
+    domains: HashMap<String, Vec<(String, Domain)>>,
+}
+
+// The most columns a key found by the catalog has.
+pub const KEY_COLS: usize = 2;
+
+#[derive(Default, Debug, Clone)]
+pub struct Stats {
+    pub rows: usize,
+    pub keys: Vec<Vec<String>>,
+    pub fds: Dependencies,
+    // The number of distinct values in each column that's been joined on.
+    pub ndv: HashMap<String, usize>,
+}
+
+// What running a query saw, to be recorded into the catalog afterwards.
+#[derive(Default, Debug)]
+pub struct Observed {
+    pub ndv: Vec<(String, String, usize)>,
+    pub joins: Vec<(Vec<String>, usize)>,
+    // The joins of the query itself, not of its named results.
+    pub plan: Plan,
+    pub intermediates: Vec<Intermediate>,
+    // Why the row a query was asked about isn't in its result.
+    pub why_not: Option<WhyNot>,
+    // The lineage of the last named result run, and of each one so far.
+    pub lineage: Vec<Lineage>,
+    pub lineages: HashMap<String, Vec<Lineage>>,
+}
+
+impl Stats {
+    // The number of distinct values in `col`, if it's known. A column
+    // determined by another can't have more distinct values than it does.
+    pub fn ndv(&self, col: &str) -> Option<usize> {
+        self.ndv.get(col).copied().or_else(|| {
+            self.fds
+                .iter()
+                .filter(|(lhs, rhs)| *rhs == col && lhs.len() == 1)
+                .filter_map(|(lhs, _)| self.ndv.get(&lhs[0]).copied())
+                .min()
+        })
+    }
+}
+
+impl Catalog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Adds or replaces a relation. Anything learned about a relation of the
+    // same name is forgotten.
+    pub fn insert(&mut self, name: impl Into<String>, rel: Relation) {
+        let name = name.into();
+        self.joins.retain(|names, _| !names.contains(&name));
+        let keys = rel.candidate_keys(KEY_COLS);
+        let ndv = keys
+            .iter()
+            .filter_map(|key| match key.as_slice() {
+                [col] => Some((col.clone(), rel.data.len())),
+                _ => None,
+            })
+            .collect();
+        self.stats.insert(
+            name.clone(),
+            Stats {
+                rows: rel.data.len(),
+                keys,
+                fds: Dependencies::discover(&rel),
+                ndv,
+            },
+        );
+        self.relations.insert(name, rel);
+    }
+
+    // Adds rows to the end of a relation, which is created with `cols` if
+    // there isn't one. Its keys and dependencies may not hold anymore, so
+    // they're forgotten rather than found again.
+    pub fn append(
+        &mut self,
+        name: &str,
+        cols: &[&str],
+        rows: impl IntoIterator<Item = Vec<Value>>,
+    ) {
+        self.joins
+            .retain(|names, _| !names.iter().any(|n| n == name));
+        let rel = self
+            .relations
+            .entry(name.to_string())
+            .or_insert_with(|| Relation::new(cols.iter().copied()));
+        rel.data.extend(rows);
+        self.stats.insert(
+            name.to_string(),
+            Stats {
+                rows: rel.data.len(),
+                ..Stats::default()
+            },
+        );
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Relation> {
+        let (catalog, name) = self.resolve(name)?;
+        catalog.relations.get(name)
+    }
+
+    // The names of this catalog's own relations, not counting those of its
+    // namespaces, in order.
+    pub fn names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.relations.keys().map(|n| n.as_str()).collect();
+        names.sort();
+        names
+    }
+
+    pub fn stats(&self, name: &str) -> Option<&Stats> {
+        let (catalog, name) = self.resolve(name)?;
+        catalog.stats.get(name)
+    }
+
+    // The namespace called `name`, which is created empty if there isn't
+    // one.
+    pub fn namespace_mut(&mut self, name: &str) -> &mut Catalog {
+        self.namespaces.entry(name.to_string()).or_default()
+    }
+
+    pub fn namespace(&self, name: &str) -> Option<&Catalog> {
+        self.namespaces.get(name)
+    }
+
+    // Limits for every query that joins any of this catalog's relations,
+    // on top of the query's own.
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    // The limits of this catalog and of every namespace `name` is in.
+    pub fn limits_for(&self, name: &str) -> Vec<Limits> {
+        self.path(name)
+            .iter()
+            .map(|(catalog, _)| catalog.limits)
+            .collect()
+    }
+
+    // This catalog and every namespace `name` is in, each with what `name`
+    // is called in it, ending with the one the relation is in if there is
+    // one. Filters and domains can be given in any of them.
+    fn path<'a>(&self, name: &'a str) -> Vec<(&Catalog, &'a str)> {
+        let mut path = vec![(self, name)];
+        if !self.relations.contains_key(name) {
+            if let Some((ns, rest)) = name.split_once('.') {
+                if let Some(catalog) = self.namespaces.get(ns) {
+                    path.extend(catalog.path(rest));
+                }
+            }
+        }
+        path
+    }
+
+    // Makes every query against this catalog only see the rows of the
+    // relation `name` that `pred` is true for, such as those of one tenant.
+    // The rows are filtered as they're read, before any of the query's own
+    // operators, so nothing a query does can get at the others. A relation
+    // with more than one filter only shows rows that meet all of them,
+    // whichever catalog on the way to it they were added to, and filters
+    // stay when the relation is replaced.
+    pub fn add_filter(&mut self, name: impl Into<String>, pred: Pred) {
+        self.filters.entry(name.into()).or_default().push(pred);
+    }
+
+    // The rows of `name` that queries see, which is all of them unless it
+    // has filters.
+    pub fn scan(&self, name: &str) -> Option<Cow<'_, Relation>> {
+        let rel = self.get(name)?;
+        let path = self.path(name);
+        let mut filters = path
+            .iter()
+            .flat_map(|(catalog, name)| catalog.filters.get(*name))
+            .flatten()
+            .peekable();
+        match filters.peek() {
+            None => Some(Cow::Borrowed(rel)),
+            Some(_) => Some(Cow::Owned(
+                filters.fold(rel.clone(), |rel, pred| rel.filter_expr(pred)),
+            )),
+        }
+    }
+
+    // Declares that the values of `col` in the relation `name` are all in
+    // `domain`, replacing what it was declared as before. Nothing checks
+    // this unless asked to, with `try_insert` or `Query::check_domains`, but
+    // queries trust it: rows of another relation joined with `name` on
+    // `col` that have values outside it are dropped before joining, and
+    // joins of columns whose domains don't overlap are expected to be
+    // empty. A domain of only so many values caps the column's estimated
+    // distinct values. A domain declared in the catalog the relation is in
+    // wins over one declared for the same column from outside it.
+    pub fn declare_domain(&mut self, name: impl Into<String>, col: &str, domain: Domain) {
+        let domains = self.domains.entry(name.into()).or_default();
+        domains.retain(|(c, _)| c != col);
+        domains.push((col.to_string(), domain));
+    }
+
+    // The domains declared for the columns of `name`.
+    pub fn domains(&self, name: &str) -> Vec<(String, Domain)> {
+        let mut domains: Vec<(String, Domain)> = Vec::new();
+        for (catalog, name) in self.path(name).into_iter().rev() {
+            for (col, domain) in catalog.domains.get(name).into_iter().flatten() {
+                if !domains.iter().any(|(c, _)| c == col) {
+                    domains.push((col.clone(), domain.clone()));
+                }
+            }
+        }
+        domains
+    }
+
+    pub fn domain(&self, name: &str, col: &str) -> Option<Domain> {
+        self.domains(name)
+            .into_iter()
+            .find(|(c, _)| c == col)
+            .map(|(_, d)| d)
+    }
+
+    // Like `insert`, unless a value of `rel` isn't in the domain declared
+    // for its column.
+    pub fn try_insert(
+        &mut self,
+        name: impl Into<String>,
+        rel: Relation,
+    ) -> Result<(), DomainError> {
+        let name = name.into();
+        rel.check_domains(&self.domains(&name))?;
+        self.insert(name, rel);
+        Ok(())
+    }
+
+    // The catalog that `name` is a relation of, and its name there. A name
+    // of this catalog's own wins over one in a namespace.
+    fn resolve<'a>(&self, name: &'a str) -> Option<(&Catalog, &'a str)> {
+        if self.relations.contains_key(name) {
+            return Some((self, name));
+        }
+        let (ns, rest) = name.split_once('.')?;
+        self.namespaces.get(ns)?.resolve(rest)
+    }
+
+    fn resolve_mut<'a>(&mut self, name: &'a str) -> Option<(&mut Catalog, &'a str)> {
+        if self.relations.contains_key(name) {
+            return Some((self, name));
+        }
+        let (ns, rest) = name.split_once('.')?;
+        self.namespaces.get_mut(ns)?.resolve_mut(rest)
+    }
+
+    // How many rows joining `names` produced the last time a query did it.
+    pub fn learned_rows(&self, names: &[&str]) -> Option<usize> {
+        let mut names: Vec<_> = names.iter().map(|n| n.to_string()).collect();
+        names.sort();
+        self.joins.get(&names).copied()
+    }
+
+    // The estimated number of rows from joining `inputs`, which are catalog
+    // relations if they're named. Each column shared by several inputs is
+    // assumed to divide the product of their sizes by the number of
+    // distinct values of all but the one with the fewest.
+    pub fn estimate(&self, inputs: &[(Option<&str>, &Relation)]) -> f64 {
+        let names: Option<Vec<&str>> = inputs.iter().map(|(name, _)| *name).collect();
+        if let Some(rows) = names.and_then(|names| self.learned_rows(&names)) {
+            return rows as f64;
+        }
+
+        let mut estimate: f64 = inputs
+            .iter()
+            .map(|(_, rel)| rel.data.len() as f64)
+            .product();
+        let mut ndvs: HashMap<&String, Vec<f64>> = HashMap::new();
+        let mut domains: HashMap<&String, Vec<Domain>> = HashMap::new();
+        for (name, rel) in inputs {
+            for col in rel.col_names.iter() {
+                let stats = name.and_then(|name| self.stats(name));
+                let domain = name.and_then(|name| self.domain(name, col));
+                let ndv = stats
+                    .and_then(|stats| stats.ndv(col))
+                    .unwrap_or(rel.data.len())
+                    .min(domain.as_ref().and_then(|d| d.size()).unwrap_or(usize::MAX));
+                ndvs.entry(col).or_default().push(ndv.max(1) as f64);
+                domains.entry(col).or_default().extend(domain);
+            }
+        }
+        let disjoint = domains.values().any(|ds| {
+            ds.iter()
+                .enumerate()
+                .any(|(i, a)| ds[i + 1..].iter().any(|b| !a.overlaps(b)))
+        });
+        if disjoint {
+            return 0.0;
+        }
+        for mut ndv in ndvs.into_values() {
+            ndv.sort_by(f64::total_cmp);
+            estimate /= ndv.iter().skip(1).product::<f64>();
+        }
+        estimate
+    }
+
+    pub fn record(&mut self, observed: Observed) {
+        for (name, col, ndv) in observed.ndv {
+            if let Some((catalog, name)) = self.resolve_mut(&name) {
+                if let Some(stats) = catalog.stats.get_mut(name) {
+                    stats.ndv.insert(col, ndv);
+                }
+            }
+        }
+        for (mut names, rows) in observed.joins {
+            names.sort();
+            self.joins.insert(names, rows);
+        }
+    }
+
+    // Likely join keys between every pair of relations, judged by how much
+    // their values overlap rather than by their names. It reads every row
+    // of every relation, so it's only done when asked for.
+    pub fn infer_join_keys(&self) -> Vec<(&str, &str, InferredKey)> {
+        let mut names: Vec<&String> = self.relations.keys().collect();
+        names.sort();
+        let mut keys = Vec::new();
+        for (i, left) in names.iter().enumerate() {
+            for right in names[i + 1..].iter() {
+                for key in infer_join_keys(&self.relations[*left], &self.relations[*right]) {
+                    keys.push((left.as_str(), right.as_str(), key));
+                }
+            }
+        }
+        keys
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::expr::{col, lit};
+    use crate::query::{Query, QueryError};
+
+    fn tenants() -> Catalog {
+        let mut catalog = Catalog::new();
+        catalog.namespace_mut("acme").insert(
+            "invoices",
+            Relation::new(["tenant_id", "invoice"]).rows([[1, 100], [2, 200], [1, 101]]),
+        );
+        catalog
+    }
+
+    #[test]
+    fn filters_from_outside_a_namespace_still_apply() {
+        let mut catalog = tenants();
+        catalog.add_filter("acme.invoices", col("tenant_id").equals(lit(1)));
+        let scanned = catalog.scan("acme.invoices").unwrap();
+        assert_eq!(scanned.data.len(), 2);
+        assert!(scanned.data.iter().all(|row| row[0] == Value::from(1)));
+        catalog
+            .namespace_mut("acme")
+            .add_filter("invoices", col("invoice").equals(lit(101)));
+        assert_eq!(
+            catalog.scan("acme.invoices").unwrap().data,
+            [vec![Value::from(1), Value::from(101)]]
+        );
+        let seen = Query::new()
+            .join_named("acme.invoices")
+            .execute_with(&mut catalog)
+            .unwrap();
+        assert_eq!(seen.data.len(), 1);
+    }
+
+    #[test]
+    fn domains_from_outside_a_namespace_still_apply() {
+        let mut catalog = tenants();
+        catalog.declare_domain("acme.invoices", "tenant_id", Domain::one_of([1, 2]));
+        catalog
+            .namespace_mut("acme")
+            .declare_domain("invoices", "tenant_id", Domain::one_of([1]));
+        catalog.declare_domain("acme.invoices", "invoice", Domain::range(0, 999));
+        let domains = catalog.domains("acme.invoices");
+        assert_eq!(domains.len(), 2);
+        assert_eq!(
+            catalog.domain("acme.invoices", "tenant_id"),
+            Some(Domain::one_of([1]))
+        );
+        assert_eq!(
+            catalog.domain("acme.invoices", "invoice"),
+            Some(Domain::range(0, 999))
+        );
+        let bad = Relation::new(["tenant_id", "invoice"]).rows([[1, 5000]]);
+        assert!(catalog.try_insert("acme.invoices", bad).is_err());
+    }
+
+    #[test]
+    fn learns_from_queries_until_a_relation_changes() {
+        let mut catalog = Catalog::new();
+        catalog.insert(
+            "a",
+            Relation::new(["x", "y"]).rows([[1, 1], [2, 1], [3, 2]]),
+        );
+        catalog.insert(
+            "b",
+            Relation::new(["y", "z"]).rows([[1, 5], [1, 6], [2, 7], [9, 8]]),
+        );
+        // Before any query, `y` is only known to have no more distinct
+        // values than `z`, the key that determines it.
+        assert_eq!(catalog.stats("b").unwrap().ndv("y"), Some(4));
+        let ab = |catalog: &Catalog| {
+            let (a, b) = (catalog.get("a").unwrap(), catalog.get("b").unwrap());
+            catalog.estimate(&[(Some("a"), a), (Some("b"), b)])
+        };
+        let run = |catalog: &mut Catalog| {
+            Query::new()
+                .join_named("a")
+                .join_named("b")
+                .strategy(crate::query::Strategy::Bfs)
+                .execute_with(catalog)
+                .unwrap()
+        };
+        assert_eq!(run(&mut catalog).data.len(), 5);
+        // The size of the join, whichever way round it's asked about, and
+        // the distinct values of the column it was built on.
+        assert_eq!(catalog.learned_rows(&["b", "a"]), Some(5));
+        assert_eq!(ab(&catalog), 5.0);
+        assert_eq!(catalog.stats("b").unwrap().ndv("y"), Some(3));
+        // Replacing or adding to either side forgets what was learned.
+        catalog.append("b", &["y", "z"], [vec![Value::from(2), Value::from(9)]]);
+        assert_eq!(catalog.learned_rows(&["a", "b"]), None);
+        assert_eq!(catalog.stats("b").unwrap().rows, 5);
+        assert_eq!(run(&mut catalog).data.len(), 6);
+        assert_eq!(catalog.learned_rows(&["a", "b"]), Some(6));
+        catalog.insert("a", Relation::new(["x", "y"]).row([1, 1]));
+        assert_eq!(catalog.learned_rows(&["a", "b"]), None);
+        assert_ne!(ab(&catalog), 6.0);
+    }
+
+    #[test]
+    fn namespaces_resolve_names_and_hold_queries_to_their_limits() {
+        let mut catalog = tenants();
+        catalog.insert("acme.invoices", Relation::new(["shadow"]).row([1]));
+        // A relation of the catalog's own wins over one in a namespace.
+        assert_eq!(catalog.get("acme.invoices").unwrap().col_names, ["shadow"]);
+        catalog.relations.remove("acme.invoices");
+        assert_eq!(catalog.get("acme.invoices").unwrap().data.len(), 3);
+        assert_eq!(catalog.namespace("acme").unwrap().names(), ["invoices"]);
+        assert!(catalog.names().is_empty());
+        assert!(catalog.get("globex.invoices").is_none());
+
+        let limits = Limits {
+            max_output_rows: Some(2),
+            ..Limits::default()
+        };
+        catalog.namespace_mut("acme").set_limits(limits);
+        assert_eq!(catalog.limits_for("acme.invoices").len(), 2);
+        assert_eq!(catalog.limits_for("acme.invoices")[1], limits);
+        let err = Query::new()
+            .join_named("acme.invoices")
+            .execute_with(&mut catalog)
+            .unwrap_err();
+        assert!(
+            matches!(err, QueryError::OutputRows { limit: 2, .. }),
+            "{err:?}"
+        );
+    }
+
+    #[test]
+    fn filters_outlast_the_relation_they_filter() {
+        let mut catalog = Catalog::new();
+        catalog.add_filter("users", col("tenant").equals(lit(1)));
+        assert!(catalog.scan("users").is_none());
+        catalog.insert(
+            "users",
+            Relation::new(["tenant", "user"]).rows([[1, 10], [2, 20]]),
+        );
+        catalog.insert(
+            "orders",
+            Relation::new(["user", "total"]).rows([[10, 5], [20, 7]]),
+        );
+        let joined = Query::new()
+            .join_named("users")
+            .join_named("orders")
+            .execute_with(&mut catalog)
+            .unwrap();
+        assert_eq!(joined.data.len(), 1);
+        catalog.insert(
+            "users",
+            Relation::new(["tenant", "user"]).rows([[2, 20], [1, 30]]),
+        );
+        assert_eq!(
+            catalog.scan("users").unwrap().data,
+            [vec![Value::from(1), Value::from(30)]]
+        );
+        // The relation itself is still all there.
+        assert_eq!(catalog.get("users").unwrap().data.len(), 2);
+    }
+}
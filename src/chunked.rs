@@ -0,0 +1,289 @@
+use std::borrow::Cow;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::{process, thread};
+
+use crate::csv::{files_in, SchemaEvolution};
+use crate::value::Value;
+use crate::Relation;
+
+// How many rows each chunk holds by default.
+pub const CHUNK_ROWS: usize = 4096;
+
+// Tells apart the spilled chunks of different relations in one directory.
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+// A relation stored as runs of rows that are each kept in memory or on disk
+// on their own, with the range of every column in each run kept in memory
+// either way. Scans skip chunks whose ranges rule them out, can be spread
+// over threads a chunk at a time, and only ever need one spilled chunk in
+// memory per thread.
+#[derive(Debug)]
+pub struct Chunked {
+    pub col_names: Vec<String>,
+    chunks: Vec<Chunk>,
+    id: usize,
+}
+
+#[derive(Debug)]
+pub struct Chunk {
+    pub len: usize,
+    pub min: Vec<Value>,
+    pub max: Vec<Value>,
+    rows: Storage,
+}
+
+#[derive(Debug)]
+enum Storage {
+    Memory(Vec<Vec<Value>>),
+    Disk(PathBuf),
+}
+
+impl Relation {
+    // Splits the relation into chunks of `chunk_rows` rows, in order.
+    pub fn into_chunks(self, chunk_rows: usize) -> Chunked {
+        let mut chunks = Vec::new();
+        let mut data = self.data.into_iter().peekable();
+        while data.peek().is_some() {
+            let rows: Vec<Vec<Value>> = data.by_ref().take(chunk_rows.max(1)).collect();
+            let mut min = rows[0].clone();
+            let mut max = rows[0].clone();
+            for row in rows[1..].iter() {
+                for (c, v) in row.iter().enumerate() {
+                    if *v < min[c] {
+                        min[c] = v.clone();
+                    } else if *v > max[c] {
+                        max[c] = v.clone();
+                    }
+                }
+            }
+            chunks.push(Chunk {
+                len: rows.len(),
+                min,
+                max,
+                rows: Storage::Memory(rows),
+            });
+        }
+        Chunked {
+            col_names: self.col_names,
+            chunks,
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+        }
+    }
+}
+
+impl Chunk {
+    pub fn spilled(&self) -> bool {
+        matches!(self.rows, Storage::Disk(_))
+    }
+
+    // Whether the chunk could have a row with every column in `bounds`,
+    // given as a column position and an inclusive range.
+    fn overlaps(&self, bounds: &[(usize, Value, Value)]) -> bool {
+        bounds
+            .iter()
+            .all(|(c, lo, hi)| self.min[*c] <= *hi && *lo <= self.max[*c])
+    }
+
+    fn rows(&self) -> io::Result<Cow<'_, [Vec<Value>]>> {
+        match &self.rows {
+            Storage::Memory(rows) => Ok(Cow::Borrowed(rows)),
+            Storage::Disk(path) => Ok(Cow::Owned(Relation::load(path)?.data)),
+        }
+    }
+}
+
+impl Chunked {
+    pub fn len(&self) -> usize {
+        self.chunks.iter().map(|c| c.len).sum()
+    }
+
+    pub fn chunks(&self) -> &[Chunk] {
+        &self.chunks
+    }
+
+    // Reads every `.csv` and `.parquet` file in `dir` into chunks of
+    // `chunk_rows` rows, however many rows each file has, so a directory of
+    // many small part files scans and splits over threads as well as one big
+    // file does. The files are read on as many threads as there are cores,
+    // a share of the files each, but their rows are in order of file name.
+    // Parquet files need the `parquet` feature.
+    pub fn load_dir(
+        dir: impl AsRef<Path>,
+        evolution: SchemaEvolution,
+        chunk_rows: usize,
+    ) -> io::Result<Chunked> {
+        let paths = files_in(dir.as_ref(), &["csv", "parquet"])?;
+        let threads = thread::available_parallelism().map_or(1, |n| n.get());
+        let parts: Vec<io::Result<Vec<(PathBuf, Relation)>>> = thread::scope(|s| {
+            let handles: Vec<_> = paths
+                .chunks(paths.len().div_ceil(threads).max(1))
+                .map(|mine| {
+                    s.spawn(move || {
+                        mine.iter()
+                            .map(|path| read_part(path).map(|rel| (path.clone(), rel)))
+                            .collect()
+                    })
+                })
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+        let mut files = Vec::new();
+        for part in parts {
+            files.extend(part?);
+        }
+        Ok(Relation::concat_files(files, evolution)?.into_chunks(chunk_rows))
+    }
+
+    // Writes every chunk still in memory to a file of its own in `dir` and
+    // frees its rows. The files are named for this relation and process, so
+    // relations can share a directory, and are removed when the relation is
+    // dropped.
+    pub fn spill(&mut self, dir: impl AsRef<Path>) -> io::Result<()> {
+        let dir = dir.as_ref();
+        fs::create_dir_all(dir)?;
+        for (i, chunk) in self.chunks.iter_mut().enumerate() {
+            if let Storage::Memory(rows) = &mut chunk.rows {
+                let path = dir.join(format!("chunk_{}_{}_{}.nbjr", process::id(), self.id, i));
+                Relation::new_with_data(self.col_names.iter().cloned(), std::mem::take(rows))
+                    .save(&path)?;
+                chunk.rows = Storage::Disk(path);
+            }
+        }
+        Ok(())
+    }
+
+    // The rows with each column in `bounds` within its inclusive range, in
+    // order, reading only the chunks that could have any. Only ints are
+    // ever in range, and a column the relation doesn't have is an error.
+    pub fn scan(&self, bounds: &[(&str, i64, i64)]) -> io::Result<Relation> {
+        self.par_scan(bounds, 1)
+    }
+
+    // Like `scan`, but with the chunks shared out between `threads` threads.
+    pub fn par_scan(&self, bounds: &[(&str, i64, i64)], threads: usize) -> io::Result<Relation> {
+        let bounds = bounds
+            .iter()
+            .map(
+                |(col, lo, hi)| match self.col_names.iter().position(|c| c == col) {
+                    Some(c) => Ok((c, Value::Int(*lo), Value::Int(*hi))),
+                    None => Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("no column {:?} in {:?}", col, self.col_names),
+                    )),
+                },
+            )
+            .collect::<io::Result<Vec<_>>>()?;
+        let threads = threads.max(1);
+        let chunks: Vec<&Chunk> = self
+            .chunks
+            .iter()
+            .filter(|chunk| chunk.overlaps(&bounds))
+            .collect();
+
+        let parts: Vec<io::Result<Vec<Vec<Value>>>> = thread::scope(|s| {
+            let handles: Vec<_> = chunks
+                .chunks(chunks.len().div_ceil(threads).max(1))
+                .map(|mine| {
+                    let bounds = &bounds;
+                    s.spawn(move || {
+                        let mut out = Vec::new();
+                        for chunk in mine {
+                            out.extend(
+                                chunk
+                                    .rows()?
+                                    .iter()
+                                    .filter(|row| {
+                                        bounds
+                                            .iter()
+                                            .all(|(c, lo, hi)| (lo..=hi).contains(&&row[*c]))
+                                    })
+                                    .cloned(),
+                            );
+                        }
+                        Ok(out)
+                    })
+                })
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+
+        let mut data = Vec::new();
+        for part in parts {
+            data.extend(part?);
+        }
+        Ok(Relation::new_with_data(
+            self.col_names.iter().cloned(),
+            data,
+        ))
+    }
+}
+
+// Reads a part file on the calling thread alone, since the others are busy
+// with files of their own.
+fn read_part(path: &Path) -> io::Result<Relation> {
+    match path.extension().and_then(|e| e.to_str()) {
+        #[cfg(feature = "parquet")]
+        Some("parquet") => Relation::from_parquet(path),
+        #[cfg(not(feature = "parquet"))]
+        Some("parquet") => Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!(
+                "{}: reading Parquet needs the parquet feature",
+                path.display()
+            ),
+        )),
+        _ => Relation::load_csv_with_threads(path, 1),
+    }
+}
+
+impl Drop for Chunked {
+    fn drop(&mut self) {
+        for chunk in self.chunks.iter() {
+            if let Storage::Disk(path) = &chunk.rows {
+                let _ = fs::remove_file(path);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scratch::Scratch;
+
+    #[test]
+    fn spilled_relations_can_share_a_directory() {
+        let dir = Scratch::new("chunks");
+        let mut a = Relation::new(["x"])
+            .rows((0..10).map(|i| [i]))
+            .into_chunks(4);
+        let mut b = Relation::new(["x"])
+            .rows((100..110).map(|i| [i]))
+            .into_chunks(4);
+        a.spill(&dir).unwrap();
+        b.spill(&dir).unwrap();
+        assert!(a.chunks().iter().all(Chunk::spilled));
+        assert_eq!(
+            a.scan(&[]).unwrap().data,
+            (0..10).map(|i| vec![Value::from(i)]).collect::<Vec<_>>()
+        );
+        drop(b);
+        assert_eq!(a.scan(&[("x", 5, 200)]).unwrap().data.len(), 5);
+        drop(a);
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn scanning_an_unknown_column_is_an_error() {
+        let chunks = Relation::new(["x"])
+            .rows((0..10).map(|i| [i]))
+            .into_chunks(4);
+        for threads in [1, 4] {
+            let err = chunks.par_scan(&[("y", 0, 5)], threads).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        }
+    }
+}
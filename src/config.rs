@@ -0,0 +1,238 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crate::query::{Limits, Strategy};
+use crate::scheduler::Scheduler;
+
+// Settings for running queries, read from a file of `key = value` lines.
+// Blank lines and anything after a `#` are ignored, and keys that aren't
+// given keep their defaults:
+//
+//   memory_budget = 1073741824
+//   threads = 4
+//   strategy = dp
+//   max_output_rows = 100000
+//   max_wall_time_ms = 5000
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    // The bytes of queries a scheduler lets run at once.
+    pub memory_budget: usize,
+    pub threads: usize,
+    // How queries that don't pick a strategy order their joins.
+    pub strategy: Option<Strategy>,
+    // Limits every query is held to, on top of its own.
+    pub limits: Limits,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            memory_budget: 1 << 30,
+            threads: thread::available_parallelism().map_or(1, |n| n.get()),
+            strategy: None,
+            limits: Limits::default(),
+        }
+    }
+}
+
+impl Config {
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Config> {
+        let path = path.as_ref();
+        let invalid = |line: usize, msg: String| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}:{}: {}", path.display(), line + 1, msg),
+            )
+        };
+        let mut config = Config::default();
+        let text = fs::read_to_string(path)
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))?;
+        for (i, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let Some((key, value)) = line.split_once('=') else {
+                return Err(invalid(
+                    i,
+                    format!("expected `key = value`, found {:?}", line),
+                ));
+            };
+            let (key, value) = (key.trim(), value.trim());
+            let number = || {
+                value
+                    .parse::<usize>()
+                    .map_err(|_| invalid(i, format!("{} takes a number, not {:?}", key, value)))
+            };
+            match key {
+                "memory_budget" => config.memory_budget = number()?,
+                "threads" => config.threads = number()?.max(1),
+                "strategy" => {
+                    config.strategy = Some(match value {
+                        "bfs" => Strategy::Bfs,
+                        "greedy" => Strategy::Greedy,
+                        "dp" => Strategy::Dp,
+                        "bushy" => Strategy::Bushy,
+                        _ => {
+                            return Err(invalid(
+                                i,
+                                format!("no strategy {:?}; try bfs, greedy, dp or bushy", value),
+                            ))
+                        }
+                    })
+                }
+                "max_output_rows" => config.limits.max_output_rows = Some(number()?),
+                "max_intermediate_rows" => config.limits.max_intermediate_rows = Some(number()?),
+                "max_memory" => config.limits.max_memory = Some(number()?),
+                "max_wall_time_ms" => {
+                    config.limits.max_wall_time = Some(Duration::from_millis(number()? as u64))
+                }
+                "max_join_growth" => {
+                    config.limits.max_join_growth = Some(value.parse().map_err(|_| {
+                        invalid(i, format!("{} takes a number, not {:?}", key, value))
+                    })?)
+                }
+                _ => return Err(invalid(i, format!("unknown setting {:?}", key))),
+            }
+        }
+        Ok(config)
+    }
+
+    // A scheduler with the threads and memory budget of this config.
+    pub fn scheduler(&self) -> Scheduler {
+        Scheduler::new(self.threads, self.memory_budget)
+    }
+
+    // Gives a running scheduler the threads and memory budget of this
+    // config, such as after a reload.
+    pub fn apply(&self, scheduler: &mut Scheduler) {
+        scheduler.resize(self.threads, self.memory_budget);
+    }
+}
+
+// How many SIGHUPs the process has had. Each `Reloading` keeps the count it
+// last reloaded at, so every one of them reloads once per hangup.
+static HANGUPS: AtomicUsize = AtomicUsize::new(0);
+
+#[cfg(unix)]
+extern "C" fn on_hangup(_: i32) {
+    HANGUPS.fetch_add(1, Ordering::SeqCst);
+}
+
+#[cfg(unix)]
+fn watch_hangups() {
+    const SIGHUP: i32 = 1;
+    extern "C" {
+        fn signal(signum: i32, handler: extern "C" fn(i32)) -> usize;
+    }
+    // Safe since the handler only adds to an atomic.
+    unsafe {
+        signal(SIGHUP, on_hangup);
+    }
+}
+
+#[cfg(not(unix))]
+fn watch_hangups() {}
+
+// A config file that's read again whenever the process gets SIGHUP, or
+// `reload` is called. A file that stops parsing leaves the last good config
+// in place.
+pub struct Reloading {
+    path: PathBuf,
+    current: Mutex<Arc<Config>>,
+    // The hangups seen as of the last reload.
+    hangups: AtomicUsize,
+}
+
+impl Reloading {
+    pub fn new(path: impl Into<PathBuf>) -> io::Result<Reloading> {
+        let path = path.into();
+        let hangups = HANGUPS.load(Ordering::SeqCst);
+        let config = Config::load(&path)?;
+        watch_hangups();
+        Ok(Reloading {
+            path,
+            current: Mutex::new(Arc::new(config)),
+            hangups: AtomicUsize::new(hangups),
+        })
+    }
+
+    // The config as of the last reload, reloading first if there's been a
+    // SIGHUP since.
+    pub fn get(&self) -> Arc<Config> {
+        let hangups = HANGUPS.load(Ordering::SeqCst);
+        if self.hangups.swap(hangups, Ordering::SeqCst) != hangups {
+            if let Err(e) = self.reload() {
+                eprintln!("keeping the old config: {}", e);
+            }
+        }
+        self.current.lock().unwrap().clone()
+    }
+
+    pub fn reload(&self) -> io::Result<()> {
+        let config = Config::load(&self.path)?;
+        *self.current.lock().unwrap() = Arc::new(config);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scratch::Scratch;
+
+    fn parse(name: &str, text: &str) -> io::Result<Config> {
+        let dir = Scratch::new(name);
+        let path = dir.join("nbjoiner.conf");
+        fs::write(&path, text).unwrap();
+        Config::load(&path)
+    }
+
+    #[test]
+    fn reads_settings_and_rejects_bad_lines() {
+        let config = parse(
+            "good",
+            "# comment\n\nthreads = 0\nstrategy = bushy # inline\nmax_join_growth = 2.5\n",
+        )
+        .unwrap();
+        assert_eq!(config.threads, 1);
+        assert_eq!(config.strategy, Some(Strategy::Bushy));
+        assert_eq!(config.limits.max_join_growth, Some(2.5));
+        assert_eq!(config.memory_budget, Config::default().memory_budget);
+        for (text, message) in [
+            ("threads = 2\nthreads", ":2: expected `key = value`"),
+            ("threads = many", ":1: threads takes a number, not \"many\""),
+            ("strategy = fast", ":1: no strategy \"fast\""),
+            ("max_join_growth = x", ":1: max_join_growth takes a number"),
+            ("colour = blue", ":1: unknown setting \"colour\""),
+        ] {
+            let e = parse("bad", text).unwrap_err();
+            assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+            assert!(e.to_string().contains(message), "{e}");
+        }
+        let e = Config::load(Scratch::new("missing").join("nbjoiner.conf")).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn every_reloading_config_sees_a_hangup() {
+        let dir = Scratch::new("hangup");
+        let path = dir.join("nbjoiner.conf");
+        fs::write(&path, "threads = 2\n").unwrap();
+        let first = Reloading::new(&path).unwrap();
+        let second = Reloading::new(&path).unwrap();
+        fs::write(&path, "threads = 3\n").unwrap();
+        HANGUPS.fetch_add(1, Ordering::SeqCst);
+        assert_eq!(first.get().threads, 3);
+        assert_eq!(second.get().threads, 3);
+        // A file that stops parsing keeps the last good config.
+        fs::write(&path, "threads = lots\n").unwrap();
+        HANGUPS.fetch_add(1, Ordering::SeqCst);
+        assert_eq!(first.get().threads, 3);
+    }
+}
@@ -0,0 +1,43 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::value::Value;
+use crate::Relation;
+
+// Small lookup tables from application code. Pairs and maps become
+// relations with `key` and `value` columns, which `with_names` can rename.
+impl<K: Into<Value>, V: Into<Value>> From<Vec<(K, V)>> for Relation {
+    fn from(pairs: Vec<(K, V)>) -> Relation {
+        Relation::new(["key", "value"]).rows(pairs.into_iter().map(|(k, v)| [k.into(), v.into()]))
+    }
+}
+
+// Rows come out in key order, rather than the map's.
+impl<K: Into<Value>, V: Into<Value>, S> From<HashMap<K, V, S>> for Relation {
+    fn from(map: HashMap<K, V, S>) -> Relation {
+        let mut rel = Relation::from(map.into_iter().collect::<Vec<_>>());
+        rel.data.sort();
+        rel
+    }
+}
+
+impl<K: Into<Value>, V: Into<Value>> From<BTreeMap<K, V>> for Relation {
+    fn from(map: BTreeMap<K, V>) -> Relation {
+        Relation::from(map.into_iter().collect::<Vec<_>>())
+    }
+}
+
+impl Relation {
+    // The same rows under new column names, one for each column.
+    pub fn with_names(mut self, names: impl IntoIterator<Item = impl Into<String>>) -> Relation {
+        let names: Vec<String> = names.into_iter().map(Into::into).collect();
+        assert_eq!(
+            names.len(),
+            self.col_names.len(),
+            "{:?} doesn't name every column of {:?}",
+            names,
+            self.col_names
+        );
+        self.col_names = names;
+        self
+    }
+}
@@ -0,0 +1,127 @@
+use std::fmt;
+use std::sync::Arc;
+
+// What the operators of a plan cost, in whatever unit a model likes, given
+// how many rows of how many columns each one handles. The planner only
+// compares costs, so only their ratios matter.
+pub trait CostModel: Send + Sync {
+    fn name(&self) -> &str;
+    fn scan(&self, rows: f64, width: usize) -> f64;
+    fn hash_build(&self, rows: f64, width: usize) -> f64;
+    fn probe(&self, rows: f64, width: usize) -> f64;
+    fn sort(&self, rows: f64, width: usize) -> f64;
+    // The cost of producing each output row, on top of finding it.
+    fn per_row(&self, rows: f64, width: usize) -> f64;
+
+    // A hash join that builds on its right side and probes with its left,
+    // producing `output` rows `width` columns wide.
+    fn hash_join(&self, left: (f64, usize), right: (f64, usize), output: f64, width: usize) -> f64 {
+        self.hash_build(right.0, right.1) + self.probe(left.0, left.1) + self.per_row(output, width)
+    }
+}
+
+// Every row touched costs the same, whatever it's touched for, except that
+// sorting costs a factor of the log of the rows more.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RowCount;
+
+impl CostModel for RowCount {
+    fn name(&self) -> &str {
+        "row count"
+    }
+
+    fn scan(&self, rows: f64, _: usize) -> f64 {
+        rows
+    }
+
+    fn hash_build(&self, rows: f64, _: usize) -> f64 {
+        rows
+    }
+
+    fn probe(&self, rows: f64, _: usize) -> f64 {
+        rows
+    }
+
+    fn sort(&self, rows: f64, _: usize) -> f64 {
+        rows * rows.max(2.0).log2()
+    }
+
+    fn per_row(&self, rows: f64, _: usize) -> f64 {
+        rows
+    }
+}
+
+// Charges separately for the pages of bytes read and written and for the
+// work done on each row. Building a hash table writes the rows it holds,
+// and probing one is a random access per row, which costs more than
+// reading in order.
+#[derive(Debug, Clone, Copy)]
+pub struct IoCpu {
+    pub page_bytes: f64,
+    pub io_cost: f64,
+    pub cpu_cost: f64,
+    // How much more a random access to a hash table costs than the work on
+    // a row read in order.
+    pub random_access: f64,
+}
+
+impl Default for IoCpu {
+    fn default() -> Self {
+        Self {
+            page_bytes: 4096.0,
+            io_cost: 1.0,
+            cpu_cost: 0.01,
+            random_access: 4.0,
+        }
+    }
+}
+
+impl IoCpu {
+    fn pages(&self, rows: f64, width: usize) -> f64 {
+        (rows * width as f64 * 8.0 / self.page_bytes).ceil()
+    }
+}
+
+impl CostModel for IoCpu {
+    fn name(&self) -> &str {
+        "io/cpu"
+    }
+
+    fn scan(&self, rows: f64, width: usize) -> f64 {
+        self.pages(rows, width) * self.io_cost + rows * self.cpu_cost
+    }
+
+    fn hash_build(&self, rows: f64, width: usize) -> f64 {
+        2.0 * self.pages(rows, width) * self.io_cost + rows * self.cpu_cost
+    }
+
+    fn probe(&self, rows: f64, width: usize) -> f64 {
+        self.pages(rows, width) * self.io_cost + rows * self.cpu_cost * self.random_access
+    }
+
+    fn sort(&self, rows: f64, width: usize) -> f64 {
+        let passes = rows.max(2.0).log2();
+        2.0 * self.pages(rows, width) * self.io_cost + rows * passes * self.cpu_cost
+    }
+
+    fn per_row(&self, rows: f64, width: usize) -> f64 {
+        self.pages(rows, width) * self.io_cost + rows * self.cpu_cost
+    }
+}
+
+// A shared cost model that queries can hold on to, be cloned with, and
+// compare by which model it is.
+#[derive(Clone)]
+pub struct Model(pub Arc<dyn CostModel>);
+
+impl fmt::Debug for Model {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Model({:?})", self.0.name())
+    }
+}
+
+impl PartialEq for Model {
+    fn eq(&self, other: &Model) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
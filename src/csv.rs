@@ -0,0 +1,584 @@
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+
+use crate::schema::{ColumnType, Schema, NULLS};
+use crate::value::Value;
+use crate::Relation;
+
+// What loading several files into one relation does when their headers
+// differ.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SchemaEvolution {
+    // Every file has to have the same columns as the first, in any order.
+    #[default]
+    Strict,
+    // The relation has every column any file has, in the order they're
+    // first seen, and rows from files without one get nulls in it.
+    Lenient,
+}
+
+impl Relation {
+    // Reads a CSV file with a header line of column names. Values are split
+    // on commas, and can be quoted with `"` to have commas, newlines or
+    // spaces around them, with `""` for a quote. Unquoted values that are
+    // empty or like `NULL` are nulls, and the rest are ints, floats or bools
+    // if they parse as one, or strings if not; quoted values are always
+    // strings. Big files are parsed on as many threads as there are cores.
+    pub fn load_csv(path: impl AsRef<Path>) -> io::Result<Relation> {
+        Relation::load_csv_with_threads(path, cores())
+    }
+
+    // Like `load_csv`, with files of more than `PARALLEL_BYTES` split into
+    // `threads` runs of whole rows, each parsed on a thread of its own.
+    pub fn load_csv_with_threads(path: impl AsRef<Path>, threads: usize) -> io::Result<Relation> {
+        let path = path.as_ref();
+        let bytes = fs::read(path)?;
+        let text = std::str::from_utf8(&bytes).map_err(|e| invalid(path, &e.to_string()))?;
+        let mut records = Records::new(text);
+        let col_names = header(&mut records).map_err(|msg| invalid(path, &msg))?;
+        let first = records.line + 1;
+        let data = parse_runs(records.rest(), threads, col_names.len(), |_, fields| {
+            fields.iter().map(parse).collect::<Vec<_>>()
+        })
+        .map_err(|(line, msg)| invalid(path, &format!("line {} {}", first + line, msg)))?;
+        Ok(Relation::new_with_data(col_names, data))
+    }
+
+    // Reads a CSV file with a header line of column names, like
+    // `from_csv_reader`, on as many threads as there are cores if it's big.
+    pub fn from_csv_path(path: impl AsRef<Path>, schema: Option<&Schema>) -> io::Result<Relation> {
+        let path = path.as_ref();
+        fs::read_to_string(path)
+            .and_then(|text| Relation::from_csv_text(&text, schema, cores()))
+            .map_err(|e| invalid(path, &e.to_string()))
+    }
+
+    // Reads CSV with a header line of column names, quoted like `load_csv`
+    // reads it. Every value of a column is read as the column's type in
+    // `schema`, which has to have a column for each in the header, in any
+    // order; without one, the types are inferred from all the rows like
+    // `infer_schema` does. Unquoted values that are empty or like `NULL` are
+    // nulls, which a column has to be nullable in `schema` to have.
+    pub fn from_csv_reader(
+        mut reader: impl BufRead,
+        schema: Option<&Schema>,
+    ) -> io::Result<Relation> {
+        let mut text = String::new();
+        reader.read_to_string(&mut text)?;
+        Relation::from_csv_text(&text, schema, 1)
+    }
+
+    fn from_csv_text(text: &str, schema: Option<&Schema>, threads: usize) -> io::Result<Relation> {
+        let mut records = Records::new(text);
+        let col_names = header(&mut records).map_err(invalid_data)?;
+        let first = records.line + 1;
+        let rows = parse_runs(records.rest(), threads, col_names.len(), |line, fields| {
+            (first + line, fields)
+        })
+        .map_err(|(line, msg)| invalid_data(format!("line {} {}", first + line, msg)))?;
+
+        let inferred;
+        let schema = match schema {
+            Some(schema) => schema,
+            None => {
+                let names: Vec<&str> = col_names.iter().map(|c| c.as_str()).collect();
+                inferred = Relation::infer_schema(&names, rows.iter().map(|(_, row)| row));
+                &inferred
+            }
+        };
+        let columns = col_names
+            .iter()
+            .map(|name| {
+                schema
+                    .columns
+                    .iter()
+                    .find(|c| c.name == *name)
+                    .ok_or_else(|| invalid_data(format!("the schema has no column {:?}", name)))
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+
+        let mut data = Vec::with_capacity(rows.len());
+        for (line, row) in rows {
+            let row = row
+                .iter()
+                .zip(&columns)
+                .map(|(field, col)| {
+                    let v = match col.ty {
+                        ColumnType::Text => field.text.as_str(),
+                        _ => field.text.trim(),
+                    };
+                    if !field.quoted && NULLS.contains(&v) {
+                        return match col.nullable {
+                            true => Ok(Value::Null),
+                            false => Err(invalid_data(format!(
+                                "line {} has a null in {}, which isn't nullable",
+                                line, col.name
+                            ))),
+                        };
+                    }
+                    typed(v, col.ty).ok_or_else(|| {
+                        invalid_data(format!(
+                            "line {} has {:?} in {}, which isn't {:?}",
+                            line, v, col.name, col.ty
+                        ))
+                    })
+                })
+                .collect::<io::Result<Vec<_>>>()?;
+            data.push(row);
+        }
+        Ok(Relation::new_with_data(col_names, data))
+    }
+
+    // Writes a header line of column names and a line per row, that
+    // `load_csv` and `from_csv_reader` read back. Nulls are empty, and
+    // values with commas, quotes, newlines or spaces around them are quoted,
+    // as are strings that would otherwise read back as something else.
+    pub fn to_csv(&self, writer: &mut impl Write) -> io::Result<()> {
+        let names: Vec<_> = self.col_names.iter().map(|c| quote(c, false)).collect();
+        writeln!(writer, "{}", names.join(","))?;
+        for row in &self.data {
+            let values: Vec<_> = row
+                .iter()
+                .map(|v| match v {
+                    Value::Null => String::new(),
+                    Value::Str(s) => quote(s, !matches!(parse(&Field::plain(s)), Value::Str(_))),
+                    v => quote(&v.to_string(), false),
+                })
+                .collect();
+            writeln!(writer, "{}", values.join(","))?;
+        }
+        Ok(())
+    }
+
+    // Reads every `.csv` file in `dir`, in order of name, into one
+    // relation, with `evolution` deciding what happens when their columns
+    // differ.
+    pub fn load_csv_dir(dir: impl AsRef<Path>, evolution: SchemaEvolution) -> io::Result<Relation> {
+        let files = files_in(dir.as_ref(), &["csv"])?
+            .into_iter()
+            .map(|path| Relation::load_csv(&path).map(|rel| (path, rel)))
+            .collect::<io::Result<Vec<_>>>()?;
+        Relation::concat_files(files, evolution)
+    }
+
+    // The rows of `files`, which were read from the paths they're with, in
+    // the order they're in, with `evolution` deciding what happens when
+    // their columns differ.
+    pub fn concat_files(
+        files: Vec<(PathBuf, Relation)>,
+        evolution: SchemaEvolution,
+    ) -> io::Result<Relation> {
+        let mut col_names: Vec<String> = Vec::new();
+        for (i, (path, rel)) in files.iter().enumerate() {
+            if i == 0 {
+                col_names = rel.col_names.clone();
+            } else if evolution == SchemaEvolution::Strict {
+                let mut expected = col_names.clone();
+                let mut found = rel.col_names.clone();
+                expected.sort();
+                found.sort();
+                if expected != found {
+                    return Err(invalid(
+                        path,
+                        &format!("has columns {:?}, not {:?}", rel.col_names, col_names),
+                    ));
+                }
+            } else {
+                for c in rel.col_names.iter() {
+                    if !col_names.contains(c) {
+                        col_names.push(c.clone());
+                    }
+                }
+            }
+        }
+
+        let mut data = Vec::new();
+        for (_, rel) in files {
+            let positions: Vec<Option<usize>> = col_names
+                .iter()
+                .map(|c| rel.col_names.iter().position(|r| r == c))
+                .collect();
+            data.extend(rel.data.into_iter().map(|row| {
+                positions
+                    .iter()
+                    .map(|p| p.map_or(Value::Null, |p| row[p].clone()))
+                    .collect::<Vec<_>>()
+            }));
+        }
+        Ok(Relation::new_with_data(col_names, data))
+    }
+}
+
+// The files in `dir` with one of `extensions`, in order of name.
+pub fn files_in(dir: &Path, extensions: &[&str]) -> io::Result<Vec<PathBuf>> {
+    let mut paths = fs::read_dir(dir)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<io::Result<Vec<_>>>()?;
+    paths.retain(|p| {
+        p.extension()
+            .is_some_and(|e| extensions.iter().any(|x| e == *x))
+    });
+    paths.sort();
+    Ok(paths)
+}
+
+// Files bigger than this are worth parsing on more than one thread.
+pub const PARALLEL_BYTES: usize = 1 << 20;
+
+fn cores() -> usize {
+    thread::available_parallelism().map_or(1, |n| n.get())
+}
+
+// A value as it's written in a CSV file. A quoted one is exactly what was
+// between the quotes, with `""` for each quote in it; the rest have the
+// spaces around them trimmed.
+struct Field {
+    text: String,
+    quoted: bool,
+}
+
+impl Field {
+    fn plain(text: &str) -> Field {
+        Field {
+            text: text.to_string(),
+            quoted: false,
+        }
+    }
+}
+
+impl AsRef<str> for Field {
+    fn as_ref(&self) -> &str {
+        &self.text
+    }
+}
+
+// The records of CSV text, split on the commas and newlines that aren't in
+// quotes, so a quoted value can have either in it. A quote only starts a
+// quoted value at the start of one, so `5"` is just text. Blank lines are
+// skipped. Each record comes with the line it starts on, counted from 0,
+// and a record with an unterminated quote is the last one.
+struct Records<'a> {
+    text: &'a str,
+    pos: usize,
+    line: usize,
+}
+
+impl<'a> Records<'a> {
+    fn new(text: &'a str) -> Self {
+        Self {
+            text,
+            pos: 0,
+            line: 0,
+        }
+    }
+
+    // The text after the records read so far.
+    fn rest(&self) -> &'a str {
+        &self.text[self.pos..]
+    }
+}
+
+impl Iterator for Records<'_> {
+    type Item = Result<(usize, Vec<Field>), (usize, String)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.pos < self.text.len() {
+            let start = self.line;
+            let mut fields = Vec::new();
+            let mut field = Field::plain("");
+            let mut quoting = false;
+            let mut end = self.text.len();
+            let mut chars = self.text[self.pos..].char_indices().peekable();
+            while let Some((i, c)) = chars.next() {
+                match (c, quoting) {
+                    ('"', true) if chars.peek().map(|(_, c)| *c) == Some('"') => {
+                        chars.next();
+                        field.text.push('"');
+                    }
+                    ('"', true) => quoting = false,
+                    ('"', false) if !field.quoted && field.text.trim().is_empty() => {
+                        field = Field {
+                            text: String::new(),
+                            quoted: true,
+                        };
+                        quoting = true;
+                    }
+                    (',', false) => {
+                        fields.push(finish(std::mem::replace(&mut field, Field::plain(""))))
+                    }
+                    ('\n', false) => {
+                        self.line += 1;
+                        end = self.pos + i + 1;
+                        break;
+                    }
+                    ('\n', true) => {
+                        self.line += 1;
+                        field.text.push(c);
+                    }
+                    // Whatever's between a closing quote and the next comma
+                    // is kept unless it's just space, like a `\r`.
+                    (c, false) if field.quoted && c.is_whitespace() => {}
+                    (c, _) => field.text.push(c),
+                }
+            }
+            self.pos = end;
+            if quoting {
+                return Some(Err((start, "has an unterminated quote".to_string())));
+            }
+            fields.push(finish(field));
+            if let [Field {
+                text,
+                quoted: false,
+            }] = fields.as_slice()
+            {
+                if text.is_empty() {
+                    continue;
+                }
+            }
+            return Some(Ok((start, fields)));
+        }
+        None
+    }
+}
+
+fn finish(field: Field) -> Field {
+    match field.quoted {
+        true => field,
+        false => Field::plain(field.text.trim()),
+    }
+}
+
+// The column names in the first record.
+fn header(records: &mut Records) -> Result<Vec<String>, String> {
+    match records.next() {
+        Some(Ok((_, fields))) => Ok(fields.into_iter().map(|f| f.text).collect()),
+        Some(Err((_, msg))) => Err(format!("the header {}", msg)),
+        None => Err("no header".to_string()),
+    }
+}
+
+// Splits `body` into at most `threads` runs of whole records. Finding where
+// they end has to follow the quotes just like `Records` does, but it only
+// looks at bytes, so it's quick next to parsing the runs.
+fn split_runs(body: &str, threads: usize) -> Vec<&str> {
+    let bytes = body.as_bytes();
+    let target = body.len() / threads;
+    let mut runs = Vec::with_capacity(threads);
+    let (mut start, mut quoting, mut blank) = (0, false, true);
+    let mut i = 0;
+    while i < bytes.len() {
+        match (bytes[i], quoting) {
+            (b'"', true) if bytes.get(i + 1) == Some(&b'"') => i += 1,
+            (b'"', true) => quoting = false,
+            (b'"', false) if blank => {
+                quoting = true;
+                blank = false;
+            }
+            (b',', false) => blank = true,
+            (b'\n', false) => {
+                blank = true;
+                if i + 1 - start >= target && runs.len() + 1 < threads {
+                    runs.push(&body[start..i + 1]);
+                    start = i + 1;
+                }
+            }
+            (b, false) if !b.is_ascii_whitespace() => blank = false,
+            _ => {}
+        }
+        i += 1;
+    }
+    runs.push(&body[start..]);
+    runs
+}
+
+// Parses the records of `body`, which each have to have `cols` fields,
+// making each into a row with `row`, which is also given the line it starts
+// on. Bodies of more than `PARALLEL_BYTES` are split into `threads` runs,
+// each parsed on a thread of its own. Errors are with the line they're on,
+// counted from 0.
+fn parse_runs<T: Send>(
+    body: &str,
+    threads: usize,
+    cols: usize,
+    row: impl Fn(usize, Vec<Field>) -> T + Sync,
+) -> Result<Vec<T>, (usize, String)> {
+    let runs = match body.len() > PARALLEL_BYTES {
+        true => split_runs(body, threads.max(1)),
+        false => vec![body],
+    };
+    let mut firsts = Vec::with_capacity(runs.len());
+    let mut lines = 0;
+    for run in runs.iter() {
+        firsts.push(lines);
+        lines += run.bytes().filter(|b| *b == b'\n').count();
+    }
+    let row = &row;
+    let parsed: Vec<Result<Vec<T>, (usize, String)>> = thread::scope(|s| {
+        let handles: Vec<_> = runs
+            .iter()
+            .zip(firsts)
+            .map(|(run, first)| {
+                s.spawn(move || {
+                    Records::new(run)
+                        .map(|record| {
+                            let (line, fields) = record.map_err(|(l, msg)| (first + l, msg))?;
+                            match fields.len() == cols {
+                                true => Ok(row(first + line, fields)),
+                                false => Err((
+                                    first + line,
+                                    format!("has {} values for {} columns", fields.len(), cols),
+                                )),
+                            }
+                        })
+                        .collect()
+                })
+            })
+            .collect();
+        handles.into_iter().map(|h| h.join().unwrap()).collect()
+    });
+    let mut rows = Vec::new();
+    for run in parsed {
+        rows.extend(run?);
+    }
+    Ok(rows)
+}
+
+fn parse(field: &Field) -> Value {
+    let v = field.text.as_str();
+    if field.quoted {
+        Value::Str(v.into())
+    } else if NULLS.contains(&v) {
+        Value::Null
+    } else if let Ok(v) = v.parse::<i64>() {
+        Value::Int(v)
+    } else if let Ok(v) = v.parse::<f64>() {
+        Value::Float(v)
+    } else if let Ok(v) = v.parse::<bool>() {
+        Value::Bool(v)
+    } else {
+        Value::Str(v.into())
+    }
+}
+
+// A value as `ty`, if it is one.
+fn typed(v: &str, ty: ColumnType) -> Option<Value> {
+    match ty {
+        ColumnType::Bool => match v.to_lowercase().as_str() {
+            "true" => Some(Value::Bool(true)),
+            "false" => Some(Value::Bool(false)),
+            _ => None,
+        },
+        ColumnType::Int => v.parse().ok().map(Value::Int),
+        ColumnType::Float => v.parse().ok().map(Value::Float),
+        ColumnType::Text => Some(Value::Str(v.into())),
+    }
+}
+
+// `v` as a CSV value, in quotes if it has to be for `Records` to read it
+// back the same, or if `always`.
+fn quote(v: &str, always: bool) -> String {
+    if always || v.contains([',', '"', '\n', '\r']) || v.trim() != v {
+        format!("\"{}\"", v.replace('"', "\"\""))
+    } else {
+        v.to_string()
+    }
+}
+
+fn invalid_data(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn invalid(path: &Path, msg: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("{}: {}", path.display(), msg),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scratch::Scratch;
+
+    fn scratch(name: &str, contents: &[u8]) -> (Scratch, PathBuf) {
+        let dir = Scratch::new(name);
+        let path = dir.join("data.csv");
+        fs::write(&path, contents).unwrap();
+        (dir, path)
+    }
+
+    fn awkward() -> Relation {
+        Relation::new(["id", "note, quoted", "score"])
+            .row([
+                Value::Int(1),
+                Value::Str("fragile, \"glass\"".into()),
+                Value::Float(1.5),
+            ])
+            .row([Value::Int(2), Value::Str("two\nlines".into()), Value::Null])
+            .row([
+                Value::Int(3),
+                Value::Str("  padded ".into()),
+                Value::Float(2.0),
+            ])
+            .row([Value::Int(4), Value::Str("12".into()), Value::Float(7.0)])
+            .row([
+                Value::Int(5),
+                Value::Str("NULL".into()),
+                Value::Float(-3.25),
+            ])
+            .row([Value::Int(6), Value::Str("".into()), Value::Float(0.5)])
+            .row([Value::Int(7), Value::Null, Value::Float(4.0)])
+    }
+
+    #[test]
+    fn to_csv_reads_back_through_every_loader() {
+        let rel = awkward();
+        let mut csv = Vec::new();
+        rel.to_csv(&mut csv).unwrap();
+        let (_dir, path) = scratch("round_trip", &csv);
+        assert_eq!(Relation::load_csv(&path).unwrap(), rel);
+        assert_eq!(Relation::from_csv_path(&path, None).unwrap(), rel);
+        assert_eq!(Relation::from_csv_reader(&csv[..], None).unwrap(), rel);
+    }
+
+    #[test]
+    fn big_files_split_between_rows_and_not_inside_quotes() {
+        let mut rel = Relation::new(["id", "text"]);
+        for i in 0..60_000 {
+            let text = match i % 3 {
+                0 => format!("line {}\nand, \"more\"\n", i),
+                _ => format!("plain {}", i),
+            };
+            rel.data.push(vec![Value::Int(i), Value::Str(text.into())]);
+        }
+        let mut csv = Vec::new();
+        rel.to_csv(&mut csv).unwrap();
+        assert!(csv.len() > PARALLEL_BYTES);
+        let (_dir, path) = scratch("parallel", &csv);
+        for threads in [1, 3, 8] {
+            assert_eq!(
+                Relation::load_csv_with_threads(&path, threads).unwrap(),
+                rel
+            );
+        }
+        assert_eq!(Relation::from_csv_path(&path, None).unwrap(), rel);
+    }
+
+    #[test]
+    fn errors_give_the_line_counting_quoted_newlines() {
+        let (_dir, path) = scratch("errors", b"a,b\n1,\"x\ny\"\n2\n");
+        let err = Relation::load_csv(&path).unwrap_err().to_string();
+        assert!(
+            err.ends_with("line 4 has 1 values for 2 columns"),
+            "{}",
+            err
+        );
+        fs::write(&path, "a,b\n1,2\n3,\"open\n").unwrap();
+        let err = Relation::from_csv_path(&path, None)
+            .unwrap_err()
+            .to_string();
+        assert!(err.ends_with("line 3 has an unterminated quote"), "{}", err);
+    }
+}
@@ -0,0 +1,76 @@
+use std::collections::HashMap;
+
+use crate::domain::compare;
+use crate::value::Value;
+use crate::Relation;
+
+// How many of a column's most frequent values `describe` lists.
+pub const TOP_K: usize = 3;
+
+impl Relation {
+    // A row for each column, in order, with what kind of values it has, how
+    // many distinct ones, the smallest and biggest, what fraction of rows
+    // have a null in it, and its most frequent values with how often each
+    // is there, as a relation so that it prints like any other.
+    pub fn describe(&self) -> Relation {
+        let rows = self.data.len();
+        let described = self.col_names.iter().enumerate().map(|(i, name)| {
+            let mut counts: HashMap<&Value, usize> = HashMap::new();
+            let mut nulls = 0;
+            for row in self.data.iter() {
+                match &row[i] {
+                    Value::Null => nulls += 1,
+                    v => *counts.entry(v).or_default() += 1,
+                }
+            }
+            let mut top: Vec<(&Value, usize)> = counts.iter().map(|(v, n)| (*v, *n)).collect();
+            top.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+            let top: Vec<String> = top
+                .iter()
+                .take(TOP_K)
+                .map(|(v, n)| format!("{:?} ({})", v, n))
+                .collect();
+            vec![
+                Value::Str(name.as_str().into()),
+                Value::Str(kind(counts.keys().copied()).into()),
+                Value::Int(counts.len() as i64),
+                counts
+                    .keys()
+                    .min_by(|a, b| compare(a, b))
+                    .map_or(Value::Null, |v| (*v).clone()),
+                counts
+                    .keys()
+                    .max_by(|a, b| compare(a, b))
+                    .map_or(Value::Null, |v| (*v).clone()),
+                match rows {
+                    0 => Value::Null,
+                    _ => Value::Float((nulls as f64 / rows as f64 * 1000.0).round() / 1000.0),
+                },
+                Value::Str(top.join(", ").into()),
+            ]
+        });
+        Relation::new_with_data(
+            ["column", "type", "ndv", "min", "max", "nulls", "top"],
+            described.collect::<Vec<_>>(),
+        )
+    }
+}
+
+// The one type all of a column's values are, with ints counted as floats
+// next to floats, or `mixed`.
+fn kind<'a>(values: impl Iterator<Item = &'a Value>) -> &'static str {
+    values
+        .map(|v| match v {
+            Value::Null => "null",
+            Value::Bool(_) => "bool",
+            Value::Int(_) => "int",
+            Value::Float(_) => "float",
+            Value::Str(_) => "text",
+        })
+        .reduce(|a, b| match (a, b) {
+            _ if a == b => a,
+            ("int", "float") | ("float", "int") => "float",
+            _ => "mixed",
+        })
+        .unwrap_or("null")
+}
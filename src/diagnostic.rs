@@ -0,0 +1,276 @@
+use std::io::{self, Write};
+
+#[cfg(feature = "arrow")]
+use crate::arrow::ArrowError;
+use crate::builder::BuildError;
+use crate::domain::DomainError;
+use crate::expr::ExprError;
+use crate::json;
+use crate::options::JoinError;
+use crate::query::QueryError;
+use crate::sort::SortError;
+use crate::sql::SqlError;
+use crate::value::Value;
+
+// An error as something a program can act on: a code that stays the same
+// whatever the message says, the message, and the things it's about, like
+// the offending column or relation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub code: &'static str,
+    pub message: String,
+    pub details: Vec<(&'static str, Detail)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Detail {
+    Str(String),
+    Strs(Vec<String>),
+    Int(usize),
+    Float(f64),
+    Value(Value),
+    Rows(Vec<Vec<Value>>),
+}
+
+pub trait Diagnose {
+    fn diagnostic(&self) -> Diagnostic;
+}
+
+impl Diagnostic {
+    fn new(code: &'static str, error: &impl ToString) -> Self {
+        Diagnostic {
+            code,
+            message: error.to_string(),
+            details: vec![],
+        }
+    }
+
+    fn with(mut self, name: &'static str, detail: Detail) -> Self {
+        self.details.push((name, detail));
+        self
+    }
+
+    // Writes the diagnostic as a JSON object with the code, the message and
+    // each of the details as fields.
+    pub fn to_json(&self, writer: &mut impl Write) -> io::Result<()> {
+        write!(
+            writer,
+            "{{\"code\": {}, \"message\": {}",
+            json::string(self.code),
+            json::string(&self.message)
+        )?;
+        for (name, detail) in self.details.iter() {
+            write!(writer, ", {}: ", json::string(name))?;
+            match detail {
+                Detail::Str(s) => write!(writer, "{}", json::string(s))?,
+                Detail::Strs(strs) => {
+                    let strs: Vec<_> = strs.iter().map(|s| json::string(s)).collect();
+                    write!(writer, "[{}]", strs.join(", "))?
+                }
+                Detail::Int(n) => write!(writer, "{}", n)?,
+                Detail::Float(v) => json::value(writer, &Value::Float(*v))?,
+                Detail::Value(v) => json::value(writer, v)?,
+                Detail::Rows(rows) => {
+                    write!(writer, "[")?;
+                    for (i, row) in rows.iter().enumerate() {
+                        write!(writer, "{}[", if i == 0 { "" } else { ", " })?;
+                        for (j, v) in row.iter().enumerate() {
+                            write!(writer, "{}", if j == 0 { "" } else { ", " })?;
+                            json::value(writer, v)?;
+                        }
+                        write!(writer, "]")?;
+                    }
+                    write!(writer, "]")?;
+                }
+            }
+        }
+        writeln!(writer, "}}")
+    }
+}
+
+fn strs(strs: &[String]) -> Detail {
+    Detail::Strs(strs.to_vec())
+}
+
+impl Diagnose for QueryError {
+    fn diagnostic(&self) -> Diagnostic {
+        let d = |code| Diagnostic::new(code, self);
+        match self {
+            QueryError::UnknownRelation(name) => {
+                d("unknown_relation").with("relation", Detail::Str(name.clone()))
+            }
+            QueryError::Cyclic => d("cyclic_query"),
+            QueryError::OuterJoin => d("outer_join_unsupported"),
+            QueryError::OutputRows { limit, rows } => d("output_rows_limit")
+                .with("limit", Detail::Int(*limit))
+                .with("rows", Detail::Int(*rows)),
+            QueryError::IntermediateRows { limit, rows } => d("intermediate_rows_limit")
+                .with("limit", Detail::Int(*limit))
+                .with("rows", Detail::Int(*rows)),
+            QueryError::Memory { limit, bytes } => d("memory_limit")
+                .with("limit", Detail::Int(*limit))
+                .with("bytes", Detail::Int(*bytes)),
+            QueryError::WallTime { limit, elapsed } => d("wall_time_limit")
+                .with("limit_secs", Detail::Float(limit.as_secs_f64()))
+                .with("elapsed_secs", Detail::Float(elapsed.as_secs_f64())),
+            QueryError::Io(_) => d("io"),
+            QueryError::Panicked(_) => d("query_panicked"),
+            QueryError::Domain(e) => e.diagnostic(),
+            QueryError::NoSharedColumns {
+                left,
+                right,
+                suggestions,
+            } => d("no_shared_columns")
+                .with("left", Detail::Str(left.clone()))
+                .with("right", Detail::Str(right.clone()))
+                .with(
+                    "suggestions",
+                    Detail::Rows(
+                        suggestions
+                            .iter()
+                            .map(|(l, r)| vec![Value::from(l.as_str()), Value::from(r.as_str())])
+                            .collect(),
+                    ),
+                ),
+            QueryError::JoinExplosion {
+                key,
+                rows,
+                inputs,
+                worst,
+            } => d("join_explosion")
+                .with("key", strs(key))
+                .with("rows", Detail::Int(*rows))
+                .with("inputs", Detail::Int(*inputs))
+                // Each of the worst keys' values followed by how many rows
+                // each side has for them.
+                .with(
+                    "worst",
+                    Detail::Rows(
+                        worst
+                            .iter()
+                            .map(|(values, left, right)| {
+                                let mut row = values.clone();
+                                row.push(Value::Int(*left as i64));
+                                row.push(Value::Int(*right as i64));
+                                row
+                            })
+                            .collect(),
+                    ),
+                ),
+        }
+    }
+}
+
+impl Diagnose for SqlError {
+    fn diagnostic(&self) -> Diagnostic {
+        let d = |code| Diagnostic::new(code, self);
+        match self {
+            SqlError::Syntax {
+                at,
+                expected,
+                found,
+            } => d("sql_syntax")
+                .with("at", Detail::Int(*at))
+                .with("expected", Detail::Str(expected.clone()))
+                .with("found", Detail::Str(found.clone())),
+            SqlError::Using {
+                relation,
+                using,
+                common,
+            } => d("sql_using")
+                .with("relation", Detail::Str(relation.clone()))
+                .with("using", strs(using))
+                .with("common", strs(common)),
+            SqlError::InnerAfterOuter(relation) => {
+                d("sql_inner_after_outer").with("relation", Detail::Str(relation.clone()))
+            }
+            SqlError::TooDeep { at } => d("sql_too_deep").with("at", Detail::Int(*at)),
+            SqlError::Expr(e) => e.diagnostic(),
+            SqlError::Query(e) => e.diagnostic(),
+        }
+    }
+}
+
+impl Diagnose for ExprError {
+    fn diagnostic(&self) -> Diagnostic {
+        let d = |code| Diagnostic::new(code, self);
+        match self {
+            ExprError::UnknownColumn { column, columns } => d("unknown_column")
+                .with("column", Detail::Str(column.clone()))
+                .with("columns", strs(columns)),
+            ExprError::Arithmetic(a, b) => d("arithmetic_type")
+                .with("left", Detail::Value(a.clone()))
+                .with("right", Detail::Value(b.clone())),
+        }
+    }
+}
+
+impl Diagnose for SortError {
+    fn diagnostic(&self) -> Diagnostic {
+        let d = |code| Diagnostic::new(code, self);
+        match self {
+            SortError::Key(spec) => d("sort_key").with("key", Detail::Str(spec.clone())),
+            SortError::UnknownColumn { column, columns } => d("unknown_column")
+                .with("column", Detail::Str(column.clone()))
+                .with("columns", strs(columns)),
+        }
+    }
+}
+
+impl Diagnose for DomainError {
+    fn diagnostic(&self) -> Diagnostic {
+        Diagnostic::new("domain", self)
+            .with("column", Detail::Str(self.column.clone()))
+            .with("value", Detail::Value(self.value.clone()))
+            .with("domain", Detail::Str(self.domain.to_string()))
+    }
+}
+
+impl Diagnose for BuildError {
+    fn diagnostic(&self) -> Diagnostic {
+        let d = |code| Diagnostic::new(code, self);
+        match self {
+            BuildError::Length {
+                column,
+                len,
+                expected,
+            } => d("column_length")
+                .with("column", Detail::Str(column.clone()))
+                .with("len", Detail::Int(*len))
+                .with("expected", Detail::Int(*expected)),
+            BuildError::DuplicateColumn(column) => {
+                d("duplicate_column").with("column", Detail::Str(column.clone()))
+            }
+        }
+    }
+}
+
+impl Diagnose for JoinError {
+    fn diagnostic(&self) -> Diagnostic {
+        match self {
+            JoinError::NoCommonColumns { left, right } => {
+                Diagnostic::new("no_common_columns", self)
+                    .with("left", strs(left))
+                    .with("right", strs(right))
+            }
+        }
+    }
+}
+
+#[cfg(feature = "arrow")]
+impl Diagnose for ArrowError {
+    fn diagnostic(&self) -> Diagnostic {
+        let d = |code| Diagnostic::new(code, self);
+        match self {
+            ArrowError::NotAStruct(format) => {
+                d("arrow_not_a_struct").with("format", Detail::Str(format.clone()))
+            }
+            ArrowError::UnsupportedType { column, format } => d("arrow_unsupported_type")
+                .with("column", Detail::Str(column.clone()))
+                .with("format", Detail::Str(format.clone())),
+            ArrowError::OutOfRange { column } => {
+                d("arrow_out_of_range").with("column", Detail::Str(column.clone()))
+            }
+        }
+    }
+}
@@ -0,0 +1,198 @@
+use std::cmp::Ordering;
+use std::fmt;
+
+use crate::value::Value;
+use crate::Relation;
+
+// The values a column is declared to have. Nulls are in every domain.
+// Ints and floats compare as numbers, and other values in the order rows
+// sort in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Domain {
+    // Values between `low` and `high`, inclusive, either of which can be
+    // left open.
+    Range {
+        low: Option<Value>,
+        high: Option<Value>,
+    },
+    OneOf(Vec<Value>),
+}
+
+impl fmt::Display for Domain {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Domain::Range { low, high } => {
+                let bound =
+                    |b: &Option<Value>| b.as_ref().map_or(String::new(), |v| format!("{:?}", v));
+                write!(f, "[{}..{}]", bound(low), bound(high))
+            }
+            Domain::OneOf(values) => write!(f, "one of {:?}", values),
+        }
+    }
+}
+
+// A value of `column` that isn't in its domain.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DomainError {
+    pub column: String,
+    pub value: Value,
+    pub domain: Domain,
+}
+
+impl fmt::Display for DomainError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} has {:?}, which isn't in {}",
+            self.column, self.value, self.domain
+        )
+    }
+}
+
+impl std::error::Error for DomainError {}
+
+impl Domain {
+    pub fn range(low: impl Into<Value>, high: impl Into<Value>) -> Domain {
+        Domain::Range {
+            low: Some(low.into()),
+            high: Some(high.into()),
+        }
+    }
+
+    pub fn one_of(values: impl IntoIterator<Item = impl Into<Value>>) -> Domain {
+        Domain::OneOf(values.into_iter().map(Into::into).collect())
+    }
+
+    pub fn contains(&self, value: &Value) -> bool {
+        match self {
+            _ if *value == Value::Null => true,
+            Domain::Range { low, high } => {
+                low.as_ref().is_none_or(|low| compare(low, value).is_le())
+                    && high
+                        .as_ref()
+                        .is_none_or(|high| compare(value, high).is_le())
+            }
+            Domain::OneOf(values) => values.iter().any(|v| compare(v, value).is_eq()),
+        }
+    }
+
+    // How many distinct values there can be, if there can only be so many
+    // and that many fit in a usize.
+    pub fn size(&self) -> Option<usize> {
+        match self {
+            Domain::OneOf(values) => Some(values.len()),
+            Domain::Range {
+                low: Some(Value::Int(low)),
+                high: Some(Value::Int(high)),
+            } => usize::try_from((*high as i128 - *low as i128 + 1).max(0)).ok(),
+            Domain::Range { .. } => None,
+        }
+    }
+
+    // Whether a value other than null can be in both.
+    pub fn overlaps(&self, other: &Domain) -> bool {
+        match (self, other) {
+            (Domain::OneOf(values), other) | (other, Domain::OneOf(values)) => {
+                values.iter().any(|v| other.contains(v))
+            }
+            (Domain::Range { low: l1, high: h1 }, Domain::Range { low: l2, high: h2 }) => {
+                let below = |low: &Option<Value>, high: &Option<Value>| match (low, high) {
+                    (Some(low), Some(high)) => compare(low, high).is_le(),
+                    _ => true,
+                };
+                below(l1, h2) && below(l2, h1)
+            }
+        }
+    }
+}
+
+// Orders values like rows sort, except that ints and floats compare as
+// numbers.
+pub fn compare(a: &Value, b: &Value) -> Ordering {
+    match (a.as_float(), b.as_float()) {
+        (Some(a), Some(b)) => a.total_cmp(&b),
+        _ => a.cmp(b),
+    }
+}
+
+impl Relation {
+    // Checks that every value of each column in `domains` is in its domain,
+    // failing on the first that isn't. Columns the relation doesn't have are
+    // skipped.
+    pub fn check_domains(&self, domains: &[(String, Domain)]) -> Result<(), DomainError> {
+        for (col, domain) in domains {
+            let Some(i) = self.col_names.iter().position(|c| c == col) else {
+                continue;
+            };
+            if let Some(row) = self.data.iter().find(|row| !domain.contains(&row[i])) {
+                return Err(DomainError {
+                    column: col.clone(),
+                    value: row[i].clone(),
+                    domain: domain.clone(),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn domains_hold_nulls_and_compare_ints_with_floats() {
+        let pct = Domain::range(0, 100);
+        assert!(pct.contains(&Value::Null));
+        assert!(pct.contains(&Value::Float(99.5)));
+        assert!(!pct.contains(&Value::Int(101)));
+        let open = Domain::Range {
+            low: None,
+            high: Some(Value::Int(0)),
+        };
+        assert!(open.contains(&Value::Int(i64::MIN)));
+        assert!(!open.contains(&Value::Float(0.5)));
+        let sizes = Domain::one_of(["s", "m", "l"]);
+        assert!(sizes.contains(&Value::from("m")));
+        assert!(!sizes.contains(&Value::from("xl")));
+        assert!(Domain::one_of([1.0]).contains(&Value::Int(1)));
+    }
+
+    #[test]
+    fn sizes_and_overlaps() {
+        assert_eq!(Domain::range(1, 10).size(), Some(10));
+        assert_eq!(Domain::range(10, 1).size(), Some(0));
+        assert_eq!(Domain::range(i64::MIN, i64::MAX).size(), None);
+        assert_eq!(Domain::range(0.0, 1.0).size(), None);
+        assert_eq!(Domain::one_of([1, 2]).size(), Some(2));
+
+        assert!(Domain::range(0, 10).overlaps(&Domain::range(10, 20)));
+        assert!(!Domain::range(0, 10).overlaps(&Domain::range(11, 20)));
+        assert!(Domain::one_of([15]).overlaps(&Domain::range(10, 20)));
+        assert!(!Domain::range(10, 20).overlaps(&Domain::one_of([5, 25])));
+        let above = Domain::Range {
+            low: Some(Value::Int(5)),
+            high: None,
+        };
+        assert!(above.overlaps(&Domain::range(0, 5)));
+        assert!(!above.overlaps(&Domain::range(0, 4)));
+    }
+
+    #[test]
+    fn checking_stops_at_the_first_value_out_of_its_domain() {
+        let rel = Relation::new(["size", "qty"]).rows([["m", "1"], ["xl", "2"], ["xxl", "3"]]);
+        let domains = [
+            ("missing".to_string(), Domain::range(0, 0)),
+            ("size".to_string(), Domain::one_of(["s", "m", "l"])),
+        ];
+        assert_eq!(
+            rel.check_domains(&domains),
+            Err(DomainError {
+                column: "size".to_string(),
+                value: Value::from("xl"),
+                domain: domains[1].1.clone(),
+            })
+        );
+        assert_eq!(rel.check_domains(&domains[..1]), Ok(()));
+    }
+}
@@ -0,0 +1,70 @@
+use crate::kernel;
+use crate::value::Value;
+use crate::{Planner, Relation};
+
+impl Relation {
+    // Joins the rows of `self` and `other` whose values are equal in each
+    // pair of columns in `on`, the first of each pair a column of `self`
+    // and the second one of `other`, instead of in the columns they share.
+    // The output has every column of `self` and then those of `other` that
+    // `self` doesn't have. Any column they both have must be one of
+    // `other`'s keys, since that one equals its pair anyway. Like `join`,
+    // null keys don't match.
+    pub fn join_on(&self, other: &Relation, on: &[(&str, &str)]) -> Relation {
+        let (left, right): (Vec<String>, Vec<String>) = on
+            .iter()
+            .map(|(l, r)| (l.to_string(), r.to_string()))
+            .unzip();
+        for col in self.common_cols(other) {
+            assert!(
+                right.contains(&col),
+                "both sides have column {:?}, which isn't a key of {:?}",
+                col,
+                on
+            );
+        }
+        let extra: Vec<_> = (0..other.col_names.len())
+            .filter(|i| !self.col_names.contains(&other.col_names[*i]))
+            .collect();
+        let pairs = kernel::hash_join(
+            &other.data,
+            &other.positions(&right),
+            &self.data,
+            &self.positions(&left),
+            |v: &Value| !v.is_null(),
+        );
+        Relation::new_with_data(
+            self.col_names
+                .iter()
+                .chain(extra.iter().map(|i| &other.col_names[*i]))
+                .cloned(),
+            pairs.into_iter().map(|(r, l)| {
+                let mut row = self.data[l].clone();
+                row.extend(extra.iter().map(|i| other.data[r][*i].clone()));
+                row
+            }),
+        )
+    }
+}
+
+impl Planner {
+    // Like `join`, with `rel` joined to the relations already added on the
+    // pairs of columns in `on`: a column of one of them, then the column of
+    // `rel` it equals. The planner only knows joins on shared columns, so
+    // `rel`'s key columns are renamed to the columns they're joined with.
+    pub fn join_on(self, rel: Relation, on: &[(&str, &str)]) -> Self {
+        let mut rel = rel;
+        for (existing, col) in on {
+            assert!(
+                self.joined_tables
+                    .iter()
+                    .any(|t| t.col_names.iter().any(|c| c == existing)),
+                "no relation added so far has column {:?}",
+                existing
+            );
+            let i = rel.positions(&[col.to_string()])[0];
+            rel.col_names[i] = existing.to_string();
+        }
+        self.join(rel)
+    }
+}
@@ -0,0 +1,86 @@
+use std::fmt;
+use std::sync::Arc;
+
+use crate::catalog::Catalog;
+use crate::Relation;
+
+// How many rows joining some inputs is expected to produce. Inputs that
+// are relations from `catalog` come with their names, so an estimator can
+// use what the catalog knows about them.
+pub trait CardinalityEstimator: Send + Sync {
+    fn name(&self) -> &str;
+    fn estimate(&self, catalog: &Catalog, inputs: &[(Option<&str>, &Relation)]) -> f64;
+}
+
+// The catalog's own estimate from the distinct values it knows of or has
+// seen, and the sizes of joins it's seen before. See `Catalog::estimate`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Statistics;
+
+impl CardinalityEstimator for Statistics {
+    fn name(&self) -> &str {
+        "statistics"
+    }
+
+    fn estimate(&self, catalog: &Catalog, inputs: &[(Option<&str>, &Relation)]) -> f64 {
+        catalog.estimate(inputs)
+    }
+}
+
+// Joins evenly spaced samples of up to `rows` rows of each input and scales
+// the result up by how much of each input was left out. It knows nothing
+// about the data beforehand, but the samples of more than a few inputs
+// rarely have any rows in common, so it's best between pairs.
+#[derive(Debug, Clone, Copy)]
+pub struct Sampling {
+    pub rows: usize,
+}
+
+impl Default for Sampling {
+    fn default() -> Self {
+        Self { rows: 1000 }
+    }
+}
+
+impl CardinalityEstimator for Sampling {
+    fn name(&self) -> &str {
+        "sampling"
+    }
+
+    fn estimate(&self, _: &Catalog, inputs: &[(Option<&str>, &Relation)]) -> f64 {
+        let mut scale = 1.0;
+        let mut joined: Option<Relation> = None;
+        for (_, rel) in inputs {
+            let step = rel.data.len().div_ceil(self.rows.max(1)).max(1);
+            let sample = Relation::new_with_data(
+                rel.col_names.iter().cloned(),
+                rel.data.iter().step_by(step).cloned().collect::<Vec<_>>(),
+            );
+            if !sample.data.is_empty() {
+                scale *= rel.data.len() as f64 / sample.data.len() as f64;
+            }
+            joined = Some(match joined {
+                Some(joined) => joined.join(&sample),
+                None => sample,
+            });
+        }
+        joined.map_or(0.0, |rel| rel.data.len() as f64 * scale)
+    }
+}
+
+// A shared estimator that queries can hold on to, be cloned with, and
+// compare by which estimator it is.
+#[derive(Clone)]
+pub struct Estimator(pub Arc<dyn CardinalityEstimator>);
+
+impl fmt::Debug for Estimator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Estimator({:?})", self.0.name())
+    }
+}
+
+impl PartialEq for Estimator {
+    fn eq(&self, other: &Estimator) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
@@ -0,0 +1,90 @@
+use std::fmt;
+
+use crate::plan::{Plan, PlanStep};
+use crate::Planner;
+
+// The order a planner would join its relations in, with the key each join
+// uses and how many rows it's estimated to make.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Explain {
+    pub plan: Plan,
+    // The rows in each input, in the same order as the plan's steps.
+    pub input_rows: Vec<usize>,
+    // The estimated rows in the result after each step, which for the first
+    // step is just the first input.
+    pub estimates: Vec<f64>,
+}
+
+impl Planner {
+    // How `plan` would join the relations.
+    pub fn explain(&self) -> Explain {
+        let order = self.cost_based_order();
+        let mut explain = Explain {
+            plan: Plan::default(),
+            input_rows: vec![],
+            estimates: vec![],
+        };
+        for (step, i) in order.iter().enumerate() {
+            let rel = &self.joined_tables[*i];
+            let mut key: Vec<String> = rel
+                .col_names
+                .iter()
+                .filter(|c| {
+                    order[..step]
+                        .iter()
+                        .any(|j| self.joined_tables[*j].col_names.contains(c))
+                })
+                .cloned()
+                .collect();
+            key.sort();
+            explain.plan.steps.push(PlanStep {
+                input: format!("({})", rel.col_names.join(", ")),
+                key,
+            });
+            explain.input_rows.push(rel.data.len());
+            explain.estimates.push(self.estimate(&order[..=step]));
+        }
+        explain
+    }
+}
+
+// Like a plan, with each join's estimate and each input's size:
+//
+//   join on [c] ~3 rows
+//     join on [b] ~3 rows
+//       (a, b) 3 rows
+//       (b, c) 3 rows
+//     (c, d) 3 rows
+impl fmt::Display for Explain {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let steps = &self.plan.steps;
+        for (depth, (step, estimate)) in steps
+            .iter()
+            .zip(self.estimates.iter())
+            .skip(1)
+            .rev()
+            .enumerate()
+        {
+            writeln!(
+                f,
+                "{:3$}join on [{}] ~{:.0} rows",
+                "",
+                step.key.join(", "),
+                estimate,
+                depth * 2
+            )?;
+        }
+        let depth = steps.len().saturating_sub(1);
+        for (i, (step, rows)) in steps.iter().zip(self.input_rows.iter()).enumerate() {
+            writeln!(
+                f,
+                "{:3$}{} {} rows",
+                "",
+                step.input,
+                rows,
+                (depth - i.saturating_sub(1)) * 2
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,421 @@
+use std::{fmt, ops};
+
+use crate::value::Value;
+use crate::Relation;
+
+// Arithmetic over a row's columns. Ints stay ints, with overflow wrapping
+// and dividing by zero giving a null, anything with a float is a float, and
+// anything with a null is a null.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expr {
+    Col(String),
+    Lit(Value),
+    Add(Box<Expr>, Box<Expr>),
+    Sub(Box<Expr>, Box<Expr>),
+    Mul(Box<Expr>, Box<Expr>),
+    Div(Box<Expr>, Box<Expr>),
+    Rem(Box<Expr>, Box<Expr>),
+}
+
+pub fn col(name: &str) -> Expr {
+    Expr::Col(name.to_string())
+}
+
+pub fn lit(value: impl Into<Value>) -> Expr {
+    Expr::Lit(value.into())
+}
+
+macro_rules! binary_op {
+    ($trait:ident, $method:ident, $variant:ident) => {
+        impl ops::$trait for Expr {
+            type Output = Expr;
+
+            fn $method(self, rhs: Expr) -> Expr {
+                Expr::$variant(Box::new(self), Box::new(rhs))
+            }
+        }
+    };
+}
+
+binary_op!(Add, add, Add);
+binary_op!(Sub, sub, Sub);
+binary_op!(Mul, mul, Mul);
+binary_op!(Div, div, Div);
+binary_op!(Rem, rem, Rem);
+
+// Why an expression couldn't be bound to a relation's columns or worked out
+// for a row.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExprError {
+    UnknownColumn {
+        column: String,
+        columns: Vec<String>,
+    },
+    Arithmetic(Value, Value),
+}
+
+impl fmt::Display for ExprError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExprError::UnknownColumn { column, columns } => {
+                write!(f, "no column {:?} in {:?}", column, columns)
+            }
+            ExprError::Arithmetic(a, b) => write!(f, "can't do arithmetic on {} and {}", a, b),
+        }
+    }
+}
+
+impl std::error::Error for ExprError {}
+
+// An expression with its columns looked up in a relation's columns.
+pub struct Bound(Node);
+
+enum Node {
+    Col(usize),
+    Lit(Value),
+    Op(
+        fn(i64, i64) -> Option<i64>,
+        fn(f64, f64) -> f64,
+        Box<Node>,
+        Box<Node>,
+    ),
+}
+
+impl Expr {
+    // Panics if the expression has a column that isn't in `col_names`.
+    pub fn bind(&self, col_names: &[String]) -> Bound {
+        self.try_bind(col_names).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    pub fn try_bind(&self, col_names: &[String]) -> Result<Bound, ExprError> {
+        Ok(Bound(self.node(col_names)?))
+    }
+
+    // Every column the expression reads, in order, with repeats.
+    pub fn columns<'a>(&'a self, out: &mut Vec<&'a str>) {
+        match self {
+            Expr::Col(name) => out.push(name),
+            Expr::Lit(_) => {}
+            Expr::Add(a, b)
+            | Expr::Sub(a, b)
+            | Expr::Mul(a, b)
+            | Expr::Div(a, b)
+            | Expr::Rem(a, b) => {
+                a.columns(out);
+                b.columns(out);
+            }
+        }
+    }
+
+    fn node(&self, col_names: &[String]) -> Result<Node, ExprError> {
+        let op = |f: fn(i64, i64) -> Option<i64>, g: fn(f64, f64) -> f64, a: &Expr, b: &Expr| {
+            Ok(Node::Op(
+                f,
+                g,
+                Box::new(a.node(col_names)?),
+                Box::new(b.node(col_names)?),
+            ))
+        };
+        match self {
+            Expr::Col(name) => match col_names.iter().position(|c| c == name) {
+                Some(i) => Ok(Node::Col(i)),
+                None => Err(ExprError::UnknownColumn {
+                    column: name.clone(),
+                    columns: col_names.to_vec(),
+                }),
+            },
+            Expr::Lit(v) => Ok(Node::Lit(v.clone())),
+            Expr::Add(a, b) => op(|a, b| Some(a.wrapping_add(b)), ops::Add::add, a, b),
+            Expr::Sub(a, b) => op(|a, b| Some(a.wrapping_sub(b)), ops::Sub::sub, a, b),
+            Expr::Mul(a, b) => op(|a, b| Some(a.wrapping_mul(b)), ops::Mul::mul, a, b),
+            Expr::Div(a, b) => op(
+                |a, b| (b != 0).then(|| a.wrapping_div(b)),
+                ops::Div::div,
+                a,
+                b,
+            ),
+            Expr::Rem(a, b) => op(
+                |a, b| (b != 0).then(|| a.wrapping_rem(b)),
+                ops::Rem::rem,
+                a,
+                b,
+            ),
+        }
+    }
+}
+
+impl Bound {
+    // Panics on arithmetic over something that isn't a number.
+    pub fn eval(&self, row: &[Value]) -> Value {
+        self.try_eval(row).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    pub fn try_eval(&self, row: &[Value]) -> Result<Value, ExprError> {
+        self.0.eval(row)
+    }
+}
+
+impl Node {
+    fn eval(&self, row: &[Value]) -> Result<Value, ExprError> {
+        Ok(match self {
+            Node::Col(i) => row[*i].clone(),
+            Node::Lit(v) => v.clone(),
+            Node::Op(f, g, a, b) => match (a.eval(row)?, b.eval(row)?) {
+                (Value::Null, _) | (_, Value::Null) => Value::Null,
+                (Value::Int(a), Value::Int(b)) => f(a, b).map_or(Value::Null, Value::Int),
+                (a, b) => match (a.as_float(), b.as_float()) {
+                    (Some(a), Some(b)) => Value::Float(g(a, b)),
+                    _ => return Err(ExprError::Arithmetic(a, b)),
+                },
+            },
+        })
+    }
+}
+
+// A condition on a row's columns, true, false or, when it compares a null,
+// unknown, which like in SQL is kept apart from false so that negating it
+// doesn't make it true. Only rows it's true for are kept.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Pred {
+    Cmp(Cmp, Expr, Expr),
+    And(Box<Pred>, Box<Pred>),
+    Or(Box<Pred>, Box<Pred>),
+    Not(Box<Pred>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cmp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Expr {
+    pub fn equals(self, rhs: Expr) -> Pred {
+        Pred::Cmp(Cmp::Eq, self, rhs)
+    }
+
+    pub fn not_equals(self, rhs: Expr) -> Pred {
+        Pred::Cmp(Cmp::Ne, self, rhs)
+    }
+
+    pub fn lt(self, rhs: Expr) -> Pred {
+        Pred::Cmp(Cmp::Lt, self, rhs)
+    }
+
+    pub fn le(self, rhs: Expr) -> Pred {
+        Pred::Cmp(Cmp::Le, self, rhs)
+    }
+
+    pub fn gt(self, rhs: Expr) -> Pred {
+        Pred::Cmp(Cmp::Gt, self, rhs)
+    }
+
+    pub fn ge(self, rhs: Expr) -> Pred {
+        Pred::Cmp(Cmp::Ge, self, rhs)
+    }
+}
+
+impl ops::BitAnd for Pred {
+    type Output = Pred;
+
+    fn bitand(self, rhs: Pred) -> Pred {
+        Pred::And(Box::new(self), Box::new(rhs))
+    }
+}
+
+impl ops::BitOr for Pred {
+    type Output = Pred;
+
+    fn bitor(self, rhs: Pred) -> Pred {
+        Pred::Or(Box::new(self), Box::new(rhs))
+    }
+}
+
+impl ops::Not for Pred {
+    type Output = Pred;
+
+    fn not(self) -> Pred {
+        Pred::Not(Box::new(self))
+    }
+}
+
+// A predicate with its columns looked up in a relation's columns.
+pub enum BoundPred {
+    Cmp(Cmp, Bound, Bound),
+    And(Box<BoundPred>, Box<BoundPred>),
+    Or(Box<BoundPred>, Box<BoundPred>),
+    Not(Box<BoundPred>),
+}
+
+impl Pred {
+    // Panics if the predicate has a column that isn't in `col_names`.
+    pub fn bind(&self, col_names: &[String]) -> BoundPred {
+        self.try_bind(col_names).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    pub fn try_bind(&self, col_names: &[String]) -> Result<BoundPred, ExprError> {
+        let bind = |p: &Pred| p.try_bind(col_names).map(Box::new);
+        Ok(match self {
+            Pred::Cmp(cmp, a, b) => {
+                BoundPred::Cmp(*cmp, a.try_bind(col_names)?, b.try_bind(col_names)?)
+            }
+            Pred::And(a, b) => BoundPred::And(bind(a)?, bind(b)?),
+            Pred::Or(a, b) => BoundPred::Or(bind(a)?, bind(b)?),
+            Pred::Not(a) => BoundPred::Not(bind(a)?),
+        })
+    }
+
+    // Every column the predicate reads, in order, with repeats.
+    pub fn columns<'a>(&'a self, out: &mut Vec<&'a str>) {
+        match self {
+            Pred::Cmp(_, a, b) => {
+                a.columns(out);
+                b.columns(out);
+            }
+            Pred::And(a, b) | Pred::Or(a, b) => {
+                a.columns(out);
+                b.columns(out);
+            }
+            Pred::Not(a) => a.columns(out),
+        }
+    }
+}
+
+impl BoundPred {
+    // Whether the predicate is true for `row`, rather than false or unknown.
+    // Panics on arithmetic over something that isn't a number.
+    pub fn holds(&self, row: &[Value]) -> bool {
+        self.try_holds(row).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    pub fn try_holds(&self, row: &[Value]) -> Result<bool, ExprError> {
+        Ok(self.eval(row)? == Some(true))
+    }
+
+    fn eval(&self, row: &[Value]) -> Result<Option<bool>, ExprError> {
+        Ok(match self {
+            BoundPred::Cmp(cmp, a, b) => {
+                let (a, b) = (a.try_eval(row)?, b.try_eval(row)?);
+                if a.is_null() || b.is_null() {
+                    return Ok(None);
+                }
+                // Ints and floats compare by value rather than by type.
+                let order = match (a.as_float(), b.as_float()) {
+                    (Some(x), Some(y)) if !matches!((&a, &b), (Value::Int(_), Value::Int(_))) => {
+                        x.total_cmp(&y)
+                    }
+                    _ => a.cmp(&b),
+                };
+                Some(match cmp {
+                    Cmp::Eq => order.is_eq(),
+                    Cmp::Ne => order.is_ne(),
+                    Cmp::Lt => order.is_lt(),
+                    Cmp::Le => order.is_le(),
+                    Cmp::Gt => order.is_gt(),
+                    Cmp::Ge => order.is_ge(),
+                })
+            }
+            BoundPred::And(a, b) => match (a.eval(row)?, b.eval(row)?) {
+                (Some(false), _) | (_, Some(false)) => Some(false),
+                (Some(true), Some(true)) => Some(true),
+                _ => None,
+            },
+            BoundPred::Or(a, b) => match (a.eval(row)?, b.eval(row)?) {
+                (Some(true), _) | (_, Some(true)) => Some(true),
+                (Some(false), Some(false)) => Some(false),
+                _ => None,
+            },
+            BoundPred::Not(a) => a.eval(row)?.map(|v| !v),
+        })
+    }
+}
+
+impl Relation {
+    // Joins the rows where `lhs` over this relation's row equals `rhs` over
+    // `other`'s, on top of any columns they share. Each side's key is
+    // worked out into a column of its own for the join, which is dropped
+    // from the output.
+    pub fn join_on_expr(&self, other: &Relation, lhs: Expr, rhs: Expr) -> Relation {
+        let mut key = "key".to_string();
+        while self.col_names.contains(&key) || other.col_names.contains(&key) {
+            key.insert(0, '_');
+        }
+        let with_key = |rel: &Relation, expr: Expr| {
+            let expr = expr.bind(&rel.col_names);
+            Relation::new_with_data(
+                rel.col_names.iter().chain([&key]).cloned(),
+                rel.data
+                    .iter()
+                    .map(|row| {
+                        let mut row = row.clone();
+                        row.push(expr.eval(&row));
+                        row
+                    })
+                    .collect::<Vec<_>>(),
+            )
+        };
+        let joined = with_key(self, lhs).join(&with_key(other, rhs));
+
+        let k = joined.col_names.iter().position(|c| *c == key).unwrap();
+        Relation::new_with_data(
+            joined.col_names.iter().filter(|c| **c != key).cloned(),
+            joined
+                .data
+                .into_iter()
+                .map(|mut row| {
+                    row.remove(k);
+                    row
+                })
+                .collect::<Vec<_>>(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn joins_on_expressions_without_keeping_the_key() {
+        let orders = Relation::new(["key", "cents"]).rows([[1, 250], [2, 300], [3, 1000]]);
+        let prices = Relation::new(["dollars", "item"]).rows([[3, 7], [10, 8], [4, 9]]);
+        let joined = orders.join_on_expr(&prices, col("cents") / lit(100), col("dollars"));
+        assert_eq!(joined.col_names, ["key", "cents", "dollars", "item"]);
+        let mut rows = joined.data;
+        rows.sort();
+        assert_eq!(
+            rows,
+            Relation::new(["key", "cents", "dollars", "item"])
+                .rows([[2, 300, 3, 7], [3, 1000, 10, 8]])
+                .data
+        );
+    }
+
+    #[test]
+    fn arithmetic_wraps_nulls_dividing_by_zero_and_rejects_non_numbers() {
+        let cols = ["a", "b", "s"].map(String::from);
+        let row = [Value::Int(i64::MAX), Value::Int(0), Value::from("x")];
+        let eval = |e: Expr| e.try_bind(&cols).unwrap().try_eval(&row);
+        assert_eq!(eval(col("a") + lit(1)), Ok(Value::Int(i64::MIN)));
+        assert_eq!(eval(col("a") * lit(2)), Ok(Value::Int(-2)));
+        assert_eq!(eval(col("a") / col("b")), Ok(Value::Null));
+        assert_eq!(eval(col("a") % col("b")), Ok(Value::Null));
+        assert_eq!(eval(lit(i64::MIN) / lit(-1)), Ok(Value::Int(i64::MIN)));
+        assert_eq!(eval(lit(1.5) / col("b")), Ok(Value::Float(f64::INFINITY)));
+        assert_eq!(eval(col("b") + lit(Value::Null)), Ok(Value::Null));
+        assert_eq!(
+            eval(col("s") + lit(1)),
+            Err(ExprError::Arithmetic(Value::from("x"), Value::Int(1)))
+        );
+        assert!(matches!(
+            col("c").try_bind(&cols),
+            Err(ExprError::UnknownColumn { column, .. }) if column == "c"
+        ));
+        let pred = (col("s") + lit(1)).gt(lit(0)).try_bind(&cols).unwrap();
+        assert!(pred.try_holds(&row).is_err());
+    }
+}
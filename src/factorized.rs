@@ -0,0 +1,249 @@
+use std::collections::HashMap;
+
+#[cfg(feature = "rand")]
+use rand::Rng;
+
+use crate::value::{Nulls, Value};
+use crate::{yannakakis, Planner, Relation};
+
+// The result of an acyclic join kept in factorized form: the inputs are
+// arranged in a join tree, and each relation's rows are grouped by the
+// columns they share with their parent. An output row is one row from each
+// relation, chosen from the group that matches the row chosen for its
+// parent, so a high fan-out join takes space proportional to its inputs
+// rather than its output.
+#[derive(Debug)]
+pub struct Factorized {
+    col_names: Vec<String>,
+    // Parents come before their children.
+    nodes: Vec<Node>,
+}
+
+#[derive(Debug)]
+struct Node {
+    rel: Relation,
+    parent: Option<usize>,
+    // The columns shared with the parent, as positions in the parent's rows.
+    parent_key: Vec<usize>,
+    // This relation's rows grouped by the values of those columns.
+    groups: HashMap<Vec<Value>, Vec<usize>>,
+    // Positions of the columns this relation adds to the output.
+    extra: Vec<usize>,
+}
+
+impl Factorized {
+    // Returns nothing if the join is cyclic.
+    pub fn new(mut rels: Vec<Relation>) -> Option<Self> {
+        let graph = rels
+            .iter()
+            .fold(Planner::default(), |planner, rel| {
+                planner.join(Relation::new(rel.col_names.iter().cloned()))
+            })
+            .query_graph;
+        let tree = graph.join_tree()?;
+        // With no dangling rows left, every group a row looks for exists.
+        yannakakis::reduce(&mut rels, &graph, Nulls::Distinct);
+
+        let mut slot = vec![0; rels.len()];
+        for (i, (vertex, _)) in tree.iter().enumerate() {
+            slot[*vertex] = i;
+        }
+        let mut rels: Vec<_> = rels.into_iter().map(Some).collect();
+        let mut col_names: Vec<String> = Vec::new();
+        let mut nodes: Vec<Node> = Vec::new();
+        for (vertex, parent) in tree {
+            let rel = rels[vertex].take().unwrap();
+            let (parent, key_cols) = match parent {
+                Some(p) => (Some(slot[p]), nodes[slot[p]].rel.common_cols(&rel)),
+                None => (None, vec![]),
+            };
+            let key = rel.positions(&key_cols);
+            let mut groups: HashMap<_, Vec<_>> = HashMap::new();
+            for (i, row) in rel.data.iter().enumerate() {
+                // As in a join, rows with a null key don't match anything.
+                if key.iter().any(|k| row[*k].is_null()) {
+                    continue;
+                }
+                groups
+                    .entry(key.iter().map(|k| row[*k].clone()).collect())
+                    .or_default()
+                    .push(i);
+            }
+            let extra = (0..rel.col_names.len())
+                .filter(|i| !col_names.contains(&rel.col_names[*i]))
+                .collect::<Vec<_>>();
+            col_names.extend(extra.iter().map(|i| rel.col_names[*i].clone()));
+            nodes.push(Node {
+                parent_key: parent.map_or(vec![], |p| nodes[p].rel.positions(&key_cols)),
+                rel,
+                parent,
+                groups,
+                extra,
+            });
+        }
+
+        Some(Self { col_names, nodes })
+    }
+
+    pub fn col_names(&self) -> &[String] {
+        &self.col_names
+    }
+
+    // The number of rows in the result, without listing them.
+    pub fn count(&self) -> usize {
+        let weights = self.weights();
+        self.nodes
+            .iter()
+            .enumerate()
+            .filter(|(_, node)| node.parent.is_none())
+            .map(|(i, _)| weights[i].iter().sum::<usize>())
+            .product()
+    }
+
+    // `n` rows drawn uniformly and independently from the result, without
+    // listing it. Each relation's row is picked in proportion to the number
+    // of output rows it's part of given the rows picked above it.
+    #[cfg(feature = "rand")]
+    pub fn sample(&self, n: usize, rng: &mut impl Rng) -> Relation {
+        let weights = self.weights();
+        let mut data = Vec::new();
+        if self.nodes.is_empty() || weights[0].iter().all(|w| *w == 0) {
+            return Relation::new(self.col_names.iter().cloned());
+        }
+        for _ in 0..n {
+            let mut chosen: Vec<usize> = Vec::with_capacity(self.nodes.len());
+            let mut row = Vec::with_capacity(self.col_names.len());
+            for (i, node) in self.nodes.iter().enumerate() {
+                let rows = match node.parent {
+                    Some(p) => self.group(i, &self.nodes[p].rel.data[chosen[p]]),
+                    None => node.groups.get(&vec![]).map_or(&[][..], |g| g.as_slice()),
+                };
+                let total: usize = rows.iter().map(|r| weights[i][*r]).sum();
+                let mut pick = rng.gen_range(0..total);
+                let mut r = rows[0];
+                for candidate in rows {
+                    if pick < weights[i][*candidate] {
+                        r = *candidate;
+                        break;
+                    }
+                    pick -= weights[i][*candidate];
+                }
+                chosen.push(r);
+                row.extend(node.extra.iter().map(|c| node.rel.data[r][*c].clone()));
+            }
+            data.push(row);
+        }
+        Relation::new_with_data(self.col_names.iter().cloned(), data)
+    }
+
+    // How many ways each row of each relation can be extended by its
+    // descendants, working up from the leaves.
+    fn weights(&self) -> Vec<Vec<usize>> {
+        let mut weights: Vec<Vec<usize>> = self
+            .nodes
+            .iter()
+            .map(|node| vec![1; node.rel.data.len()])
+            .collect();
+        for (i, node) in self.nodes.iter().enumerate().rev() {
+            let Some(p) = node.parent else {
+                continue;
+            };
+            for (r, row) in self.nodes[p].rel.data.iter().enumerate() {
+                let group = self.group(i, row);
+                let ways: usize = group.iter().map(|c| weights[i][*c]).sum();
+                weights[p][r] *= ways;
+            }
+        }
+        weights
+    }
+
+    pub fn iter(&self) -> Iter<'_> {
+        let mut iter = Iter {
+            factorized: self,
+            choices: Vec::new(),
+            done: false,
+        };
+        iter.fill(0);
+        iter
+    }
+
+    pub fn to_relation(&self) -> Relation {
+        Relation::new_with_data(self.col_names.iter().cloned(), self.iter())
+    }
+
+    // The rows of node `i` that match `parent_row`.
+    fn group(&self, i: usize, parent_row: &[Value]) -> &[usize] {
+        let node = &self.nodes[i];
+        let key = node
+            .parent_key
+            .iter()
+            .map(|k| parent_row[*k].clone())
+            .collect::<Vec<_>>();
+        node.groups.get(&key).map_or(&[], |g| g.as_slice())
+    }
+}
+
+// Lists the rows of a factorized result one at a time, like an odometer
+// over the row chosen for each relation.
+pub struct Iter<'a> {
+    factorized: &'a Factorized,
+    // For each node, the rows it can choose from and which one it's on.
+    choices: Vec<(&'a [usize], usize)>,
+    done: bool,
+}
+
+impl<'a> Iter<'a> {
+    // Chooses the first row for every node from `from` on.
+    fn fill(&mut self, from: usize) {
+        self.choices.truncate(from);
+        for i in from..self.factorized.nodes.len() {
+            let node = &self.factorized.nodes[i];
+            let rows = match node.parent {
+                Some(p) => {
+                    let (rows, at) = self.choices[p];
+                    self.factorized
+                        .group(i, &self.factorized.nodes[p].rel.data[rows[at]])
+                }
+                None => node.groups.get(&vec![]).map_or(&[][..], |g| g.as_slice()),
+            };
+            if rows.is_empty() {
+                self.done = true;
+                return;
+            }
+            self.choices.push((rows, 0));
+        }
+    }
+}
+
+impl Iterator for Iter<'_> {
+    type Item = Vec<Value>;
+
+    fn next(&mut self) -> Option<Vec<Value>> {
+        if self.done || self.factorized.nodes.is_empty() {
+            return None;
+        }
+        let mut row = Vec::with_capacity(self.factorized.col_names.len());
+        for (node, (rows, at)) in self.factorized.nodes.iter().zip(self.choices.iter()) {
+            let chosen = &node.rel.data[rows[*at]];
+            row.extend(node.extra.iter().map(|i| chosen[*i].clone()));
+        }
+
+        // Move the last node that has another row to choose on to it, and
+        // start everything after it over.
+        let mut i = self.choices.len();
+        loop {
+            if i == 0 {
+                self.done = true;
+                break;
+            }
+            i -= 1;
+            let (rows, at) = &mut self.choices[i];
+            if *at + 1 < rows.len() {
+                *at += 1;
+                self.fill(i + 1);
+                break;
+            }
+        }
+        Some(row)
+    }
+}
@@ -0,0 +1,78 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::Relation;
+
+// Functional dependencies between columns: each says that rows agreeing on
+// a set of columns also agree on another column.
+#[derive(Default, Debug, Clone)]
+pub struct Dependencies {
+    fds: Vec<(Vec<String>, String)>,
+}
+
+impl Dependencies {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Adds a dependency that's known to hold without checking it.
+    pub fn declare(mut self, lhs: &[&str], rhs: &str) -> Self {
+        self.fds
+            .push((lhs.iter().map(|c| c.to_string()).collect(), rhs.to_string()));
+        self
+    }
+
+    // The dependencies that hold in `rel` with a single column on the left.
+    pub fn discover(rel: &Relation) -> Self {
+        let mut fds = Vec::new();
+        for (a, lhs) in rel.col_names.iter().enumerate() {
+            for (b, rhs) in rel.col_names.iter().enumerate() {
+                let mut seen = HashMap::new();
+                if a != b
+                    && rel
+                        .data
+                        .iter()
+                        .all(|row| *seen.entry(&row[a]).or_insert(&row[b]) == &row[b])
+                {
+                    fds.push((vec![lhs.clone()], rhs.clone()));
+                }
+            }
+        }
+        Self { fds }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&[String], &str)> {
+        self.fds
+            .iter()
+            .map(|(lhs, rhs)| (lhs.as_slice(), rhs.as_str()))
+    }
+
+    // Every column whose value is fixed by the values of `cols`.
+    pub fn closure(&self, cols: &[String]) -> HashSet<String> {
+        let mut closure: HashSet<String> = cols.iter().cloned().collect();
+        loop {
+            let before = closure.len();
+            for (lhs, rhs) in self.fds.iter() {
+                if lhs.iter().all(|c| closure.contains(c)) {
+                    closure.insert(rhs.clone());
+                }
+            }
+            if closure.len() == before {
+                return closure;
+            }
+        }
+    }
+
+    // `cols` without the columns the rest of them determine, so grouping or
+    // deduplicating on what's left gives the same groups. Columns are
+    // dropped from the end first.
+    pub fn minimal(&self, cols: &[String]) -> Vec<String> {
+        let mut kept = cols.to_vec();
+        for i in (0..cols.len()).rev() {
+            let rest: Vec<_> = kept.iter().filter(|c| **c != cols[i]).cloned().collect();
+            if self.closure(&rest).contains(&cols[i]) {
+                kept = rest;
+            }
+        }
+        kept
+    }
+}
@@ -0,0 +1,117 @@
+use crate::expr::{ExprError, Pred};
+use crate::value::Value;
+use crate::Relation;
+
+// A row along with the names of its columns, so a filter can look values
+// up by name.
+#[derive(Debug, Clone, Copy)]
+pub struct RowView<'a> {
+    pub col_names: &'a [String],
+    pub values: &'a [Value],
+}
+
+impl RowView<'_> {
+    pub fn get(&self, col: &str) -> &Value {
+        match self.col_names.iter().position(|c| c == col) {
+            Some(i) => &self.values[i],
+            None => panic!("no column {:?} in {:?}", col, self.col_names),
+        }
+    }
+}
+
+impl Relation {
+    // The rows `pred` returns true for, in the same order and with the same
+    // columns.
+    pub fn filter(&self, pred: impl Fn(RowView) -> bool) -> Relation {
+        Relation::new_with_data(
+            self.col_names.iter().cloned(),
+            self.data
+                .iter()
+                .filter(|row| {
+                    pred(RowView {
+                        col_names: &self.col_names,
+                        values: row,
+                    })
+                })
+                .cloned(),
+        )
+    }
+
+    // The rows `pred` is true for, leaving out those it's false or unknown
+    // for. Panics where `try_filter_expr` would fail.
+    pub fn filter_expr(&self, pred: &Pred) -> Relation {
+        self.try_filter_expr(pred)
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    // Like `filter_expr`, but failing if `pred` has a column this relation
+    // doesn't, or does arithmetic on something that isn't a number.
+    pub fn try_filter_expr(&self, pred: &Pred) -> Result<Relation, ExprError> {
+        let pred = pred.try_bind(&self.col_names)?;
+        let mut data = Vec::new();
+        for row in self.data.iter() {
+            if pred.try_holds(row)? {
+                data.push(row.clone());
+            }
+        }
+        Ok(Relation::new_with_data(
+            self.col_names.iter().cloned(),
+            data,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::expr::{col, lit};
+
+    fn scores() -> Relation {
+        Relation::new_with_data(
+            ["name", "score"].map(String::from),
+            [
+                ("al", Value::Int(3)),
+                ("bo", Value::Null),
+                ("cy", Value::Int(7)),
+            ]
+            .map(|(name, score)| vec![Value::from(name), score]),
+        )
+    }
+
+    #[test]
+    fn closures_look_columns_up_by_name() {
+        let kept = scores().filter(|row| *row.get("name") != Value::from("bo"));
+        assert_eq!(kept.col_names, ["name", "score"]);
+        assert_eq!(kept.data.len(), 2);
+        assert!(scores().filter(|_| false).data.is_empty());
+    }
+
+    #[test]
+    fn predicates_keep_only_rows_they_are_true_for() {
+        let names = |pred| {
+            let kept = scores().filter_expr(&pred);
+            kept.data
+                .into_iter()
+                .map(|row| row[0].clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(col("score").gt(lit(5))), [Value::from("cy")]);
+        assert_eq!(names(!col("score").gt(lit(5))), [Value::from("al")]);
+        assert_eq!(
+            names(col("score").gt(lit(5)) | col("name").equals(lit("bo"))),
+            [Value::from("bo"), Value::from("cy")]
+        );
+    }
+
+    #[test]
+    fn bad_predicates_are_errors() {
+        assert!(matches!(
+            scores().try_filter_expr(&col("age").gt(lit(1))),
+            Err(ExprError::UnknownColumn { column, .. }) if column == "age"
+        ));
+        assert!(matches!(
+            scores().try_filter_expr(&(col("name") * lit(2)).gt(lit(1))),
+            Err(ExprError::Arithmetic(..))
+        ));
+    }
+}
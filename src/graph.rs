@@ -0,0 +1,493 @@
+// The query graph and the join orders planned over it, written against
+// `core` and `alloc` alone like `kernel.rs`, so a `no_std` engine can plan
+// its joins with the same code. Relations are only vertices here: the
+// `Planner` above holds the relations themselves and says how big each set
+// of them is estimated to be.
+use alloc::boxed::Box;
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+
+// The most relations `dp_order` and `bushy_order` look at every set of,
+// past which they switch to greedy algorithms.
+pub const MAX_DP_RELATIONS: usize = 12;
+
+#[derive(Default, Debug)]
+pub struct Graph {
+    edges: Vec<Vec<usize>>,
+    cols: Vec<Vec<String>>,
+}
+
+impl Graph {
+    pub fn edge(&mut self, a: usize, b: usize) {
+        while self.edges.len() <= a.max(b) {
+            self.edges.push(Vec::new());
+        }
+        self.edges[a].push(b);
+        self.edges[b].push(a);
+    }
+
+    pub fn remove_edge(&mut self, a: usize, b: usize) {
+        if let Some(edges) = self.edges.get_mut(a) {
+            edges.retain(|v| *v != b);
+        }
+        if let Some(edges) = self.edges.get_mut(b) {
+            edges.retain(|v| *v != a);
+        }
+    }
+
+    // Moves all of `from`'s edges onto `into`, leaving `from` connected only
+    // to `into`.
+    pub fn merge(&mut self, into: usize, from: usize) {
+        for n in self.neighbours(from) {
+            self.remove_edge(from, n);
+            if n != into && !self.neighbours(into).contains(&n) {
+                self.edge(into, n);
+            }
+        }
+        self.edge(into, from);
+    }
+
+    pub fn neighbours(&self, vertex: usize) -> Vec<usize> {
+        self.edges.get(vertex).cloned().unwrap_or_default()
+    }
+
+    pub fn set_cols(&mut self, vertex: usize, cols: &[String]) {
+        while self.cols.len() <= vertex {
+            self.cols.push(Vec::new());
+        }
+        self.cols[vertex] = cols.to_vec();
+    }
+
+    // The graph in Graphviz's DOT language, with each vertex labelled by its
+    // columns and each edge by the columns its ends share.
+    pub fn to_dot(&self) -> String {
+        self.dot_with_labels(|v| format!("{}: ({})", v, self.vertex_cols(v).join(", ")))
+    }
+
+    pub fn dot_with_labels(&self, label: impl Fn(usize) -> String) -> String {
+        let mut out = String::from("graph query {\n");
+        for v in 0..self.edges.len().max(self.cols.len()) {
+            out.push_str(&format!("  {} [label={:?}];\n", v, label(v)));
+        }
+        for (a, neighbours) in self.edges.iter().enumerate() {
+            for b in neighbours.iter().filter(|b| a < **b) {
+                let shared: Vec<_> = self
+                    .vertex_cols(a)
+                    .iter()
+                    .filter(|c| self.vertex_cols(*b).contains(c))
+                    .map(String::as_str)
+                    .collect();
+                out.push_str(&format!(
+                    "  {} -- {} [label={:?}];\n",
+                    a,
+                    b,
+                    shared.join(", ")
+                ));
+            }
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    fn vertex_cols(&self, vertex: usize) -> &[String] {
+        self.cols.get(vertex).map_or(&[], Vec::as_slice)
+    }
+
+    // Whether the hypergraph with a hyperedge for each vertex's columns is
+    // acyclic, i.e. whether the query has a join tree.
+    pub fn is_acyclic_hypergraph(&self) -> bool {
+        self.join_tree().is_some()
+    }
+
+    // A join tree found by GYO reduction, as each vertex along with its
+    // parent, parents first. Repeatedly drops columns that only one vertex
+    // has left and removes vertices whose remaining columns are all covered
+    // by some other vertex, which becomes their parent. The query is acyclic
+    // if that gets down to a single vertex.
+    pub fn join_tree(&self) -> Option<Vec<(usize, Option<usize>)>> {
+        let mut edges: Vec<Option<BTreeSet<&String>>> = self
+            .cols
+            .iter()
+            .map(|cols| Some(cols.iter().collect()))
+            .collect();
+        let mut ears = Vec::new();
+        loop {
+            let mut counts = BTreeMap::new();
+            for col in edges.iter().flatten().flatten() {
+                *counts.entry(*col).or_insert(0) += 1;
+            }
+            for edge in edges.iter_mut().flatten() {
+                edge.retain(|col| counts[col] > 1);
+            }
+
+            let ear = (0..edges.len()).find_map(|i| {
+                let edge = edges[i].as_ref()?;
+                (0..edges.len())
+                    .find(|j| *j != i && edges[*j].as_ref().is_some_and(|e| edge.is_subset(e)))
+                    .map(|parent| (i, parent))
+            });
+            match ear {
+                Some((i, parent)) => {
+                    edges[i] = None;
+                    ears.push((i, Some(parent)));
+                }
+                None => break,
+            }
+        }
+
+        let mut remaining = (0..edges.len()).filter(|i| edges[*i].is_some());
+        let mut tree = match (remaining.next(), remaining.next()) {
+            (Some(root), None) => vec![(root, None)],
+            (None, None) => vec![],
+            _ => return None,
+        };
+        tree.extend(ears.into_iter().rev());
+        Some(tree)
+    }
+
+    // The left-deep order of the first `n` vertices whose joins cost the
+    // least in total, found by working out the cheapest order for every set
+    // of relations from the cheapest orders of its subsets one smaller.
+    // `cost` is given the relations in the order they'd be joined and says
+    // what the join that adds the last one costs. Orders that only ever add
+    // a relation connected to the ones joined so far win over any that
+    // don't, so a cross product is only used when the query graph isn't
+    // connected. Past `MAX_DP_RELATIONS` there are too many sets, and the
+    // greedy order is used instead; queries that use `Dp` switch to
+    // `goo_order` there, since they can join bushy plans.
+    pub fn dp_order(&self, n: usize, mut cost: impl FnMut(&[usize]) -> f64) -> Vec<usize> {
+        if n > MAX_DP_RELATIONS {
+            return self.greedy_order(n, cost);
+        }
+        // The number of cross products and the cost of the best order of
+        // each set, by the bits of the relations in it.
+        let mut best: Vec<Option<(usize, f64, Vec<usize>)>> = vec![None; 1 << n];
+        best[0] = Some((0, 0.0, vec![]));
+        for set in 1..best.len() {
+            for last in (0..n).filter(|r| set & (1 << r) != 0) {
+                let rest = set & !(1 << last);
+                let Some((crosses, so_far, order)) = &best[rest] else {
+                    continue;
+                };
+                let connected =
+                    rest == 0 || self.neighbours(last).iter().any(|r| rest & (1 << r) != 0);
+                let mut order = order.clone();
+                order.push(last);
+                let candidate = (
+                    crosses + usize::from(!connected),
+                    so_far + cost(&order),
+                    order,
+                );
+                let better = match &best[set] {
+                    Some((c, total, _)) => (candidate.0, candidate.1) < (*c, *total),
+                    None => true,
+                };
+                if better {
+                    best[set] = Some(candidate);
+                }
+            }
+        }
+        best.pop().flatten().map_or(vec![], |(_, _, order)| order)
+    }
+
+    // An order of the first `n` vertices that starts from the relation
+    // `estimate` says is smallest, then keeps adding whichever relation
+    // connected to the ones joined so far gives the smallest estimated
+    // result. `estimate` is given the relations that would have been joined.
+    pub fn greedy_order(&self, n: usize, mut estimate: impl FnMut(&[usize]) -> f64) -> Vec<usize> {
+        let mut plan: Vec<usize> = vec![];
+        let mut remaining: Vec<_> = (0..n).collect();
+        while !remaining.is_empty() {
+            let connected: Vec<_> = remaining
+                .iter()
+                .copied()
+                .filter(|r| plan.iter().any(|p| self.neighbours(*p).contains(r)))
+                .collect();
+            let candidates = if connected.is_empty() {
+                remaining.clone()
+            } else {
+                connected
+            };
+            let (_, next) = candidates
+                .into_iter()
+                .map(|c| {
+                    plan.push(c);
+                    let cost = estimate(&plan);
+                    plan.pop();
+                    (cost, c)
+                })
+                .min_by(|a, b| a.0.total_cmp(&b.0))
+                .unwrap();
+            plan.push(next);
+            remaining.retain(|r| *r != next);
+        }
+        plan
+    }
+
+    // The join tree of the first `n` vertices whose joins make the fewest
+    // rows in total by `estimate`, found with DPccp: every pair of
+    // connected sets of relations that don't overlap but are connected to
+    // each other is tried as the two sides of a join, smaller pairs first,
+    // so the best tree for each side is already known. A query graph in
+    // several pieces has each planned on its own and then crossed, smallest
+    // first. Past `MAX_DP_RELATIONS` there are too many sets to try, and
+    // `goo_order` is used instead.
+    pub fn bushy_order(&self, n: usize, mut estimate: impl FnMut(&[usize]) -> f64) -> JoinTree {
+        if n > MAX_DP_RELATIONS {
+            return self.goo_order(n, estimate);
+        }
+        let mut estimates: BTreeMap<u64, f64> = BTreeMap::new();
+        let mut estimate = |set: u64| {
+            *estimates
+                .entry(set)
+                .or_insert_with(|| estimate(&members(set).collect::<Vec<_>>()))
+        };
+
+        let mut pairs = vec![];
+        for left in self.connected_subgraphs(n) {
+            pairs.extend(self.complements(left).into_iter().map(|r| (left, r)));
+        }
+        pairs.sort_by_key(|(left, right)| (left | right).count_ones());
+
+        // The total estimated rows of the best tree for each set, and the
+        // tree.
+        let mut best: BTreeMap<u64, (f64, JoinTree)> =
+            (0..n).map(|i| (1 << i, (0.0, JoinTree::Leaf(i)))).collect();
+        for (left, right) in pairs {
+            let set = left | right;
+            let (Some(l), Some(r)) = (best.get(&left), best.get(&right)) else {
+                continue;
+            };
+            let cost = l.0 + r.0 + estimate(set);
+            if best.get(&set).is_some_and(|(c, _)| *c <= cost) {
+                continue;
+            }
+            // Build on whichever side is estimated to be smaller.
+            let (probe, build) = if estimate(left) < estimate(right) {
+                (r.1.clone(), l.1.clone())
+            } else {
+                (l.1.clone(), r.1.clone())
+            };
+            best.insert(
+                set,
+                (cost, JoinTree::Join(Box::new(probe), Box::new(build))),
+            );
+        }
+
+        let mut pieces: Vec<(f64, u64)> = vec![];
+        let mut seen = 0;
+        for i in 0..n {
+            if seen & (1 << i) != 0 {
+                continue;
+            }
+            let mut piece = 1 << i;
+            loop {
+                let next = self.neighbourhood(piece, 0);
+                if next == 0 {
+                    break;
+                }
+                piece |= next;
+            }
+            seen |= piece;
+            pieces.push((estimate(piece), piece));
+        }
+        pieces.sort_by(|a, b| a.0.total_cmp(&b.0));
+        pieces
+            .into_iter()
+            .map(|(_, piece)| best.remove(&piece).unwrap().1)
+            .reduce(|tree, next| JoinTree::Join(Box::new(tree), Box::new(next)))
+            .expect("no relations to join")
+    }
+
+    // Greedy operator ordering of the first `n` vertices: starting from
+    // every relation on its own, keeps joining whichever two trees give the
+    // smallest estimated result, of those with a query graph edge between
+    // them, until there's one. Trees with nothing between them are only
+    // crossed once no others are left, smallest first.
+    pub fn goo_order(&self, n: usize, mut estimate: impl FnMut(&[usize]) -> f64) -> JoinTree {
+        let mut estimates: BTreeMap<Vec<usize>, f64> = BTreeMap::new();
+        let mut estimate = |rels: &[usize]| {
+            let mut rels = rels.to_vec();
+            rels.sort();
+            *estimates
+                .entry(rels)
+                .or_insert_with_key(|rels| estimate(rels))
+        };
+        let connected = |a: &[usize], b: &[usize]| {
+            a.iter().any(|a| {
+                let neighbours = self.neighbours(*a);
+                b.iter().any(|b| neighbours.contains(b))
+            })
+        };
+        // The trees so far by an id of their own, with the relations in
+        // them, and whether each pair of them would be a cross product and
+        // what joining them is estimated to give.
+        let mut trees: BTreeMap<usize, (Vec<usize>, JoinTree)> =
+            (0..n).map(|i| (i, (vec![i], JoinTree::Leaf(i)))).collect();
+        let mut pairs: BTreeMap<(usize, usize), (bool, f64)> = BTreeMap::new();
+        for (a, (a_rels, _)) in trees.iter() {
+            for (b, (b_rels, _)) in trees.range(a + 1..) {
+                let cross = !connected(a_rels, b_rels);
+                pairs.insert(
+                    (*a, *b),
+                    (cross, estimate(&[a_rels.as_slice(), b_rels].concat())),
+                );
+            }
+        }
+        let mut next_id = trees.len();
+        while let Some((&(a, b), _)) = pairs
+            .iter()
+            .min_by(|(_, x), (_, y)| x.0.cmp(&y.0).then(x.1.total_cmp(&y.1)))
+        {
+            pairs.retain(|(x, y), _| ![a, b].contains(x) && ![a, b].contains(y));
+            let (left_rels, left) = trees.remove(&a).unwrap();
+            let (right_rels, right) = trees.remove(&b).unwrap();
+            // Build on whichever side is estimated to be smaller.
+            let (probe, build) = if estimate(&left_rels) < estimate(&right_rels) {
+                (right, left)
+            } else {
+                (left, right)
+            };
+            let rels = [left_rels, right_rels].concat();
+            for (other, (other_rels, _)) in trees.iter() {
+                let cross = !connected(&rels, other_rels);
+                let joined = [rels.as_slice(), other_rels].concat();
+                pairs.insert((*other, next_id), (cross, estimate(&joined)));
+            }
+            trees.insert(
+                next_id,
+                (rels, JoinTree::Join(Box::new(probe), Box::new(build))),
+            );
+            next_id += 1;
+        }
+        trees.pop_first().expect("no relations to join").1 .1
+    }
+
+    // The vertices next to one in `set` that aren't in it or `excluded`.
+    pub fn neighbourhood(&self, set: u64, excluded: u64) -> u64 {
+        let mut next = 0;
+        for v in members(set) {
+            for n in self.neighbours(v) {
+                next |= 1 << n;
+            }
+        }
+        next & !set & !excluded
+    }
+
+    // Every connected set of the first `n` vertices, each once, found by
+    // growing each vertex into its neighbourhood without going back to a
+    // lower vertex, as in DPccp.
+    pub fn connected_subgraphs(&self, n: usize) -> Vec<u64> {
+        assert!(n <= 64, "can't enumerate sets of {} vertices", n);
+        let mut out = vec![];
+        for i in (0..n).rev() {
+            out.push(1 << i);
+            self.grow(1 << i, up_to(i), &mut out);
+        }
+        out
+    }
+
+    // Every connected set that doesn't overlap `set` but is connected to
+    // it, leaving out those with a vertex lower than all of `set`'s, so
+    // that each pair is only found from one side.
+    pub fn complements(&self, set: u64) -> Vec<u64> {
+        let excluded = set | up_to(set.trailing_zeros() as usize);
+        let next = self.neighbourhood(set, excluded);
+        let mut out = vec![];
+        for i in members(next).rev() {
+            out.push(1 << i);
+            self.grow(1 << i, excluded | (next & up_to(i)), &mut out);
+        }
+        out
+    }
+
+    fn grow(&self, set: u64, excluded: u64, out: &mut Vec<u64>) {
+        let next = self.neighbourhood(set, excluded);
+        let grown = subsets(next);
+        out.extend(grown.iter().map(|sub| set | sub));
+        for sub in grown {
+            self.grow(set | sub, excluded | next, out);
+        }
+    }
+}
+
+// Sets of vertices are bits, with vertex `i` as bit `i`.
+fn members(set: u64) -> impl DoubleEndedIterator<Item = usize> {
+    (0..64).filter(move |i| set & (1 << i) != 0)
+}
+
+// Every nonempty subset of `set`.
+fn subsets(set: u64) -> Vec<u64> {
+    let mut subsets = vec![];
+    let mut sub = set;
+    while sub != 0 {
+        subsets.push(sub);
+        sub = (sub - 1) & set;
+    }
+    subsets
+}
+
+// The vertices up to and including `i`.
+fn up_to(i: usize) -> u64 {
+    u64::MAX >> (63 - i)
+}
+
+// An order to join relations in that isn't necessarily left-deep: either
+// side of a join can be the join of others. Relations are indexes into
+// whatever they were planned from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JoinTree {
+    Leaf(usize),
+    // The left side is probed with, and the right side built on.
+    Join(Box<JoinTree>, Box<JoinTree>),
+}
+
+impl JoinTree {
+    // Joins the relations in this order, onto the first of them.
+    pub fn left_deep(order: &[usize]) -> JoinTree {
+        let mut order = order.iter();
+        let first = JoinTree::Leaf(*order.next().expect("no relations to join"));
+        order.fold(first, |tree, i| {
+            JoinTree::Join(Box::new(tree), Box::new(JoinTree::Leaf(*i)))
+        })
+    }
+
+    // The relations joined, left to right.
+    pub fn relations(&self) -> Vec<usize> {
+        match self {
+            JoinTree::Leaf(i) => vec![*i],
+            JoinTree::Join(left, right) => {
+                let mut rels = left.relations();
+                rels.extend(right.relations());
+                rels
+            }
+        }
+    }
+
+    // The leftmost relation, and the right side of each join on the way
+    // back up from it. Joining each of those onto the leftmost in turn is
+    // joining the whole tree.
+    pub fn spine(&self) -> (usize, Vec<&JoinTree>) {
+        match self {
+            JoinTree::Leaf(i) => (*i, vec![]),
+            JoinTree::Join(left, right) => {
+                let (first, mut rights) = left.spine();
+                rights.push(right);
+                (first, rights)
+            }
+        }
+    }
+}
+
+impl fmt::Display for JoinTree {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JoinTree::Leaf(i) => write!(f, "{}", i),
+            JoinTree::Join(left, right) => write!(f, "({} ⋈ {})", left, right),
+        }
+    }
+}
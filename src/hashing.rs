@@ -0,0 +1,90 @@
+use std::collections::hash_map::{DefaultHasher, RandomState};
+use std::hash::{BuildHasher, Hasher};
+
+use crate::kernel;
+
+// How hash tables keyed on join keys hash them. `Fast` is a multiply and
+// rotate hash that's cheap for integer keys but easy to find collisions
+// for, so keys from untrusted sources should use `Random`, which is SipHash
+// with keys chosen randomly for each table.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Hashing {
+    #[default]
+    Fast,
+    Random,
+}
+
+#[derive(Debug, Clone)]
+pub struct KeyHasher {
+    random: Option<RandomState>,
+}
+
+impl KeyHasher {
+    pub fn new(hashing: Hashing) -> Self {
+        Self {
+            random: match hashing {
+                Hashing::Fast => None,
+                Hashing::Random => Some(RandomState::new()),
+            },
+        }
+    }
+}
+
+impl Default for KeyHasher {
+    fn default() -> Self {
+        Self::new(Hashing::Fast)
+    }
+}
+
+impl BuildHasher for KeyHasher {
+    type Hasher = KeyHash;
+
+    fn build_hasher(&self) -> KeyHash {
+        match &self.random {
+            None => KeyHash::Fast(0),
+            Some(state) => KeyHash::Sip(state.build_hasher()),
+        }
+    }
+}
+
+pub enum KeyHash {
+    Fast(u64),
+    Sip(DefaultHasher),
+}
+
+impl Hasher for KeyHash {
+    fn write(&mut self, bytes: &[u8]) {
+        match self {
+            KeyHash::Fast(_) => {
+                for chunk in bytes.chunks(8) {
+                    let mut word = [0; 8];
+                    word[..chunk.len()].copy_from_slice(chunk);
+                    self.write_u64(u64::from_le_bytes(word));
+                }
+            }
+            KeyHash::Sip(h) => h.write(bytes),
+        }
+    }
+
+    fn write_u64(&mut self, word: u64) {
+        match self {
+            KeyHash::Fast(hash) => *hash = kernel::mix(*hash, word),
+            KeyHash::Sip(h) => h.write_u64(word),
+        }
+    }
+
+    fn write_i64(&mut self, word: i64) {
+        self.write_u64(word as u64);
+    }
+
+    fn write_usize(&mut self, word: usize) {
+        self.write_u64(word as u64);
+    }
+
+    fn finish(&self) -> u64 {
+        match self {
+            KeyHash::Fast(hash) => *hash,
+            KeyHash::Sip(h) => h.finish(),
+        }
+    }
+}
@@ -0,0 +1,96 @@
+use std::fmt;
+
+use crate::catalog::Catalog;
+
+// The estimated selectivity of joining each pair of a catalog's relations,
+// which is the fraction of the pairs of their rows that join: the smaller
+// it is, the fewer rows the join keeps. Pairs with no columns in common
+// don't have one, since every pair of their rows would join.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Selectivities {
+    pub names: Vec<String>,
+    // By position in `names`, the same both ways round.
+    pub matrix: Vec<Vec<Option<f64>>>,
+}
+
+// Shades from the least selective joins to the most.
+const SHADES: [char; 5] = [' ', '░', '▒', '▓', '█'];
+
+impl Catalog {
+    // The selectivities of every pair of this catalog's own relations, as
+    // `estimate` would have them, so they're the sizes of joins it's seen
+    // run where it has.
+    pub fn selectivities(&self) -> Selectivities {
+        let names = self.names();
+        let matrix = names
+            .iter()
+            .map(|a| {
+                names
+                    .iter()
+                    .map(|b| {
+                        let (ra, rb) = (self.get(a).unwrap(), self.get(b).unwrap());
+                        if a == b || ra.common_cols(rb).is_empty() {
+                            return None;
+                        }
+                        let pairs = ra.data.len() as f64 * rb.data.len() as f64;
+                        let rows = self.estimate(&[(Some(a), ra), (Some(b), rb)]);
+                        Some(match pairs {
+                            0.0 => 0.0,
+                            _ => (rows / pairs).min(1.0),
+                        })
+                    })
+                    .collect()
+            })
+            .collect();
+        Selectivities {
+            names: names.iter().map(|n| n.to_string()).collect(),
+            matrix,
+        }
+    }
+}
+
+impl Selectivities {
+    // How dark a selectivity's cell is, by its order of magnitude next to
+    // the most selective pair's.
+    fn shade(&self, selectivity: f64) -> char {
+        let magnitude = |s: f64| -s.max(f64::MIN_POSITIVE).log10();
+        let darkest = self
+            .matrix
+            .iter()
+            .flatten()
+            .flatten()
+            .map(|s| magnitude(*s))
+            .fold(0.0, f64::max);
+        if darkest == 0.0 {
+            return SHADES[0];
+        }
+        let step = magnitude(selectivity) / darkest * (SHADES.len() - 1) as f64;
+        SHADES[step.round() as usize]
+    }
+}
+
+// A matrix with a shaded cell for each pair, darker the more selective its
+// join is, and `-` for pairs that don't share a column.
+impl fmt::Display for Selectivities {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let width = self.names.iter().map(|n| n.len()).max().unwrap_or(0);
+        let cell = 10.max(width);
+        write!(f, "{:width$}", "", width = width)?;
+        for name in self.names.iter() {
+            write!(f, " {:>cell$}", name, cell = cell)?;
+        }
+        writeln!(f)?;
+        for (name, row) in self.names.iter().zip(&self.matrix) {
+            write!(f, "{:width$}", name, width = width)?;
+            for selectivity in row {
+                let text = match selectivity {
+                    Some(s) => format!("{} {:.1e}", self.shade(*s), s),
+                    None => "-".to_string(),
+                };
+                write!(f, " {:>cell$}", text, cell = cell)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
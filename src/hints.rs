@@ -0,0 +1,63 @@
+// Planner hints written the way other databases take them, in comments
+// starting with a `+` such as `/*+ leading(r t) hash_join(s) */`. Hints
+// name the relations they're about, so they only apply to named inputs.
+// Like elsewhere, a hint that isn't understood doesn't fail the query;
+// it's kept in `ignored` so it can be reported.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Hints {
+    // The inputs to join first, in this order.
+    pub leading: Vec<String>,
+    // Inputs that are always joined with a hash table, however small.
+    pub hash_join: Vec<String>,
+    pub ignored: Vec<String>,
+}
+
+impl Hints {
+    // The hints in every hint comment in `text`.
+    pub fn parse(text: &str) -> Hints {
+        let mut hints = Hints::default();
+        let mut rest = text;
+        while let Some(start) = rest.find("/*+") {
+            let body = &rest[start + 3..];
+            let end = body.find("*/").unwrap_or(body.len());
+            hints.parse_comment(&body[..end]);
+            rest = &body[end..];
+        }
+        hints
+    }
+
+    fn parse_comment(&mut self, mut body: &str) {
+        loop {
+            body = body.trim_start();
+            if body.is_empty() {
+                return;
+            }
+            let name_end = body
+                .find(|c: char| c == '(' || c.is_whitespace())
+                .unwrap_or(body.len());
+            let name = &body[..name_end];
+            let after = body[name_end..].trim_start();
+            let Some(args) = after.strip_prefix('(') else {
+                self.ignored.push(name.to_string());
+                body = after;
+                continue;
+            };
+            let Some(close) = args.find(')') else {
+                self.ignored.push(body.to_string());
+                return;
+            };
+            let hint = &body[..body.len() - args.len() + close + 1];
+            let args: Vec<String> = args[..close]
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|a| !a.is_empty())
+                .map(|a| a.to_string())
+                .collect();
+            match name.to_lowercase().as_str() {
+                "leading" if !args.is_empty() && self.leading.is_empty() => self.leading = args,
+                "hash_join" | "use_hash" if !args.is_empty() => self.hash_join.extend(args),
+                _ => self.ignored.push(hint.to_string()),
+            }
+            body = &body[hint.len()..];
+        }
+    }
+}
@@ -0,0 +1,106 @@
+use std::fmt::Write;
+
+use crate::plan::Plan;
+use crate::query::{ExecutionStats, Intermediate};
+
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+const STYLE: &str = "body { font-family: sans-serif; }
+details { margin-left: 1.5em; }
+summary { cursor: pointer; }
+.input { margin-left: 3em; }
+.bar { display: inline-block; height: 0.8em; background: #4a90d9; margin-left: 0.5em; }
+.estimate { display: inline-block; height: 0.8em; border: 1px dashed #888; margin-left: 0.5em; }";
+
+impl Plan {
+    // The plan as a page of its own, with each join a collapsible node over
+    // its inputs like in the `Display` tree.
+    pub fn to_html(&self) -> String {
+        self.page(&[])
+    }
+
+    // Like `to_html`, with a bar for how many rows each join produced next
+    // to a dashed one for how many it was expected to, scaled to the
+    // biggest, and both numbers shown on hover. Joins are only measured if
+    // the query was asked to keep its intermediates.
+    pub fn to_html_with_stats(&self, stats: &ExecutionStats) -> String {
+        self.page(stats.intermediates())
+    }
+
+    fn page(&self, intermediates: &[Intermediate]) -> String {
+        let mut html = String::new();
+        writeln!(
+            html,
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">"
+        )
+        .unwrap();
+        writeln!(
+            html,
+            "<title>query plan</title>\n<style>\n{}\n</style>",
+            STYLE
+        )
+        .unwrap();
+        writeln!(html, "</head>\n<body>").unwrap();
+        if self.steps.is_empty() {
+            writeln!(html, "<p>empty plan</p>").unwrap();
+        }
+        // The intermediate for step `i` is the one that joined its first
+        // `i + 1` inputs.
+        let measured = |i: usize| intermediates.iter().find(|m| m.inputs.len() == i + 1);
+        let widest = intermediates
+            .iter()
+            .map(|m| (m.rows as f64).max(m.estimated_rows))
+            .fold(1.0, f64::max);
+        let bar = |m: &Intermediate| {
+            let width = |rows: f64| (rows / widest * 300.0).round() as usize;
+            format!(
+                "<span class=\"bar\" style=\"width: {}px\"></span><span class=\"estimate\" style=\"width: {}px\"></span>",
+                width(m.rows as f64),
+                width(m.estimated_rows)
+            )
+        };
+
+        // The last join is at the top, so the steps open in reverse and
+        // close in order.
+        for (i, step) in self.steps.iter().enumerate().skip(1).rev() {
+            let m = measured(i);
+            let title = match m {
+                Some(m) => format!(
+                    "{} rows, estimated {:.0}, joining {}",
+                    m.rows,
+                    m.estimated_rows,
+                    m.inputs.join(", ")
+                ),
+                None => format!("joining {} inputs", i + 1),
+            };
+            writeln!(
+                html,
+                "<details open>\n<summary title=\"{}\">join on [{}]{}</summary>",
+                escape(&title),
+                escape(&step.key.join(", ")),
+                m.map(bar).unwrap_or_default()
+            )
+            .unwrap();
+        }
+        for (i, step) in self.steps.iter().enumerate() {
+            writeln!(html, "<div class=\"input\">{}</div>", escape(&step.input)).unwrap();
+            if i > 0 {
+                writeln!(html, "</details>").unwrap();
+            }
+        }
+        writeln!(html, "</body>\n</html>").unwrap();
+        html
+    }
+}
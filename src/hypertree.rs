@@ -0,0 +1,175 @@
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+use crate::hashing::Hashing;
+use crate::value::Nulls;
+use crate::Relation;
+
+// A generalized hypertree decomposition of a query. The bags come from a
+// tree decomposition of the graph connecting every pair of columns that
+// appear in the same relation, found by repeatedly eliminating the column
+// with the fewest neighbours. The bags always form an acyclic query, so a
+// cyclic query can be turned into an acyclic one by joining the relations
+// within each bag first.
+//
+// Every relation is joined in whole into one bag that has all its columns.
+// Any of a bag's columns that its own relations don't have come from the
+// distinct projections of other relations, which can only filter rows the
+// full join would have dropped anyway, so the result is the same, duplicates
+// included.
+#[derive(Debug)]
+pub struct Decomposition {
+    pub bags: Vec<Vec<String>>,
+    // The bag each relation is joined into.
+    pub assignment: Vec<usize>,
+    // The relations whose projections fill in the rest of each bag.
+    pub covers: Vec<Vec<usize>>,
+}
+
+impl Decomposition {
+    pub fn new(rels: &[Relation]) -> Self {
+        let mut neighbours: BTreeMap<&String, BTreeSet<&String>> = BTreeMap::new();
+        for rel in rels {
+            for a in rel.col_names.iter() {
+                let adjacent = neighbours.entry(a).or_default();
+                adjacent.extend(rel.col_names.iter().filter(|b| *b != a));
+            }
+        }
+
+        let mut bags: Vec<BTreeSet<&String>> = Vec::new();
+        while let Some(col) = neighbours
+            .iter()
+            .min_by_key(|(_, adjacent)| adjacent.len())
+            .map(|(col, _)| *col)
+        {
+            let adjacent = neighbours.remove(col).unwrap();
+            for a in adjacent.iter() {
+                let others = neighbours.get_mut(a).unwrap();
+                others.remove(col);
+                others.extend(adjacent.iter().filter(|b| *b != a));
+            }
+            let mut bag = adjacent;
+            bag.insert(col);
+            bags.push(bag);
+        }
+        // Dropping bags contained in other bags leaves them acyclic, and
+        // saves computing the same rows twice.
+        let bags: Vec<_> = (0..bags.len())
+            .filter(|i| {
+                !(0..bags.len()).any(|j| {
+                    j != *i && bags[*i].is_subset(&bags[j]) && (bags[*i] != bags[j] || j < *i)
+                })
+            })
+            .map(|i| bags[i].iter().map(|c| c.to_string()).collect::<Vec<_>>())
+            .collect();
+
+        let assignment: Vec<_> = rels
+            .iter()
+            .map(|rel| {
+                bags.iter()
+                    .position(|bag| rel.col_names.iter().all(|c| bag.contains(c)))
+                    .unwrap_or(0)
+            })
+            .collect();
+
+        let covers = bags
+            .iter()
+            .enumerate()
+            .map(|(b, bag)| {
+                let mut uncovered: HashSet<&String> = bag.iter().collect();
+                for (r, rel) in rels.iter().enumerate() {
+                    if assignment[r] == b {
+                        for c in rel.col_names.iter() {
+                            uncovered.remove(c);
+                        }
+                    }
+                }
+                let mut cover = Vec::new();
+                while !uncovered.is_empty() {
+                    let (r, rel) = rels
+                        .iter()
+                        .enumerate()
+                        .max_by_key(|(_, rel)| {
+                            rel.col_names
+                                .iter()
+                                .filter(|c| uncovered.contains(c))
+                                .count()
+                        })
+                        .unwrap();
+                    for c in rel.col_names.iter() {
+                        uncovered.remove(c);
+                    }
+                    cover.push(r);
+                }
+                cover
+            })
+            .collect();
+
+        Self {
+            bags,
+            assignment,
+            covers,
+        }
+    }
+
+    // The most relations any bag needs to join.
+    pub fn width(&self) -> usize {
+        (0..self.bags.len())
+            .map(|b| self.assignment.iter().filter(|a| **a == b).count() + self.covers[b].len())
+            .max()
+            .unwrap_or(0)
+    }
+
+    // Joins the relations in each bag, hashing keys with `hashing` and
+    // matching null keys as `nulls` says, calling `check` with the number of
+    // rows and width of each join's output as it goes.
+    pub fn bags<E>(
+        &self,
+        rels: &[Relation],
+        hashing: Hashing,
+        nulls: Nulls,
+        mut check: impl FnMut(usize, usize) -> Result<(), E>,
+    ) -> Result<Vec<Relation>, E> {
+        let mut result = Vec::new();
+        for (b, bag) in self.bags.iter().enumerate() {
+            let assigned = rels
+                .iter()
+                .enumerate()
+                .filter(|(r, _)| self.assignment[*r] == b)
+                .map(|(_, rel)| rel.clone());
+            let projected = self.covers[b]
+                .iter()
+                .map(|r| distinct_projection(&rels[*r], bag));
+            let mut inputs = assigned.chain(projected);
+            let mut rel = inputs.next().unwrap_or_default();
+            for next in inputs {
+                let width = bag.len();
+                rel = rel.try_join_with(&next, hashing, nulls, |rows| check(rows, width))?;
+            }
+            result.push(rel);
+        }
+        Ok(result)
+    }
+}
+
+// The distinct rows of `rel` restricted to its columns that are in `cols`.
+fn distinct_projection(rel: &Relation, cols: &[String]) -> Relation {
+    let kept: Vec<_> = rel
+        .col_names
+        .iter()
+        .filter(|c| cols.contains(c))
+        .cloned()
+        .collect();
+    let positions = rel.positions(&kept);
+    let mut seen = HashSet::new();
+    let data = rel
+        .data
+        .iter()
+        .map(|row| {
+            positions
+                .iter()
+                .map(|i| row[*i].clone())
+                .collect::<Vec<_>>()
+        })
+        .filter(|row| seen.insert(row.clone()));
+    Relation::new_with_data(kept, data.collect::<Vec<_>>())
+}
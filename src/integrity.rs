@@ -0,0 +1,21 @@
+use crate::value::Nulls;
+use crate::Relation;
+
+// The rows of `child` whose `child_col` doesn't match `parent_col` of any
+// row of `parent`, i.e. the rows that break the foreign key. Like in SQL, a
+// row with a null key doesn't refer to anything, so it doesn't break it.
+pub fn check_foreign_key(
+    child: &Relation,
+    child_col: &str,
+    parent: &Relation,
+    parent_col: &str,
+) -> Relation {
+    // The parent's keys under the child's name for them, so that's the only
+    // column the anti-join compares.
+    let p = parent.positions(&[parent_col.to_string()])[0];
+    let keys = Relation::new([child_col]).rows(parent.data.iter().map(|row| [row[p].clone()]));
+    let c = child.positions(&[child_col.to_string()])[0];
+    let mut orphans = child.without_matches(&keys, Nulls::Distinct);
+    orphans.data.retain(|row| !row[c].is_null());
+    orphans
+}
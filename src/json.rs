@@ -0,0 +1,54 @@
+use std::io::{self, Write};
+
+use crate::value::Value;
+use crate::Relation;
+
+impl Relation {
+    // Writes the rows as a JSON array with an object per row, keyed by
+    // column name in the order of the columns. Floats that JSON can't hold,
+    // like NaN, are nulls.
+    pub fn to_json(&self, writer: &mut impl Write) -> io::Result<()> {
+        write!(writer, "[")?;
+        for (i, row) in self.data.iter().enumerate() {
+            let sep = if i == 0 { "" } else { "," };
+            write!(writer, "{}\n  {{", sep)?;
+            for (j, (col, v)) in self.col_names.iter().zip(row).enumerate() {
+                let sep = if j == 0 { "" } else { ", " };
+                write!(writer, "{}{}: ", sep, string(col))?;
+                value(writer, v)?;
+            }
+            write!(writer, "}}")?;
+        }
+        let end = if self.data.is_empty() { "" } else { "\n" };
+        writeln!(writer, "{}]", end)
+    }
+}
+
+pub fn value(writer: &mut impl Write, v: &Value) -> io::Result<()> {
+    match v {
+        Value::Null => write!(writer, "null"),
+        Value::Bool(v) => write!(writer, "{}", v),
+        Value::Int(v) => write!(writer, "{}", v),
+        Value::Float(v) if v.is_finite() => write!(writer, "{:?}", v),
+        Value::Float(_) => write!(writer, "null"),
+        Value::Str(v) => write!(writer, "{}", string(v)),
+    }
+}
+
+pub fn string(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
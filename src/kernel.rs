@@ -0,0 +1,278 @@
+// The hash table every equijoin goes through, whether it's built for one
+// join or kept as a `HashIndex` and probed by many, written against `core`
+// and `alloc` alone so it can be built into a `no_std` engine as is, along
+// with `value.rs` and the join orders in `graph.rs`. Everything that reads
+// files, spawns threads or keeps time needs `std`, and is in the modules
+// above them.
+use alloc::vec::Vec;
+use core::hash::{BuildHasher, BuildHasherDefault, Hash, Hasher};
+
+// The multiplier from the hash rustc uses internally.
+const SEED: u64 = 0x51_7c_c1_b7_27_22_0a_95;
+
+const EMPTY: usize = usize::MAX;
+
+// One step of the multiply and rotate hash behind `Hashing::Fast`.
+pub fn mix(hash: u64, word: u64) -> u64 {
+    (hash.rotate_left(5) ^ word).wrapping_mul(SEED)
+}
+
+// `no_std` has no default hasher, so the kernel brings its own.
+#[derive(Default)]
+pub struct FastHasher(u64);
+
+impl Hasher for FastHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        for chunk in bytes.chunks(8) {
+            let mut word = [0; 8];
+            word[..chunk.len()].copy_from_slice(chunk);
+            self.write_u64(u64::from_le_bytes(word));
+        }
+    }
+
+    fn write_u64(&mut self, word: u64) {
+        self.0 = mix(self.0, word);
+    }
+
+    fn write_i64(&mut self, word: i64) {
+        self.write_u64(word as u64);
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+// Hints that what `item` points to is about to be read.
+pub fn prefetch<T>(item: *const T) {
+    // SAFETY: SSE is part of the x86_64 baseline, and a prefetch never
+    // faults, whatever the address.
+    #[cfg(target_arch = "x86_64")]
+    unsafe {
+        core::arch::x86_64::_mm_prefetch::<{ core::arch::x86_64::_MM_HINT_T0 }>(item.cast());
+    }
+    #[cfg(not(target_arch = "x86_64"))]
+    let _ = item;
+}
+
+fn key_hash<V: Hash>(hasher: &impl BuildHasher, row: &[V], key: &[usize]) -> u64 {
+    let mut hasher = hasher.build_hasher();
+    for i in key {
+        row[*i].hash(&mut hasher);
+    }
+    hasher.finish()
+}
+
+// A hash table over the rows of a slice, keyed on the columns at `key`. It
+// holds only indexes into the rows, so it's built once and then probed with
+// the same rows passed back in: a bucket array with the rows in each bucket
+// chained through `next`, which is two allocations however many rows there
+// are. Each chain is in the order of the rows.
+pub struct Table<S> {
+    hasher: S,
+    key: Vec<usize>,
+    heads: Vec<usize>,
+    next: Vec<usize>,
+    keys: usize,
+}
+
+impl<S: BuildHasher> Table<S> {
+    // Rows with a key value that isn't `matchable` are left out, so they
+    // match nothing.
+    pub fn build<V: Hash + Eq, R: AsRef<[V]>>(
+        hasher: S,
+        rows: &[R],
+        key: &[usize],
+        matchable: impl Fn(&V) -> bool,
+    ) -> Self {
+        let buckets = rows.len().next_power_of_two().max(1);
+        let mut heads = alloc::vec![EMPTY; buckets];
+        let mut next = alloc::vec![EMPTY; rows.len()];
+        let mut keys = 0;
+        for (i, row) in rows.iter().enumerate().rev() {
+            let row = row.as_ref();
+            if !key.iter().all(|c| matchable(&row[*c])) {
+                continue;
+            }
+            let bucket = key_hash(&hasher, row, key) as usize & (buckets - 1);
+            let mut b = heads[bucket];
+            while b != EMPTY && !key.iter().all(|c| rows[b].as_ref()[*c] == row[*c]) {
+                b = next[b];
+            }
+            if b == EMPTY {
+                keys += 1;
+            }
+            next[i] = heads[bucket];
+            heads[bucket] = i;
+        }
+        Self {
+            hasher,
+            key: key.to_vec(),
+            heads,
+            next,
+            keys,
+        }
+    }
+
+    // How many distinct keys the rows in the table have.
+    pub fn keys(&self) -> usize {
+        self.keys
+    }
+
+    // The rows in the table, in no particular order.
+    pub fn rows(&self) -> impl Iterator<Item = usize> + '_ {
+        self.heads
+            .iter()
+            .flat_map(|head| self.chain_from(Some(*head).filter(|b| *b != EMPTY)))
+    }
+
+    // The bucket that `row`'s values in the columns at `key` hash to. A
+    // batch of probes can hash every key and prefetch its bucket, then read
+    // every bucket's `first` row, before following any chain.
+    pub fn bucket<V: Hash>(&self, row: &[V], key: &[usize]) -> usize {
+        key_hash(&self.hasher, row, key) as usize & (self.heads.len() - 1)
+    }
+
+    pub fn prefetch_bucket(&self, bucket: usize) {
+        prefetch(&self.heads[bucket]);
+    }
+
+    // The first row in `bucket`, which is where a probe of it starts.
+    pub fn first(&self, bucket: usize) -> Option<usize> {
+        Some(self.heads[bucket]).filter(|b| *b != EMPTY)
+    }
+
+    // The rows from `head` on in its chain whose key equals `row`'s values in
+    // the columns at `key`. `rows` has to be what the table was built over.
+    pub fn chain<'a, V: Eq, R: AsRef<[V]>>(
+        &'a self,
+        head: Option<usize>,
+        rows: &'a [R],
+        row: &'a [V],
+        key: &'a [usize],
+    ) -> impl Iterator<Item = usize> + 'a {
+        self.chain_from(head).filter(move |b| {
+            let build = rows[*b].as_ref();
+            self.key.iter().zip(key).all(|(x, y)| build[*x] == row[*y])
+        })
+    }
+
+    fn chain_from(&self, head: Option<usize>) -> impl Iterator<Item = usize> + '_ {
+        core::iter::successors(head, |b| Some(self.next[*b]).filter(|b| *b != EMPTY))
+    }
+
+    // The rows whose key equals `row`'s values in the columns at `key`, in
+    // the order of the rows.
+    pub fn matches<'a, V: Hash + Eq, R: AsRef<[V]>>(
+        &'a self,
+        rows: &'a [R],
+        row: &'a [V],
+        key: &'a [usize],
+    ) -> impl Iterator<Item = usize> + 'a {
+        self.chain(self.first(self.bucket(row, key)), rows, row, key)
+    }
+}
+
+// The pairs of indexes of rows of `build` and `probe` whose values in the
+// columns at `build_key` and `probe_key` are equal, in the order of the
+// probe rows. Rows with a key value that isn't `matchable` match nothing.
+pub fn hash_join<V: Hash + Eq, R: AsRef<[V]>>(
+    build: &[R],
+    build_key: &[usize],
+    probe: &[R],
+    probe_key: &[usize],
+    matchable: impl Fn(&V) -> bool,
+) -> Vec<(usize, usize)> {
+    let table = Table::build(
+        BuildHasherDefault::<FastHasher>::default(),
+        build,
+        build_key,
+        &matchable,
+    );
+    let mut pairs = Vec::new();
+    for (p, row) in probe.iter().enumerate() {
+        let row = row.as_ref();
+        if probe_key.iter().all(|i| matchable(&row[*i])) {
+            pairs.extend(table.matches(build, row, probe_key).map(|b| (b, p)));
+        }
+    }
+    pairs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scratch::Scratch;
+    use std::process::Command;
+
+    #[test]
+    fn tables_chain_rows_in_order_and_count_keys() {
+        let rows = [[1, 10], [2, 20], [1, 11], [0, 0], [2, 21], [1, 12]];
+        let table = Table::build(
+            BuildHasherDefault::<FastHasher>::default(),
+            &rows,
+            &[0],
+            |v: &i32| *v != 0,
+        );
+        assert_eq!(table.keys(), 2);
+        let mut indexed: Vec<_> = table.rows().collect();
+        indexed.sort();
+        assert_eq!(indexed, [0, 1, 2, 4, 5]);
+        assert_eq!(
+            table.matches(&rows, &[9, 1], &[1]).collect::<Vec<_>>(),
+            [0, 2, 5]
+        );
+        assert_eq!(table.matches(&rows, &[2], &[0]).collect::<Vec<_>>(), [1, 4]);
+        assert_eq!(table.matches(&rows, &[0], &[0]).count(), 0);
+        assert_eq!(table.matches(&rows, &[3], &[0]).count(), 0);
+    }
+
+    #[test]
+    fn hash_join_pairs_follow_the_probe_rows() {
+        let build = [[1], [2], [1]];
+        let probe = [[2], [1], [3]];
+        assert_eq!(
+            hash_join(&build, &[0], &probe, &[0], |_: &i32| true),
+            [(1, 0), (0, 1), (2, 1)]
+        );
+    }
+
+    // Compiles this file and `value.rs` into a `#![no_std]` library, so
+    // anything from `std` creeping into either fails here rather than in
+    // whichever engine embeds them.
+    #[test]
+    fn builds_without_std() {
+        let src = concat!(env!("CARGO_MANIFEST_DIR"), "/src");
+        let dir = Scratch::new("no_std");
+        let lib = dir.join("lib.rs");
+        std::fs::write(
+            &lib,
+            format!(
+                "#![no_std]\nextern crate alloc;\n\
+                 #[path = \"{src}/kernel.rs\"]\npub mod kernel;\n\
+                 #[path = \"{src}/value.rs\"]\npub mod value;\n\
+                 #[path = \"{src}/graph.rs\"]\npub mod graph;\n"
+            ),
+        )
+        .unwrap();
+        let output = Command::new(std::env::var("RUSTC").unwrap_or_else(|_| "rustc".into()))
+            .args([
+                "--edition",
+                "2021",
+                "--crate-type",
+                "lib",
+                "--emit",
+                "metadata",
+            ])
+            .arg("--out-dir")
+            .arg(dir.path())
+            .arg(&lib)
+            .output()
+            .unwrap();
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+}
@@ -0,0 +1,76 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::value::Value;
+use crate::Relation;
+
+impl Relation {
+    // The sets of at most `max_cols` columns whose values are different in
+    // every row, smallest first. Sets containing a smaller key are left out,
+    // since they're unique only because it is.
+    pub fn candidate_keys(&self, max_cols: usize) -> Vec<Vec<String>> {
+        let mut keys: Vec<Vec<usize>> = Vec::new();
+        let mut sets: Vec<Vec<usize>> = vec![vec![]];
+        for _ in 0..max_cols.min(self.col_names.len()) {
+            // Extend each set by a column after its last, so each set of
+            // columns comes up once.
+            sets = sets
+                .iter()
+                .flat_map(|set| {
+                    let from = set.last().map_or(0, |c| c + 1);
+                    (from..self.col_names.len()).map(move |c| {
+                        let mut set = set.clone();
+                        set.push(c);
+                        set
+                    })
+                })
+                .filter(|set| !keys.iter().any(|key| key.iter().all(|c| set.contains(c))))
+                .collect();
+            for set in sets.iter() {
+                let mut seen = HashSet::new();
+                if self
+                    .data
+                    .iter()
+                    .all(|row| seen.insert(set.iter().map(|c| &row[*c]).collect::<Vec<_>>()))
+                {
+                    keys.push(set.clone());
+                }
+            }
+        }
+        keys.into_iter()
+            .map(|key| key.iter().map(|c| self.col_names[*c].clone()).collect())
+            .collect()
+    }
+
+    // The values of `key_cols` that more than one row has, with how many
+    // rows have them in a `count` column, most repeated first.
+    pub fn duplicate_keys(&self, key_cols: &[&str]) -> Relation {
+        let key_cols: Vec<_> = key_cols.iter().map(|c| c.to_string()).collect();
+        let mut dups: Vec<_> = self
+            .key_counts(&key_cols)
+            .into_iter()
+            .filter(|(_, count)| *count > 1)
+            .collect();
+        dups.sort_by(|(a, x), (b, y)| y.cmp(x).then_with(|| a.cmp(b)));
+        Relation::new_with_data(
+            key_cols.into_iter().chain(["count".to_string()]),
+            dups.into_iter()
+                .map(|(mut key, count)| {
+                    key.push(Value::Int(count as i64));
+                    key
+                })
+                .collect::<Vec<_>>(),
+        )
+    }
+
+    // How many rows have each value of `key_cols`.
+    pub fn key_counts(&self, key_cols: &[String]) -> HashMap<Vec<Value>, usize> {
+        let positions = self.positions(key_cols);
+        let mut counts = HashMap::new();
+        for row in self.data.iter() {
+            *counts
+                .entry(positions.iter().map(|i| row[*i].clone()).collect())
+                .or_insert(0) += 1;
+        }
+        counts
+    }
+}
@@ -1,35 +1,127 @@
+extern crate alloc;
+
+mod aggregate;
+mod approx;
+#[cfg(feature = "arrow")]
+mod arrow;
+mod audit;
+mod builder;
+mod bushy;
+mod catalog;
+mod chunked;
+mod config;
+mod convert;
+mod cost;
+mod csv;
+mod describe;
+mod diagnostic;
+mod domain;
+mod equijoin;
+mod estimate;
+mod explain;
+mod expr;
+mod factorized;
+mod fd;
+mod filter;
+mod graph;
+mod hashing;
+mod heatmap;
+mod hints;
+mod html;
+mod hypertree;
+mod integrity;
+mod json;
+mod kernel;
+mod keys;
+mod merge;
+mod names;
+#[cfg(feature = "evcxr")]
+mod notebook;
+mod options;
+mod orders;
+mod parallel;
+#[cfg(feature = "parquet")]
+mod parquet;
+mod partition;
+mod persist;
+mod plan;
+mod query;
+mod scheduler;
+mod schema;
+#[cfg(test)]
+mod scratch;
+mod setops;
+mod sketch;
+mod sort;
+mod spill;
+mod sql;
+mod star;
+mod stream;
+mod suggest;
+mod theta;
+mod trace;
+mod value;
+mod whynot;
+mod yannakakis;
+mod zonemap;
+
+use std::collections::hash_map::DefaultHasher;
 use std::collections::{HashMap, HashSet};
+use std::convert::Infallible;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::sync::OnceLock;
 
+use aggregate::{parallel_group_by, parallel_group_by_with, Agg};
+use catalog::Catalog;
+use cost::{CostModel, IoCpu, RowCount};
+use csv::SchemaEvolution;
+use diagnostic::Diagnose;
+use domain::Domain;
+use estimate::{CardinalityEstimator, Sampling, Statistics};
+use expr::{col, lit};
+use fd::Dependencies;
+use graph::{Graph, JoinTree, MAX_DP_RELATIONS};
+use hashing::{Hashing, KeyHasher};
+use hints::Hints;
+use hypertree::Decomposition;
+use integrity::check_foreign_key;
+use names::Normalizer;
+use options::{JoinOptions, NoCommonColumns};
+use parallel::ParallelJoin;
+use plan::PlanHistory;
+#[cfg(feature = "pretty")]
 use prettytable::{Cell, Row, Table};
+use query::{Intermediates, Limits, Outer, Query, Strategy};
+#[cfg(feature = "rand")]
 use rand::Rng;
-
-#[derive(Default, Debug)]
-struct Graph {
-    edges: Vec<Vec<usize>>,
-}
-
-impl Graph {
-    fn edge(&mut self, a: usize, b: usize) {
-        while self.edges.len() <= a.max(b) {
-            self.edges.push(Vec::new());
-        }
-        self.edges[a].push(b);
-        self.edges[b].push(a);
-    }
-
-    fn neighbours(&self, vertex: usize) -> Vec<usize> {
-        self.edges.get(vertex).cloned().unwrap_or_default()
-    }
-}
+use scheduler::Scheduler;
+use schema::{Column, ColumnType, Schema};
+use spill::{ExternalSort, SpillJoin};
+use star::{denormalize, star_join};
+use value::{Nulls, Value};
 
 #[derive(Default, Debug)]
 struct Planner {
     joined_tables: Vec<Relation>,
     query_graph: Graph,
+    normalizer: Option<Normalizer>,
 }
 
 impl Planner {
+    // Renames the columns of every relation joined from now on with
+    // `normalizer`, so that columns whose names differ only in how they're
+    // written are treated as shared.
+    fn normalize_names(mut self, normalizer: Normalizer) -> Self {
+        self.normalizer = Some(normalizer);
+        self
+    }
+
     fn join(mut self, rel: Relation) -> Self {
+        let rel = match &self.normalizer {
+            Some(normalizer) => rel.normalize_names(normalizer),
+            None => rel,
+        };
         for (i, t) in self.joined_tables.iter().enumerate() {
             // If there is a common column between the current relation and the
             // new relation, add an edge between them.
@@ -38,40 +130,252 @@ impl Planner {
                 self.query_graph.edge(self.joined_tables.len(), i);
             }
         }
+        self.query_graph
+            .set_cols(self.joined_tables.len(), &rel.col_names);
         self.joined_tables.push(rel);
         self
     }
 
-    fn plan(mut self) -> Vec<Relation> {
-        let mut plan = vec![];
-        let mut remaining: HashSet<_> = (0..self.joined_tables.len()).collect();
-        // Grab an unjoined relation.
-        while let Some(next) = remaining.iter().next() {
-            // Expand outwards adding relations that are connected to the
-            // current result.
-            let mut frontier = vec![*next];
-            while let Some(relation) = frontier.pop() {
-                remaining.remove(&relation);
-                plan.push(relation);
-                frontier.extend(
-                    self.query_graph
-                        .neighbours(relation)
-                        .into_iter()
-                        .filter(|n| remaining.contains(n)),
-                );
-            }
+    // Lets the `a`th and `b`th relations joined be joined straight onto each
+    // other, as though they shared a column.
+    fn add_edge(mut self, a: usize, b: usize) -> Self {
+        self.check_vertex(a);
+        self.check_vertex(b);
+        if a != b && !self.query_graph.neighbours(a).contains(&b) {
+            self.query_graph.edge(a, b);
         }
+        self
+    }
+
+    // Keeps the `a`th and `b`th relations from being joined straight onto
+    // each other, so one is only joined once something else connects them.
+    fn remove_edge(mut self, a: usize, b: usize) -> Self {
+        self.check_vertex(a);
+        self.check_vertex(b);
+        self.query_graph.remove_edge(a, b);
+        self
+    }
 
-        plan.into_iter()
+    // Treats the `from`th relation as part of the `into`th one: whatever it
+    // was connected to is connected to `into` instead, and it's only
+    // connected to `into`, so it's joined on once `into` has been.
+    fn merge_vertices(mut self, into: usize, from: usize) -> Self {
+        self.check_vertex(into);
+        self.check_vertex(from);
+        assert_ne!(into, from, "can't merge relation {} into itself", into);
+        self.query_graph.merge(into, from);
+        self
+    }
+
+    // The query graph as DOT, with each relation also labelled by its size
+    // and where it comes in the order the planner picked.
+    fn query_graph_dot(&self) -> String {
+        let order = self.cost_based_order();
+        self.query_graph.dot_with_labels(|v| {
+            format!(
+                "{}: ({})\n{} rows, joined {}",
+                v,
+                self.joined_tables[v].col_names.join(", "),
+                self.joined_tables[v].data.len(),
+                order.iter().position(|o| *o == v).unwrap() + 1
+            )
+        })
+    }
+
+    fn check_vertex(&self, i: usize) {
+        assert!(
+            i < self.joined_tables.len(),
+            "no relation {} in a planner with {}",
+            i,
+            self.joined_tables.len()
+        );
+    }
+
+    fn plan(mut self) -> Vec<Relation> {
+        self.cost_based_order()
+            .into_iter()
             .map(|i| std::mem::take(&mut self.joined_tables[i]))
             .collect()
     }
+
+    // The order to join the relations in, as indexes in the order they were
+    // added to the planner.
+    fn order(&self) -> Vec<usize> {
+        self.trace_order(&mut std::io::sink()).unwrap()
+    }
+
+    // The order with the smallest total size of the intermediate results,
+    // estimated from the relations' row counts.
+    fn cost_based_order(&self) -> Vec<usize> {
+        self.dp_order(|rels| self.estimate(rels))
+    }
+
+    // The estimated rows in the join of these relations.
+    fn estimate(&self, rels: &[usize]) -> f64 {
+        let inputs: Vec<_> = rels
+            .iter()
+            .map(|i| (None, &self.joined_tables[*i]))
+            .collect();
+        Catalog::new().estimate(&inputs)
+    }
+
+    // The left-deep order whose joins cost the least in total; see
+    // `Graph::dp_order`.
+    fn dp_order(&self, cost: impl FnMut(&[usize]) -> f64) -> Vec<usize> {
+        self.query_graph.dp_order(self.joined_tables.len(), cost)
+    }
+
+    // The greedy left-deep order; see `Graph::greedy_order`.
+    fn greedy_order(&self, estimate: impl FnMut(&[usize]) -> f64) -> Vec<usize> {
+        self.query_graph
+            .greedy_order(self.joined_tables.len(), estimate)
+    }
+}
+
+// Hashes the values at `key` in `row`, for partitioning rows by join key.
+fn hash_key(row: &[Value], key: &[usize]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    for i in key {
+        row[*i].hash(&mut hasher);
+    }
+    hasher.finish()
+}
+
+// How many output rows `Relation::try_join` produces between checks.
+const CHECK_INTERVAL: usize = 1024;
+
+// How many probe rows `HashIndex::try_join_rows` looks up at once.
+const PROBE_BATCH: usize = 32;
+
+// Joins with at most this many pairs of rows are done with a nested loop.
+const NESTED_LOOP_PAIRS: usize = 256;
+
+// How many rows of the left side a nested loop join compares against each
+// row of the right side at once.
+const NESTED_LOOP_BLOCK: usize = 64;
+
+// A hash table over a relation's rows keyed on some of its columns. Joins
+// against the relation can probe the same index any number of times rather
+// than each building their own.
+struct HashIndex<'a> {
+    rel: &'a Relation,
+    key_cols: Vec<String>,
+    table: kernel::Table<KeyHasher>,
+}
+
+impl<'a> HashIndex<'a> {
+    // The indexed rows whose key equals `row`'s values in the columns at
+    // `key`, in the order of the relation.
+    fn matches<'r>(
+        &'r self,
+        row: &'r [Value],
+        key: &'r [usize],
+    ) -> impl Iterator<Item = &'a Vec<Value>> + 'r {
+        let rel = self.rel;
+        self.table
+            .matches(&rel.data, row, key)
+            .map(move |i| &rel.data[i])
+    }
+
+    // How many distinct keys the indexed rows have.
+    fn keys(&self) -> usize {
+        self.table.keys()
+    }
+
+    // Joins the indexed relation with `probe`, which must share exactly the
+    // index's key columns with it.
+    fn join(&self, probe: &Relation) -> Relation {
+        self.try_join(probe, |_| Ok::<_, Infallible>(()))
+            .unwrap_or_else(|e| match e {})
+    }
+
+    fn try_join<E>(
+        &self,
+        probe: &Relation,
+        check: impl FnMut(usize) -> Result<(), E>,
+    ) -> Result<Relation, E> {
+        self.try_join_rows(probe, probe.data.iter(), check)
+    }
+
+    // Joins with only the given rows of `probe`.
+    fn try_join_rows<'p, E>(
+        &self,
+        probe: &Relation,
+        rows: impl Iterator<Item = &'p Vec<Value>>,
+        mut check: impl FnMut(usize) -> Result<(), E>,
+    ) -> Result<Relation, E> {
+        let common_cols = self.rel.common_cols(probe);
+        assert!(
+            common_cols.len() == self.key_cols.len()
+                && common_cols.iter().all(|c| self.key_cols.contains(c)),
+            "index on {:?} can't be used to join on {:?}",
+            self.key_cols,
+            common_cols,
+        );
+
+        let output_cols = self.rel.col_names.iter().cloned().chain(
+            probe
+                .col_names
+                .iter()
+                .filter(|c| !self.rel.col_names.contains(c))
+                .cloned(),
+        );
+
+        let right_key = probe.positions(&self.key_cols);
+
+        // Probing a batch of rows at a time lets the cache misses of all
+        // their lookups be in flight together rather than waited on in turn.
+        // Every key in the batch is hashed and its bucket prefetched, then
+        // the first row in every bucket is read and prefetched, and only
+        // then are the chains followed and the keys compared.
+        let mut rows = rows.peekable();
+        let mut buckets = Vec::with_capacity(PROBE_BATCH);
+        let mut batch = Vec::with_capacity(PROBE_BATCH);
+        let mut result = Vec::new();
+        while rows.peek().is_some() {
+            buckets.clear();
+            for row in rows.by_ref().take(PROBE_BATCH) {
+                let bucket = self.table.bucket(row, &right_key);
+                self.table.prefetch_bucket(bucket);
+                buckets.push((row, bucket));
+            }
+            batch.clear();
+            for (row, bucket) in buckets.iter() {
+                if let Some(head) = self.table.first(*bucket) {
+                    kernel::prefetch(self.rel.data[head].as_ptr());
+                    batch.push((*row, head));
+                }
+            }
+            for (row, head) in batch.iter() {
+                let matches = self
+                    .table
+                    .chain(Some(*head), &self.rel.data, row, &right_key);
+                for left_row in matches.map(|i| &self.rel.data[i]) {
+                    let mut new_row = left_row.clone();
+                    new_row.extend(
+                        row.iter()
+                            .enumerate()
+                            .filter(|(i, _)| !right_key.contains(i))
+                            .map(|(_, v)| v)
+                            .cloned(),
+                    );
+                    result.push(new_row);
+                    if result.len() % CHECK_INTERVAL == 0 {
+                        check(result.len())?;
+                    }
+                }
+            }
+        }
+        check(result.len())?;
+
+        Ok(Relation::new_with_data(output_cols, result))
+    }
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone, PartialEq)]
 struct Relation {
     col_names: Vec<String>,
-    data: Vec<Vec<i64>>,
+    data: Vec<Vec<Value>>,
 }
 
 impl Relation {
@@ -84,7 +388,7 @@ impl Relation {
 
     fn new_with_data(
         col_names: impl IntoIterator<Item = impl Into<String>>,
-        data: impl IntoIterator<Item = Vec<i64>>,
+        data: impl IntoIterator<Item = Vec<Value>>,
     ) -> Self {
         Self {
             col_names: col_names.into_iter().map(|x| x.into()).collect(),
@@ -92,69 +396,361 @@ impl Relation {
         }
     }
 
-    fn row(mut self, row: impl IntoIterator<Item = i64>) -> Self {
-        self.data.push(row.into_iter().collect());
+    fn row(mut self, row: impl IntoIterator<Item = impl Into<Value>>) -> Self {
+        self.data.push(row.into_iter().map(Into::into).collect());
         self
     }
 
-    fn rows(mut self, rows: impl IntoIterator<Item = impl IntoIterator<Item = i64>>) -> Self {
-        self.data = rows.into_iter().map(|r| r.into_iter().collect()).collect();
+    fn rows(
+        mut self,
+        rows: impl IntoIterator<Item = impl IntoIterator<Item = impl Into<Value>>>,
+    ) -> Self {
+        self.data = rows
+            .into_iter()
+            .map(|r| r.into_iter().map(Into::into).collect())
+            .collect();
         self
     }
 
-    fn join(&self, other: &Relation) -> Relation {
-        let common_cols = self
-            .col_names
+    fn common_cols(&self, other: &Relation) -> Vec<String> {
+        self.col_names
             .iter()
+            .filter(|&col| other.col_names.contains(col))
             .cloned()
-            .filter(|col| other.col_names.contains(col))
-            .collect::<Vec<_>>();
+            .collect()
+    }
 
-        let output_cols = self.col_names.iter().cloned().chain(
-            other
-                .col_names
+    fn positions(&self, cols: &[String]) -> Vec<usize> {
+        cols.iter()
+            .map(|col| self.col_names.iter().position(|c| c == col).unwrap())
+            .collect()
+    }
+
+    // The columns `cols` of every row, in that order.
+    fn project(&self, cols: impl IntoIterator<Item = impl Into<String>>) -> Relation {
+        let cols: Vec<String> = cols.into_iter().map(Into::into).collect();
+        for col in cols.iter() {
+            assert!(
+                self.col_names.contains(col),
+                "no column {:?} in {:?}",
+                col,
+                self.col_names
+            );
+        }
+        let positions = self.positions(&cols);
+        let data: Vec<_> = self
+            .data
+            .iter()
+            .map(|row| positions.iter().map(|i| row[*i].clone()).collect())
+            .collect();
+        Relation::new_with_data(cols, data)
+    }
+
+    fn join(&self, other: &Relation) -> Relation {
+        self.try_join(other, |_| Ok::<_, Infallible>(()))
+            .unwrap_or_else(|e| match e {})
+    }
+
+    // Like `join`, but calls `check` with the number of rows produced so far
+    // every so often while the output is being built, and gives up with its
+    // error if it returns one.
+    fn try_join<E>(
+        &self,
+        other: &Relation,
+        check: impl FnMut(usize) -> Result<(), E>,
+    ) -> Result<Relation, E> {
+        self.try_join_with(other, Hashing::Fast, Nulls::Distinct, check)
+    }
+
+    // Like `join`, but rows whose keys are null in the same columns match.
+    fn join_null_equals_null(&self, other: &Relation) -> Relation {
+        self.try_join_with(other, Hashing::Fast, Nulls::Equal, |_| {
+            Ok::<_, Infallible>(())
+        })
+        .unwrap_or_else(|e| match e {})
+    }
+
+    // Like `try_join`, with the keys of any hash table hashed with `hashing`
+    // and nulls in them matching each other or not as `nulls` says.
+    fn try_join_with<E>(
+        &self,
+        other: &Relation,
+        hashing: Hashing,
+        nulls: Nulls,
+        check: impl FnMut(usize) -> Result<(), E>,
+    ) -> Result<Relation, E> {
+        let common_cols = self.common_cols(other);
+        if common_cols.is_empty() {
+            return self.try_cross_join(other, check);
+        }
+        // For tiny inputs building a hash table costs more than just
+        // comparing every pair.
+        if self.data.len() * other.data.len() <= NESTED_LOOP_PAIRS {
+            let left_key = self.positions(&common_cols);
+            let right_key = other.positions(&common_cols);
+            return self.nested_loop_join(
+                other,
+                |l, r| {
+                    left_key
+                        .iter()
+                        .zip(&right_key)
+                        .all(|(a, b)| l[*a] == r[*b] && (nulls == Nulls::Equal || !l[*a].is_null()))
+                },
+                check,
+            );
+        }
+        self.index_with(&common_cols, hashing, nulls)
+            .try_join(other, check)
+    }
+
+    // Like `join`, but straight through `kernel::hash_join`, with no
+    // `HashIndex` or nested loop around it, so keys are always hashed with
+    // `Hashing::Fast`.
+    fn kernel_join(&self, other: &Relation, nulls: Nulls) -> Relation {
+        let common_cols = self.common_cols(other);
+        let extra: Vec<_> = (0..other.col_names.len())
+            .filter(|i| !self.col_names.contains(&other.col_names[*i]))
+            .collect();
+        let pairs = kernel::hash_join(
+            &other.data,
+            &other.positions(&common_cols),
+            &self.data,
+            &self.positions(&common_cols),
+            |v: &Value| nulls == Nulls::Equal || !v.is_null(),
+        );
+        Relation::new_with_data(
+            self.col_names
                 .iter()
-                .filter(|c| !self.col_names.contains(c))
+                .chain(extra.iter().map(|i| &other.col_names[*i]))
                 .cloned(),
+            pairs.into_iter().map(|(r, l)| {
+                let mut row = self.data[l].clone();
+                row.extend(extra.iter().map(|i| other.data[r][*i].clone()));
+                row
+            }),
+        )
+    }
+
+    // Every row of `self` alongside every row of `other`. A hash table
+    // doesn't help with that, so it's always a nested loop.
+    fn cross_join(&self, other: &Relation) -> Relation {
+        self.try_cross_join(other, |_| Ok::<_, Infallible>(()))
+            .unwrap_or_else(|e| match e {})
+    }
+
+    fn try_cross_join<E>(
+        &self,
+        other: &Relation,
+        check: impl FnMut(usize) -> Result<(), E>,
+    ) -> Result<Relation, E> {
+        assert!(
+            self.common_cols(other).is_empty(),
+            "cross join of relations with columns in common"
         );
+        self.nested_loop_join(other, |_, _| true, check)
+    }
 
-        let left_key = common_cols
-            .iter()
-            .map(|col| self.col_names.iter().position(|c| c == col).unwrap())
-            .collect::<Vec<_>>();
+    // Like `join`, but keeps the rows of `self` that don't match any row of
+    // `other`, with nulls in the columns only `other` has.
+    fn left_join(&self, other: &Relation) -> Relation {
+        self.outer_join(other, Nulls::Distinct, true, false)
+    }
 
-        let right_key = common_cols
-            .iter()
-            .map(|col| other.col_names.iter().position(|c| c == col).unwrap())
-            .collect::<Vec<_>>();
+    // Like `join`, but keeps the rows of `other` that don't match any row of
+    // `self`, with nulls in the columns only `self` has. The columns are in
+    // the same order as `join`'s.
+    fn right_join(&self, other: &Relation) -> Relation {
+        self.outer_join(other, Nulls::Distinct, false, true)
+    }
 
-        let mut table = HashMap::new();
-        for row in self.data.iter() {
-            let key = left_key.iter().map(|i| row[*i]).collect::<Vec<_>>();
-            table.entry(key).or_insert_with(Vec::new).push(row);
-        }
+    // Like `join`, but keeps the rows of either side that don't match.
+    fn full_join(&self, other: &Relation) -> Relation {
+        self.outer_join(other, Nulls::Distinct, true, true)
+    }
 
+    // Builds a hash table of `other`'s rows by position, so that the rows
+    // that never matched can be found afterwards.
+    fn outer_join(
+        &self,
+        other: &Relation,
+        nulls: Nulls,
+        keep_left: bool,
+        keep_right: bool,
+    ) -> Relation {
+        let common_cols = self.common_cols(other);
+        let left_key = self.positions(&common_cols);
+        let right_key = other.positions(&common_cols);
+        let extra: Vec<_> = (0..other.col_names.len())
+            .filter(|i| !right_key.contains(i))
+            .collect();
+        let key_of = |row: &[Value], key: &[usize]| {
+            let null = key.iter().any(|i| row[*i].is_null());
+            (nulls == Nulls::Equal || !null).then(|| key.iter().map(|i| row[*i].clone()).collect())
+        };
+
+        let mut table: HashMap<Vec<Value>, Vec<usize>> = HashMap::new();
+        for (j, row) in other.data.iter().enumerate() {
+            if let Some(key) = key_of(row, &right_key) {
+                table.entry(key).or_default().push(j);
+            }
+        }
+        let mut matched = vec![false; other.data.len()];
         let mut result = Vec::new();
-        for row in other.data.iter() {
-            if let Some(rows) = table.get(&right_key.iter().map(|i| row[*i]).collect::<Vec<_>>()) {
-                for left_row in rows {
-                    let mut new_row = (*left_row).clone();
-                    new_row.extend(
-                        row.iter()
-                            .enumerate()
-                            .filter(|(i, _)| !right_key.contains(i))
-                            .map(|(_, v)| v)
-                            .cloned(),
-                    );
+        for row in self.data.iter() {
+            let matches = key_of(row, &left_key).and_then(|key| table.get(&key));
+            match matches {
+                Some(matches) => {
+                    for j in matches {
+                        matched[*j] = true;
+                        let mut new_row = row.clone();
+                        new_row.extend(extra.iter().map(|i| other.data[*j][*i].clone()));
+                        result.push(new_row);
+                    }
+                }
+                None if keep_left => {
+                    let mut new_row = row.clone();
+                    new_row.resize(row.len() + extra.len(), Value::Null);
                     result.push(new_row);
                 }
+                None => {}
+            }
+        }
+        if keep_right {
+            let from_other: Vec<Option<usize>> = self
+                .col_names
+                .iter()
+                .map(|c| other.col_names.iter().position(|o| o == c))
+                .collect();
+            for (row, _) in other.data.iter().zip(matched).filter(|(_, m)| !m) {
+                let mut new_row: Vec<Value> = from_other
+                    .iter()
+                    .map(|o| o.map_or(Value::Null, |o| row[o].clone()))
+                    .collect();
+                new_row.extend(extra.iter().map(|i| row[*i].clone()));
+                result.push(new_row);
             }
         }
 
+        let output_cols = self
+            .col_names
+            .iter()
+            .chain(extra.iter().map(|i| &other.col_names[*i]))
+            .cloned();
         Relation::new_with_data(output_cols, result)
     }
 
-    fn print(&self) {
+    // Joins every pair of rows `matches` accepts, a block of this relation's
+    // rows at a time. The output has the same columns as `join`, and its rows
+    // are in the same order as a hash join's: by row of `other`, and then by
+    // row of this relation.
+    fn nested_loop_join<E>(
+        &self,
+        other: &Relation,
+        matches: impl Fn(&[Value], &[Value]) -> bool,
+        mut check: impl FnMut(usize) -> Result<(), E>,
+    ) -> Result<Relation, E> {
+        let extra: Vec<_> = (0..other.col_names.len())
+            .filter(|i| !self.col_names.contains(&other.col_names[*i]))
+            .collect();
+        let output_cols = self
+            .col_names
+            .iter()
+            .chain(extra.iter().map(|i| &other.col_names[*i]))
+            .cloned();
+
+        // Each block's matches are kept with the row of `other` they're for
+        // until every block is done.
+        let mut matched: Vec<Vec<Vec<Value>>> = vec![Vec::new(); other.data.len()];
+        let mut rows = 0;
+        for block in self.data.chunks(NESTED_LOOP_BLOCK) {
+            for (right_row, out) in other.data.iter().zip(matched.iter_mut()) {
+                for left_row in block {
+                    if matches(left_row, right_row) {
+                        let mut new_row = left_row.clone();
+                        new_row.extend(extra.iter().map(|i| right_row[*i].clone()));
+                        out.push(new_row);
+                        rows += 1;
+                        if rows % CHECK_INTERVAL == 0 {
+                            check(rows)?;
+                        }
+                    }
+                }
+            }
+        }
+        check(rows)?;
+
+        Ok(Relation::new_with_data(
+            output_cols,
+            matched.into_iter().flatten(),
+        ))
+    }
+
+    // The distinct rows of `self` that join with at least one row of
+    // `other` on their common columns.
+    fn semi_join(&self, other: &Relation) -> Relation {
+        self.reduce_by(other, Nulls::Distinct).distinct()
+    }
+
+    // The distinct rows of `self` that don't join with any row of `other`.
+    fn anti_join(&self, other: &Relation) -> Relation {
+        self.without_matches(other, Nulls::Distinct).distinct()
+    }
+
+    // The rows that aren't the same as one before them.
+    fn distinct(&self) -> Relation {
+        let mut seen = HashSet::new();
+        Relation::new_with_data(
+            self.col_names.iter().cloned(),
+            self.data.iter().filter(|row| seen.insert(*row)).cloned(),
+        )
+    }
+
+    // The rows of `self` that join with at least one row of `other`.
+    fn reduce_by(&self, other: &Relation, nulls: Nulls) -> Relation {
+        self.filter_matches(other, nulls, true)
+    }
+
+    // The rows of `self` that don't join with any row of `other`, which
+    // includes every row with a null key unless nulls are equal.
+    fn without_matches(&self, other: &Relation, nulls: Nulls) -> Relation {
+        self.filter_matches(other, nulls, false)
+    }
+
+    fn filter_matches(&self, other: &Relation, nulls: Nulls, matched: bool) -> Relation {
+        let common_cols = self.common_cols(other);
+        let index = other.index_with(&common_cols, Hashing::Fast, nulls);
+        let key = self.positions(&common_cols);
+        Relation::new_with_data(
+            self.col_names.iter().cloned(),
+            self.data
+                .iter()
+                .filter(|row| index.matches(row, &key).next().is_some() == matched)
+                .cloned(),
+        )
+    }
+
+    fn index(&self, key_cols: &[String]) -> HashIndex<'_> {
+        self.index_with(key_cols, Hashing::Fast, Nulls::Distinct)
+    }
+
+    // Rows with a null key are left out of the table unless nulls are equal,
+    // since nothing could find them.
+    fn index_with(&self, key_cols: &[String], hashing: Hashing, nulls: Nulls) -> HashIndex<'_> {
+        let table = kernel::Table::build(
+            KeyHasher::new(hashing),
+            &self.data,
+            &self.positions(key_cols),
+            |v: &Value| nulls == Nulls::Equal || !v.is_null(),
+        );
+        HashIndex {
+            rel: self,
+            key_cols: key_cols.to_vec(),
+            table,
+        }
+    }
+
+    // The columns in the order they're shown in, which is by name.
+    fn display_order(&self) -> Vec<(String, usize)> {
         let mut sorted_cols = self
             .col_names
             .iter()
@@ -163,6 +759,38 @@ impl Relation {
             .map(|(i, x)| (x, i))
             .collect::<Vec<_>>();
         sorted_cols.sort();
+        sorted_cols
+    }
+
+    // The first `n` rows.
+    fn limit(&self, n: usize) -> Relation {
+        Relation::new_with_data(
+            self.col_names.iter().cloned(),
+            self.data.iter().take(n).cloned(),
+        )
+    }
+
+    // Every row but the first `n`.
+    fn offset(&self, n: usize) -> Relation {
+        Relation::new_with_data(
+            self.col_names.iter().cloned(),
+            self.data.iter().skip(n).cloned(),
+        )
+    }
+
+    // Prints every row, or as many as `--limit` allows.
+    #[cfg(not(feature = "pretty"))]
+    fn print(&self) {
+        let shown = print_limit().unwrap_or(self.data.len());
+        print!("{:.1$}", self, shown);
+    }
+
+    #[cfg(feature = "pretty")]
+    fn print(&self) {
+        let shown = print_limit()
+            .unwrap_or(self.data.len())
+            .min(self.data.len());
+        let sorted_cols = self.display_order();
         let mut table = Table::new();
         table.add_row(Row::new(
             sorted_cols
@@ -170,20 +798,127 @@ impl Relation {
                 .map(|(name, _)| Cell::new(name.as_str()))
                 .collect(),
         ));
-        for row in self.data.iter() {
+        for row in self.data[..shown].iter() {
             table.add_row(Row::new(
                 sorted_cols
                     .iter()
-                    .map(|(_, i)| Cell::new(format!("{}", row[*i]).as_str()))
+                    .map(|(_, i)| Cell::new(&row[*i].to_string()))
                     .collect::<Vec<_>>(),
             ));
         }
 
-        table.printstd()
+        table.printstd();
+        match self.data.len() - shown {
+            0 => {}
+            1 => println!("(1 more row)"),
+            n => println!("({} more rows)", n),
+        }
+    }
+}
+
+// The most rows `print` shows, from `--limit N` on the command line.
+static PRINT_LIMIT: OnceLock<Option<usize>> = OnceLock::new();
+
+fn print_limit() -> Option<usize> {
+    *PRINT_LIMIT.get_or_init(|| {
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            let value = match arg.strip_prefix("--limit") {
+                Some("") => args.next(),
+                Some(rest) => rest.strip_prefix('=').map(String::from),
+                None => continue,
+            };
+            match value.as_deref().map(str::parse::<usize>) {
+                Some(Ok(n)) => return Some(n),
+                Some(Err(e)) => eprintln!("--limit takes a number of rows: {}", e),
+                None => eprintln!("--limit takes a number of rows"),
+            }
+            std::process::exit(2);
+        }
+        None
+    })
+}
+
+// How many rows formatting a relation with `{}` shows before leaving the
+// rest out. `{:.N}` shows N of them instead.
+const DISPLAY_ROWS: usize = 20;
+
+// A compact table, with the columns in the same order as `print`.
+impl fmt::Display for Relation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let cols = self.display_order();
+        let shown = f.precision().unwrap_or(DISPLAY_ROWS).min(self.data.len());
+        let cells: Vec<Vec<String>> = self.data[..shown]
+            .iter()
+            .map(|row| cols.iter().map(|(_, i)| row[*i].to_string()).collect())
+            .collect();
+        let widths: Vec<usize> = cols
+            .iter()
+            .enumerate()
+            .map(|(c, (name, _))| {
+                cells
+                    .iter()
+                    .map(|row| row[c].chars().count())
+                    .fold(name.chars().count(), usize::max)
+            })
+            .collect();
+
+        let line = |f: &mut fmt::Formatter<'_>, values: Vec<&str>| {
+            let padded: Vec<String> = values
+                .iter()
+                .zip(&widths)
+                .map(|(v, w)| format!("{:1$}", v, w))
+                .collect();
+            writeln!(f, "{}", padded.join(" | ").trim_end())
+        };
+        line(f, cols.iter().map(|(name, _)| name.as_str()).collect())?;
+        let rule: Vec<String> = widths.iter().map(|w| "-".repeat(*w)).collect();
+        writeln!(f, "{}", rule.join("-+-"))?;
+        for row in cells.iter() {
+            line(f, row.iter().map(|v| v.as_str()).collect())?;
+        }
+        match self.data.len() - shown {
+            0 => Ok(()),
+            1 => writeln!(f, "(1 more row)"),
+            n => writeln!(f, "({} more rows)", n),
+        }
     }
 }
 
 fn main() {
+    // `describe PATH` prints statistics about a CSV or Parquet file rather
+    // than running the examples.
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let [command, path, ..] = args.as_slice() {
+        if command == "describe" {
+            let rel = match path.ends_with(".parquet") {
+                #[cfg(feature = "parquet")]
+                true => Relation::from_parquet(path),
+                _ => Relation::load_csv(path),
+            };
+            match rel {
+                Ok(rel) => {
+                    println!("{} rows", rel.data.len());
+                    rel.describe().print();
+                }
+                Err(e) => eprintln!("{}", e),
+            }
+            return;
+        }
+    }
+
+    // `--config PATH` runs the examples that use a config with that one.
+    let config_path = match args.iter().position(|a| a == "--config") {
+        Some(i) => match args.get(i + 1) {
+            Some(path) => Some(std::path::PathBuf::from(path)),
+            None => {
+                eprintln!("usage: joiner [--config PATH] [--limit N]");
+                std::process::exit(2);
+            }
+        },
+        None => None,
+    };
+
     let r = Relation::new(["a", "b"])
         .row([1, 2])
         .row([3, 4])
@@ -215,6 +950,14 @@ fn main() {
 
     r2.join(&t2).join(&s2).print();
 
+    // Joining through disk, one partition at a time. Running it again with
+    // the same directory picks up the already completed partitions.
+    let spill = SpillJoin::new(std::env::temp_dir().join("nbjoiner_spill"), 8);
+    spill.clear().unwrap();
+    println!("{} rows", spill.join(&r2, &s2).unwrap().data.len());
+    println!("{} rows", spill.join(&r2, &s2).unwrap().data.len());
+    spill.clear().unwrap();
+
     let s = Relation::new(["b", "c"])
         .row([2, 10])
         .row([4, 20])
@@ -230,6 +973,7 @@ fn main() {
     r.join(&t).join(&s).print();
 
     let planner = Planner::default().join(r).join(t).join(s);
+    print!("{}", planner.explain());
 
     let plan = planner.plan();
     let result = plan
@@ -244,16 +988,26 @@ fn main() {
             Relation::new_with_data(
                 [format!("col_{}", i), format!("col_{}", i + 1)],
                 (0..10)
-                    .map(|j| vec![j * 10i64.pow(i), j * 10i64.pow(i + 1)])
+                    .map(|j| {
+                        vec![
+                            Value::Int(j * 10i64.pow(i)),
+                            Value::Int(j * 10i64.pow(i + 1)),
+                        ]
+                    })
                     .collect::<Vec<_>>(),
             )
         })
         .collect();
 
-    let mut rng = rand::thread_rng();
-    for i in 0..9 {
-        many_relations.swap(i, rng.gen_range(i..10));
+    #[cfg(feature = "rand")]
+    {
+        let mut rng = rand::thread_rng();
+        for i in 0..9 {
+            many_relations.swap(i, rng.gen_range(i..10));
+        }
     }
+    #[cfg(not(feature = "rand"))]
+    many_relations.reverse();
 
     let plan = many_relations
         .into_iter()
@@ -270,4 +1024,1720 @@ fn main() {
         .unwrap();
 
     result.print();
+
+    // Named intermediate results are computed once and can be referenced
+    // any number of times.
+    let result = Query::new()
+        .with(
+            "bc",
+            Query::new().join(Relation::new(["b", "c"]).rows([[2, 10], [4, 20], [6, 30]])),
+        )
+        .with(
+            "abc",
+            Query::new()
+                .join(Relation::new(["a", "b"]).rows([[1, 2], [3, 4], [5, 6]]))
+                .join_named("bc"),
+        )
+        .join_named("abc")
+        .join_named("bc")
+        .join(Relation::new(["c", "d"]).rows([[10, 100], [20, 200], [30, 300]]))
+        .execute()
+        .unwrap();
+
+    result.print();
+
+    let path = std::env::temp_dir().join("nbjoiner_result.bin");
+    result.save(&path).unwrap();
+    Relation::load(&path).unwrap().print();
+
+    // Queries submitted together share the pool's threads and memory
+    // budget, with higher priorities getting to run first.
+    let scheduler = Scheduler::new(2, 1 << 20);
+    let handles: Vec<_> = (0..4i64)
+        .map(|i| {
+            let query = Query::new()
+                .join(Relation::new(["a", "b"]).rows((0..100).map(|j| vec![j, j % (i + 1)])))
+                .join(Relation::new(["b", "c"]).rows((0..10).map(|j| vec![j, j * 10])));
+            scheduler.submit(query, i as i32)
+        })
+        .collect();
+    for handle in handles {
+        println!("{} rows", handle.wait().unwrap().data.len());
+    }
+
+    // A query that would produce too many rows is stopped partway through.
+    let err = Query::new()
+        .join(Relation::new(["a", "b"]).rows((0..1000).map(|i| vec![i, i % 2])))
+        .join(Relation::new(["b", "c"]).rows((0..1000).map(|i| vec![i % 2, i])))
+        .limits(Limits {
+            max_output_rows: Some(10_000),
+            ..Limits::default()
+        })
+        .execute()
+        .unwrap_err();
+    println!("{}", err);
+
+    // Most of these rows share a single key, which the parallel join spreads
+    // over all of its threads rather than leaving to one of them.
+    let skewed = Relation::new(["a", "b"])
+        .rows((0..10_000).map(|i| vec![i, if i % 10 == 0 { i } else { 0 }]));
+    let lookup = Relation::new(["b", "c"]).rows((0..10_000).map(|i| vec![i, i * 2]));
+    let parallel = ParallelJoin::new(4).join(&skewed, &lookup);
+    println!(
+        "{} rows, expected {}",
+        parallel.data.len(),
+        skewed.join(&lookup).data.len()
+    );
+
+    // Hot keys can also be salted by hand.
+    let salted = ParallelJoin::new(4).salt([0], 2).join(&skewed, &lookup);
+    println!("{} rows", salted.data.len());
+
+    // A dimension table's hash table can be built once and probed by any
+    // number of other relations.
+    let customers = Relation::new(["customer", "region"]).rows((0..100).map(|i| vec![i, i % 5]));
+    let index = customers.index(&["customer".to_string()]);
+    let orders = Relation::new(["order", "customer"]).rows((0..1000).map(|i| vec![i, i % 100]));
+    let returns = Relation::new(["return", "customer"]).rows((0..50).map(|i| vec![i, i * 2]));
+    println!(
+        "{} orders, {} returns",
+        index.join(&orders).data.len(),
+        index.join(&returns).data.len()
+    );
+
+    // Or several dimensions can be joined to a fact table in a single pass.
+    let regions = Relation::new(["region", "name"]).rows((0..5).map(|i| vec![i, i * 100]));
+    let products = Relation::new(["product", "price"]).rows((0..10).map(|i| vec![i, i * 3]));
+    let sales =
+        Relation::new(["customer", "product"]).rows((0..1000).map(|i| vec![i % 100, i % 10]));
+    let star = star_join(
+        &sales,
+        &[
+            index,
+            products.index(&["product".to_string()]),
+            regions.index(&["region".to_string()]),
+        ],
+    );
+    println!(
+        "{} rows, expected {}",
+        star.data.len(),
+        sales
+            .join(&customers)
+            .join(&products)
+            .join(&regions)
+            .data
+            .len()
+    );
+
+    // Most of these rows don't make it into the result, and a semi-join
+    // reduction removes them before any joining happens.
+    let result = Query::new()
+        .join(Relation::new(["a", "b"]).rows((0..1000).map(|i| vec![i, i])))
+        .join(Relation::new(["b", "c"]).rows((0..1000).map(|i| vec![i, i % 10])))
+        .join(Relation::new(["c", "d"]).rows((0..10).map(|i| vec![i * 100, i])))
+        .semijoin_reduce()
+        .execute()
+        .unwrap();
+    result.print();
+
+    // Every pair of these shares a column, but they still have a join tree.
+    let graph = [["a", "b"], ["a", "c"], ["a", "d"]]
+        .into_iter()
+        .fold(Planner::default(), |planner, cols| {
+            planner.join(Relation::new(cols))
+        })
+        .query_graph;
+    println!(
+        "acyclic: {}, join tree: {:?}",
+        graph.is_acyclic_hypergraph(),
+        graph.join_tree()
+    );
+
+    // A cycle of four relations has no join tree. Its decomposition has two
+    // bags that overlap on two columns, which do have one.
+    let square = [
+        Relation::new(["a", "b"]).rows((0..100).map(|i| vec![i % 10, i / 10])),
+        Relation::new(["b", "c"]).rows((0..100).map(|i| vec![i % 10, i / 10])),
+        Relation::new(["c", "d"]).rows((0..100).map(|i| vec![i % 10, i / 10])),
+        Relation::new(["d", "a"]).rows((0..10).map(|i| vec![i, i])),
+    ];
+    let decomposition = Decomposition::new(&square);
+    println!(
+        "bags: {:?}, width: {}",
+        decomposition.bags,
+        decomposition.width()
+    );
+    let result = square
+        .iter()
+        .cloned()
+        .fold(Query::new(), |query, rel| query.join(rel))
+        .semijoin_reduce()
+        .execute()
+        .unwrap();
+    println!(
+        "{} rows, expected {}",
+        result.data.len(),
+        square[0]
+            .join(&square[1])
+            .join(&square[2])
+            .join(&square[3])
+            .data
+            .len()
+    );
+
+    // Each key here matches 100 rows on both sides, so the flat result is
+    // far bigger than the factorized one.
+    let fan_out = Query::new()
+        .join(Relation::new(["a", "k"]).rows((0..1000).map(|i| vec![i, i % 10])))
+        .join(Relation::new(["k", "b"]).rows((0..1000).map(|i| vec![i % 10, i])))
+        .join(Relation::new(["b", "c"]).rows((0..1000).map(|i| vec![i, i * 2])))
+        .execute_factorized()
+        .unwrap();
+    println!(
+        "{} rows over {:?}, first: {:?}, listed: {}",
+        fan_out.count(),
+        fan_out.col_names(),
+        fan_out.iter().next(),
+        fan_out.to_relation().data.len()
+    );
+
+    // A few rows of a result that's much bigger than its inputs.
+    #[cfg(feature = "rand")]
+    Query::new()
+        .join(Relation::new(["a", "k"]).rows((0..1000).map(|i| vec![i, i % 10])))
+        .join(Relation::new(["k", "b"]).rows((0..1000).map(|i| vec![i % 10, i])))
+        .sample_result(5)
+        .unwrap()
+        .print();
+
+    // On a sorted column, zone maps let scans and joins skip most chunks.
+    let events = Relation::new(["time", "user"]).rows((0..100_000).map(|i| vec![i, i % 100]));
+    let zones = events.zone_map();
+    let recent = zones.filter_range(&events, "time", 99_000..=99_999);
+    let sessions =
+        Relation::new(["time", "session"]).rows((50_000..51_000).map(|i| vec![i, i / 10]));
+    let joined = sessions
+        .index(&["time".to_string()])
+        .join_pruned(&events, &zones);
+    println!(
+        "{} recent, {} joined, {} of {} zones scanned",
+        recent.data.len(),
+        joined.data.len(),
+        zones.zones_overlapping(&[(0, 50_000, 50_999)]),
+        events.data.len().div_ceil(zonemap::ZONE_ROWS)
+    );
+
+    // Partitioned by day, so a day's filter or join only touches that day.
+    let by_day = events.partition_by("time", 1000);
+    let day = Relation::new(["time", "note"]).rows((42_000..42_010).map(|i| vec![i, i % 3]));
+    println!(
+        "{} partitions, {} rows on day 7, {} joined, {} in parallel",
+        by_day.partitions().len(),
+        by_day.filter_range(7000..=7999).data.len(),
+        by_day.join(&day).data.len(),
+        ParallelJoin::new(4)
+            .join_partitioned(&by_day, &sessions)
+            .data
+            .len()
+    );
+
+    // Every order of a small query, best first.
+    Planner::default()
+        .join(Relation::new(["a", "b"]).rows((0..100).map(|i| vec![i, i % 10])))
+        .join(Relation::new(["b", "c"]).rows((0..100).map(|i| vec![i % 10, i])))
+        .join(Relation::new(["c", "d"]).rows((0..5).map(|i| vec![i, i])))
+        .print_order_comparison();
+
+    // Summarizing a join's output, aggregated per thread and then merged.
+    let sales = Relation::new(["store", "item"]).rows((0..10_000).map(|i| vec![i % 4, i % 100]));
+    let prices = Relation::new(["item", "price"]).rows((0..100).map(|i| vec![i, i * 3]));
+    parallel_group_by(
+        &sales.join(&prices),
+        &["store"],
+        &[
+            Agg::Count,
+            Agg::Sum("price".to_string()),
+            Agg::Min("price".to_string()),
+            Agg::Max("price".to_string()),
+            Agg::Avg("price".to_string()),
+        ],
+        4,
+    )
+    .print();
+
+    // The first few rows of a parallel join in order, without sorting all
+    // of it in one place.
+    let (col_names, sorted) =
+        ParallelJoin::new(4).join_sorted(&sales, &prices, &["price", "store"]);
+    Relation::new(col_names)
+        .rows(sorted.skip(2495).take(10))
+        .print();
+
+    // Sorting with room for only a few thousand rows at a time, spilling
+    // sorted runs to disk and merging them back.
+    let joined = sales.join(&prices);
+    let sorter = ExternalSort::new(std::env::temp_dir().join("nbjoiner_sort"), 64 * 1024);
+    let sorted = sorter
+        .sort(
+            &joined.col_names,
+            joined.data.iter().cloned(),
+            &["price", "store"],
+        )
+        .unwrap();
+    let runs = sorted.spilled_runs();
+    let rows = sorted.collect::<Result<Vec<_>, _>>().unwrap();
+    println!(
+        "{} runs spilled, first {:?}, last {:?}",
+        runs,
+        rows.first(),
+        rows.last()
+    );
+
+    // Statistics learned by running a query against a catalog. Before, the
+    // join columns are assumed to be unique; after, what was seen is used.
+    let mut catalog = Catalog::new();
+    catalog.insert(
+        "orders",
+        Relation::new(["customer", "item"]).rows((0..1000).map(|i| vec![i % 10, i % 50])),
+    );
+    catalog.insert(
+        "customers",
+        Relation::new(["customer", "region"]).rows((0..10).map(|i| vec![i, i % 3])),
+    );
+    let orders = catalog.get("orders").unwrap();
+    let customers = catalog.get("customers").unwrap();
+    let guess = catalog.estimate(&[(Some("orders"), orders), (Some("customers"), customers)]);
+    let rows = Query::new()
+        .join_named("orders")
+        .join_named("customers")
+        .execute_with(&mut catalog)
+        .unwrap()
+        .data
+        .len();
+    let stats = catalog.stats("orders").unwrap();
+    println!(
+        "estimated {} rows, got {}, learned {:?}; orders has {} rows, keys {:?}, ndv {:?}",
+        guess,
+        rows,
+        catalog.learned_rows(&["customers", "orders"]),
+        stats.rows,
+        stats.keys,
+        stats.ndv
+    );
+
+    // Tracking a query's plan across runs. Replacing a relation changes
+    // what's known about it, which changes the plan.
+    let history_path = std::env::temp_dir().join("nbjoiner_plans.tsv");
+    let mut history = PlanHistory::new();
+    let tracked = || Query::new().join_named("orders").join_named("customers");
+    tracked()
+        .execute_tracked("customer_orders", &mut catalog, &mut history)
+        .unwrap();
+    history.save(&history_path).unwrap();
+    let mut history = PlanHistory::load(&history_path).unwrap();
+    catalog.insert(
+        "customers",
+        Relation::new(["customer", "region"]).rows((0..5000).map(|i| vec![i % 10, i % 3])),
+    );
+    let (_, diff) = tracked()
+        .execute_tracked("customer_orders", &mut catalog, &mut history)
+        .unwrap();
+    match diff {
+        Some(diff) => print!("{}", diff),
+        None => println!("plan for customer_orders unchanged"),
+    }
+
+    // Order lines that point at orders that don't exist.
+    let lines = Relation::new(["line", "order_id"]).rows((0..10).map(|i| vec![i, i * 3]));
+    let order_ids = Relation::new(["id", "total"]).rows((0..20).map(|i| vec![i, i * 100]));
+    check_foreign_key(&lines, "order_id", &order_ids, "id").print();
+
+    // A schema for rows read as strings, some of them short.
+    let sample = [
+        vec!["1", "alice", "3.5", "true"],
+        vec!["2", "bob", "4", "false"],
+        vec!["3", "", "NA"],
+        vec!["4", "bob", "2", "true"],
+    ];
+    print!(
+        "{}",
+        Relation::infer_schema(&["id", "name", "score", "active"], sample)
+    );
+
+    // Keys nobody declared: neither column alone is unique, but together
+    // they are.
+    let grid = Relation::new(["x", "y", "z"]).rows((0..100).map(|i| vec![i / 10, i % 10, i % 7]));
+    println!("grid keys {:?}", grid.candidate_keys(2));
+
+    // Grouping by a store and its city only needs to hash the store, since
+    // the store decides the city.
+    let visits = Relation::new(["store", "city", "spend"])
+        .rows((0..1000).map(|i| vec![i % 6, i % 6 / 2, i]));
+    let deps = Dependencies::discover(&visits);
+    println!(
+        "store, city groups on {:?}",
+        deps.minimal(&["store".to_string(), "city".to_string()])
+    );
+    parallel_group_by_with(
+        &visits,
+        &["store", "city"],
+        &[Agg::Sum("spend".to_string())],
+        2,
+        &deps,
+    )
+    .print();
+    let declared = Dependencies::new().declare(&["city"], "store");
+    println!("declared {:?}", declared.iter().collect::<Vec<_>>());
+
+    // Joining on a column that's almost always the same is caught as soon
+    // as the join has grown too far, with the keys to blame.
+    let err = Query::new()
+        .join(Relation::new(["user", "flag"]).rows((0..500).map(|i| vec![i, (i % 50 != 0) as i64])))
+        .join(Relation::new(["flag", "item"]).rows((0..500).map(|i| vec![(i % 7 != 0) as i64, i])))
+        .limits(Limits {
+            max_join_growth: Some(10.0),
+            ..Limits::default()
+        })
+        .execute()
+        .unwrap_err();
+    println!("{}", err);
+
+    // Which keys repeat, and how often.
+    Relation::new(["a", "b"])
+        .rows((0..20).map(|i| vec![i % 3, i % 5]))
+        .duplicate_keys(&["a"])
+        .print();
+
+    // Checking that ordering the joins differently doesn't change the rows.
+    let validated = Query::new()
+        .with(
+            "big",
+            Query::new()
+                .join(Relation::new(["a", "b"]).rows((0..300).map(|i| vec![i, i % 30])))
+                .join(Relation::new(["b", "c"]).rows((0..30).map(|i| vec![i, i % 5]))),
+        )
+        .join_named("big")
+        .join(Relation::new(["c", "d"]).rows((0..5).map(|i| vec![i, i * i])))
+        .join(Relation::new(["d", "e"]).rows((0..3).map(|i| vec![i, -i])))
+        .validate_strategies()
+        .execute()
+        .unwrap();
+    let greedy = Query::new()
+        .join(Relation::new(["x"]).row([1]))
+        .strategy(Strategy::Greedy)
+        .execute()
+        .unwrap();
+    println!(
+        "validated {} rows, {} row greedily",
+        validated.data.len(),
+        greedy.data.len()
+    );
+
+    // Keys from somewhere untrusted get randomly keyed SipHash rather than
+    // the fast hash, whose collisions are easy to find.
+    let untrusted = Query::new()
+        .join(Relation::new(["k", "v"]).rows((0..1000i64).map(|i| vec![i << 32, i])))
+        .join(Relation::new(["k", "w"]).rows((0..1000i64).map(|i| vec![i << 32, -i])))
+        .hashing(Hashing::Random)
+        .execute()
+        .unwrap();
+    println!("{} rows with random hashing", untrusted.data.len());
+
+    // Joining readings to the bucket of ten they fall in.
+    let readings = Relation::new(["reading", "value"]).rows((0..5).map(|i| vec![i, i * 7]));
+    let buckets = Relation::new(["bucket", "label"]).rows((0..4).map(|i| vec![i, 100 + i]));
+    readings
+        .join_on_expr(&buckets, col("value") / lit(10), col("bucket"))
+        .print();
+
+    // Headers written by different people still line up once normalized.
+    let plan = Planner::default()
+        .normalize_names(Normalizer::all())
+        .join(Relation::new(["UserId", "HTTPStatus"]).rows([[1, 200], [2, 404]]))
+        .join(Relation::new([" user_id", "Signup Date"]).rows([[1, 20240101], [2, 20240202]]))
+        .plan();
+    plan.into_iter()
+        .reduce(|result, next| result.join(&next))
+        .unwrap()
+        .print();
+    let trimmed = Normalizer {
+        trim: true,
+        ..Normalizer::default()
+    };
+    println!("{:?}", trimmed.normalize("  Signup Date "));
+
+    // Join columns that don't line up get suggestions rather than a cross
+    // product.
+    let err = Query::new()
+        .join(Relation::new(["user_id", "name"]).rows((0..50).map(|i| vec![i, i * 2])))
+        .join(Relation::new(["uid", "plan"]).rows((10..40).map(|i| vec![i, i % 3])))
+        .forbid_cross_products()
+        .execute()
+        .unwrap_err();
+    println!("{}", err);
+
+    // Headers that say nothing, with the keys found from the values alone.
+    let mut opaque = Catalog::new();
+    opaque.insert(
+        "t1",
+        Relation::new(["c0", "c1"]).rows((0..2000).map(|i| vec![i, i % 7])),
+    );
+    opaque.insert(
+        "t2",
+        Relation::new(["c0", "c1"]).rows((0..500).map(|i| vec![i * 3 % 7, i * 4 + 1])),
+    );
+    for (left, right, key) in opaque.infer_join_keys() {
+        println!("{}.{} = {}.{}: {}", left, key.left, right, key.right, key);
+    }
+
+    // Monthly exports that gained a column partway through.
+    let exports = std::env::temp_dir().join("nbjoiner_exports");
+    std::fs::create_dir_all(&exports).unwrap();
+    std::fs::write(exports.join("2024-01.csv"), "id,amount\n1,10\n2,20\n").unwrap();
+    std::fs::write(exports.join("2024-02.csv"), "amount,id,region\n30,3,1\n").unwrap();
+    Relation::load_csv_dir(&exports, SchemaEvolution::Lenient)
+        .unwrap()
+        .print();
+    println!(
+        "{}",
+        Relation::load_csv_dir(&exports, SchemaEvolution::default()).unwrap_err()
+    );
+    std::fs::remove_dir_all(&exports).unwrap();
+
+    // A relation kept in chunks, scanned in parallel after being spilled,
+    // reading only the chunks a range of timestamps could be in.
+    let mut log = Relation::new(["ts", "level"])
+        .rows((0..20_000).map(|i| vec![i, i % 4]))
+        .into_chunks(chunked::CHUNK_ROWS);
+    log.spill(std::env::temp_dir().join("nbjoiner_chunks"))
+        .unwrap();
+    let recent = log
+        .par_scan(&[("ts", 15_000, 16_000), ("level", 3, 3)], 4)
+        .unwrap();
+    println!(
+        "{} of {} rows from {} spilled chunks",
+        recent.data.len(),
+        log.len(),
+        log.chunks().iter().filter(|c| c.spilled()).count()
+    );
+    println!(
+        "{} rows at level 0",
+        log.scan(&[("level", 0, 0)]).unwrap().data.len()
+    );
+
+    // A join awaited a batch at a time. With room for only two batches,
+    // the join waits for each to be taken before producing more.
+    let visits = Relation::new(["user", "page"]).rows((0..10_000).map(|i| vec![i % 100, i]));
+    let users = Relation::new(["user", "cohort"]).rows((0..100).map(|i| vec![i, i % 5]));
+    let mut joined = users.join_stream(visits, 1000, 2);
+    let (batches, rows) = stream::block_on(async {
+        let (mut batches, mut rows) = (0, 0);
+        while let Some(batch) = joined.next().await {
+            batches += 1;
+            rows += batch.len();
+        }
+        (batches, rows)
+    });
+    println!(
+        "{:?}: {} rows in {} batches",
+        joined.col_names, rows, batches
+    );
+
+    // Consuming a query's output as the last join produces it.
+    let batches = Query::new()
+        .join(Relation::new(["user", "cohort"]).rows((0..100).map(|i| vec![i, i % 5])))
+        .join(Relation::new(["cohort", "label"]).rows((0..5).map(|i| vec![i, 10 * i])))
+        .join(Relation::new(["user", "page"]).rows((0..5000).map(|i| vec![i % 100, i])))
+        .execute_streaming(1024)
+        .unwrap();
+    println!("streaming {:?}", batches.col_names);
+    let mut streamed = 0;
+    for batch in batches {
+        streamed += batch.unwrap().len();
+    }
+    println!("{} rows streamed", streamed);
+
+    // Two names for the same join are computed once, and a definition
+    // nothing uses isn't computed at all.
+    let regions = Query::new()
+        .join(Relation::new(["store", "city"]).rows((0..50).map(|i| vec![i, i % 10])))
+        .join(Relation::new(["city", "region"]).rows((0..10).map(|i| vec![i, i % 3])));
+    let spooled = Query::new()
+        .with("by_store", regions.clone())
+        .with("by_store_again", regions)
+        .with("unused", Query::new().join_named("missing"))
+        .join_named("by_store")
+        .join_named("by_store_again")
+        .execute()
+        .unwrap();
+    println!("{} rows from one spooled join", spooled.data.len());
+
+    // Ordering by what each join costs under two models, and what each
+    // thinks sorting the output would cost on top.
+    let costed = || {
+        Query::new()
+            .join(Relation::new(["user", "page"]).rows((0..5000).map(|i| vec![i % 100, i])))
+            .join(Relation::new(["user", "cohort"]).rows((0..100).map(|i| vec![i, i % 5])))
+            .join(Relation::new(["cohort", "label"]).rows((0..5).map(|i| vec![i, 10 * i])))
+    };
+    let row_count = costed().cost_model(RowCount).execute().unwrap();
+    let io_cpu = costed().cost_model(IoCpu::default()).execute().unwrap();
+    for (model, out) in [
+        (&RowCount as &dyn CostModel, row_count),
+        (&IoCpu::default(), io_cpu),
+    ] {
+        println!(
+            "{}: {} rows, sorting costs {:.0}",
+            model.name(),
+            out.data.len(),
+            model.sort(out.data.len() as f64, out.col_names.len())
+        );
+    }
+
+    // Two estimators' guesses at the same join, and ordering by one of them.
+    let pages = Relation::new(["user", "page"]).rows((0..5000).map(|i| vec![i % 100, i]));
+    let cohorts = Relation::new(["user", "cohort"]).rows((0..100).map(|i| vec![i, i % 5]));
+    let estimators: [&dyn CardinalityEstimator; 2] = [&Statistics, &Sampling::default()];
+    for estimator in estimators {
+        println!(
+            "{} estimate: {:.0}",
+            estimator.name(),
+            estimator.estimate(&Catalog::new(), &[(None, &pages), (None, &cohorts)])
+        );
+    }
+    let sampled = Query::new()
+        .join(pages)
+        .join(cohorts)
+        .cardinality_estimator(Sampling { rows: 100 })
+        .execute()
+        .unwrap();
+    println!("{} rows ordered by sampling", sampled.data.len());
+
+    // Hints in a comment the way other databases take them, overriding the
+    // order the catalog's estimates gave.
+    let hints = Hints::parse("select * /*+ leading(pages cohorts) hash_join(pages) parallel(4) */");
+    println!("leading {:?}, ignored {:?}", hints.leading, hints.ignored);
+    let mut hinted_catalog = Catalog::new();
+    hinted_catalog.insert(
+        "pages",
+        Relation::new(["user", "page"]).rows((0..50).map(|i| vec![i % 10, i])),
+    );
+    hinted_catalog.insert(
+        "cohorts",
+        Relation::new(["user", "cohort"]).rows((0..10).map(|i| vec![i, i % 2])),
+    );
+    let mut hinted_history = PlanHistory::new();
+    let hinted = Query::new().join_named("pages").join_named("cohorts");
+    hinted
+        .clone()
+        .execute_tracked("hinted", &mut hinted_catalog, &mut hinted_history)
+        .unwrap();
+    let (_, diff) = hinted
+        .hints(&hints)
+        .execute_tracked("hinted", &mut hinted_catalog, &mut hinted_history)
+        .unwrap();
+    println!("{}", diff.unwrap());
+
+    // Queries that differ only in their rows and the names of their
+    // definitions share a fingerprint, and so a plan history.
+    let shaped = |name: &str, rows: i64| {
+        Query::new()
+            .with(
+                name,
+                Query::new()
+                    .join(Relation::new(["user", "plan"]).rows((0..rows).map(|i| vec![i, i % 3]))),
+            )
+            .join_named(name)
+            .join_named("cohorts")
+    };
+    println!(
+        "{:016x} {}",
+        shaped("a", 10).fingerprint(),
+        shaped("a", 10).fingerprint() == shaped("b", 20).fingerprint()
+    );
+    shaped("a", 10)
+        .execute_fingerprinted(&mut hinted_catalog, &mut hinted_history)
+        .unwrap();
+
+    // A namespace per tenant. Queries against the root can reach into any
+    // of them, held to the limits of those they use, while a query against
+    // a tenant's own namespace can't see anyone else's.
+    let mut tenants = Catalog::new();
+    for (tenant, rows) in [("acme", 100), ("globex", 5)] {
+        let ns = tenants.namespace_mut(tenant);
+        ns.insert(
+            "orders",
+            Relation::new(["order", "customer"]).rows((0..rows).map(|i| vec![i, i % 5])),
+        );
+        ns.insert(
+            "customers",
+            Relation::new(["customer", "tier"]).rows((0..5).map(|i| vec![i, i % 2])),
+        );
+        ns.set_limits(Limits {
+            max_output_rows: Some(50),
+            ..Limits::default()
+        });
+    }
+    for tenant in ["acme", "globex"] {
+        let result = Query::new()
+            .join_named(format!("{}.orders", tenant))
+            .join_named(format!("{}.customers", tenant))
+            .execute_with(&mut tenants);
+        match result {
+            Ok(rel) => println!("{}: {} rows", tenant, rel.data.len()),
+            Err(e) => println!("{}: {}", tenant, e),
+        }
+    }
+    let isolated = Query::new()
+        .join_named("acme.orders")
+        .execute_with(tenants.namespace_mut("globex"));
+    println!(
+        "{} {}",
+        isolated.unwrap_err(),
+        tenants.namespace("initech").is_none()
+    );
+
+    // Every join's result next to what the catalog thought it would be,
+    // first kept in memory and then written out.
+    let labels = Relation::new(["cohort", "label"]).rows([[0, 5], [1, 10]]);
+    let (_, stats) = Query::new()
+        .join_named("pages")
+        .join_named("cohorts")
+        .join(labels.clone())
+        .intermediates(Intermediates::Keep)
+        .execute_with_stats(Some(&mut hinted_catalog))
+        .unwrap();
+    for step in stats.intermediates() {
+        println!(
+            "{:?}: estimated {:.0}, got {} ({} kept)",
+            step.inputs,
+            step.estimated_rows,
+            step.rows,
+            step.result.as_ref().map_or(0, |r| r.data.len())
+        );
+    }
+    let report = std::env::temp_dir().join("nbjoiner_plan.html");
+    std::fs::write(&report, stats.plan().to_html_with_stats(&stats)).unwrap();
+    println!(
+        "{} byte plan page, {} without stats",
+        std::fs::metadata(&report).unwrap().len(),
+        stats.plan().to_html().len()
+    );
+    let dump = std::env::temp_dir().join("nbjoiner_intermediates");
+    let (_, stats) = Query::new()
+        .join(hinted_catalog.get("pages").unwrap().clone())
+        .join(labels)
+        .join(hinted_catalog.get("cohorts").unwrap().clone())
+        .intermediates(Intermediates::Dump(dump.clone()))
+        .execute_with_stats(None)
+        .unwrap();
+    let last = stats.intermediates().last().unwrap();
+    println!(
+        "{} rows in {}",
+        Relation::load(last.path.as_ref().unwrap())
+            .unwrap()
+            .data
+            .len(),
+        last.path.as_ref().unwrap().display()
+    );
+    std::fs::remove_dir_all(&dump).unwrap();
+
+    // A parallel join that gives its rows in the same order every time,
+    // which is the order of the same join on one thread.
+    let in_order = ParallelJoin::new(4).deterministic().join(&skewed, &lookup);
+    let sequential = skewed.join(&lookup);
+    println!(
+        "{} rows, same order as one thread: {}",
+        in_order.data.len(),
+        in_order == sequential
+    );
+
+    // Building a relation a column at a time.
+    let built = Relation::builder()
+        .column("id", (0..4).collect::<Vec<_>>())
+        .column("score", vec![90, 75, 82, 60])
+        .build()
+        .unwrap();
+    built.print();
+    println!(
+        "{}",
+        Relation::builder()
+            .column("id", vec![1, 2, 3])
+            .column("score", vec![90])
+            .build()
+            .unwrap_err()
+    );
+    println!(
+        "{}",
+        Relation::builder()
+            .column("id", vec![1])
+            .column("id", vec![2])
+            .build()
+            .unwrap_err()
+    );
+
+    // Joining on string keys, with values of other types alongside.
+    let users = Relation::new(["user", "age", "admin"]).rows([
+        vec![Value::from("ada"), 36.into(), true.into()],
+        vec!["grace".into(), 45.into(), false.into()],
+        vec!["linus".into(), Value::Null, false.into()],
+    ]);
+    let visits = Relation::new(["user", "page", "seconds"]).rows([
+        vec![Value::from("ada"), "/home".into(), 1.5.into()],
+        vec!["ada".into(), "/docs".into(), 12.25.into()],
+        vec!["linus".into(), "/home".into(), 0.75.into()],
+        vec!["ken".into(), "/home".into(), 3.0.into()],
+    ]);
+    let joined = users.join(&visits);
+    joined.print();
+    let seconds = joined
+        .col_names
+        .iter()
+        .position(|c| c == "seconds")
+        .unwrap();
+    let age = joined.col_names.iter().position(|c| c == "age").unwrap();
+    let user = joined.col_names.iter().position(|c| c == "user").unwrap();
+    println!(
+        "{} seconds in all, {} visits by users of unknown age, first visitor {:?}",
+        joined
+            .data
+            .iter()
+            .filter_map(|row| row[seconds].as_float())
+            .sum::<f64>(),
+        joined.data.iter().filter(|row| row[age].is_null()).count(),
+        joined.data[0][user].as_str(),
+    );
+
+    // Lookup tables straight from Rust collections.
+    let plans =
+        Relation::from(vec![(1, "free"), (2, "pro"), (3, "team")]).with_names(["plan", "tier"]);
+    let seats =
+        Relation::from(HashMap::from([(1, 1), (2, 5), (3, 50)])).with_names(["plan", "seats"]);
+    let prices = Relation::from(std::collections::BTreeMap::from([
+        ("pro", 12.0),
+        ("team", 40.0),
+    ]))
+    .with_names(["tier", "price"]);
+    plans.join(&seats).join(&prices).print();
+
+    // Null join keys match nothing, unless asked to match each other.
+    let left =
+        Relation::new(["k", "l"]).rows([[Value::from(1), "a".into()], [Value::Null, "b".into()]]);
+    let right =
+        Relation::new(["k", "r"]).rows([[Value::from(1), "x".into()], [Value::Null, "y".into()]]);
+    println!(
+        "{} rows with nulls distinct, {} with nulls equal, {} from a query with nulls equal",
+        left.join(&right).data.len(),
+        left.join_null_equals_null(&right).data.len(),
+        Query::new()
+            .join(left.clone())
+            .join(right.clone())
+            .null_equals_null()
+            .execute()
+            .unwrap()
+            .data
+            .len(),
+    );
+
+    // Formatting relations and plans rather than printing them.
+    let squares = Relation::new(["n", "square"]).rows((1..=30i64).map(|n| [n, n * n]));
+    print!("{:.3}", squares);
+    print!("{}", Relation::from(vec![(1, "one"), (2, "two")]));
+    if let Some(plan) = history.get("customer_orders") {
+        print!("{}", plan);
+        #[cfg(feature = "evcxr")]
+        plan.evcxr_display();
+    }
+    #[cfg(feature = "evcxr")]
+    squares.evcxr_display();
+
+    // Outer joins keep the rows that don't match, padded with nulls.
+    let people = Relation::new(["id", "name"])
+        .rows([[Value::from(1), "ada".into()], [2.into(), "grace".into()]]);
+    let pets = Relation::new(["id", "pet"])
+        .rows([[Value::from(1), "cat".into()], [3.into(), "dog".into()]]);
+    print!("{}", people.left_join(&pets));
+    print!("{}", people.right_join(&pets));
+    print!("{}", people.full_join(&pets));
+    let owners = Query::new()
+        .outer_join(Outer::Left, pets.clone())
+        .join(people.clone())
+        .join(Relation::new(["name", "city"]).rows([["ada", "london"], ["grace", "nyc"]]))
+        .execute()
+        .unwrap();
+    print!("{}", owners);
+
+    for outer in [Outer::Right, Outer::Full] {
+        let joined = Query::new()
+            .with("pets", Query::new().join(pets.clone()))
+            .join(people.clone())
+            .outer_join_named(outer, "pets")
+            .execute()
+            .unwrap();
+        println!("{:?} join: {} rows", outer, joined.data.len());
+    }
+
+    // Semi and anti joins keep just the left side's rows, once each.
+    let visits = Relation::new(["id", "day"]).rows([[1, 1], [1, 2], [3, 1]]);
+    print!("{}", people.semi_join(&visits));
+    print!("{}", people.anti_join(&visits));
+
+    // Joining relations with no columns in common is a cross product
+    // unless asked to fail instead.
+    let sizes = Relation::new(["size"]).rows([["s"], ["m"]]);
+    println!("cross join: {} rows", people.cross_join(&sizes).data.len());
+    let strict = JoinOptions {
+        no_common_columns: NoCommonColumns::Error,
+        ..JoinOptions::default()
+    };
+    match people.join_with_options(&sizes, &strict) {
+        Ok(rel) => println!("joined {} rows", rel.data.len()),
+        Err(e) => println!("join failed: {}", e),
+    }
+    println!(
+        "joined on id: {} rows",
+        people.join_with_options(&pets, &strict).unwrap().data.len()
+    );
+
+    // The no_std kernel joins the same rows as the hash join above it.
+    let wide = Relation::new(["id", "x"]).rows((0..500).map(|i| [i % 50, i]));
+    let narrow = Relation::new(["id", "y"]).rows((0..100).map(|i| [i % 70, i]));
+    let mut expected = wide.join(&narrow).data;
+    let mut kernel_rows = wide.kernel_join(&narrow, Nulls::Distinct).data;
+    expected.sort();
+    kernel_rows.sort();
+    println!("kernel join matches: {}", expected == kernel_rows);
+
+    // Columns that don't share a name can be joined on explicitly.
+    let orders = Relation::new(["order", "customer_id"]).rows([[1, 1], [2, 2], [3, 1]]);
+    print!("{}", orders.join_on(&people, &[("customer_id", "id")]));
+    let on_keys = Planner::default()
+        .join(orders.clone())
+        .join_on(people.clone(), &[("customer_id", "id")])
+        .plan()
+        .into_iter()
+        .reduce(|result, next| result.join(&next))
+        .unwrap();
+    println!("planned join on keys: {} rows", on_keys.data.len());
+
+    // A traced join narrates the hash table and every probe, and a traced
+    // plan why each input comes where it does.
+    let mut trace = Vec::new();
+    people.join_traced(&pets, &mut trace).unwrap();
+    Planner::default()
+        .join(orders.clone())
+        .join(sizes.clone())
+        .join(people.clone())
+        .join(pets.clone())
+        .trace_order(&mut trace)
+        .unwrap();
+    print!("{}", String::from_utf8(trace).unwrap());
+
+    // Theta joins keep the pairs a condition holds for, not just equal ones.
+    let events = Relation::new(["event", "at"]).rows([[1, 5], [2, 15], [3, 25]]);
+    let windows = Relation::new(["window", "start", "end"]).rows([[1, 0, 10], [2, 10, 20]]);
+    print!(
+        "{}",
+        events.join_where_expr(
+            &windows,
+            col("start").le(col("at")) & col("at").lt(col("end"))
+        )
+    );
+    let outside = events.join_where_expr(
+        &windows,
+        (col("at").lt(col("start")) | col("at").ge(col("end")))
+            & !col("window").equals(lit(1))
+            & col("event").not_equals(lit(2))
+            & col("end").gt(col("start")),
+    );
+    let late = events.join_where(&windows, |e, w| e[1] > w[2]);
+    println!("{} outside, {} after", outside.data.len(), late.data.len());
+
+    // Filtering keeps the rows a closure or a predicate is true for.
+    let early = events.filter(|row| row.get("at").as_int().is_some_and(|at| at < 20));
+    let late = events.filter_expr(&col("at").ge(lit(20)));
+    println!("{} early, {} late", early.data.len(), late.data.len());
+
+    // Reconciling data where a null key is a category of its own.
+    let ledger = Relation::new(["region", "booked"])
+        .rows([[Value::from("eu"), 10.into()], [Value::Null, 3.into()]]);
+    let bank = Relation::new(["region", "paid"])
+        .rows([[Value::from("eu"), 10.into()], [Value::Null, 2.into()]]);
+    let null_safe = JoinOptions {
+        nulls: Nulls::Equal,
+        ..JoinOptions::default()
+    };
+    print!(
+        "{}",
+        ledger
+            .outer_join_with_options(&bank, Outer::Full, &null_safe)
+            .unwrap()
+    );
+    println!(
+        "{} rows matching nulls, {} without",
+        ledger
+            .join_with_options(&bank, &null_safe)
+            .unwrap()
+            .data
+            .len(),
+        ledger.join(&bank).data.len()
+    );
+
+    // Projection picks and orders columns, and a query that selects its
+    // output drops the columns nothing needs before joining.
+    print!("{}", orders.project(["customer_id", "order"]));
+    let selected = Query::new()
+        .join(orders.clone().with_names(["order", "id"]))
+        .join(people.clone())
+        .join(pets.clone())
+        .select(["name", "order"]);
+    let batches = selected.clone().execute_streaming(2).unwrap();
+    let streamed: usize = batches.map(|batch| batch.unwrap().len()).sum();
+    let selected = selected.execute().unwrap();
+    println!(
+        "selected {:?}: {} rows, {} streamed",
+        selected.col_names,
+        selected.data.len(),
+        streamed
+    );
+
+    // Summarizing a join's result by group, and all of it at once.
+    let bookings = Relation::new(["id", "nights"]).rows([[1, 3], [1, 5], [2, 2]]);
+    let stays = people.join(&bookings);
+    print!(
+        "{}",
+        stays.aggregate(
+            &["name"],
+            &[
+                Agg::Count,
+                Agg::Sum("nights".to_string()),
+                Agg::Avg("nights".to_string()),
+            ],
+        )
+    );
+    print!(
+        "{}",
+        Relation::new(["nights"]).aggregate(&[], &[Agg::Count, Agg::Max("nights".to_string())])
+    );
+
+    // Reconciling two copies of the same rows by how many of each they have.
+    let expected = Relation::new(["sku", "qty"]).rows([[1, 1], [1, 1], [2, 5], [3, 1]]);
+    let shipped = Relation::new(["qty", "sku"]).rows([[1, 1], [5, 2], [5, 2], [1, 3]]);
+    print!("{}", expected.except_with_counts(&shipped));
+
+    // Duplicates can be dropped after the fact, or by joining as sets.
+    let tags = Relation::new(["id", "tag"]).rows([[1, 7], [1, 7], [2, 8]]);
+    let as_set = JoinOptions {
+        distinct: true,
+        ..JoinOptions::default()
+    };
+    println!(
+        "{} distinct tags, {} joined as a bag, {} as a set",
+        tags.distinct().data.len(),
+        people.join(&tags).data.len(),
+        people.join_with_options(&tags, &as_set).unwrap().data.len()
+    );
+
+    // Provenance says which row of each input every output row came from.
+    let traced = Query::new()
+        .join(people.clone())
+        .join(bookings.clone())
+        .outer_join(Outer::Left, pets.clone())
+        .select(["name", "nights"])
+        .provenance()
+        .execute()
+        .unwrap();
+    print!("{}", traced);
+
+    // Sorting on several columns, which the result remembers.
+    let by_stay = stays.sort_by(&["name", "nights desc"]);
+    print!("{}", by_stay.relation());
+    println!(
+        "sorted on {:?}: by name {}, by nights {}",
+        by_stay.keys(),
+        by_stay.is_sorted_on(&["name"]),
+        by_stay.is_sorted_on(&["nights"])
+    );
+    assert_eq!(by_stay.into_relation().data.len(), stays.data.len());
+
+    // Asking why an expected row isn't in a query's result.
+    let stay_query = Query::new()
+        .join(people.clone())
+        .join(bookings.clone())
+        .join(pets.clone());
+    for expected in [
+        vec![("name", Value::from("ada")), ("nights", 3.into())],
+        vec![("name", "grace".into())],
+        vec![("name", "ada".into()), ("nights", 4.into())],
+        vec![("color", "red".into())],
+    ] {
+        match stay_query.clone().why_not(&expected).unwrap() {
+            Some(why) => println!("why not {:?}: {}", expected, why),
+            None => println!("{:?} is there", expected),
+        }
+    }
+
+    // Column lineage follows output columns back through named results.
+    let (_, stats) = Query::new()
+        .with(
+            "guests",
+            Query::new().join(people.clone()).join(bookings.clone()),
+        )
+        .join_named("guests")
+        .join(pets.clone())
+        .select(["name", "id", "pet"])
+        .provenance()
+        .execute_with_stats(None)
+        .unwrap();
+    for lineage in stats.plan().lineage() {
+        println!("{}", lineage);
+    }
+
+    // Paging through a result a few rows at a time.
+    squares.offset(10).limit(3).print();
+
+    // A mandatory filter keeps a tenant's queries to its own rows.
+    let mut tenant_catalog = Catalog::new();
+    tenant_catalog.insert(
+        "invoices",
+        Relation::new(["tenant_id", "invoice", "id"]).rows([[1, 100, 1], [2, 200, 2], [1, 101, 2]]),
+    );
+    tenant_catalog.add_filter("invoices", col("tenant_id").equals(lit(1)));
+    let seen = Query::new()
+        .join_named("invoices")
+        .join(people.clone())
+        .execute_with(&mut tenant_catalog)
+        .unwrap();
+    println!("tenant 1 sees {} of 3 invoices", seen.data.len());
+
+    // Set operations over relations with the same columns.
+    let march = Relation::new(["sku", "qty"]).rows([[1, 1], [1, 1], [2, 5]]);
+    let april = Relation::new(["qty", "sku"]).rows([[1, 1], [9, 4]]);
+    println!(
+        "union {} / {}, intersect {} / {}, except {} / {}",
+        march.union(&april).data.len(),
+        march.union_all(&april).data.len(),
+        march.intersect(&april).data.len(),
+        march.intersect_all(&april).data.len(),
+        march.except(&april).data.len(),
+        march.except_all(&april).data.len()
+    );
+
+    // A quick preview of a big join from a tenth of its inputs.
+    let visits = Relation::new(["visit", "page"]).rows((0..20_000).map(|i| vec![i, i % 2000]));
+    let clicks = Relation::new(["page", "ms"]).rows((0..20_000).map(|i| vec![i % 2000, i % 7]));
+    let preview = visits.approx_join(&clicks, 4000);
+    println!(
+        "sampled {:.0}% for {} rows: count {}, sum of ms {}, exactly {}",
+        preview.fraction() * 100.0,
+        preview.sample().data.len(),
+        preview.count(),
+        preview.sum("ms"),
+        visits.join(&clicks).data.len()
+    );
+
+    // A data file loaded with inferred types, then with zip codes as text.
+    let customers = std::env::temp_dir().join("nbjoiner_customers.csv");
+    std::fs::write(
+        &customers,
+        "id,name,zip,vip\n1,\"Smith, Ann\",02134,true\n2,Bo,,false\n",
+    )
+    .unwrap();
+    let inferred = Relation::from_csv_path(&customers, None).unwrap();
+    inferred.print();
+    let column = |name: &str, ty, nullable| Column {
+        name: name.to_string(),
+        ty,
+        nullable,
+    };
+    let zips = Schema {
+        columns: vec![
+            column("id", ColumnType::Int, false),
+            column("name", ColumnType::Text, false),
+            column("zip", ColumnType::Text, true),
+            column("vip", ColumnType::Bool, false),
+        ],
+        keys: vec!["id".to_string()],
+    };
+    Relation::from_csv_path(&customers, Some(&zips))
+        .unwrap()
+        .join(&Relation::from_csv_reader("id,orders\n1,3\n2,1\n".as_bytes(), None).unwrap())
+        .print();
+    println!(
+        "{}",
+        Relation::from_csv_reader("id,vip\n1,maybe\n".as_bytes(), Some(&zips)).unwrap_err()
+    );
+    std::fs::remove_file(&customers).unwrap();
+
+    // Join output written out for other tools.
+    let shipped = Relation::new(["id", "note"])
+        .row([Value::Int(1), Value::Str("fragile, \"glass\"".into())])
+        .row([Value::Int(2), Value::Null])
+        .join(
+            &Relation::new(["id", "weight"])
+                .row([Value::Int(1), Value::Float(1.5)])
+                .row([Value::Int(2), Value::Float(2.0)]),
+        );
+    let mut csv = Vec::new();
+    shipped.to_csv(&mut csv).unwrap();
+    print!("{}", String::from_utf8_lossy(&csv));
+    assert_eq!(
+        Relation::from_csv_reader(&csv[..], None)
+            .unwrap()
+            .data
+            .len(),
+        shipped.data.len()
+    );
+    shipped.to_json(&mut std::io::stdout()).unwrap();
+
+    // Declared domains prune rows that can't match and catch bad loads.
+    let mut regional = Catalog::new();
+    regional.declare_domain("regions", "region", Domain::one_of([1, 2]));
+    regional.declare_domain("sales", "amount", Domain::range(0, 1000));
+    regional.insert(
+        "regions",
+        Relation::new(["region", "zone"]).rows([[1, 10], [2, 20]]),
+    );
+    regional.insert(
+        "sales",
+        Relation::new(["sale", "region", "amount"]).rows([[1, 1, 5], [2, 3, 7], [3, 2, 9]]),
+    );
+    let sold = Query::new()
+        .join_named("sales")
+        .join_named("regions")
+        .check_domains()
+        .execute_with(&mut regional)
+        .unwrap();
+    println!("{} sales in known regions", sold.data.len());
+    println!(
+        "{}",
+        regional
+            .try_insert(
+                "sales",
+                Relation::new(["sale", "region", "amount"]).rows([[4, 1, -5]])
+            )
+            .unwrap_err()
+    );
+    let mut decades = Catalog::new();
+    decades.declare_domain("old", "year", Domain::range(1990, 1999));
+    decades.declare_domain("new", "year", Domain::range(2020, 2029));
+    decades.insert("old", Relation::new(["year", "a"]).rows([[1991, 1]]));
+    decades.insert("new", Relation::new(["year", "b"]).rows([[2021, 1]]));
+    let old = decades.get("old").unwrap();
+    let new = decades.get("new").unwrap();
+    println!(
+        "old and new years estimated to share {} rows",
+        decades.estimate(&[(Some("old"), old), (Some("new"), new)])
+    );
+
+    // A fact table with all its dimensions joined on in one call. Both
+    // dimensions have a `name`, and a city hangs off its store.
+    let stores = Relation::new(["store", "name", "city"]).rows([[1, 10, 100], [2, 20, 200]]);
+    let cities = Relation::new(["city", "name"]).rows([[100, 7], [200, 8]]);
+    let receipts = Relation::new(["receipt", "store"]).rows([[1, 1], [2, 2], [3, 9]]);
+    denormalize(&receipts, &[("city", &cities), ("store", &stores)]).print();
+
+    // A Parquet file from `--parquet PATH`, all of it and then just two of
+    // its columns.
+    #[cfg(feature = "parquet")]
+    {
+        let mut args = std::env::args().skip_while(|a| a != "--parquet").skip(1);
+        if let Some(path) = args.next() {
+            let rel = match Relation::from_parquet(&path) {
+                Ok(rel) => rel,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    std::process::exit(1);
+                }
+            };
+            rel.print();
+            let cols: Vec<&str> = rel
+                .col_names
+                .iter()
+                .rev()
+                .take(2)
+                .map(|c| c.as_str())
+                .collect();
+            Relation::from_parquet_columns(&path, &cols)
+                .unwrap()
+                .print();
+        }
+    }
+
+    // A relation handed to Arrow and back, as another library would take
+    // and give the raw structs.
+    #[cfg(feature = "arrow")]
+    {
+        let mixed = Relation::new(["id", "score", "label", "flag", "nothing"])
+            .row([
+                Value::Int(1),
+                Value::Int(3),
+                Value::Str("a".into()),
+                Value::Bool(true),
+                Value::Null,
+            ])
+            .row([
+                Value::Int(2),
+                Value::Float(2.5),
+                Value::Null,
+                Value::Bool(false),
+                Value::Null,
+            ])
+            .row([
+                Value::Null,
+                Value::Null,
+                Value::Int(7),
+                Value::Null,
+                Value::Null,
+            ]);
+        let (array, schema) = mixed.to_arrow().into_raw();
+        let batch = unsafe { arrow::RecordBatch::from_raw(array, schema) };
+        Relation::from_arrow(batch).unwrap().print();
+    }
+
+    // A file big enough to be parsed on several threads, which reads the
+    // same as it does on one.
+    let big = std::env::temp_dir().join("nbjoiner_big.csv");
+    let mut contents = String::from("id,group,score\n");
+    for i in 0..100_000 {
+        contents.push_str(&format!("{},{},{}.5\n", i, i % 7, i % 100));
+    }
+    std::fs::write(&big, &contents).unwrap();
+    let parallel = Relation::load_csv(&big).unwrap();
+    assert_eq!(parallel, Relation::load_csv_with_threads(&big, 1).unwrap());
+    println!(
+        "{} rows from {} bytes, last {:?}",
+        parallel.data.len(),
+        contents.len(),
+        parallel.data.last()
+    );
+    std::fs::write(&big, contents.replace("9999,", "9999,,")).unwrap();
+    println!("{}", Relation::load_csv_with_threads(&big, 4).unwrap_err());
+    std::fs::remove_file(&big).unwrap();
+
+    // Hundreds of tiny part files loaded into a few full chunks.
+    let parts = std::env::temp_dir().join("nbjoiner_parts");
+    std::fs::create_dir_all(&parts).unwrap();
+    for i in 0..300 {
+        let rows: String = (0..10).map(|j| format!("{},{}\n", i * 10 + j, j)).collect();
+        std::fs::write(
+            parts.join(format!("part-{:05}.csv", i)),
+            format!("id,n\n{}", rows),
+        )
+        .unwrap();
+    }
+    let compacted = chunked::Chunked::load_dir(&parts, SchemaEvolution::default(), 1000).unwrap();
+    println!(
+        "{} rows from 300 files in {} chunks, {} in range",
+        compacted.len(),
+        compacted.chunks().len(),
+        compacted.scan(&[("id", 2995, 3005)]).unwrap().data.len()
+    );
+    std::fs::remove_dir_all(&parts).unwrap();
+
+    // The same kind of query written as SQL.
+    let mut shop = Catalog::new();
+    shop.insert(
+        "users",
+        Relation::new(["user", "city"]).rows([[1, 10], [2, 20], [3, 10]]),
+    );
+    shop.insert(
+        "purchases",
+        Relation::new(["user", "item", "price"]).rows([[1, 100, 5], [1, 101, 12], [2, 100, 5]]),
+    );
+    shop.insert(
+        "items",
+        Relation::new(["item", "kind"]).rows([[100, 1], [101, 2]]),
+    );
+    sql::parse(
+        "SELECT user, item, price FROM users JOIN purchases USING (user) NATURAL JOIN items
+         WHERE city = 10 AND (price * 2 > 20 OR kind <> 2) /*+ leading(items) */",
+    )
+    .unwrap()
+    .run(&mut shop)
+    .unwrap()
+    .print();
+    sql::parse("select * from users left join purchases")
+        .unwrap()
+        .run(&mut shop)
+        .unwrap()
+        .print();
+    for bad in [
+        "SELECT * FROM users JOIN purchases USING (city)",
+        "SELECT * FROM users WHERE city = ",
+        "SELECT * FROM users LEFT JOIN purchases JOIN items",
+        "SELECT cty FROM users",
+        "SELECT * FROM users WHERE city + 'x' > 1",
+    ] {
+        match sql::parse(bad) {
+            Ok(select) => println!("{}", select.run(&mut shop).unwrap_err()),
+            Err(e) => println!("{}", e),
+        }
+    }
+
+    // A quick look at an input before joining it.
+    Relation::new(["id", "tier", "spend"])
+        .row([Value::Int(1), Value::Str("gold".into()), Value::Float(10.5)])
+        .row([Value::Int(2), Value::Str("gold".into()), Value::Int(30)])
+        .row([Value::Int(3), Value::Null, Value::Float(7.0)])
+        .row([Value::Int(4), Value::Str("basic".into()), Value::Null])
+        .describe()
+        .print();
+
+    // How selective joining each pair of the shop's relations is.
+    print!("{}", shop.selectivities());
+
+    // Correcting the query graph by hand: (x, y) and (y, z) share y, but
+    // forbidding that join and forcing one between (x, y) and (z, w) changes
+    // the order, and merging (v) into (z, w) keeps it next to (z, w).
+    let edited = Planner::default()
+        .join(Relation::new(["x", "y"]).row([1, 2]))
+        .join(Relation::new(["y", "z"]).row([2, 3]))
+        .join(Relation::new(["z", "w"]).row([3, 4]))
+        .join(Relation::new(["v"]).row([5]))
+        .remove_edge(0, 1)
+        .add_edge(0, 2)
+        .merge_vertices(2, 3);
+    println!("edited order: {:?}", edited.order());
+    print!("{}", edited.explain());
+
+    // The edited graph as DOT, to paste into Graphviz.
+    print!("{}", edited.query_graph_dot());
+    print!("{}", graph.to_dot());
+
+    // A join whose inputs don't fit in the memory limit fails, but a
+    // scheduler that retries with spilling gets it through a partition at a
+    // time.
+    let tight = || {
+        Query::new()
+            .join(Relation::new(["a", "b"]).rows((0..1000).map(|i| vec![i, i])))
+            .join(Relation::new(["b", "c"]).rows((0..1000).map(|i| vec![i, i])))
+            .limits(Limits {
+                max_memory: Some(150_000),
+                ..Limits::default()
+            })
+    };
+    println!("{}", tight().execute().unwrap_err());
+    let retrying =
+        Scheduler::new(1, 1 << 20).retry_spilling(std::env::temp_dir().join("nbjoiner_retry"), 8);
+    println!(
+        "{} rows after retrying",
+        retrying.submit(tight(), 0).wait().unwrap().data.len()
+    );
+
+    // Going by row counts, the planner starts from the small end of a chain
+    // rather than wherever the first relation it was given is.
+    let chain = Planner::default()
+        .join(Relation::new(["a", "b"]).rows((0..1000).map(|i| vec![i, i % 100])))
+        .join(Relation::new(["b", "c"]).rows((0..100).map(|i| vec![i, i % 10])))
+        .join(Relation::new(["c", "d"]).rows((0..2).map(|i| vec![i, i])));
+    println!(
+        "graph order {:?}, cost-based order {:?}",
+        chain.order(),
+        chain.cost_based_order()
+    );
+    print!("{}", chain.explain());
+
+    // Errors as JSON diagnostics, for a program to act on rather than a
+    // person to read.
+    let mut out = std::io::stdout();
+    for bad in [
+        "SELECT * FROM users JOIN purchases USING (city)",
+        "SELECT * FROM users WHERE city = ",
+    ] {
+        let e = sql::parse(bad)
+            .and_then(|select| select.run(&mut shop))
+            .unwrap_err();
+        e.diagnostic().to_json(&mut out).unwrap();
+    }
+    tight()
+        .execute()
+        .unwrap_err()
+        .diagnostic()
+        .to_json(&mut out)
+        .unwrap();
+    Relation::builder()
+        .column("a", [1, 2])
+        .column("a", [3, 4])
+        .build()
+        .unwrap_err()
+        .diagnostic()
+        .to_json(&mut out)
+        .unwrap();
+    stays
+        .try_sort_by(&["nights sideways"])
+        .unwrap_err()
+        .diagnostic()
+        .to_json(&mut out)
+        .unwrap();
+
+    // Audited queries leave a row each in a log that can itself be queried.
+    shop.execute_audited(
+        "ana",
+        Query::new().join_named("users").join_named("purchases"),
+    )
+    .unwrap();
+    shop.execute_audited("bo", Query::new().join_named("nope"))
+        .unwrap_err();
+    Query::new()
+        .join_named(audit::AUDIT_LOG)
+        .select(["user", "plan", "rows", "error"])
+        .execute_with(&mut shop)
+        .unwrap()
+        .print();
+
+    // Greedy ordering commits to whichever join is smallest next even when
+    // that makes later ones bigger, where trying every order finds the
+    // cheapest in total.
+    let total = |planner: &Planner, order: &[usize]| -> f64 {
+        (1..=order.len())
+            .map(|i| planner.estimate(&order[..i]))
+            .sum()
+    };
+    let chain = Planner::default()
+        .join(Relation::new(["a", "b"]).rows((0..200).map(|i| vec![i, i])))
+        .join(Relation::new(["b", "c"]).rows((0..200).map(|i| vec![i, i])))
+        .join(Relation::new(["c", "d"]).rows((0..2).map(|i| vec![i, i])))
+        .join(Relation::new(["d", "e"]).rows((0..1000).map(|i| vec![i, i])))
+        .join(Relation::new(["e"]).rows((0..50).map(|i| vec![i])));
+    let greedy = chain.greedy_order(|rels| chain.estimate(rels));
+    let dp = chain.dp_order(|rels| chain.estimate(rels));
+    println!(
+        "greedy {:?} costs {:.0}, dp {:?} costs {:.0}",
+        greedy,
+        total(&chain, &greedy),
+        dp,
+        total(&chain, &dp)
+    );
+
+    // Letting either side of a join be a join itself can beat the best
+    // left-deep order, here by joining each end of the chain on its own.
+    // The estimates are the real sizes, from doing the joins.
+    let ends = Planner::default()
+        .join(Relation::new(["a", "b"]).rows((0..1000).map(|i| vec![i, i % 100])))
+        .join(Relation::new(["b", "c"]).rows((0..10).map(|i| vec![i, i % 2])))
+        .join(Relation::new(["c", "d"]).rows((0..10).map(|i| vec![i % 2, i])))
+        .join(Relation::new(["d", "e"]).rows((0..1000).map(|i| vec![i % 100, i])));
+    let actual = |rels: &[usize]| {
+        rels.iter()
+            .map(|i| ends.joined_tables[*i].clone())
+            .reduce(|result, next| result.join(&next))
+            .map_or(0.0, |result| result.data.len() as f64)
+    };
+    let total = |tree: &JoinTree| -> f64 {
+        fn sets(tree: &JoinTree, out: &mut Vec<Vec<usize>>) {
+            if let JoinTree::Join(left, right) = tree {
+                sets(left, out);
+                sets(right, out);
+                out.push(tree.relations());
+            }
+        }
+        let mut joins = vec![];
+        sets(tree, &mut joins);
+        joins.iter().map(|rels| actual(rels)).sum()
+    };
+    let bushy = ends.bushy_order(actual);
+    let left_deep =
+        JoinTree::left_deep(&ends.dp_order(
+            |rels| {
+                if rels.len() == 1 {
+                    0.0
+                } else {
+                    actual(rels)
+                }
+            },
+        ));
+    println!(
+        "{} connected sets, bushy {} makes {} rows, left-deep {} makes {}",
+        ends.query_graph.connected_subgraphs(4).len(),
+        bushy,
+        total(&bushy),
+        left_deep,
+        total(&left_deep)
+    );
+    // A query planned the same way, by row counts alone.
+    let rows = ends
+        .joined_tables
+        .iter()
+        .fold(Query::new(), |query, rel| query.join(rel.clone()))
+        .strategy(Strategy::Bushy)
+        .execute()
+        .unwrap()
+        .data
+        .len();
+    println!(
+        "{} rows, and by row counts alone {}",
+        rows,
+        ends.bushy_plan()
+    );
+
+    // Settings from `--config PATH`, or else from a file written here.
+    // Getting SIGHUP, or calling `reload`, reads the file again.
+    let config_path = match &config_path {
+        Some(path) => path.clone(),
+        None => {
+            let path = std::env::temp_dir().join("nbjoiner.conf");
+            std::fs::write(
+                &path,
+                "threads = 2\nstrategy = dp\nmax_output_rows = 2 # short\n",
+            )
+            .unwrap();
+            path
+        }
+    };
+    let config = match config::Reloading::new(&config_path) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    let mut scheduler = config.get().scheduler();
+    let configured = |scheduler: &Scheduler| {
+        scheduler
+            .submit(
+                Query::new()
+                    .join(Relation::new(["a", "b"]).rows((0..1000).map(|i| vec![i, i])))
+                    .join(Relation::new(["b", "c"]).rows((0..1000).map(|i| vec![i, i])))
+                    .configure(&config.get()),
+                0,
+            )
+            .wait()
+    };
+    match configured(&scheduler) {
+        Ok(rel) => println!("{} rows", rel.data.len()),
+        Err(e) => println!("{}", e),
+    }
+    if args.iter().all(|a| a != "--config") {
+        std::fs::write(&config_path, "threads = 1\nmax_output_rows = 5000\n").unwrap();
+        config.reload().unwrap();
+        config.get().apply(&mut scheduler);
+        println!(
+            "{} rows after reloading",
+            configured(&scheduler).unwrap().data.len()
+        );
+    }
+
+    // With an empty input none of the joins are done, and the result still
+    // has every column.
+    shop.insert("returns", Relation::new(["item", "reason"]));
+    let none = Query::new()
+        .join_named("users")
+        .join_named("purchases")
+        .join_named("returns")
+        .execute_with_stats(Some(&mut shop))
+        .unwrap();
+    println!(
+        "{:?} with {} rows, joined {:?}",
+        none.0.col_names,
+        none.0.data.len(),
+        none.1
+            .plan()
+            .steps
+            .iter()
+            .map(|s| &s.input)
+            .collect::<Vec<_>>()
+    );
+    print!(
+        "{}",
+        Planner::default()
+            .join(Relation::new(["a", "b"]).rows((0..100).map(|i| vec![i, i])))
+            .join(Relation::new(["b", "c"]))
+            .explain()
+    );
+
+    // Too many relations to try every set of, so the bushy plan for this
+    // chain of 60 merges the cheapest pair of trees at a time instead, and
+    // so does a query over it that's left to `Dp`.
+    let long = (0..60).fold(Planner::default(), |planner, i| {
+        planner.join(Relation::new_with_data(
+            [format!("c{}", i), format!("c{}", i + 1)],
+            (0..10).map(|j| vec![Value::Int(j), Value::Int((j + i) % 10)]),
+        ))
+    });
+    let tree = long.bushy_plan();
+    println!(
+        "{} relations in {:.60}..., {} rows",
+        tree.relations().len(),
+        tree.to_string(),
+        long.joined_tables
+            .iter()
+            .fold(Query::new(), |query, rel| query.join(rel.clone()))
+            .execute()
+            .unwrap()
+            .data
+            .len()
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cost_based_order_stays_connected_with_an_empty_input() {
+        let planner = Planner::default()
+            .join(Relation::new(["x", "y"]).rows([[1, 2], [3, 4]]))
+            .join(Relation::new(["y", "z"]).rows([[2, 5], [4, 6]]))
+            .join(Relation::new(["z", "w"]));
+        let order = planner.cost_based_order();
+        assert_eq!(order.len(), 3);
+        for (n, i) in order.iter().enumerate().skip(1) {
+            let neighbours = planner.query_graph.neighbours(*i);
+            assert!(
+                order[..n].iter().any(|j| neighbours.contains(j)),
+                "{order:?} joins {i} before anything it shares a column with"
+            );
+        }
+    }
+
+    #[test]
+    fn hash_indexes_join_through_the_kernel() {
+        let left = Relation::new(["k", "a"]).rows((0..40).map(|i| match i % 7 {
+            0 => [Value::Null, Value::from(i)],
+            _ => [Value::from(i % 5), Value::from(i)],
+        }));
+        let right = Relation::new(["k", "b"]).rows((0..100).map(|i| match i % 9 {
+            0 => [Value::Null, Value::from(i)],
+            _ => [Value::from(i % 4), Value::from(i)],
+        }));
+        let key = ["k".to_string()];
+        for nulls in [Nulls::Distinct, Nulls::Equal] {
+            let mut expected = right
+                .kernel_join(&left, nulls)
+                .project(["k", "a", "b"])
+                .data;
+            expected.sort();
+            for hashing in [Hashing::Fast, Hashing::Random] {
+                let index = left.index_with(&key, hashing, nulls);
+                assert_eq!(index.keys(), if nulls == Nulls::Equal { 6 } else { 5 });
+                let mut joined = index.join(&right).project(["k", "a", "b"]).data;
+                joined.sort();
+                assert_eq!(joined, expected, "{nulls:?} {hashing:?}");
+            }
+            let reduced = left.reduce_by(&right, nulls);
+            let unmatched = left.without_matches(&right, nulls);
+            assert_eq!(reduced.data.len() + unmatched.data.len(), left.data.len());
+            assert_eq!(
+                reduced.data.iter().any(|row| row[0].is_null()),
+                nulls == Nulls::Equal
+            );
+        }
+    }
+
+    #[test]
+    fn nested_loops_order_rows_like_hash_joins() {
+        let left = Relation::new(["k", "a"]).rows((0..150).map(|i| [i % 3, i]));
+        let right = Relation::new(["k", "b"]).rows([[2, 0], [0, 1], [1, 2]]);
+        let key = ["k".to_string()];
+        assert!(left.data.len() > NESTED_LOOP_BLOCK);
+        let nested = left
+            .nested_loop_join(&right, |l, r| l[0] == r[0], |_| Ok::<_, Infallible>(()))
+            .unwrap();
+        assert_eq!(nested, left.index(&key).join(&right));
+        assert_eq!(
+            nested.data[0],
+            [Value::from(2), Value::from(2), Value::from(0)]
+        );
+    }
 }
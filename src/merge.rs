@@ -0,0 +1,52 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+use crate::value::Value;
+
+// Merges runs of rows that are each sorted on the columns at `key` into one
+// sorted stream, holding only the next row of each run at a time. Rows with
+// equal keys come out in the order of the runs they're from.
+pub struct Merge<I: Iterator<Item = Vec<Value>>> {
+    key: Vec<usize>,
+    runs: Vec<I>,
+    heads: BinaryHeap<Reverse<Head>>,
+}
+
+// The next row of an unfinished run, ordered by its key and then its run.
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+struct Head {
+    key: Vec<Value>,
+    run: usize,
+    row: Vec<Value>,
+}
+
+impl<I: Iterator<Item = Vec<Value>>> Merge<I> {
+    pub fn new(key: Vec<usize>, runs: impl IntoIterator<Item = I>) -> Self {
+        let mut merge = Self {
+            key,
+            runs: runs.into_iter().collect(),
+            heads: BinaryHeap::new(),
+        };
+        for run in 0..merge.runs.len() {
+            merge.advance(run);
+        }
+        merge
+    }
+
+    fn advance(&mut self, run: usize) {
+        if let Some(row) = self.runs[run].next() {
+            let key = self.key.iter().map(|k| row[*k].clone()).collect();
+            self.heads.push(Reverse(Head { key, run, row }));
+        }
+    }
+}
+
+impl<I: Iterator<Item = Vec<Value>>> Iterator for Merge<I> {
+    type Item = Vec<Value>;
+
+    fn next(&mut self) -> Option<Vec<Value>> {
+        let Reverse(Head { run, row, .. }) = self.heads.pop()?;
+        self.advance(run);
+        Some(row)
+    }
+}
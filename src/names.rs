@@ -0,0 +1,72 @@
+use crate::Relation;
+
+// Rewrites column names so that ones meaning the same thing come out equal,
+// like `UserId` and ` user_id`. Each step is off unless asked for.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Normalizer {
+    // Removes leading and trailing whitespace.
+    pub trim: bool,
+    // Lowercases the name.
+    pub case_fold: bool,
+    // Splits the name into words at case changes and anything that isn't a
+    // letter or digit, and joins them lowercased with underscores.
+    pub snake_case: bool,
+}
+
+impl Normalizer {
+    // Every step.
+    pub fn all() -> Self {
+        Self {
+            trim: true,
+            case_fold: true,
+            snake_case: true,
+        }
+    }
+
+    pub fn normalize(&self, name: &str) -> String {
+        let mut name = if self.trim { name.trim() } else { name }.to_string();
+        if self.snake_case {
+            name = snake_case(&name);
+        }
+        if self.case_fold {
+            name = name.to_lowercase();
+        }
+        name
+    }
+}
+
+fn snake_case(name: &str) -> String {
+    let chars: Vec<char> = name.chars().collect();
+    let mut words: Vec<String> = vec![String::new()];
+    for (i, c) in chars.iter().enumerate() {
+        if !c.is_alphanumeric() {
+            words.push(String::new());
+            continue;
+        }
+        // A word starts at an uppercase letter after a lowercase one or a
+        // digit, or at the last uppercase letter of a run followed by a
+        // lowercase one, as in `HTTPServer`.
+        let prev = i.checked_sub(1).map(|p| chars[p]);
+        let next = chars.get(i + 1);
+        if c.is_uppercase()
+            && (prev.is_some_and(|p| p.is_lowercase() || p.is_ascii_digit())
+                || (prev.is_some_and(|p| p.is_uppercase())
+                    && next.is_some_and(|n| n.is_lowercase())))
+        {
+            words.push(String::new());
+        }
+        words.last_mut().unwrap().extend(c.to_lowercase());
+    }
+    words.retain(|w| !w.is_empty());
+    words.join("_")
+}
+
+impl Relation {
+    // The same relation with every column renamed by `normalizer`.
+    pub fn normalize_names(mut self, normalizer: &Normalizer) -> Relation {
+        for c in self.col_names.iter_mut() {
+            *c = normalizer.normalize(c);
+        }
+        self
+    }
+}
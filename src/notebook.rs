@@ -0,0 +1,67 @@
+use std::fmt::Write;
+
+use crate::html::escape;
+use crate::plan::Plan;
+use crate::{Relation, DISPLAY_ROWS};
+
+// Rendering for Rust notebooks run by evcxr, which shows whatever a value's
+// `evcxr_display` prints between its markers as that type of content.
+fn show_html(html: &str) {
+    println!("EVCXR_BEGIN_CONTENT text/html\n{}\nEVCXR_END_CONTENT", html);
+}
+
+impl Relation {
+    // The same rows as `Display`, as an HTML table.
+    pub fn html_table(&self) -> String {
+        let order = self.display_order();
+        let mut html = String::from("<table>\n<tr>");
+        for (name, _) in order.iter() {
+            write!(html, "<th>{}</th>", escape(name)).unwrap();
+        }
+        html.push_str("</tr>\n");
+        for row in self.data.iter().take(DISPLAY_ROWS) {
+            html.push_str("<tr>");
+            for (_, i) in order.iter() {
+                write!(html, "<td>{}</td>", escape(&row[*i].to_string())).unwrap();
+            }
+            html.push_str("</tr>\n");
+        }
+        html.push_str("</table>");
+        if self.data.len() > DISPLAY_ROWS {
+            write!(
+                html,
+                "\n<p>({} more rows)</p>",
+                self.data.len() - DISPLAY_ROWS
+            )
+            .unwrap();
+        }
+        html
+    }
+
+    pub fn evcxr_display(&self) {
+        show_html(&self.html_table());
+    }
+}
+
+impl Plan {
+    // The tree `Display` draws, as nested lists.
+    pub fn html_tree(&self) -> String {
+        let Some((first, rest)) = self.steps.split_first() else {
+            return String::from("<ul></ul>");
+        };
+        let mut html = format!("<li>{}</li>", escape(&first.input));
+        for step in rest {
+            html = format!(
+                "<li>join on [{}]<ul>{}<li>{}</li></ul></li>",
+                escape(&step.key.join(", ")),
+                html,
+                escape(&step.input)
+            );
+        }
+        format!("<ul>{}</ul>", html)
+    }
+
+    pub fn evcxr_display(&self) {
+        show_html(&self.html_tree());
+    }
+}
@@ -0,0 +1,103 @@
+use std::fmt;
+
+use crate::hashing::Hashing;
+use crate::query::Outer;
+use crate::value::Nulls;
+use crate::Relation;
+
+// What joining relations with no columns in common does. Every row matches
+// every other, which is sometimes what's wanted, but is more often a sign
+// that a column was misnamed on one side.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum NoCommonColumns {
+    #[default]
+    Cross,
+    Error,
+}
+
+// Everything about how two relations are joined, other than which ones.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct JoinOptions {
+    pub hashing: Hashing,
+    pub nulls: Nulls,
+    pub no_common_columns: NoCommonColumns,
+    // Whether the result is a set, with each row once, rather than a bag
+    // with a row for every pair of rows that match.
+    pub distinct: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JoinError {
+    NoCommonColumns {
+        left: Vec<String>,
+        right: Vec<String>,
+    },
+}
+
+impl fmt::Display for JoinError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JoinError::NoCommonColumns { left, right } => write!(
+                f,
+                "no columns in common between {:?} and {:?}; use cross_join for a cross product",
+                left, right
+            ),
+        }
+    }
+}
+
+impl std::error::Error for JoinError {}
+
+impl Relation {
+    // Like `join`, as `options` says. A join of relations with no columns in
+    // common fails unless `options` allows cross products.
+    pub fn join_with_options(
+        &self,
+        other: &Relation,
+        options: &JoinOptions,
+    ) -> Result<Relation, JoinError> {
+        if self.common_cols(other).is_empty() {
+            match options.no_common_columns {
+                NoCommonColumns::Cross => return Ok(options.finish(self.cross_join(other))),
+                NoCommonColumns::Error => {
+                    return Err(JoinError::NoCommonColumns {
+                        left: self.col_names.clone(),
+                        right: other.col_names.clone(),
+                    })
+                }
+            }
+        }
+        let joined = self.try_join_with(other, options.hashing, options.nulls, |_| Ok(()))?;
+        Ok(options.finish(joined))
+    }
+
+    // Like `left_join`, `right_join` or `full_join`, as `options` says. With
+    // nulls equal, a row with a null key matches rows with a null in the
+    // same key columns, like `IS NOT DISTINCT FROM`, rather than being kept
+    // as unmatched.
+    pub fn outer_join_with_options(
+        &self,
+        other: &Relation,
+        outer: Outer,
+        options: &JoinOptions,
+    ) -> Result<Relation, JoinError> {
+        if self.common_cols(other).is_empty() && options.no_common_columns == NoCommonColumns::Error
+        {
+            return Err(JoinError::NoCommonColumns {
+                left: self.col_names.clone(),
+                right: other.col_names.clone(),
+            });
+        }
+        let (left, right) = outer.keeps();
+        Ok(options.finish(self.outer_join(other, options.nulls, left, right)))
+    }
+}
+
+impl JoinOptions {
+    fn finish(&self, joined: Relation) -> Relation {
+        match self.distinct {
+            true => joined.distinct(),
+            false => joined,
+        }
+    }
+}
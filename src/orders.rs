@@ -0,0 +1,133 @@
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "pretty")]
+use prettytable::{Cell, Row, Table};
+
+use crate::{Planner, Relation};
+
+// Enumerating orders joins every prefix of every permutation, so it's only
+// allowed for small queries.
+pub const MAX_RELATIONS: usize = 8;
+
+// How a single left-deep join order did when it was run.
+#[derive(Debug, Clone)]
+pub struct OrderReport {
+    pub order: Vec<usize>,
+    // The rows produced by each join along the order, ending with the
+    // output.
+    pub sizes: Vec<usize>,
+    // The total number of rows produced by all the joins.
+    pub cost: usize,
+    pub elapsed: Duration,
+    // Whether any of the joins had no columns in common.
+    pub cross_product: bool,
+}
+
+impl Planner {
+    // Runs every left-deep join order and returns them cheapest first. Orders
+    // sharing a prefix share the joins for it, and each is timed as the sum
+    // of the joins along it.
+    pub fn compare_orders(&self) -> Vec<OrderReport> {
+        let n = self.joined_tables.len();
+        assert!(
+            n <= MAX_RELATIONS,
+            "too many relations ({}) to try every order",
+            n
+        );
+        let mut reports = Vec::new();
+        for first in 0..n {
+            let rest: Vec<_> = (0..n).filter(|i| *i != first).collect();
+            let rel = &self.joined_tables[first];
+            let partial = OrderReport {
+                order: vec![first],
+                // A lone relation is its own output.
+                sizes: if n == 1 { vec![rel.data.len()] } else { vec![] },
+                cost: 0,
+                elapsed: Duration::ZERO,
+                cross_product: false,
+            };
+            self.extend_orders(rel, partial, &rest, &mut reports);
+        }
+        reports.sort_by_key(|r| (r.cost, r.elapsed));
+        reports
+    }
+
+    fn extend_orders(
+        &self,
+        result: &Relation,
+        partial: OrderReport,
+        remaining: &[usize],
+        reports: &mut Vec<OrderReport>,
+    ) {
+        if remaining.is_empty() {
+            reports.push(partial);
+            return;
+        }
+        for (i, next) in remaining.iter().enumerate() {
+            let rel = &self.joined_tables[*next];
+            let start = Instant::now();
+            let joined = result.join(rel);
+            let elapsed = start.elapsed();
+
+            let mut report = partial.clone();
+            report.order.push(*next);
+            report.sizes.push(joined.data.len());
+            report.cost += joined.data.len();
+            report.elapsed += elapsed;
+            report.cross_product |= result.common_cols(rel).is_empty();
+
+            let mut rest = remaining.to_vec();
+            rest.remove(i);
+            self.extend_orders(&joined, report, &rest, reports);
+        }
+    }
+
+    // Prints a table of every join order ranked by cost.
+    pub fn print_order_comparison(&self) {
+        let label = |i: usize| format!("{}({})", i, self.joined_tables[i].col_names.join(","));
+        let header = ["rank", "order", "rows per join", "cost", "time"];
+        let mut rows = Vec::new();
+        for (rank, report) in self.compare_orders().iter().enumerate() {
+            let order = report
+                .order
+                .iter()
+                .map(|i| label(*i))
+                .collect::<Vec<_>>()
+                .join(" ⋈ ");
+            let sizes = report
+                .sizes
+                .iter()
+                .map(|s| s.to_string())
+                .collect::<Vec<_>>()
+                .join(", ");
+            rows.push([
+                (rank + 1).to_string(),
+                order,
+                sizes,
+                format!(
+                    "{}{}",
+                    report.cost,
+                    if report.cross_product { " (cross)" } else { "" }
+                ),
+                format!("{:?}", report.elapsed),
+            ]);
+        }
+
+        #[cfg(feature = "pretty")]
+        {
+            let mut table = Table::new();
+            table.add_row(Row::new(header.iter().map(|h| Cell::new(h)).collect()));
+            for row in rows.iter() {
+                table.add_row(Row::new(row.iter().map(|c| Cell::new(c)).collect()));
+            }
+            table.printstd();
+        }
+        #[cfg(not(feature = "pretty"))]
+        {
+            println!("{}", header.join(" | "));
+            for row in rows.iter() {
+                println!("{}", row.join(" | "));
+            }
+        }
+    }
+}
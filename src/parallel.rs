@@ -0,0 +1,312 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::{thread, vec};
+
+use crate::merge::Merge;
+use crate::partition::PartitionedRelation;
+use crate::value::Value;
+use crate::{hash_key, Relation};
+
+// A hash join that partitions both inputs by join key and joins the
+// partitions on separate threads.
+//
+// Partitioning by hash alone lets a single hot key pile all of its rows
+// into one partition, so a sample of each input is checked for keys that
+// would make up more than one thread's share of the rows. Those keys are
+// salted: the side with more rows for the key has them spread over several
+// partitions, and the other side's rows for the key are copied into each of
+// those partitions. A key that's spread over every partition is
+// effectively broadcasting the smaller side.
+pub struct ParallelJoin {
+    threads: usize,
+    salts: HashMap<Vec<Value>, usize>,
+    deterministic: bool,
+}
+
+// How many rows of each input are looked at to find hot keys.
+const SAMPLE_SIZE: usize = 1000;
+
+// Which side of the join a hot key's rows are spread across.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Side {
+    Left,
+    Right,
+}
+
+// How a hot key is split: `spread`'s rows are dealt out over `buckets`
+// partitions and the other side's are copied into each of them.
+#[derive(Debug, Clone, Copy)]
+struct Salt {
+    spread: Side,
+    buckets: usize,
+}
+
+impl ParallelJoin {
+    pub fn new(threads: usize) -> Self {
+        Self {
+            threads: threads.max(1),
+            salts: HashMap::new(),
+            deterministic: false,
+        }
+    }
+
+    // Makes `join` give its rows in the same order every time, however
+    // many threads there are: the order a hash join on one thread gives,
+    // by the right side's row and then the left side's. Each partition's
+    // output is sorted by where its rows came from and the partitions are
+    // merged in that order, which costs a sort and a merge on top of the
+    // join.
+    pub fn deterministic(mut self) -> Self {
+        self.deterministic = true;
+        self
+    }
+
+    // Salts `key` into `buckets` partitions regardless of what the sample
+    // says about it.
+    pub fn salt(mut self, key: impl IntoIterator<Item = impl Into<Value>>, buckets: usize) -> Self {
+        self.salts
+            .insert(key.into_iter().map(Into::into).collect(), buckets.max(1));
+        self
+    }
+
+    pub fn join(&self, left: &Relation, right: &Relation) -> Relation {
+        if self.deterministic {
+            return self.join_in_order(left, right);
+        }
+        let mut outputs = self.join_parts(left, right, |_| {}).into_iter();
+        let mut result = outputs.next().unwrap();
+        for out in outputs {
+            result.data.extend(out.data);
+        }
+        result
+    }
+
+    // Joins `left` and `right` with the output sorted on `order_by`. Each
+    // thread sorts its own partition's output, and the sorted partitions are
+    // merged as the rows are read rather than gathered and sorted again.
+    pub fn join_sorted(
+        &self,
+        left: &Relation,
+        right: &Relation,
+        order_by: &[&str],
+    ) -> (Vec<String>, Merge<vec::IntoIter<Vec<Value>>>) {
+        let col_names = Relation::new(left.col_names.iter().cloned())
+            .join(&Relation::new(right.col_names.iter().cloned()))
+            .col_names;
+        let order_by: Vec<_> = order_by.iter().map(|c| c.to_string()).collect();
+        let key = Relation::new(col_names.iter().cloned()).positions(&order_by);
+        let outputs = self.join_parts(left, right, |out| {
+            out.data
+                .sort_by(|a, b| key.iter().map(|k| &a[*k]).cmp(key.iter().map(|k| &b[*k])))
+        });
+        let runs = outputs.into_iter().map(|out| out.data.into_iter());
+        (col_names, Merge::new(key, runs))
+    }
+
+    fn join_in_order(&self, left: &Relation, right: &Relation) -> Relation {
+        let mut names = ["left_row".to_string(), "right_row".to_string()];
+        for name in names.iter_mut() {
+            while left.col_names.contains(name) || right.col_names.contains(name) {
+                name.insert(0, '_');
+            }
+        }
+        let with_positions = |rel: &Relation, name: &String| {
+            Relation::new_with_data(
+                rel.col_names.iter().chain([name]).cloned(),
+                rel.data
+                    .iter()
+                    .enumerate()
+                    .map(|(i, row)| {
+                        let mut row = row.clone();
+                        row.push(Value::Int(i as i64));
+                        row
+                    })
+                    .collect::<Vec<_>>(),
+            )
+        };
+        let (col_names, rows) = self.join_sorted(
+            &with_positions(left, &names[0]),
+            &with_positions(right, &names[1]),
+            &[&names[1], &names[0]],
+        );
+        let kept: Vec<usize> = (0..col_names.len())
+            .filter(|c| !names.contains(&col_names[*c]))
+            .collect();
+        Relation::new_with_data(
+            kept.iter().map(|c| col_names[*c].clone()),
+            rows.map(|row| kept.iter().map(|c| row[*c].clone()).collect::<Vec<_>>())
+                .collect::<Vec<_>>(),
+        )
+    }
+
+    // Joins each pair of partitions on its own thread, running `finish` on
+    // each output on the same thread.
+    fn join_parts(
+        &self,
+        left: &Relation,
+        right: &Relation,
+        finish: impl Fn(&mut Relation) + Sync,
+    ) -> Vec<Relation> {
+        let common_cols = left.common_cols(right);
+        let left_key = left.positions(&common_cols);
+        let right_key = right.positions(&common_cols);
+
+        let salts = self.salts_for(left, &left_key, right, &right_key);
+        let left_parts = self.partition(left, &left_key, &salts, Side::Left);
+        let right_parts = self.partition(right, &right_key, &salts, Side::Right);
+
+        let finish = &finish;
+        thread::scope(|s| {
+            let handles: Vec<_> = left_parts
+                .iter()
+                .zip(right_parts.iter())
+                .map(|(l, r)| {
+                    s.spawn(move || {
+                        let mut out = l.join(r);
+                        finish(&mut out);
+                        out
+                    })
+                })
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        })
+    }
+
+    // Joins a partitioned relation one partition at a time, with the
+    // partitions handed out to the threads as they become free. Partitions
+    // that can't match anything in `right` are skipped entirely.
+    pub fn join_partitioned(&self, left: &PartitionedRelation, right: &Relation) -> Relation {
+        let pairs = left.join_pairs(right);
+        let next = AtomicUsize::new(0);
+        let mut outputs: Vec<_> = thread::scope(|s| {
+            let handles: Vec<_> = (0..self.threads.min(pairs.len()))
+                .map(|_| {
+                    s.spawn(|| {
+                        let mut outputs = Vec::new();
+                        loop {
+                            let i = next.fetch_add(1, Ordering::Relaxed);
+                            let Some((part, other)) = pairs.get(i) else {
+                                return outputs;
+                            };
+                            outputs.push((i, part.join(other)));
+                        }
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .flat_map(|h| h.join().unwrap())
+                .collect()
+        });
+        outputs.sort_by_key(|(i, _)| *i);
+
+        let mut result = left.join_schema(right);
+        for (_, out) in outputs {
+            result.data.extend(out.data);
+        }
+        result
+    }
+
+    // Works out which keys to salt, from the sample and from any keys that
+    // were salted by hand.
+    fn salts_for(
+        &self,
+        left: &Relation,
+        left_key: &[usize],
+        right: &Relation,
+        right_key: &[usize],
+    ) -> HashMap<Vec<Value>, Salt> {
+        if self.threads == 1 {
+            return HashMap::new();
+        }
+        let left_counts = self.sample(left, left_key);
+        let right_counts = self.sample(right, right_key);
+
+        // The estimated number of rows with `key`, as a number of threads'
+        // worth of rows.
+        let shares = |counts: &HashMap<Vec<Value>, usize>, rel: &Relation, key: &Vec<Value>| {
+            let sampled = rel.data.len().clamp(1, SAMPLE_SIZE);
+            counts.get(key).copied().unwrap_or(0) as f64 / sampled as f64 * self.threads as f64
+        };
+        let salt = |key: &Vec<Value>, buckets: usize| {
+            let spread = if shares(&left_counts, left, key) >= shares(&right_counts, right, key) {
+                Side::Left
+            } else {
+                Side::Right
+            };
+            Salt {
+                spread,
+                buckets: buckets.min(self.threads),
+            }
+        };
+
+        let candidates: HashSet<&Vec<Value>> =
+            left_counts.keys().chain(right_counts.keys()).collect();
+        let mut salts: HashMap<_, _> = candidates
+            .into_iter()
+            .filter_map(|k| {
+                let shares = shares(&left_counts, left, k).max(shares(&right_counts, right, k));
+                if shares > 1.0 {
+                    Some((k.clone(), salt(k, shares.ceil() as usize)))
+                } else {
+                    None
+                }
+            })
+            .collect();
+        for (key, buckets) in self.salts.iter() {
+            salts.insert(key.clone(), salt(key, *buckets));
+        }
+        salts
+    }
+
+    // Counts the keys in an evenly spaced sample of the rows.
+    fn sample(&self, rel: &Relation, key: &[usize]) -> HashMap<Vec<Value>, usize> {
+        let step = (rel.data.len() / SAMPLE_SIZE).max(1);
+        let mut counts = HashMap::new();
+        for row in rel.data.iter().step_by(step).take(SAMPLE_SIZE) {
+            *counts
+                .entry(key.iter().map(|i| row[*i].clone()).collect())
+                .or_insert(0) += 1;
+        }
+        counts
+    }
+
+    fn partition(
+        &self,
+        rel: &Relation,
+        key: &[usize],
+        salts: &HashMap<Vec<Value>, Salt>,
+        side: Side,
+    ) -> Vec<Relation> {
+        let mut parts: Vec<_> = (0..self.threads)
+            .map(|_| Relation::new(rel.col_names.iter().cloned()))
+            .collect();
+        let mut next = HashMap::new();
+        for row in rel.data.iter() {
+            let home = hash_key(row, key) as usize % self.threads;
+            let salt = if salts.is_empty() {
+                None
+            } else {
+                salts.get_key_value(&key.iter().map(|i| row[*i].clone()).collect::<Vec<_>>())
+            };
+            // A salted key's partitions are the `buckets` partitions starting
+            // at the one it would have gone to anyway.
+            match salt {
+                None => parts[home].data.push(row.clone()),
+                Some((k, salt)) if salt.spread == side => {
+                    let bucket = next.entry(k).or_insert(0);
+                    parts[(home + *bucket) % self.threads]
+                        .data
+                        .push(row.clone());
+                    *bucket = (*bucket + 1) % salt.buckets;
+                }
+                Some((_, salt)) => {
+                    for bucket in 0..salt.buckets {
+                        parts[(home + bucket) % self.threads].data.push(row.clone());
+                    }
+                }
+            }
+        }
+        parts
+    }
+}
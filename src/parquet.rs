@@ -0,0 +1,917 @@
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use crate::value::Value;
+use crate::Relation;
+
+// Reading Parquet files without depending on a Parquet library. It covers
+// what writers produce for flat tables by default: a column per field, none
+// of them nested or repeated, in data pages of either version whose values
+// are plain or dictionary encoded, and that are uncompressed or compressed
+// with Snappy, and that aren't decimals, timestamps or unsigned 64-bit ints,
+// which don't have a value of their own here. Anything else, including
+// metadata that doesn't add up, is an `InvalidData` error rather than a
+// guess or a panic.
+
+const MAGIC: &[u8] = b"PAR1";
+
+impl Relation {
+    // Reads every column of a Parquet file.
+    pub fn from_parquet(path: impl AsRef<Path>) -> io::Result<Relation> {
+        read(path.as_ref(), None)
+    }
+
+    // Reads only `cols` of a Parquet file, in that order. The other columns'
+    // pages are never read from the file.
+    pub fn from_parquet_columns(path: impl AsRef<Path>, cols: &[&str]) -> io::Result<Relation> {
+        read(path.as_ref(), Some(cols))
+    }
+}
+
+fn read(path: &Path, cols: Option<&[&str]>) -> io::Result<Relation> {
+    let mut file = File::open(path)?;
+    read_file(&mut file, cols).map_err(|e| match e.kind() {
+        io::ErrorKind::InvalidData => invalid(format!("{}: {}", path.display(), e)),
+        _ => e,
+    })
+}
+
+fn read_file(file: &mut File, cols: Option<&[&str]>) -> io::Result<Relation> {
+    let len = file.seek(SeekFrom::End(0))?;
+    if len < 12 {
+        return Err(invalid("too short to be a Parquet file".to_string()));
+    }
+    let mut tail = [0; 8];
+    file.seek(SeekFrom::End(-8))?;
+    file.read_exact(&mut tail)?;
+    if &tail[4..] != MAGIC {
+        return Err(invalid("not a Parquet file".to_string()));
+    }
+    let footer_len = u32::from_le_bytes(tail[..4].try_into().unwrap()) as u64;
+    if footer_len + 12 > len {
+        return Err(invalid("footer is longer than the file".to_string()));
+    }
+    let mut footer = vec![0; footer_len as usize];
+    file.seek(SeekFrom::Start(len - 8 - footer_len))?;
+    file.read_exact(&mut footer)?;
+    let meta = Thrift::parse_struct(&mut &footer[..])?;
+
+    let schema = columns(meta.list(2)?)?;
+    let wanted: Vec<usize> = match cols {
+        None => (0..schema.len()).collect(),
+        Some(cols) => cols
+            .iter()
+            .map(|c| {
+                schema
+                    .iter()
+                    .position(|s| s.name == *c)
+                    .ok_or_else(|| invalid(format!("no column {:?}", c)))
+            })
+            .collect::<io::Result<_>>()?,
+    };
+
+    let mut data: Vec<Vec<Value>> = Vec::new();
+    for group in meta.list(4)? {
+        let rows = group.size(3)?;
+        let chunks = group.list(1)?;
+        let mut group_cols = Vec::with_capacity(wanted.len());
+        for i in wanted.iter() {
+            let chunk = chunks
+                .get(*i)
+                .ok_or_else(|| invalid(format!("row group has no chunk for column {}", i)))?
+                .field(3)?;
+            let values = read_chunk(file, len, chunk, &schema[*i])?;
+            if values.len() != rows {
+                return Err(invalid(format!(
+                    "column {} has {} values for {} rows",
+                    schema[*i].name,
+                    values.len(),
+                    rows
+                )));
+            }
+            group_cols.push(values.into_iter());
+        }
+        for _ in 0..rows {
+            data.push(group_cols.iter_mut().map(|c| c.next().unwrap()).collect());
+        }
+    }
+    Ok(Relation::new_with_data(
+        wanted.iter().map(|i| schema[*i].name.clone()),
+        data,
+    ))
+}
+
+// A leaf column of the schema.
+struct Column {
+    name: String,
+    ty: i64,
+    type_length: usize,
+    optional: bool,
+    // Its ints are unsigned, so they're read as such.
+    unsigned: bool,
+}
+
+// The schema is the tree of its elements in depth-first order, under a root
+// whose children are the table's columns.
+fn columns(elements: &[Thrift]) -> io::Result<Vec<Column>> {
+    let Some((_, fields)) = elements.split_first() else {
+        return Err(invalid("the schema is empty".to_string()));
+    };
+    fields
+        .iter()
+        .map(|e| {
+            let name = String::from_utf8_lossy(e.binary(4)?).into_owned();
+            if e.get(5).is_some() {
+                return Err(invalid(format!("column {:?} is nested", name)));
+            }
+            let optional = match e.get(3).map(|r| r.as_int()).transpose()? {
+                None | Some(0) => false,
+                Some(1) => true,
+                Some(_) => return Err(invalid(format!("column {:?} is repeated", name))),
+            };
+            // The converted type, and the logical type that replaced it,
+            // which writers set either or both of.
+            let converted = e.get(6).map(|c| c.as_int()).transpose()?;
+            let logical = e.get(10).and_then(|l| match l {
+                Thrift::Struct(fields) => fields.first(),
+                _ => None,
+            });
+            // Whether an int logical type is unsigned, and how wide it is.
+            let int = match logical {
+                Some((10, int)) => Some((
+                    !int.get(2).is_some_and(|s| s.as_bool()),
+                    int.get(1).map(|w| w.as_int()).transpose()?,
+                )),
+                _ => None,
+            };
+            let unsupported = match (converted, logical, int) {
+                (Some(5), _, _) | (_, Some((5, _)), _) => Some("a decimal"),
+                (Some(9 | 10), _, _) | (_, Some((8, _)), _) => Some("a timestamp"),
+                (Some(14), _, _) | (_, _, Some((true, Some(64)))) => Some("an unsigned 64-bit int"),
+                _ => None,
+            };
+            if let Some(what) = unsupported {
+                return Err(invalid(format!("column {:?} is {}", name, what)));
+            }
+            let unsigned = matches!(converted, Some(11..=13)) || matches!(int, Some((true, _)));
+            Ok(Column {
+                name,
+                ty: e.int(1)?,
+                type_length: match e.get(2) {
+                    Some(_) => e.size(2)?,
+                    None => 0,
+                },
+                optional,
+                unsigned,
+            })
+        })
+        .collect()
+}
+
+// Reads the values of a column chunk from `file`, which is `len` bytes long.
+fn read_chunk(file: &mut File, len: u64, meta: &Thrift, col: &Column) -> io::Result<Vec<Value>> {
+    let codec = meta.int(4)?;
+    let num_values = meta.size(5)?;
+    let size = meta.size(7)?;
+    let data = meta.size(9)?;
+    let start = match meta.get(11).map(|o| o.as_int()).transpose()? {
+        Some(dict) if dict > 0 => (dict as usize).min(data),
+        _ => data,
+    };
+    if (start as u64).saturating_add(size as u64) > len {
+        return Err(invalid(format!(
+            "column {:?} runs past the end of the file",
+            col.name
+        )));
+    }
+    let mut bytes = vec![0; size];
+    file.seek(SeekFrom::Start(start as u64))?;
+    file.read_exact(&mut bytes)?;
+
+    let mut rest = &bytes[..];
+    let mut dictionary: Option<Vec<Value>> = None;
+    let mut values = Vec::with_capacity(num_values.min(size));
+    while values.len() < num_values && !rest.is_empty() {
+        let header = Thrift::parse_struct(&mut rest)?;
+        let compressed = header.size(3)?;
+        if compressed > rest.len() {
+            return Err(invalid("page runs past its column chunk".to_string()));
+        }
+        let (page, after) = rest.split_at(compressed);
+        rest = after;
+        let uncompressed = header.size(2)?;
+        // Checked before anything is made for each of a page's values.
+        let check = |count: usize| match count > num_values - values.len() {
+            true => Err(invalid(format!(
+                "column {:?} has more values than its chunk says",
+                col.name
+            ))),
+            false => Ok(count),
+        };
+        match header.int(1)? {
+            // A dictionary page, whose values are plain encoded.
+            2 => {
+                let count = header.field(7)?.size(1)?;
+                let page = decompress(codec, page, uncompressed)?;
+                dictionary = Some(plain(&mut &page[..], col, count)?);
+            }
+            // A data page, with its levels compressed along with its values.
+            0 => {
+                let page_header = header.field(5)?;
+                let count = check(page_header.size(1)?)?;
+                let page = decompress(codec, page, uncompressed)?;
+                let mut page = &page[..];
+                let defined = match col.optional {
+                    true => {
+                        let len = u32::from_le_bytes(take(&mut page, 4)?.try_into().unwrap());
+                        let mut levels = take(&mut page, len as usize)?;
+                        hybrid(&mut levels, 1, count)?
+                    }
+                    false => vec![1; count],
+                };
+                let encoding = page_header.int(2)?;
+                decode_values(&mut page, encoding, &defined, col, &dictionary, &mut values)?;
+            }
+            // A version two data page, whose levels come first and are never
+            // compressed.
+            3 => {
+                let page_header = header.field(8)?;
+                let count = check(page_header.size(1)?)?;
+                let def_len = page_header.size(5)?;
+                let rep_len = page_header.size(6)?;
+                let mut page = page;
+                take(&mut page, rep_len)?;
+                let mut levels = take(&mut page, def_len)?;
+                let defined = match col.optional {
+                    true => hybrid(&mut levels, 1, count)?,
+                    false => vec![1; count],
+                };
+                let is_compressed = page_header.get(7).is_none_or(|c| c.as_bool());
+                let page = match is_compressed {
+                    true => {
+                        decompress(codec, page, uncompressed.saturating_sub(def_len + rep_len))?
+                    }
+                    false => page.to_vec(),
+                };
+                let encoding = page_header.int(4)?;
+                decode_values(
+                    &mut &page[..],
+                    encoding,
+                    &defined,
+                    col,
+                    &dictionary,
+                    &mut values,
+                )?;
+            }
+            _ => {}
+        }
+    }
+    Ok(values)
+}
+
+// Appends a value for each level in `defined`, which is null where the
+// level is zero and the next value of `page` where it's one.
+fn decode_values(
+    page: &mut &[u8],
+    encoding: i64,
+    defined: &[u32],
+    col: &Column,
+    dictionary: &Option<Vec<Value>>,
+    values: &mut Vec<Value>,
+) -> io::Result<()> {
+    let present = defined.iter().filter(|d| **d == 1).count();
+    let decoded = match encoding {
+        0 => plain(page, col, present)?,
+        2 | 8 => {
+            let dictionary = dictionary
+                .as_ref()
+                .ok_or_else(|| invalid(format!("column {:?} has no dictionary", col.name)))?;
+            let bit_width = take(page, 1)?[0] as u32;
+            hybrid(page, bit_width, present)?
+                .into_iter()
+                .map(|i| {
+                    dictionary.get(i as usize).cloned().ok_or_else(|| {
+                        invalid(format!("column {:?} has a bad dictionary index", col.name))
+                    })
+                })
+                .collect::<io::Result<_>>()?
+        }
+        e => {
+            return Err(invalid(format!(
+                "column {:?} has unsupported encoding {}",
+                col.name, e
+            )))
+        }
+    };
+    let mut decoded = decoded.into_iter();
+    values.extend(defined.iter().map(|d| match d {
+        1 => decoded.next().unwrap(),
+        _ => Value::Null,
+    }));
+    Ok(())
+}
+
+fn plain(bytes: &mut &[u8], col: &Column, count: usize) -> io::Result<Vec<Value>> {
+    let mut values = Vec::with_capacity(count.min(bytes.len()));
+    match col.ty {
+        // Booleans are packed a bit each, lowest first.
+        0 => {
+            let packed = take(bytes, count.div_ceil(8))?;
+            values.extend((0..count).map(|i| Value::Bool(packed[i / 8] >> (i % 8) & 1 == 1)));
+        }
+        1 => {
+            for _ in 0..count {
+                let v: [u8; 4] = take(bytes, 4)?.try_into().unwrap();
+                values.push(Value::Int(match col.unsigned {
+                    true => u32::from_le_bytes(v) as i64,
+                    false => i32::from_le_bytes(v) as i64,
+                }));
+            }
+        }
+        2 => {
+            for _ in 0..count {
+                values.push(Value::Int(i64::from_le_bytes(
+                    take(bytes, 8)?.try_into().unwrap(),
+                )));
+            }
+        }
+        4 => {
+            for _ in 0..count {
+                let v = f32::from_le_bytes(take(bytes, 4)?.try_into().unwrap());
+                values.push(Value::Float(v as f64));
+            }
+        }
+        5 => {
+            for _ in 0..count {
+                values.push(Value::Float(f64::from_le_bytes(
+                    take(bytes, 8)?.try_into().unwrap(),
+                )));
+            }
+        }
+        6 => {
+            for _ in 0..count {
+                let len = u32::from_le_bytes(take(bytes, 4)?.try_into().unwrap());
+                let v = take(bytes, len as usize)?;
+                values.push(Value::Str(String::from_utf8_lossy(v).into()));
+            }
+        }
+        7 => {
+            for _ in 0..count {
+                let v = take(bytes, col.type_length)?;
+                values.push(Value::Str(String::from_utf8_lossy(v).into()));
+            }
+        }
+        ty => {
+            return Err(invalid(format!(
+                "column {:?} has unsupported type {}",
+                col.name, ty
+            )))
+        }
+    }
+    Ok(values)
+}
+
+// Decodes `count` values of the hybrid of run length encoding and bit
+// packing that levels and dictionary indexes are stored in. The values are
+// at most 32 bits wide.
+fn hybrid(bytes: &mut &[u8], bit_width: u32, count: usize) -> io::Result<Vec<u32>> {
+    if bit_width > 32 {
+        return Err(invalid(format!("bit width {} is over 32", bit_width)));
+    }
+    let mut values = Vec::with_capacity(count.min(bytes.len() * 8));
+    let byte_width = bit_width.div_ceil(8) as usize;
+    while values.len() < count {
+        let header = varint(bytes)?;
+        if header & 1 == 0 {
+            let run = (header >> 1) as usize;
+            let mut value = [0; 4];
+            value[..byte_width].copy_from_slice(take(bytes, byte_width)?);
+            let value = u32::from_le_bytes(value);
+            values.extend(std::iter::repeat_n(value, run.min(count - values.len())));
+        } else {
+            let groups = (header >> 1) as usize;
+            let len = groups
+                .checked_mul(bit_width as usize)
+                .ok_or_else(|| invalid("bit packed run is too long".to_string()))?;
+            let packed = take(bytes, len)?;
+            let mask = (1u64 << bit_width) - 1;
+            for i in 0..(groups * 8).min(count - values.len()) {
+                let bit = i * bit_width as usize;
+                let mut word = 0u64;
+                for (j, b) in packed[bit / 8..].iter().take(5).enumerate() {
+                    word |= (*b as u64) << (8 * j);
+                }
+                values.push(((word >> (bit % 8)) & mask) as u32);
+            }
+        }
+    }
+    Ok(values)
+}
+
+fn decompress(codec: i64, page: &[u8], uncompressed: usize) -> io::Result<Vec<u8>> {
+    match codec {
+        0 => Ok(page.to_vec()),
+        1 => snappy(page, uncompressed),
+        c => Err(invalid(format!("unsupported compression codec {}", c))),
+    }
+}
+
+// Snappy is a length and then literal runs and copies of what's already
+// been decompressed, which can overlap what they're copying to.
+fn snappy(mut bytes: &[u8], uncompressed: usize) -> io::Result<Vec<u8>> {
+    let len = varint(&mut bytes)? as usize;
+    if len != uncompressed {
+        return Err(invalid("snappy page has the wrong length".to_string()));
+    }
+    // Each byte of input makes at most 64 of output.
+    let mut out = Vec::with_capacity(len.min(bytes.len().saturating_mul(64)));
+    while !bytes.is_empty() {
+        let tag = take(&mut bytes, 1)?[0];
+        let (len, offset) = match tag & 3 {
+            0 => {
+                let len = match tag >> 2 {
+                    n @ 0..60 => n as usize + 1,
+                    n => {
+                        let mut len = [0; 4];
+                        let width = n as usize - 59;
+                        len[..width].copy_from_slice(take(&mut bytes, width)?);
+                        u32::from_le_bytes(len) as usize + 1
+                    }
+                };
+                out.extend_from_slice(take(&mut bytes, len)?);
+                continue;
+            }
+            1 => (
+                ((tag >> 2) & 7) as usize + 4,
+                ((tag as usize >> 5) << 8) | take(&mut bytes, 1)?[0] as usize,
+            ),
+            2 => (
+                (tag >> 2) as usize + 1,
+                u16::from_le_bytes(take(&mut bytes, 2)?.try_into().unwrap()) as usize,
+            ),
+            _ => (
+                (tag >> 2) as usize + 1,
+                u32::from_le_bytes(take(&mut bytes, 4)?.try_into().unwrap()) as usize,
+            ),
+        };
+        if offset == 0 || offset > out.len() {
+            return Err(invalid("snappy copy from before the start".to_string()));
+        }
+        let from = out.len() - offset;
+        for i in 0..len {
+            out.push(out[from + i]);
+        }
+    }
+    if out.len() != len {
+        return Err(invalid("snappy page has the wrong length".to_string()));
+    }
+    Ok(out)
+}
+
+// How deeply Thrift structs and lists can nest. Parquet's own metadata
+// nests a handful deep; this only stops a corrupt footer from overflowing
+// the stack.
+const MAX_THRIFT_DEPTH: usize = 64;
+
+// A value of the Thrift compact protocol, which Parquet's metadata is in.
+// Structs are kept as their fields by id, so only the fields needed have to
+// be known.
+#[derive(Debug)]
+enum Thrift {
+    Bool(bool),
+    Int(i64),
+    Double,
+    Binary(Vec<u8>),
+    List(Vec<Thrift>),
+    Struct(Vec<(i16, Thrift)>),
+}
+
+impl Thrift {
+    fn parse_struct(bytes: &mut &[u8]) -> io::Result<Thrift> {
+        Thrift::parse_struct_at(bytes, 0)
+    }
+
+    // A struct nested `depth` structs, lists or maps deep.
+    fn parse_struct_at(bytes: &mut &[u8], depth: usize) -> io::Result<Thrift> {
+        let mut fields = Vec::new();
+        let mut id: i16 = 0;
+        loop {
+            let header = take(bytes, 1)?[0];
+            if header == 0 {
+                return Ok(Thrift::Struct(fields));
+            }
+            id = match header >> 4 {
+                0 => zigzag(varint(bytes)?) as i16,
+                delta => id + delta as i16,
+            };
+            let value = match header & 0xf {
+                1 => Thrift::Bool(true),
+                2 => Thrift::Bool(false),
+                ty => Thrift::parse(bytes, ty, depth + 1)?,
+            };
+            fields.push((id, value));
+        }
+    }
+
+    // A value of type `ty` in something nested `depth` deep.
+    fn parse(bytes: &mut &[u8], ty: u8, depth: usize) -> io::Result<Thrift> {
+        if depth == MAX_THRIFT_DEPTH {
+            return Err(invalid(format!(
+                "metadata is nested more than {} deep",
+                MAX_THRIFT_DEPTH
+            )));
+        }
+        Ok(match ty {
+            1 | 2 => Thrift::Bool(take(bytes, 1)?[0] == 1),
+            3 => Thrift::Int(take(bytes, 1)?[0] as i8 as i64),
+            4..=6 => Thrift::Int(zigzag(varint(bytes)?)),
+            7 => {
+                take(bytes, 8)?;
+                Thrift::Double
+            }
+            8 => {
+                let len = varint(bytes)? as usize;
+                Thrift::Binary(take(bytes, len)?.to_vec())
+            }
+            9 | 10 => {
+                let header = take(bytes, 1)?[0];
+                let len = match header >> 4 {
+                    15 => varint(bytes)? as usize,
+                    n => n as usize,
+                };
+                let items = (0..len)
+                    .map(|_| Thrift::parse(bytes, header & 0xf, depth + 1))
+                    .collect::<io::Result<_>>()?;
+                Thrift::List(items)
+            }
+            // Maps are only in metadata that's skipped, so they're kept as
+            // a list of keys then values. Every key and value takes at least
+            // a byte, so there can't be more of them than bytes left.
+            11 => {
+                let len = varint(bytes)? as usize;
+                if len == 0 {
+                    return Ok(Thrift::List(Vec::new()));
+                }
+                let types = take(bytes, 1)?[0];
+                let mut items = Vec::with_capacity(len.saturating_mul(2).min(bytes.len()));
+                for _ in 0..len {
+                    items.push(Thrift::parse(bytes, types >> 4, depth + 1)?);
+                    items.push(Thrift::parse(bytes, types & 0xf, depth + 1)?);
+                }
+                Thrift::List(items)
+            }
+            12 => Thrift::parse_struct_at(bytes, depth)?,
+            ty => return Err(invalid(format!("unknown thrift type {}", ty))),
+        })
+    }
+
+    fn get(&self, id: i16) -> Option<&Thrift> {
+        match self {
+            Thrift::Struct(fields) => fields.iter().find(|(i, _)| *i == id).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    fn field(&self, id: i16) -> io::Result<&Thrift> {
+        self.get(id)
+            .ok_or_else(|| invalid(format!("metadata is missing field {}", id)))
+    }
+
+    fn int(&self, id: i16) -> io::Result<i64> {
+        self.field(id)?.as_int()
+    }
+
+    // An int field that's a size, count or offset, so can't be negative.
+    fn size(&self, id: i16) -> io::Result<usize> {
+        usize::try_from(self.int(id)?)
+            .map_err(|_| invalid(format!("metadata field {} is negative", id)))
+    }
+
+    fn list(&self, id: i16) -> io::Result<&[Thrift]> {
+        match self.field(id)? {
+            Thrift::List(items) => Ok(items),
+            _ => Err(invalid(format!("metadata field {} isn't a list", id))),
+        }
+    }
+
+    fn binary(&self, id: i16) -> io::Result<&[u8]> {
+        match self.field(id)? {
+            Thrift::Binary(bytes) => Ok(bytes),
+            _ => Err(invalid(format!("metadata field {} isn't binary", id))),
+        }
+    }
+
+    fn as_int(&self) -> io::Result<i64> {
+        match self {
+            Thrift::Int(v) => Ok(*v),
+            _ => Err(invalid("metadata field isn't an integer".to_string())),
+        }
+    }
+
+    fn as_bool(&self) -> bool {
+        matches!(self, Thrift::Bool(true))
+    }
+}
+
+fn take<'a>(bytes: &mut &'a [u8], n: usize) -> io::Result<&'a [u8]> {
+    if n > bytes.len() {
+        return Err(invalid("unexpected end of data".to_string()));
+    }
+    let (taken, rest) = bytes.split_at(n);
+    *bytes = rest;
+    Ok(taken)
+}
+
+fn varint(bytes: &mut &[u8]) -> io::Result<u64> {
+    let mut value = 0;
+    for shift in (0..64).step_by(7) {
+        let b = take(bytes, 1)?[0];
+        value |= ((b & 0x7f) as u64) << shift;
+        if b & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(invalid("varint is too long".to_string()))
+}
+
+fn zigzag(v: u64) -> i64 {
+    (v >> 1) as i64 ^ -((v & 1) as i64)
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scratch::Scratch;
+
+    // Just enough of a Parquet writer to make the files the tests read,
+    // with metadata written as Thrift compact structs by field id.
+    enum T {
+        I(i64),
+        B(&'static str),
+        S(Vec<(i16, T)>),
+        L(Vec<T>),
+    }
+
+    impl T {
+        fn ty(&self) -> u8 {
+            match self {
+                T::I(_) => 6,
+                T::B(_) => 8,
+                T::L(_) => 9,
+                T::S(_) => 12,
+            }
+        }
+
+        fn write(&self, out: &mut Vec<u8>) {
+            match self {
+                T::I(v) => write_varint(out, ((v << 1) ^ (v >> 63)) as u64),
+                T::B(b) => {
+                    write_varint(out, b.len() as u64);
+                    out.extend_from_slice(b.as_bytes());
+                }
+                T::L(items) => {
+                    let ty = items.first().map_or(12, T::ty);
+                    out.push(((items.len() as u8) << 4) | ty);
+                    for item in items {
+                        item.write(out);
+                    }
+                }
+                T::S(fields) => {
+                    let mut last = 0;
+                    for (id, value) in fields {
+                        out.push((((id - last) as u8) << 4) | value.ty());
+                        value.write(out);
+                        last = *id;
+                    }
+                    out.push(0);
+                }
+            }
+        }
+
+        fn bytes(&self) -> Vec<u8> {
+            let mut out = Vec::new();
+            self.write(&mut out);
+            out
+        }
+    }
+
+    // Sets each of `fields` in `s`, in order of id.
+    fn with(s: &mut Vec<(i16, T)>, fields: Vec<(i16, T)>) {
+        for (id, value) in fields {
+            match s.iter_mut().find(|(i, _)| *i == id) {
+                Some(field) => field.1 = value,
+                None => s.push((id, value)),
+            }
+        }
+        s.sort_by_key(|(id, _)| *id);
+    }
+
+    fn write_varint(out: &mut Vec<u8>, mut v: u64) {
+        while v >= 0x80 {
+            out.push(v as u8 | 0x80);
+            v >>= 7;
+        }
+        out.push(v as u8);
+    }
+
+    // A page header of type `ty` for a page of `len` bytes, or
+    // `compressed` once compressed, with `header` as its field `id`.
+    fn page(ty: i64, len: usize, compressed: usize, id: i16, header: Vec<(i16, T)>) -> Vec<u8> {
+        let (len, compressed) = (T::I(len as i64), T::I(compressed as i64));
+        T::S(vec![
+            (1, T::I(ty)),
+            (2, len),
+            (3, compressed),
+            (id, T::S(header)),
+        ])
+        .bytes()
+    }
+
+    // A file with a row group of a column `x` of physical type `ty`, whose
+    // schema element also has `element` and whose chunk is `pages`, with
+    // `chunk` on top of its uncompressed, no values defaults. There are as
+    // many rows as the chunk has values.
+    fn file(ty: i64, element: Vec<(i16, T)>, chunk: Vec<(i16, T)>, pages: &[Vec<u8>]) -> Vec<u8> {
+        let mut out = MAGIC.to_vec();
+        let pages = pages.concat();
+        let mut meta = vec![
+            (4, T::I(0)),
+            (5, T::I(0)),
+            (7, T::I(pages.len() as i64)),
+            (9, T::I(4)),
+        ];
+        with(&mut meta, chunk);
+        out.extend(pages);
+        let rows = match &meta[1].1 {
+            T::I(v) => *v,
+            _ => unreachable!(),
+        };
+        let mut x = vec![(1, T::I(ty)), (3, T::I(0)), (4, T::B("x"))];
+        with(&mut x, element);
+        let footer = T::S(vec![
+            (1, T::I(1)),
+            (
+                2,
+                T::L(vec![T::S(vec![(4, T::B("schema")), (5, T::I(1))]), T::S(x)]),
+            ),
+            (3, T::I(rows)),
+            (
+                4,
+                T::L(vec![T::S(vec![
+                    (1, T::L(vec![T::S(vec![(3, T::S(meta))])])),
+                    (3, T::I(rows)),
+                ])]),
+            ),
+        ])
+        .bytes();
+        out.extend(&footer);
+        out.extend((footer.len() as u32).to_le_bytes());
+        out.extend(MAGIC);
+        out
+    }
+
+    // Two plain encoded int32s in one version one data page.
+    fn ints(element: Vec<(i16, T)>, values: [u32; 2]) -> Vec<u8> {
+        let body: Vec<u8> = values.iter().flat_map(|v| v.to_le_bytes()).collect();
+        let header = vec![(1, T::I(2)), (2, T::I(0)), (3, T::I(0)), (4, T::I(0))];
+        let pages = [page(0, body.len(), body.len(), 5, header), body];
+        file(1, element, vec![(5, T::I(2))], &pages)
+    }
+
+    fn read_bytes(name: &str, bytes: &[u8]) -> io::Result<Relation> {
+        let dir = Scratch::new(name);
+        let path = dir.join("rel.parquet");
+        std::fs::write(&path, bytes).unwrap();
+        Relation::from_parquet(&path)
+    }
+
+    fn invalid_data(name: &str, bytes: &[u8]) -> String {
+        let e = read_bytes(name, bytes).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData, "{e}");
+        e.to_string()
+    }
+
+    #[test]
+    fn reads_plain_ints() {
+        let rel = read_bytes("plain", &ints(vec![], [7, u32::MAX])).unwrap();
+        assert_eq!(rel.col_names, ["x"]);
+        assert_eq!(rel.data, [[Value::Int(7)], [Value::Int(-1)]]);
+    }
+
+    #[test]
+    fn reads_unsigned_ints_as_unsigned() {
+        let rel = read_bytes("uint32", &ints(vec![(6, T::I(13))], [7, u32::MAX])).unwrap();
+        assert_eq!(rel.data, [[Value::Int(7)], [Value::Int(u32::MAX as i64)]]);
+        let logical = T::S(vec![(10, T::S(vec![(1, T::I(32))]))]);
+        let rel = read_bytes("logical_uint32", &ints(vec![(10, logical)], [1, u32::MAX])).unwrap();
+        assert_eq!(rel.data[1], [Value::Int(u32::MAX as i64)]);
+    }
+
+    #[test]
+    fn reads_a_snappy_dictionary_page_with_nulls() {
+        // Three int64s in the dictionary, then five rows of an optional
+        // column: levels 1 0 1 1 1 bit packed, then indexes 2 0 1 2 bit
+        // packed two bits wide, each page behind Snappy as one literal.
+        let snappy = |data: Vec<u8>| {
+            let mut out = Vec::new();
+            write_varint(&mut out, data.len() as u64);
+            out.push(((data.len() - 1) as u8) << 2);
+            out.extend(data);
+            out
+        };
+        let dict: Vec<u8> = [10i64, 20, 30]
+            .iter()
+            .flat_map(|v| v.to_le_bytes())
+            .collect();
+        let dict_len = dict.len();
+        let dict = snappy(dict);
+        let mut data = vec![2, 0, 0, 0, 0x03, 0b0001_1101];
+        data.extend([2, 0x03, 0b1001_0010, 0]);
+        let data_len = data.len();
+        let data = snappy(data);
+        let pages = [
+            page(2, dict_len, dict.len(), 7, vec![(1, T::I(3)), (2, T::I(0))]),
+            dict,
+            page(
+                0,
+                data_len,
+                data.len(),
+                5,
+                vec![(1, T::I(5)), (2, T::I(8)), (3, T::I(3)), (4, T::I(3))],
+            ),
+            data,
+        ];
+        let bytes = file(
+            2,
+            vec![(3, T::I(1))],
+            vec![(4, T::I(1)), (5, T::I(5))],
+            &pages,
+        );
+        let rel = read_bytes("dictionary", &bytes).unwrap();
+        let x = |v: i64| vec![Value::Int(v)];
+        assert_eq!(rel.data, [x(30), vec![Value::Null], x(10), x(20), x(30)]);
+    }
+
+    #[test]
+    fn rejects_types_without_values_here() {
+        let decimal = invalid_data("decimal", &ints(vec![(6, T::I(5))], [1, 2]));
+        assert!(decimal.ends_with("column \"x\" is a decimal"), "{decimal}");
+        let timestamp = T::S(vec![(8, T::S(vec![]))]);
+        let timestamp = invalid_data("timestamp", &ints(vec![(10, timestamp)], [1, 2]));
+        assert!(timestamp.ends_with("is a timestamp"), "{timestamp}");
+        let uint64 = invalid_data("uint64", &ints(vec![(6, T::I(14))], [1, 2]));
+        assert!(uint64.ends_with("is an unsigned 64-bit int"), "{uint64}");
+    }
+
+    #[test]
+    fn rejects_negative_and_oversized_chunks() {
+        let body = [1u8, 0, 0, 0, 2, 0, 0, 0];
+        let header = vec![(1, T::I(2)), (2, T::I(0)), (3, T::I(0)), (4, T::I(0))];
+        let pages = [page(0, 8, 8, 5, header), body.to_vec()];
+        let negative = file(1, vec![], vec![(5, T::I(2)), (7, T::I(-1))], &pages);
+        assert!(invalid_data("negative", &negative).ends_with("field 7 is negative"));
+        let huge = file(1, vec![], vec![(5, T::I(2)), (7, T::I(1 << 40))], &pages);
+        assert!(invalid_data("huge", &huge).contains("past the end of the file"));
+        let count = file(1, vec![], vec![(5, T::I(-2))], &pages);
+        assert!(invalid_data("count", &count).ends_with("is negative"));
+    }
+
+    #[test]
+    fn rejects_bit_widths_over_32() {
+        for width in [33u32, 40, 64, 255] {
+            assert!(hybrid(&mut &[0x03, 0, 0, 0, 0, 0][..], width, 4).is_err());
+        }
+        // A width of 32 is fine, and uses every bit.
+        let mut bytes = vec![0x03];
+        bytes.extend(u32::MAX.to_le_bytes().repeat(8));
+        assert_eq!(hybrid(&mut &bytes[..], 32, 8).unwrap(), [u32::MAX; 8]);
+        assert_eq!(hybrid(&mut &[0x02, 0xff][..], 8, 1).unwrap(), [0xff]);
+    }
+
+    #[test]
+    fn rejects_oversized_maps_and_deep_nesting() {
+        // A struct whose field 1 is a map claiming 2^62 int pairs.
+        let mut map = vec![0x1b];
+        write_varint(&mut map, 1 << 62);
+        map.extend([0x55, 2, 4, 0, 0]);
+        assert!(Thrift::parse_struct(&mut &map[..]).is_err());
+        // Structs in structs, then lists in lists, far past the limit.
+        let mut structs = vec![0x1c; 100_000];
+        structs.extend(vec![0; 100_001]);
+        let e = Thrift::parse_struct(&mut &structs[..]).unwrap_err();
+        assert!(e.to_string().contains("nested more than"), "{e}");
+        let mut lists = vec![0x19];
+        lists.extend(vec![0x19; 100_000]);
+        let e = Thrift::parse_struct(&mut &lists[..]).unwrap_err();
+        assert!(e.to_string().contains("nested more than"), "{e}");
+        // Nesting Parquet actually uses is fine.
+        let mut shallow = vec![0x1c; 10];
+        shallow.extend(vec![0; 11]);
+        assert!(Thrift::parse_struct(&mut &shallow[..]).is_ok());
+    }
+}
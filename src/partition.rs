@@ -0,0 +1,137 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::ops::RangeInclusive;
+
+use crate::value::Value;
+use crate::Relation;
+
+// A relation split into partitions on one column, each holding the rows
+// whose value falls in one `width`-sized range. Filters and joins on that
+// column only need to look at the partitions their ranges overlap. The
+// column has to hold only ints.
+#[derive(Debug)]
+pub struct PartitionedRelation {
+    col: String,
+    width: i64,
+    col_names: Vec<String>,
+    partitions: Vec<Partition>,
+}
+
+#[derive(Debug)]
+pub struct Partition {
+    // The range of the partition column this partition covers.
+    pub range: RangeInclusive<i64>,
+    pub rel: Relation,
+}
+
+impl Relation {
+    pub fn partition_by(&self, col: &str, width: i64) -> PartitionedRelation {
+        assert!(width > 0, "partitions must have a positive width");
+        let c = self.positions(&[col.to_string()])[0];
+        let mut parts: HashMap<i64, Vec<Vec<Value>>> = HashMap::new();
+        for row in self.data.iter() {
+            let Some(v) = row[c].as_int() else {
+                panic!("can't partition on {:?}, which has a {}", col, row[c]);
+            };
+            parts
+                .entry(v.div_euclid(width))
+                .or_default()
+                .push(row.clone());
+        }
+        let mut partitions: Vec<_> = parts
+            .into_iter()
+            .map(|(p, rows)| Partition {
+                range: p * width..=p * width + (width - 1),
+                rel: Relation::new_with_data(self.col_names.iter().cloned(), rows),
+            })
+            .collect();
+        partitions.sort_by_key(|p| *p.range.start());
+        PartitionedRelation {
+            col: col.to_string(),
+            width,
+            col_names: self.col_names.clone(),
+            partitions,
+        }
+    }
+}
+
+impl PartitionedRelation {
+    pub fn partitions(&self) -> &[Partition] {
+        &self.partitions
+    }
+
+    // The partitions that could have rows with the partition column in
+    // `range`.
+    pub fn prune(&self, range: RangeInclusive<i64>) -> impl Iterator<Item = &Partition> {
+        self.partitions
+            .iter()
+            .filter(move |p| p.range.start() <= range.end() && range.start() <= p.range.end())
+    }
+
+    // The rows whose partition column is in `range`.
+    pub fn filter_range(&self, range: RangeInclusive<i64>) -> Relation {
+        let c = self.partition_col();
+        Relation::new_with_data(
+            self.col_names.iter().cloned(),
+            self.prune(range.clone())
+                .flat_map(|p| p.rel.data.iter())
+                .filter(|row| row[c].as_int().is_some_and(|v| range.contains(&v)))
+                .cloned()
+                .collect::<Vec<_>>(),
+        )
+    }
+
+    pub fn join(&self, other: &Relation) -> Relation {
+        let mut result = self.join_schema(other);
+        for (part, other) in self.join_pairs(other) {
+            result.data.extend(part.join(&other).data);
+        }
+        result
+    }
+
+    // An empty relation with the columns of the join with `other`.
+    pub fn join_schema(&self, other: &Relation) -> Relation {
+        Relation::new(self.col_names.iter().cloned())
+            .join(&Relation::new(other.col_names.iter().cloned()))
+    }
+
+    // The pairs of relations whose joins together make up the join with
+    // `other`. When the partition column is a join key, `other`'s rows are
+    // split up the same way, and partitions without any matching rows
+    // aren't joined at all. Otherwise every partition joins all of `other`.
+    pub fn join_pairs<'a>(&'a self, other: &'a Relation) -> Vec<(&'a Relation, Cow<'a, Relation>)> {
+        let Some(oc) = other.col_names.iter().position(|c| *c == self.col) else {
+            return self
+                .partitions
+                .iter()
+                .map(|p| (&p.rel, Cow::Borrowed(other)))
+                .collect();
+        };
+        // Rows of `other` without an int there can't match any partition.
+        let values = other.data.iter().filter_map(|row| row[oc].as_int());
+        let (Some(min), Some(max)) = (values.clone().min(), values.max()) else {
+            return vec![];
+        };
+
+        let mut split: HashMap<i64, Vec<Vec<Value>>> = HashMap::new();
+        for row in other.data.iter() {
+            if let Some(v) = row[oc].as_int() {
+                split
+                    .entry(v.div_euclid(self.width))
+                    .or_default()
+                    .push(row.clone());
+            }
+        }
+        self.prune(min..=max)
+            .filter_map(|p| {
+                let rows = split.remove(&p.range.start().div_euclid(self.width))?;
+                let other = Relation::new_with_data(other.col_names.iter().cloned(), rows);
+                Some((&p.rel, Cow::Owned(other)))
+            })
+            .collect()
+    }
+
+    fn partition_col(&self) -> usize {
+        self.col_names.iter().position(|c| *c == self.col).unwrap()
+    }
+}
@@ -0,0 +1,227 @@
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use crate::value::Value;
+use crate::Relation;
+
+// File layout, all integers little-endian:
+//
+//   magic "NBJR", version: u32
+//   column count: u64, then for each column its name as length: u64 + utf8
+//   row count: u64, then every row's values, each a tag: u8 and then
+//     nothing for a null, a u8 for a bool, an i64 for an int, an f64 for
+//     a float, or a length: u64 + utf8 for a string
+//
+// Version 1 files, from before there were other types, have every value
+// as just an i64, and are still read as ints.
+const MAGIC: &[u8; 4] = b"NBJR";
+const VERSION: u32 = 2;
+
+impl Relation {
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut w = BufWriter::new(File::create(path)?);
+        w.write_all(MAGIC)?;
+        w.write_all(&VERSION.to_le_bytes())?;
+        write_u64(&mut w, self.col_names.len() as u64)?;
+        for name in &self.col_names {
+            write_u64(&mut w, name.len() as u64)?;
+            w.write_all(name.as_bytes())?;
+        }
+        write_u64(&mut w, self.data.len() as u64)?;
+        for row in &self.data {
+            if row.len() != self.col_names.len() {
+                return Err(invalid("row width doesn't match column count"));
+            }
+            for v in row {
+                write_value(&mut w, v)?;
+            }
+        }
+        w.flush()
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Relation> {
+        let file = File::open(path)?;
+        // Counts in the file are only trusted as far as there are bytes for
+        // what they count, so a corrupt one can't make a huge allocation.
+        let size = file.metadata()?.len() as usize;
+        let mut r = BufReader::new(file);
+        let mut magic = [0; 4];
+        r.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid("not a relation file"));
+        }
+        let mut version = [0; 4];
+        r.read_exact(&mut version)?;
+        let version = u32::from_le_bytes(version);
+        if !(1..=VERSION).contains(&version) {
+            return Err(invalid("unsupported relation file version"));
+        }
+
+        let width = read_u64(&mut r)? as usize;
+        let mut col_names = Vec::with_capacity(width.min(size / 8));
+        for _ in 0..width {
+            let len = read_u64(&mut r)?;
+            let name = read_bytes(&mut r, len)?;
+            col_names.push(String::from_utf8(name).map_err(|_| invalid("column name isn't utf8"))?);
+        }
+
+        let len = read_u64(&mut r)? as usize;
+        let mut data = Vec::with_capacity(len.min(size / width.max(1)));
+        for _ in 0..len {
+            data.push(
+                (0..width)
+                    .map(|_| match version {
+                        1 => read_i64(&mut r).map(Value::Int),
+                        _ => read_value(&mut r),
+                    })
+                    .collect::<io::Result<_>>()?,
+            );
+        }
+
+        Ok(Relation::new_with_data(col_names, data))
+    }
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn write_u64(w: &mut impl Write, v: u64) -> io::Result<()> {
+    w.write_all(&v.to_le_bytes())
+}
+
+// `len` bytes, read as they come rather than into a buffer of a size
+// that's only claimed.
+fn read_bytes(r: &mut impl Read, len: u64) -> io::Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    r.take(len).read_to_end(&mut buf)?;
+    if (buf.len() as u64) < len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(buf)
+}
+
+fn read_u64(r: &mut impl Read) -> io::Result<u64> {
+    let mut buf = [0; 8];
+    r.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+pub fn write_value(w: &mut impl Write, v: &Value) -> io::Result<()> {
+    match v {
+        Value::Null => w.write_all(&[0]),
+        Value::Bool(b) => w.write_all(&[1, *b as u8]),
+        Value::Int(v) => {
+            w.write_all(&[2])?;
+            w.write_all(&v.to_le_bytes())
+        }
+        Value::Float(v) => {
+            w.write_all(&[3])?;
+            w.write_all(&v.to_le_bytes())
+        }
+        Value::Str(s) => {
+            w.write_all(&[4])?;
+            write_u64(w, s.len() as u64)?;
+            w.write_all(s.as_bytes())
+        }
+    }
+}
+
+pub fn read_value(r: &mut impl Read) -> io::Result<Value> {
+    let mut tag = [0; 1];
+    r.read_exact(&mut tag)?;
+    Ok(match tag[0] {
+        0 => Value::Null,
+        1 => {
+            r.read_exact(&mut tag)?;
+            Value::Bool(tag[0] != 0)
+        }
+        2 => Value::Int(read_i64(r)?),
+        3 => Value::Float(f64::from_bits(read_u64(r)?)),
+        4 => {
+            let len = read_u64(r)?;
+            let s = read_bytes(r, len)?;
+            Value::Str(
+                String::from_utf8(s)
+                    .map_err(|_| invalid("string value isn't utf8"))?
+                    .into(),
+            )
+        }
+        _ => return Err(invalid("unknown value tag")),
+    })
+}
+
+fn read_i64(r: &mut impl Read) -> io::Result<i64> {
+    let mut buf = [0; 8];
+    r.read_exact(&mut buf)?;
+    Ok(i64::from_le_bytes(buf))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scratch::Scratch;
+
+    fn load_bytes(name: &str, bytes: &[u8]) -> io::Result<Relation> {
+        let dir = Scratch::new(name);
+        let path = dir.join("rel.nbjr");
+        std::fs::write(&path, bytes).unwrap();
+        Relation::load(&path)
+    }
+
+    fn header(width: u64) -> Vec<u8> {
+        let mut out = MAGIC.to_vec();
+        out.extend(VERSION.to_le_bytes());
+        out.extend(width.to_le_bytes());
+        out
+    }
+
+    #[test]
+    fn round_trips_every_type() {
+        let rel = Relation::new_with_data(
+            vec!["a".to_string(), "b".to_string()],
+            vec![
+                vec![Value::Null, Value::Bool(true)],
+                vec![Value::Int(-7), Value::Float(0.5)],
+                vec![Value::Str("ünï".into()), Value::Str("".into())],
+            ],
+        );
+        let dir = Scratch::new("round_trip");
+        let path = dir.join("rel.nbjr");
+        rel.save(&path).unwrap();
+        assert_eq!(Relation::load(&path).unwrap(), rel);
+    }
+
+    #[test]
+    fn corrupt_files_are_errors() {
+        let e = load_bytes("magic", b"CSV!\x02\0\0\0").unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+        // Counts and lengths far bigger than the file.
+        let e = load_bytes("width", &header(u64::MAX)).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof);
+        let mut name = header(1);
+        name.extend(u64::MAX.to_le_bytes());
+        name.extend(b"a");
+        let e = load_bytes("name", &name).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof);
+        let mut rows = header(1);
+        rows.extend(1u64.to_le_bytes());
+        rows.extend(b"a");
+        rows.extend(u64::MAX.to_le_bytes());
+        let e = load_bytes("rows", &rows).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof);
+        let mut string = rows[..rows.len() - 8].to_vec();
+        string.extend(1u64.to_le_bytes());
+        string.push(4);
+        string.extend((1u64 << 60).to_le_bytes());
+        string.extend(b"abc");
+        let e = load_bytes("string", &string).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof);
+        let mut tag = rows[..rows.len() - 8].to_vec();
+        tag.extend(1u64.to_le_bytes());
+        tag.push(9);
+        let e = load_bytes("tag", &tag).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,299 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// The joins a query ran, in order. Each step joins one input onto the
+// result so far.
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub struct Plan {
+    pub steps: Vec<PlanStep>,
+    // Where each output column came from. Plans loaded from a history
+    // don't have it.
+    pub lineage: Vec<Lineage>,
+}
+
+impl Plan {
+    pub fn lineage(&self) -> &[Lineage] {
+        &self.lineage
+    }
+}
+
+// The base columns an output column came from. A column that several inputs
+// share comes from each of them, since the join made them equal.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lineage {
+    pub column: String,
+    pub sources: Vec<Source>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Source {
+    // A relation given to a query or from the catalog, named like in plans.
+    pub input: String,
+    pub column: String,
+    // How it got to the output: the named results it went through,
+    // innermost first, and anything computed from it.
+    pub via: Vec<String>,
+}
+
+impl fmt::Display for Lineage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} <-", self.column)?;
+        for (i, source) in self.sources.iter().enumerate() {
+            let sep = if i == 0 { "" } else { "," };
+            write!(f, "{} {}.{}", sep, source.input, source.column)?;
+            if !source.via.is_empty() {
+                write!(f, " via {}", source.via.join(", "))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlanStep {
+    // The input's name, or its columns in parentheses if it doesn't have one.
+    pub input: String,
+    // The columns it was joined on, which is nothing for the first input.
+    pub key: Vec<String>,
+}
+
+impl fmt::Display for PlanStep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} on [{}]", self.input, self.key.join(", "))
+    }
+}
+
+// The plan as a tree with the last join at the top and each join's inputs
+// indented under it, the result so far first:
+//
+//   join on [customer]
+//     join on [order]
+//       orders
+//       items
+//     customers
+impl fmt::Display for Plan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (depth, step) in self.steps.iter().skip(1).rev().enumerate() {
+            writeln!(f, "{:2$}join on [{}]", "", step.key.join(", "), depth * 2)?;
+        }
+        let depth = self.steps.len().saturating_sub(1);
+        for (i, step) in self.steps.iter().enumerate() {
+            writeln!(
+                f,
+                "{:2$}{}",
+                "",
+                step.input,
+                (depth - i.saturating_sub(1)) * 2
+            )?;
+        }
+        Ok(())
+    }
+}
+
+// How a query's plan differs from the one recorded for it before.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlanDiff {
+    pub query: String,
+    pub old: Plan,
+    pub new: Plan,
+    // Each step that differs, with what it was and what it is now. A plan
+    // that got shorter or longer has steps missing on one side.
+    pub changed: Vec<(usize, Option<PlanStep>, Option<PlanStep>)>,
+}
+
+impl fmt::Display for PlanDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "plan for {:?} changed:", self.query)?;
+        for (step, old, new) in self.changed.iter() {
+            let show = |s: &Option<PlanStep>| s.as_ref().map_or("-".to_string(), |s| s.to_string());
+            writeln!(f, "  step {}: {} -> {}", step, show(old), show(new))?;
+        }
+        Ok(())
+    }
+}
+
+// The last plan seen for each named query, kept in a file so that a change
+// of plan between runs can be caught.
+#[derive(Default, Debug)]
+pub struct PlanHistory {
+    plans: BTreeMap<String, Plan>,
+}
+
+impl PlanHistory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // The plan last recorded for `query`.
+    pub fn get(&self, query: &str) -> Option<&Plan> {
+        self.plans.get(query)
+    }
+
+    // Records `plan` for `query`, and returns how it differs from the plan
+    // that was recorded for it before, if it does.
+    pub fn record(&mut self, query: &str, plan: Plan) -> Option<PlanDiff> {
+        let old = self.plans.insert(query.to_string(), plan.clone())?;
+        let changed: Vec<_> = (0..old.steps.len().max(plan.steps.len()))
+            .filter_map(|i| {
+                let (was, now) = (old.steps.get(i), plan.steps.get(i));
+                (was != now).then(|| (i, was.cloned(), now.cloned()))
+            })
+            .collect();
+        if changed.is_empty() {
+            return None;
+        }
+        Some(PlanDiff {
+            query: query.to_string(),
+            old,
+            new: plan,
+            changed,
+        })
+    }
+
+    // One line per step: the query's name, the input and its comma
+    // separated key, separated by tabs. Backslashes, tabs, newlines and
+    // commas in names are escaped with a backslash.
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut out = String::new();
+        for (query, plan) in self.plans.iter() {
+            for step in plan.steps.iter() {
+                let key: Vec<_> = step.key.iter().map(|k| escape(k)).collect();
+                out.push_str(&format!(
+                    "{}\t{}\t{}\n",
+                    escape(query),
+                    escape(&step.input),
+                    key.join(",")
+                ));
+            }
+        }
+        fs::write(path, out)
+    }
+
+    // Loads a history written by `save`. A missing file is an empty history.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Self::new()),
+            Err(e) => return Err(e),
+        };
+        let mut plans: BTreeMap<String, Plan> = BTreeMap::new();
+        for line in contents.lines() {
+            let bad = || {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("bad plan history line {:?}", line),
+                )
+            };
+            let [query, input, key] = split_escaped(line, '\t')[..] else {
+                return Err(bad());
+            };
+            let key = split_escaped(key, ',')
+                .into_iter()
+                .filter(|k| !k.is_empty())
+                .map(unescape)
+                .collect::<Option<Vec<_>>>()
+                .ok_or_else(bad)?;
+            plans
+                .entry(unescape(query).ok_or_else(bad)?)
+                .or_default()
+                .steps
+                .push(PlanStep {
+                    input: unescape(input).ok_or_else(bad)?,
+                    key,
+                });
+        }
+        Ok(Self { plans })
+    }
+}
+
+// `name` with the characters that separate things in a history line escaped
+// with a backslash.
+fn escape(name: &str) -> String {
+    let mut out = String::with_capacity(name.len());
+    for c in name.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '\t' => out.push_str("\\t"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            ',' => out.push_str("\\,"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+// Undoes `escape`, failing on a backslash that doesn't escape anything.
+fn unescape(s: &str) -> Option<String> {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        out.push(match c {
+            '\\' => match chars.next()? {
+                't' => '\t',
+                'n' => '\n',
+                'r' => '\r',
+                c @ ('\\' | ',') => c,
+                _ => return None,
+            },
+            c => c,
+        });
+    }
+    Some(out)
+}
+
+// Splits `s` on the `sep`s that aren't escaped, leaving the parts escaped.
+fn split_escaped(s: &str, sep: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let (mut start, mut escaped) = (0, false);
+    for (i, c) in s.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            c if c == sep => {
+                parts.push(&s[start..i]);
+                start = i + c.len_utf8();
+            }
+            _ => {}
+        }
+    }
+    parts.push(&s[start..]);
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scratch::Scratch;
+
+    #[test]
+    fn histories_keep_names_with_separators_in_them() {
+        let plan = Plan {
+            steps: vec![
+                PlanStep {
+                    input: "orders\tfrom 2024, q1".to_string(),
+                    key: vec![],
+                },
+                PlanStep {
+                    input: "C:\\data\\items\nnew".to_string(),
+                    key: vec!["order,id".to_string(), "\\t".to_string(), "sku".to_string()],
+                },
+            ],
+            lineage: vec![],
+        };
+        let mut history = PlanHistory::new();
+        history.record("daily\treport, v2", plan.clone());
+        let dir = Scratch::new("plan_history");
+        let path = dir.join("plans.tsv");
+        history.save(&path).unwrap();
+        let loaded = PlanHistory::load(&path).unwrap();
+        assert_eq!(loaded.get("daily\treport, v2"), Some(&plan));
+
+        fs::write(&path, "q\tinput\\x\t\n").unwrap();
+        let err = PlanHistory::load(&path).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
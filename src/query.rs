@@ -0,0 +1,2198 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::mem::size_of;
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::catalog::{Catalog, Observed};
+use crate::config::Config;
+use crate::cost::{CostModel, Model};
+use crate::domain::{Domain, DomainError};
+use crate::estimate::{CardinalityEstimator, Estimator};
+use crate::factorized::Factorized;
+use crate::graph::JoinTree;
+use crate::hashing::Hashing;
+use crate::hints::Hints;
+use crate::hypertree::Decomposition;
+use crate::plan::{Lineage, Plan, PlanDiff, PlanHistory, PlanStep, Source};
+use crate::scheduler::panic_message;
+use crate::spill::SpillJoin;
+use crate::suggest::suggest_join_keys;
+use crate::value::{Nulls, Value};
+use crate::whynot::WhyNot;
+use crate::{yannakakis, HashIndex, Planner, Relation, MAX_DP_RELATIONS};
+
+// A natural join over a set of inputs. Inputs are either relations or
+// references to named intermediate results defined with `with`, which are
+// visible to every later definition and to the body of the query.
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct Query {
+    ctes: Vec<(String, Query)>,
+    inputs: Vec<Input>,
+    // The inputs that are outer joined, by position, and how.
+    outer: Vec<(usize, Outer)>,
+    limits: Limits,
+    reduce: bool,
+    strategy: Option<Strategy>,
+    validate: bool,
+    hashing: Hashing,
+    nulls: Nulls,
+    no_cross_products: bool,
+    cost_model: Option<Model>,
+    estimator: Option<Estimator>,
+    leading: Vec<String>,
+    intermediates: Option<Intermediates>,
+    select: Option<Vec<String>>,
+    provenance: bool,
+    check_domains: bool,
+    // Where to spill joins to and into how many partitions, and whether
+    // that's only after running out of memory without.
+    spill: Option<(PathBuf, usize)>,
+    retry_spilling: Option<(PathBuf, usize)>,
+    // The values of a row the result is expected to have.
+    expect: Option<Vec<(String, Value)>>,
+}
+
+// Which unmatched rows an outer join keeps: the result so far's, the
+// input's, or both's.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outer {
+    Left,
+    Right,
+    Full,
+}
+
+impl Outer {
+    // Whether the left and right sides' unmatched rows are kept.
+    pub fn keeps(self) -> (bool, bool) {
+        match self {
+            Outer::Left => (true, false),
+            Outer::Right => (false, true),
+            Outer::Full => (true, true),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Input {
+    Relation(Relation),
+    Named(String),
+}
+
+// Limits on the work a query may do. Intermediate rows are counted per
+// join, and memory is estimated from the size of the rows held by the join
+// that's running. A query that goes over any of them is aborted.
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub struct Limits {
+    pub max_output_rows: Option<usize>,
+    pub max_intermediate_rows: Option<usize>,
+    pub max_memory: Option<usize>,
+    pub max_wall_time: Option<Duration>,
+    // The most rows a join may produce, as a multiple of the rows going
+    // into it. Going over usually means joining on the wrong columns.
+    pub max_join_growth: Option<f64>,
+}
+
+impl Limits {
+    // The lower of each of the limits of both.
+    pub fn tightest(self, other: Limits) -> Limits {
+        fn min<T: PartialOrd>(a: Option<T>, b: Option<T>) -> Option<T> {
+            match (a, b) {
+                (Some(a), Some(b)) => Some(if b < a { b } else { a }),
+                (a, b) => a.or(b),
+            }
+        }
+        Limits {
+            max_output_rows: min(self.max_output_rows, other.max_output_rows),
+            max_intermediate_rows: min(self.max_intermediate_rows, other.max_intermediate_rows),
+            max_memory: min(self.max_memory, other.max_memory),
+            max_wall_time: min(self.max_wall_time, other.max_wall_time),
+            max_join_growth: min(self.max_join_growth, other.max_join_growth),
+        }
+    }
+}
+
+// What happens to the result of each of a query's joins, for seeing where
+// a plan went wrong afterwards.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Intermediates {
+    // They're kept in memory.
+    Keep,
+    // They're written to files in this directory, named by the step.
+    Dump(PathBuf),
+}
+
+// What happened while running a query.
+#[derive(Debug, Default)]
+pub struct ExecutionStats {
+    plan: Plan,
+    intermediates: Vec<Intermediate>,
+}
+
+impl ExecutionStats {
+    // The inputs the query joined, in the order it joined them.
+    pub fn plan(&self) -> &Plan {
+        &self.plan
+    }
+
+    // The result of each of the query's joins, in order, if the query was
+    // asked to keep them.
+    pub fn intermediates(&self) -> &[Intermediate] {
+        &self.intermediates
+    }
+}
+
+#[derive(Debug)]
+pub struct Intermediate {
+    // What was joined to get here, in order.
+    pub inputs: Vec<String>,
+    pub estimated_rows: f64,
+    pub rows: usize,
+    pub result: Option<Relation>,
+    pub path: Option<PathBuf>,
+}
+
+// How the joins are ordered. `Bfs` works outwards through the query graph,
+// `Greedy` keeps adding whichever join is estimated to be smallest, and `Dp`
+// tries every left-deep order of up to `MAX_DP_RELATIONS` relations for the
+// one estimated to be smallest in total, past which it uses greedy operator
+// ordering, whose joins can join the results of other joins. `Bushy` tries
+// every tree of joins like that, up to the same number of relations. By
+// default queries run against a catalog are greedy and others use `Dp`.
+//
+// Bushy plans go by estimated rows, even with a cost model, and `leading`
+// only orders the relations joined straight onto the first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Strategy {
+    Bfs,
+    Greedy,
+    Dp,
+    Bushy,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QueryError {
+    UnknownRelation(String),
+    Cyclic,
+    // The query has outer joins, which can't be factorized.
+    OuterJoin,
+    OutputRows {
+        limit: usize,
+        rows: usize,
+    },
+    IntermediateRows {
+        limit: usize,
+        rows: usize,
+    },
+    Memory {
+        limit: usize,
+        bytes: usize,
+    },
+    WallTime {
+        limit: Duration,
+        elapsed: Duration,
+    },
+    // Writing something out failed.
+    Io(String),
+    // A relation from the catalog has a value outside its column's domain.
+    Domain(DomainError),
+    // Two inputs that were to be joined had no columns in common, along
+    // with pairs of their columns that look like they could have been meant
+    // instead.
+    NoSharedColumns {
+        left: String,
+        right: String,
+        suggestions: Vec<(String, String)>,
+    },
+    JoinExplosion {
+        key: Vec<String>,
+        rows: usize,
+        inputs: usize,
+        // The key values that produce the most rows, with how many rows
+        // each side has for them.
+        worst: Vec<(Vec<Value>, usize, usize)>,
+    },
+    // The query panicked while a scheduler was running it, with the panic's
+    // message.
+    Panicked(String),
+}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QueryError::UnknownRelation(name) => write!(f, "no relation named {:?}", name),
+            QueryError::Cyclic => write!(f, "query is cyclic"),
+            QueryError::OuterJoin => write!(f, "query has outer joins"),
+            QueryError::OutputRows { limit, rows } => {
+                write!(f, "output has {} rows, limit is {}", rows, limit)
+            }
+            QueryError::IntermediateRows { limit, rows } => {
+                write!(
+                    f,
+                    "intermediate result has {} rows, limit is {}",
+                    rows, limit
+                )
+            }
+            QueryError::Memory { limit, bytes } => {
+                write!(f, "query is using {} bytes, limit is {}", bytes, limit)
+            }
+            QueryError::WallTime { limit, elapsed } => {
+                write!(f, "query has run for {:?}, limit is {:?}", elapsed, limit)
+            }
+            QueryError::Io(e) => write!(f, "{}", e),
+            QueryError::Domain(e) => write!(f, "{}", e),
+            QueryError::NoSharedColumns {
+                left,
+                right,
+                suggestions,
+            } => {
+                write!(f, "{} and {} have no columns in common", left, right)?;
+                for (i, (l, r)) in suggestions.iter().enumerate() {
+                    let sep = if i == 0 { "; did you mean" } else { " or" };
+                    write!(f, "{} {}.{} = {}.{}", sep, left, l, right, r)?;
+                }
+                if !suggestions.is_empty() {
+                    write!(f, "?")?;
+                }
+                Ok(())
+            }
+            QueryError::JoinExplosion {
+                key,
+                rows,
+                inputs,
+                worst,
+            } => {
+                write!(
+                    f,
+                    "join on {:?} produced {} rows from {} input rows",
+                    key, rows, inputs
+                )?;
+                for (i, (values, left, right)) in worst.iter().enumerate() {
+                    let sep = if i == 0 { "; most rows from" } else { "," };
+                    write!(f, "{} {:?} ({} x {})", sep, values, left, right)?;
+                }
+                Ok(())
+            }
+            QueryError::Panicked(message) => write!(f, "query panicked: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for QueryError {}
+
+impl From<io::Error> for QueryError {
+    fn from(e: io::Error) -> Self {
+        QueryError::Io(e.to_string())
+    }
+}
+
+// The limits of a running query along with when it started, how its hash
+// tables hash keys, and whether null keys match.
+struct Budget {
+    limits: Limits,
+    start: Instant,
+    hashing: Hashing,
+    nulls: Nulls,
+    spill: Option<SpillJoin>,
+}
+
+impl Budget {
+    // Checks a join that has produced `rows` rows of width `width` so far,
+    // on top of `held` bytes of its inputs.
+    fn check(
+        &self,
+        rows: usize,
+        width: usize,
+        held: usize,
+        output: bool,
+    ) -> Result<(), QueryError> {
+        let limits = &self.limits;
+        match (output, limits.max_output_rows, limits.max_intermediate_rows) {
+            (true, Some(limit), _) if rows > limit => {
+                return Err(QueryError::OutputRows { limit, rows })
+            }
+            (false, _, Some(limit)) if rows > limit => {
+                return Err(QueryError::IntermediateRows { limit, rows })
+            }
+            _ => {}
+        }
+        if let Some(limit) = limits.max_memory {
+            let bytes = held + rows * width * size_of::<Value>();
+            if bytes > limit {
+                return Err(QueryError::Memory { limit, bytes });
+            }
+        }
+        if let Some(limit) = limits.max_wall_time {
+            let elapsed = self.start.elapsed();
+            if elapsed > limit {
+                return Err(QueryError::WallTime { limit, elapsed });
+            }
+        }
+        Ok(())
+    }
+
+    // Joins `left` and `right` with `spill`, checking each pair of
+    // partitions with only them held.
+    fn join_spilled(
+        &self,
+        left: &Relation,
+        right: &Relation,
+        key: &[String],
+        width: usize,
+        output: bool,
+    ) -> Result<Relation, QueryError> {
+        let mut rows = 0;
+        let spill = self.spill.as_ref().unwrap();
+        spill.join_partitions(left, right, key, |l, r| {
+            let held = size(l) + size(r);
+            let joined = l.try_join_with(r, self.hashing, self.nulls, |n| {
+                self.check(rows + n, width, held, output)?;
+                self.check_growth(rows + n, left, right, key)
+            })?;
+            rows += joined.data.len();
+            Ok::<_, QueryError>(joined)
+        })
+    }
+
+    // Checks that a join of `left` and `right` on `key` that has produced
+    // `rows` rows so far hasn't grown past the limit.
+    fn check_growth(
+        &self,
+        rows: usize,
+        left: &Relation,
+        right: &Relation,
+        key: &[String],
+    ) -> Result<(), QueryError> {
+        let Some(limit) = self.limits.max_join_growth else {
+            return Ok(());
+        };
+        let inputs = left.data.len() + right.data.len();
+        if (rows as f64) <= limit * inputs as f64 {
+            return Ok(());
+        }
+        Err(QueryError::JoinExplosion {
+            key: key.to_vec(),
+            rows,
+            inputs,
+            worst: worst_keys(left, right, key),
+        })
+    }
+}
+
+// How many of the worst key values a join explosion error names.
+const WORST_KEYS: usize = 5;
+
+// The key values with the most rows in the join of `left` and `right`. Only
+// keys duplicated on at least one side can produce more rows than went in.
+fn worst_keys(
+    left: &Relation,
+    right: &Relation,
+    key: &[String],
+) -> Vec<(Vec<Value>, usize, usize)> {
+    let left_counts = left.key_counts(key);
+    let right_counts = right.key_counts(key);
+    let mut worst: Vec<_> = left_counts
+        .into_iter()
+        .filter_map(|(values, l)| {
+            let r = *right_counts.get(&values)?;
+            (l * r > 1).then_some((values, l, r))
+        })
+        .collect();
+    worst.sort_by_key(|(values, l, r)| (std::cmp::Reverse(l * r), values.clone()));
+    worst.truncate(WORST_KEYS);
+    worst
+}
+
+// The columns of `rel` in sorted order, and its rows with their values in
+// that order, sorted too.
+fn canonical(rel: &Relation) -> (Vec<String>, Vec<Vec<Value>>) {
+    let mut cols = rel.col_names.clone();
+    cols.sort();
+    let positions = rel.positions(&cols);
+    let mut rows: Vec<_> = rel
+        .data
+        .iter()
+        .map(|row| {
+            positions
+                .iter()
+                .map(|i| row[*i].clone())
+                .collect::<Vec<_>>()
+        })
+        .collect();
+    rows.sort();
+    (cols, rows)
+}
+
+// How many batches a streaming query gets ahead of its caller.
+pub const STREAM_BATCHES: usize = 4;
+
+// The output of `Query::execute_streaming`, a batch of rows at a time.
+pub struct Batches {
+    pub col_names: Vec<String>,
+    rx: Receiver<Result<Vec<Vec<Value>>, QueryError>>,
+    // The thread running the query, until it's been seen to finish.
+    worker: Option<JoinHandle<()>>,
+}
+
+impl Iterator for Batches {
+    type Item = Result<Vec<Vec<Value>>, QueryError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.rx.recv() {
+            Ok(batch) => Some(batch),
+            // A query that panics stops sending like one that's done, so
+            // it'd look like the rest of its rows were never there.
+            Err(_) => match self.worker.take()?.join() {
+                Ok(()) => None,
+                Err(payload) => Some(Err(QueryError::Panicked(panic_message(payload)))),
+            },
+        }
+    }
+}
+
+// Where a streaming query sends its output: first its columns, once the
+// last join has its first rows or has finished, then the rows.
+struct Stream {
+    batch_rows: usize,
+    // The columns to send, if not all of them.
+    select: Option<Vec<String>>,
+    header: Option<SyncSender<Result<Vec<String>, QueryError>>>,
+    batches: SyncSender<Result<Vec<Vec<Value>>, QueryError>>,
+}
+
+impl Stream {
+    // Sends the join of `probe` with `index` a batch of probe rows at a
+    // time, and stops early if nobody's listening anymore. The relation
+    // returned has only the columns.
+    fn send_join(
+        &mut self,
+        index: &HashIndex,
+        probe: &Relation,
+        check: impl Fn(usize) -> Result<(), QueryError>,
+    ) -> Result<Relation, QueryError> {
+        let col_names: Vec<String> = index
+            .rel
+            .col_names
+            .iter()
+            .chain(
+                probe
+                    .col_names
+                    .iter()
+                    .filter(|c| !index.rel.col_names.contains(c)),
+            )
+            .cloned()
+            .collect();
+        let col_names = self.select.clone().unwrap_or(col_names);
+        let mut rows = 0;
+        for batch in probe.data.chunks(self.batch_rows) {
+            let mut joined = index.try_join_rows(probe, batch.iter(), |n| check(rows + n))?;
+            rows += joined.data.len();
+            if joined.data.is_empty() {
+                continue;
+            }
+            if self.select.is_some() {
+                joined = joined.project(col_names.iter().cloned());
+            }
+            self.send_header(&col_names);
+            if self.batches.send(Ok(joined.data)).is_err() {
+                break;
+            }
+        }
+        self.send_header(&col_names);
+        Ok(Relation::new(col_names))
+    }
+
+    fn send_header(&mut self, col_names: &[String]) {
+        if let Some(header) = self.header.take() {
+            let _ = header.send(Ok(col_names.to_vec()));
+        }
+    }
+}
+
+// What to do with each of a query's definitions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Spool {
+    Run,
+    // Nothing after it refers to it.
+    Skip,
+    // It's the same as the definition at this position, and means the same
+    // here, so that one's result can be replayed.
+    Replay(usize),
+}
+
+// Drops the rows of each inner joined input that have a value outside the
+// domain of the same column of another inner joined input from the catalog,
+// since they can't match anything there.
+fn restrict(
+    resolved: &mut [(Option<String>, Cow<Relation>, Option<String>)],
+    outer: &[(usize, Outer)],
+    catalog: &Catalog,
+) {
+    let inner = |i: usize| !outer.iter().any(|(o, _)| *o == i);
+    let domains: Vec<Vec<(String, Domain)>> = resolved
+        .iter()
+        .enumerate()
+        .map(|(i, (_, rel, source))| match source {
+            Some(name) if inner(i) => catalog
+                .domains(name)
+                .iter()
+                .filter(|(col, _)| rel.col_names.contains(col))
+                .cloned()
+                .collect(),
+            _ => Vec::new(),
+        })
+        .collect();
+    for (i, (_, rel, _)) in resolved.iter_mut().enumerate() {
+        if !inner(i) {
+            continue;
+        }
+        let checks: Vec<(usize, &Domain)> = domains
+            .iter()
+            .enumerate()
+            .filter(|(j, _)| *j != i)
+            .flat_map(|(_, ds)| ds)
+            .filter_map(|(col, d)| rel.col_names.iter().position(|c| c == col).zip(Some(d)))
+            .collect();
+        let outside = |row: &Vec<Value>| checks.iter().any(|(p, d)| !d.contains(&row[*p]));
+        if rel.data.iter().any(outside) {
+            let kept: Vec<_> = rel
+                .data
+                .iter()
+                .filter(|row| !outside(row))
+                .cloned()
+                .collect();
+            *rel = Cow::Owned(Relation::new_with_data(rel.col_names.iter().cloned(), kept));
+        }
+    }
+}
+
+// Where each of `cols` came from, given each input's name if it has one, its
+// label and its columns, and the lineage of the named results.
+fn lineage(
+    origins: &[(Option<String>, String, Vec<String>)],
+    named: &HashMap<String, Vec<Lineage>>,
+    cols: &[String],
+) -> Vec<Lineage> {
+    cols.iter()
+        .map(|col| {
+            let mut sources = Vec::new();
+            for (i, (name, label, input_cols)) in origins.iter().enumerate() {
+                if *col == provenance_col(i) {
+                    sources.push(Source {
+                        input: label.clone(),
+                        column: col.clone(),
+                        via: vec!["row number".to_string()],
+                    });
+                    continue;
+                }
+                if !input_cols.contains(col) {
+                    continue;
+                }
+                let inner = name
+                    .as_ref()
+                    .and_then(|n| named.get(n))
+                    .and_then(|lineage| lineage.iter().find(|l| l.column == *col));
+                match (inner, name) {
+                    (Some(inner), Some(name)) => sources.extend(inner.sources.iter().map(|s| {
+                        let mut s = s.clone();
+                        s.via.push(name.clone());
+                        s
+                    })),
+                    _ => sources.push(Source {
+                        input: label.clone(),
+                        column: col.clone(),
+                        via: vec![],
+                    }),
+                }
+            }
+            Lineage {
+                column: col.clone(),
+                sources,
+            }
+        })
+        .collect()
+}
+
+fn provenance_col(input: usize) -> String {
+    format!("_row_{}", input)
+}
+
+// Drops the columns of each input that aren't in `select` and that no other
+// input has, since nothing joins on them or outputs them. Inputs that lose a
+// column become copies, so they no longer share hash tables with other
+// references to the same relation.
+fn prune(inputs: &mut [(Option<String>, Cow<Relation>, Option<String>)], select: &[String]) {
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for (_, rel, _) in inputs.iter() {
+        for col in rel.col_names.iter() {
+            *counts.entry(col).or_default() += 1;
+        }
+    }
+    let needed: Vec<Vec<String>> = inputs
+        .iter()
+        .map(|(_, rel, _)| {
+            rel.col_names
+                .iter()
+                .filter(|c| select.contains(c) || counts[c.as_str()] > 1)
+                .cloned()
+                .collect()
+        })
+        .collect();
+    for ((_, rel, _), cols) in inputs.iter_mut().zip(needed) {
+        if cols.len() < rel.col_names.len() {
+            *rel = Cow::Owned(rel.project(cols));
+        }
+    }
+}
+
+// Whether any of `ctes` or `inputs` refers to `name`, leaving out those
+// after a definition that shadows it.
+fn uses(ctes: &[(String, Query)], inputs: &[Input], name: &str) -> bool {
+    for (cte, query) in ctes {
+        if query.uses(name) {
+            return true;
+        }
+        if cte == name {
+            return false;
+        }
+    }
+    inputs
+        .iter()
+        .any(|input| matches!(input, Input::Named(n) if n == name))
+}
+
+// Decides which definitions need computing. One nothing refers to is never
+// run, and one identical to an earlier one is only run once: as long as
+// no definition in between changes what either name or anything the
+// definition refers to means, the earlier result is replayed for both.
+fn spools(ctes: &[(String, Query)], inputs: &[Input]) -> Vec<Spool> {
+    let mut spools = Vec::with_capacity(ctes.len());
+    for (j, (name, query)) in ctes.iter().enumerate() {
+        if !uses(&ctes[j + 1..], inputs, name) {
+            spools.push(Spool::Skip);
+            continue;
+        }
+        let earlier = (0..j).find(|&i| {
+            spools[i] == Spool::Run
+                && ctes[i].1 == *query
+                && ctes[i + 1..j]
+                    .iter()
+                    .all(|(between, _)| *between != ctes[i].0 && !query.uses(between))
+        });
+        spools.push(earlier.map_or(Spool::Run, Spool::Replay));
+    }
+    spools
+}
+
+// Joins two sides of a bushy plan that aren't the last join, under the
+// budget.
+fn join_sides(
+    budget: &Budget,
+    no_cross_products: bool,
+    left: &Relation,
+    right: &Relation,
+) -> Result<Relation, QueryError> {
+    let key = left.common_cols(right);
+    if key.is_empty() && no_cross_products {
+        return Err(QueryError::NoSharedColumns {
+            suggestions: suggest_join_keys(left, right)
+                .into_iter()
+                .map(|s| (s.left, s.right))
+                .collect(),
+            left: format!("({})", left.col_names.join(",")),
+            right: format!("({})", right.col_names.join(",")),
+        });
+    }
+    if left.data.is_empty() || right.data.is_empty() {
+        return Ok(empty_join(left, right));
+    }
+    let width = left.col_names.len() + right.col_names.len() - key.len();
+    if budget.spill.is_some() {
+        return budget.join_spilled(left, right, &key, width, false);
+    }
+    let held = size(left) + size(right);
+    left.try_join_with(right, budget.hashing, budget.nulls, |rows| {
+        budget.check(rows, width, held, false)?;
+        budget.check_growth(rows, left, right, &key)
+    })
+}
+
+// The columns joining `left` and `right` gives, with no rows.
+fn empty_join(left: &Relation, right: &Relation) -> Relation {
+    Relation::new(
+        left.col_names
+            .iter()
+            .chain(
+                right
+                    .col_names
+                    .iter()
+                    .filter(|c| !left.col_names.contains(c)),
+            )
+            .cloned(),
+    )
+}
+
+fn size(rel: &Relation) -> usize {
+    rel.data.len() * rel.col_names.len() * size_of::<Value>()
+}
+
+impl Query {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with(mut self, name: impl Into<String>, query: Query) -> Self {
+        self.ctes.push((name.into(), query));
+        self
+    }
+
+    pub fn join(mut self, rel: Relation) -> Self {
+        self.inputs.push(Input::Relation(rel));
+        self
+    }
+
+    pub fn join_named(mut self, name: impl Into<String>) -> Self {
+        self.inputs.push(Input::Named(name.into()));
+        self
+    }
+
+    // Outer joins can't be reordered with the joins around them, so they
+    // all run after the inner joins, on their result, in the order they're
+    // added. Only the inner joins are planned.
+    pub fn outer_join(mut self, outer: Outer, rel: Relation) -> Self {
+        self.outer.push((self.inputs.len(), outer));
+        self.join(rel)
+    }
+
+    pub fn outer_join_named(mut self, outer: Outer, name: impl Into<String>) -> Self {
+        self.outer.push((self.inputs.len(), outer));
+        self.join_named(name)
+    }
+
+    // Limits for the whole query, including its named intermediate results.
+    pub fn limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    // Holds the query to `config`'s limits as well as its own, and orders
+    // its joins the way `config` says if it hasn't been given a strategy.
+    pub fn configure(mut self, config: &Config) -> Self {
+        self.limits = self.limits.tightest(config.limits);
+        if let Some(strategy) = config.strategy {
+            self.strategy.get_or_insert(strategy);
+        }
+        self
+    }
+
+    // Semi-join reduces the inputs before joining them, so that no join
+    // produces more rows than the output has. Cyclic queries are first
+    // rewritten into acyclic ones over the bags of a hypertree
+    // decomposition, which bounds the intermediate results by the bags'
+    // sizes instead.
+    pub fn semijoin_reduce(mut self) -> Self {
+        self.reduce = true;
+        self
+    }
+
+    // How the query's hash tables, including those of its named results,
+    // hash their keys. Queries on keys from untrusted sources should use
+    // `Hashing::Random`.
+    pub fn hashing(mut self, hashing: Hashing) -> Self {
+        self.hashing = hashing;
+        self
+    }
+
+    // Has null join keys match each other, rather than nothing as in SQL.
+    // Named results have to ask for this themselves.
+    pub fn null_equals_null(mut self) -> Self {
+        self.nulls = Nulls::Equal;
+        self
+    }
+
+    // Fails the query if any of its joins would be a cross product because
+    // the inputs have no columns in common, rather than running it. Named
+    // results have to ask for this themselves.
+    pub fn forbid_cross_products(mut self) -> Self {
+        self.no_cross_products = true;
+        self
+    }
+
+    // How to order this query's joins, but not those of its named results.
+    pub fn strategy(mut self, strategy: Strategy) -> Self {
+        self.strategy = Some(strategy);
+        self
+    }
+
+    // Orders this query's joins greedily by what `model` says each join
+    // costs, rather than by the number of rows it's estimated to produce.
+    // Named results keep their own strategies.
+    pub fn cost_model(mut self, model: impl CostModel + 'static) -> Self {
+        self.cost_model = Some(Model(Arc::new(model)));
+        self.strategy.get_or_insert(Strategy::Greedy);
+        self
+    }
+
+    // Orders this query's joins greedily by the sizes `estimator` expects
+    // them to have, in place of the catalog's estimates. Named results
+    // keep the catalog's.
+    pub fn cardinality_estimator(mut self, estimator: impl CardinalityEstimator + 'static) -> Self {
+        self.estimator = Some(Estimator(Arc::new(estimator)));
+        self.strategy.get_or_insert(Strategy::Greedy);
+        self
+    }
+
+    // Only outputs the columns `cols`, in that order. Columns that aren't
+    // output and that no other input has are dropped from each input
+    // before it's joined, so intermediate results only hold the columns
+    // something needs. The result of `execute_factorized` isn't projected.
+    pub fn select(mut self, cols: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.select = Some(cols.into_iter().map(Into::into).collect());
+        self
+    }
+
+    // Adds a column `_row_i` for each input `i` of the query, in the order
+    // they were added, with the index of the row of that input each output
+    // row came from. Rows an outer join kept without a match have a null
+    // there. Numbered inputs are copies, so they no longer share hash tables
+    // with other references to the same relation.
+    pub fn provenance(mut self) -> Self {
+        self.provenance = true;
+        self
+    }
+
+    // Checks every relation the query reads from the catalog against its
+    // declared domains, failing before joining anything if a value isn't
+    // in its column's.
+    pub fn check_domains(mut self) -> Self {
+        self.check_domains = true;
+        self
+    }
+
+    // Runs each join a partition at a time, with both sides hash
+    // partitioned on their key into `partitions` files in `dir`, so only
+    // one pair of partitions counts against `max_memory` at once.
+    pub fn spill(mut self, dir: impl Into<PathBuf>, partitions: usize) -> Self {
+        self.spill = Some((dir.into(), partitions));
+        self
+    }
+
+    // If running the query goes over `max_memory`, runs it again spilling
+    // into `dir` like `spill` does, rather than failing. A streaming query
+    // is only run again if it hadn't handed over its columns yet.
+    pub fn retry_spilling(mut self, dir: impl Into<PathBuf>, partitions: usize) -> Self {
+        self.retry_spilling = Some((dir.into(), partitions));
+        self
+    }
+
+    fn spill_join(&self) -> Option<SpillJoin> {
+        let (dir, partitions) = self.spill.as_ref()?;
+        Some(SpillJoin::new(dir, *partitions))
+    }
+
+    // The query to run instead if this one goes over `max_memory`, which is
+    // this one spilling, if `retry_spilling` was set and it doesn't spill
+    // already.
+    fn take_retry(&mut self) -> Option<Query> {
+        let (dir, partitions) = self.retry_spilling.take()?;
+        match self.spill {
+            Some(_) => None,
+            None => Some(self.clone().spill(dir, partitions)),
+        }
+    }
+
+    // The columns this query outputs, if it doesn't output them all.
+    fn selected(&self) -> Option<Vec<String>> {
+        let mut select = self.select.clone()?;
+        if self.provenance {
+            select.extend((0..self.inputs.len()).map(provenance_col));
+        }
+        Some(select)
+    }
+
+    // Joins these named inputs first, in this order, before whatever order
+    // the strategy picks for the rest.
+    pub fn leading(mut self, names: &[&str]) -> Self {
+        self.leading = names.iter().map(|n| n.to_string()).collect();
+        self
+    }
+
+    // Applies the hints that this query understands. Every join against a
+    // named input already probes a hash table on it, so `hash_join` hints
+    // always hold; `leading` sets `leading`.
+    pub fn hints(self, hints: &Hints) -> Self {
+        if hints.leading.is_empty() {
+            return self;
+        }
+        let leading: Vec<&str> = hints.leading.iter().map(|n| n.as_str()).collect();
+        self.leading(&leading)
+    }
+
+    // Keeps the result of each of this query's joins, along with how big
+    // it was estimated to be, for `execute_with_stats` to return.
+    pub fn intermediates(mut self, intermediates: Intermediates) -> Self {
+        self.intermediates = Some(intermediates);
+        self
+    }
+
+    // Runs the query like `execute`, or `execute_with` if there's a
+    // catalog, and returns what it saw along with the result.
+    pub fn execute_with_stats(
+        self,
+        catalog: Option<&mut Catalog>,
+    ) -> Result<(Relation, ExecutionStats), QueryError> {
+        let (result, observed) = match catalog {
+            Some(catalog) => self.run_with(catalog),
+            None => self.run(),
+        };
+        Ok((
+            result?,
+            ExecutionStats {
+                plan: observed.plan,
+                intermediates: observed.intermediates,
+            },
+        ))
+    }
+
+    // Why the result doesn't have a row with these values in these columns,
+    // or nothing if it does. The inputs are cut down to the rows that agree
+    // with `expected` and then joined like usual, and the first input with
+    // no such rows, or the first join that leaves nothing, is what lost the
+    // row. The joins aren't reduced first, so each step sees what it would
+    // have had to match.
+    pub fn why_not(mut self, expected: &[(&str, Value)]) -> Result<Option<WhyNot>, QueryError> {
+        self.reduce = false;
+        self.expect = Some(
+            expected
+                .iter()
+                .map(|(c, v)| (c.to_string(), v.clone()))
+                .collect(),
+        );
+        let budget = Budget {
+            limits: self.limits,
+            start: Instant::now(),
+            hashing: self.hashing,
+            nulls: self.nulls,
+            spill: self.spill_join(),
+        };
+        let mut observed = Observed::default();
+        let result = self.execute_in(
+            &mut HashMap::new(),
+            None,
+            &mut observed,
+            &budget,
+            true,
+            None,
+        )?;
+        if observed.why_not.is_some() {
+            return Ok(observed.why_not);
+        }
+        let positions: Vec<_> = expected
+            .iter()
+            .map(|(c, v)| (result.col_names.iter().position(|x| x == c), v))
+            .collect();
+        let found = result.data.iter().any(|row| {
+            positions
+                .iter()
+                .all(|(p, v)| p.is_some_and(|p| row[p] == **v))
+        });
+        Ok((!found).then_some(WhyNot::Later))
+    }
+
+    // Runs the query once with each strategy, everywhere in the query, and
+    // panics if they don't give the same rows. The order of the columns and
+    // rows may differ, but nothing else should.
+    pub fn validate_strategies(mut self) -> Self {
+        self.validate = true;
+        self
+    }
+
+    fn with_strategy_everywhere(mut self, strategy: Strategy) -> Self {
+        self.strategy = Some(strategy);
+        self.ctes = self
+            .ctes
+            .into_iter()
+            .map(|(name, query)| (name, query.with_strategy_everywhere(strategy)))
+            .collect();
+        self
+    }
+
+    // A rough size in bytes of the query's inputs, used to decide how many
+    // queries can run at once.
+    pub fn memory_estimate(&self) -> usize {
+        let inputs: usize = self
+            .inputs
+            .iter()
+            .map(|input| match input {
+                Input::Relation(rel) => size(rel),
+                Input::Named(_) => 0,
+            })
+            .sum();
+        let ctes: usize = self.ctes.iter().map(|(_, q)| q.memory_estimate()).sum();
+        inputs + ctes
+    }
+
+    pub fn execute(self) -> Result<Relation, QueryError> {
+        self.run().0
+    }
+
+    // Runs the query with `run`, once with each strategy if it's validating
+    // them, and returns what the first run saw.
+    fn validated(
+        mut self,
+        mut run: impl FnMut(Query) -> (Result<Relation, QueryError>, Observed),
+    ) -> (Result<Relation, QueryError>, Observed) {
+        if !self.validate {
+            return run(self);
+        }
+        self.validate = false;
+        let (bfs, observed) = run(self.clone().with_strategy_everywhere(Strategy::Bfs));
+        let bfs = match bfs {
+            Ok(bfs) => bfs,
+            Err(e) => return (Err(e), observed),
+        };
+        for strategy in [Strategy::Greedy, Strategy::Dp, Strategy::Bushy] {
+            match run(self.clone().with_strategy_everywhere(strategy)).0 {
+                Ok(other) => assert_eq!(
+                    canonical(&bfs),
+                    canonical(&other),
+                    "join strategies gave different results"
+                ),
+                Err(e) => return (Err(e), observed),
+            }
+        }
+        (Ok(bfs), observed)
+    }
+
+    // Runs the query without a catalog, and returns what it saw along with
+    // the result.
+    fn run(self) -> (Result<Relation, QueryError>, Observed) {
+        self.validated(Query::run_once)
+    }
+
+    fn run_once(mut self) -> (Result<Relation, QueryError>, Observed) {
+        let retry = self.take_retry();
+        let budget = Budget {
+            limits: self.limits,
+            start: Instant::now(),
+            hashing: self.hashing,
+            nulls: self.nulls,
+            spill: self.spill_join(),
+        };
+        let mut observed = Observed::default();
+        let result = self.execute_in(
+            &mut HashMap::new(),
+            None,
+            &mut observed,
+            &budget,
+            true,
+            None,
+        );
+        match (result, retry) {
+            (Err(QueryError::Memory { .. }), Some(retry)) => retry.run_once(),
+            (result, _) => (result, observed),
+        }
+    }
+
+    // Runs the query with names that aren't defined by the query looked up
+    // in `catalog`. The joins are ordered by the catalog's estimates, and
+    // what the query saw of the catalog's relations is recorded back into
+    // it.
+    pub fn execute_with(self, catalog: &mut Catalog) -> Result<Relation, QueryError> {
+        self.run_with(catalog).0
+    }
+
+    // Runs the query against `catalog` like `execute_with`, and records the
+    // plan it ran with in `history` under `name`. If that isn't the plan
+    // recorded for `name` before, returns how it changed.
+    pub fn execute_tracked(
+        self,
+        name: &str,
+        catalog: &mut Catalog,
+        history: &mut PlanHistory,
+    ) -> Result<(Relation, Option<PlanDiff>), QueryError> {
+        let (result, observed) = self.run_with(catalog);
+        let rel = result?;
+        Ok((rel, history.record(name, observed.plan)))
+    }
+
+    // Like `execute_tracked`, with the query's fingerprint as its name, so
+    // that queries of the same shape share a history.
+    pub fn execute_fingerprinted(
+        self,
+        catalog: &mut Catalog,
+        history: &mut PlanHistory,
+    ) -> Result<(Relation, Option<PlanDiff>), QueryError> {
+        let name = format!("{:016x}", self.fingerprint());
+        self.execute_tracked(&name, catalog, history)
+    }
+
+    // A hash of the query's shape: which relations it joins and how its
+    // definitions refer to each other, but not the rows of the relations
+    // given to it, the names of its definitions, the order of its inputs,
+    // or any of its options. Queries that only differ in those get the same
+    // fingerprint, which stays the same from one build to the next.
+    pub fn fingerprint(&self) -> u64 {
+        // FNV-1a.
+        let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+        for b in self.shape(&mut Vec::new()).bytes() {
+            hash = (hash ^ b as u64).wrapping_mul(0x0100_0000_01b3);
+        }
+        hash
+    }
+
+    // A description of the query's shape with the definitions in scope
+    // named by where they are in `scope`, and catalog relations by name.
+    fn shape(&self, scope: &mut Vec<String>) -> String {
+        let depth = scope.len();
+        let mut ctes = Vec::new();
+        for (name, query) in self.ctes.iter() {
+            ctes.push(query.shape(scope));
+            scope.push(name.clone());
+        }
+        let mut inputs = Vec::new();
+        let mut outer = Vec::new();
+        for (i, input) in self.inputs.iter().enumerate() {
+            let shape = match input {
+                Input::Relation(rel) => {
+                    let mut cols = rel.col_names.clone();
+                    cols.sort();
+                    format!("({})", cols.join(","))
+                }
+                Input::Named(name) => match scope.iter().rposition(|s| s == name) {
+                    Some(i) => format!("${}", i),
+                    None => format!("{:?}", name),
+                },
+            };
+            match self.outer.iter().find(|(o, _)| *o == i) {
+                Some((_, kind)) => outer.push(format!("{:?} {}", kind, shape)),
+                None => inputs.push(shape),
+            }
+        }
+        inputs.sort();
+        scope.truncate(depth);
+        let shape = format!("with [{}] join [{}]", ctes.join(" "), inputs.join(" "));
+        match outer.is_empty() {
+            true => shape,
+            false => format!("{} then [{}]", shape, outer.join(" ")),
+        }
+    }
+
+    // Runs the query against `catalog` and records what it saw there,
+    // returning what was recorded as well as what wasn't. A run that's
+    // retried spilling only records what the retry saw.
+    fn run_with(self, catalog: &mut Catalog) -> (Result<Relation, QueryError>, Observed) {
+        self.validated(|query| query.run_with_once(catalog))
+    }
+
+    fn run_with_once(mut self, catalog: &mut Catalog) -> (Result<Relation, QueryError>, Observed) {
+        let retry = self.take_retry();
+        let mut names = Vec::new();
+        self.names(&mut names);
+        let limits = names
+            .iter()
+            .flat_map(|name| catalog.limits_for(name))
+            .chain(catalog.limits_for(""))
+            .fold(self.limits, Limits::tightest);
+        let budget = Budget {
+            limits,
+            start: Instant::now(),
+            hashing: self.hashing,
+            nulls: self.nulls,
+            spill: self.spill_join(),
+        };
+        let mut observed = Observed::default();
+        let result = self.execute_in(
+            &mut HashMap::new(),
+            Some(catalog),
+            &mut observed,
+            &budget,
+            true,
+            None,
+        );
+        if let (Err(QueryError::Memory { .. }), Some(retry)) = (&result, retry) {
+            return retry.run_with_once(catalog);
+        }
+        let plan = std::mem::take(&mut observed.plan);
+        let intermediates = std::mem::take(&mut observed.intermediates);
+        catalog.record(observed);
+        let unrecorded = Observed {
+            plan,
+            intermediates,
+            ..Observed::default()
+        };
+        (result, unrecorded)
+    }
+
+    // Runs the query, but leaves the result factorized over the inputs
+    // rather than listing out every row. Only acyclic queries without outer
+    // joins can be factorized this way.
+    pub fn execute_factorized(self) -> Result<Factorized, QueryError> {
+        if !self.outer.is_empty() {
+            return Err(QueryError::OuterJoin);
+        }
+        let budget = Budget {
+            limits: self.limits,
+            start: Instant::now(),
+            hashing: self.hashing,
+            nulls: self.nulls,
+            spill: self.spill_join(),
+        };
+        let mut env = HashMap::new();
+        for (name, query) in self.ctes {
+            let rel = query.execute_in(
+                &mut env,
+                None,
+                &mut Observed::default(),
+                &budget,
+                false,
+                None,
+            )?;
+            env.insert(name, rel);
+        }
+        let rels = self
+            .inputs
+            .into_iter()
+            .map(|input| match input {
+                Input::Relation(rel) => Ok(rel),
+                Input::Named(name) => match env.get(&name) {
+                    Some(rel) => Ok(rel.clone()),
+                    None => Err(QueryError::UnknownRelation(name)),
+                },
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Factorized::new(rels).ok_or(QueryError::Cyclic)
+    }
+
+    // `n` rows drawn uniformly at random from the result, with replacement,
+    // without computing the whole result.
+    #[cfg(feature = "rand")]
+    pub fn sample_result(self, n: usize) -> Result<Relation, QueryError> {
+        Ok(self
+            .execute_factorized()?
+            .sample(n, &mut rand::thread_rng()))
+    }
+
+    // Runs the query, handing over the output in batches of `batch_rows`
+    // rows as the last join produces them rather than all at once at the
+    // end. The query runs on a thread of its own, which waits for the
+    // caller whenever `STREAM_BATCHES` batches haven't been taken yet.
+    // Errors from before the last join has any rows are returned straight
+    // away, and any from after that end the batches. A query that spills
+    // hands over its output once it's all been joined.
+    pub fn execute_streaming(self, batch_rows: usize) -> Result<Batches, QueryError> {
+        let (header_tx, header) = mpsc::sync_channel(1);
+        let (batches, rx) = mpsc::sync_channel(STREAM_BATCHES);
+        let mut stream = Stream {
+            batch_rows: batch_rows.max(1),
+            select: self.selected(),
+            header: Some(header_tx),
+            batches,
+        };
+        let worker = thread::spawn(move || {
+            let mut query = self;
+            let result = if query.validate {
+                query.execute()
+            } else {
+                let mut retry = query.take_retry();
+                loop {
+                    let budget = Budget {
+                        limits: query.limits,
+                        start: Instant::now(),
+                        hashing: query.hashing,
+                        nulls: query.nulls,
+                        spill: query.spill_join(),
+                    };
+                    let result = query.execute_in(
+                        &mut HashMap::new(),
+                        None,
+                        &mut Observed::default(),
+                        &budget,
+                        true,
+                        Some(&mut stream),
+                    );
+                    // Once the header's gone so have some rows, so the query
+                    // can only start over before that.
+                    match (result, retry.take()) {
+                        (Err(QueryError::Memory { .. }), Some(next)) if stream.header.is_some() => {
+                            query = next
+                        }
+                        (result, _) => break result,
+                    }
+                }
+            };
+            match (result, stream.header.take()) {
+                // The query didn't end with a join, so its whole output is
+                // already here.
+                (Ok(rel), Some(header)) => {
+                    let _ = header.send(Ok(rel.col_names));
+                    for rows in rel.data.chunks(stream.batch_rows) {
+                        if stream.batches.send(Ok(rows.to_vec())).is_err() {
+                            break;
+                        }
+                    }
+                }
+                (Ok(_), None) => {}
+                (Err(e), Some(header)) => {
+                    let _ = header.send(Err(e));
+                }
+                (Err(e), None) => {
+                    let _ = stream.batches.send(Err(e));
+                }
+            }
+        });
+        let col_names = match header.recv() {
+            Ok(col_names) => col_names?,
+            // The query panicked, since it always sends a header otherwise.
+            Err(_) => {
+                let message = worker.join().err().map_or_else(
+                    || "the query stopped before sending its columns".to_string(),
+                    panic_message,
+                );
+                return Err(QueryError::Panicked(message));
+            }
+        };
+        Ok(Batches {
+            col_names,
+            rx,
+            worker: Some(worker),
+        })
+    }
+
+    // Every name the query or its definitions join.
+    fn names<'a>(&'a self, names: &mut Vec<&'a str>) {
+        for (_, query) in self.ctes.iter() {
+            query.names(names);
+        }
+        for input in self.inputs.iter() {
+            if let Input::Named(name) = input {
+                names.push(name);
+            }
+        }
+    }
+
+    fn uses(&self, name: &str) -> bool {
+        uses(&self.ctes, &self.inputs, name)
+    }
+
+    fn execute_in(
+        self,
+        env: &mut HashMap<String, Relation>,
+        catalog: Option<&Catalog>,
+        observed: &mut Observed,
+        budget: &Budget,
+        output: bool,
+        mut stream: Option<&mut Stream>,
+    ) -> Result<Relation, QueryError> {
+        // Each definition is materialized exactly once, no matter how many
+        // times it's referenced, and spooled for every reference to it or
+        // to a copy of it. Definitions shadow outer ones of the same name
+        // for the rest of this query only.
+        let spools = spools(&self.ctes, &self.inputs);
+        let selected = self.selected();
+        let mut names = Vec::new();
+        let mut shadowed = Vec::new();
+        let mut result = Ok(());
+        for ((name, query), spool) in self.ctes.into_iter().zip(spools) {
+            names.push(name.clone());
+            let rel = match spool {
+                Spool::Skip => continue,
+                Spool::Replay(i) => {
+                    if let Some(lineage) = observed.lineages.get(&names[i]).cloned() {
+                        observed.lineages.insert(name.clone(), lineage);
+                    }
+                    Ok(env[&names[i]].clone())
+                }
+                Spool::Run => query
+                    .execute_in(env, catalog, observed, budget, false, None)
+                    .inspect(|_| {
+                        let lineage = std::mem::take(&mut observed.lineage);
+                        observed.lineages.insert(name.clone(), lineage);
+                    }),
+            };
+            match rel {
+                Ok(rel) => shadowed.push((name.clone(), env.insert(name, rel))),
+                Err(e) => {
+                    result = Err(e);
+                    break;
+                }
+            }
+        }
+
+        let result = result.and_then(|()| {
+            // Named results only give the planner their columns; their rows
+            // stay in `env` so that every join against one can share a single
+            // hash table per set of key columns.
+            let mut planner = Planner::default();
+            let mut inputs = Vec::new();
+            // The name of each input that's a relation from the catalog.
+            let mut sources = Vec::new();
+            let mut outer = Vec::new();
+            let mut resolved = Vec::new();
+            for input in self.inputs.into_iter() {
+                resolved.push(match input {
+                    Input::Relation(rel) => (None, Cow::Owned(rel), None),
+                    Input::Named(name) => match (env.get(&name), catalog) {
+                        (Some(rel), _) => (Some(name), Cow::Borrowed(rel), None),
+                        (None, Some(catalog)) => match catalog.scan(&name) {
+                            Some(rel) if self.check_domains => {
+                                rel.check_domains(&catalog.domains(&name))
+                                    .map_err(QueryError::Domain)?;
+                                (Some(name.clone()), rel, Some(name))
+                            }
+                            Some(rel) => (Some(name.clone()), rel, Some(name)),
+                            None => return Err(QueryError::UnknownRelation(name)),
+                        },
+                        (None, None) => return Err(QueryError::UnknownRelation(name)),
+                    },
+                });
+            }
+            let origins: Vec<_> = resolved
+                .iter()
+                .map(|(name, rel, _)| {
+                    let label = name
+                        .clone()
+                        .unwrap_or_else(|| format!("({})", rel.col_names.join(",")));
+                    (name.clone(), label, rel.col_names.clone())
+                })
+                .collect();
+            if self.provenance {
+                for (i, (_, rel, _)) in resolved.iter_mut().enumerate() {
+                    let mut numbered =
+                        Relation::new(rel.col_names.iter().cloned().chain([provenance_col(i)]));
+                    numbered.data = rel
+                        .data
+                        .iter()
+                        .enumerate()
+                        .map(|(n, row)| {
+                            let mut row = row.clone();
+                            row.push(Value::Int(n as i64));
+                            row
+                        })
+                        .collect();
+                    *rel = Cow::Owned(numbered);
+                }
+            }
+            if let Some(select) = &selected {
+                prune(&mut resolved, select);
+            }
+            if let Some(catalog) = catalog {
+                restrict(&mut resolved, &self.outer, catalog);
+            }
+            if let Some(expect) = self.expect.as_ref().filter(|_| output) {
+                if let Some((col, _)) = expect
+                    .iter()
+                    .find(|(c, _)| !resolved.iter().any(|(_, rel, _)| rel.col_names.contains(c)))
+                {
+                    observed.why_not = Some(WhyNot::UnknownColumn(col.clone()));
+                }
+                for (i, (name, rel, _)) in resolved.iter_mut().enumerate() {
+                    if self.outer.iter().any(|(o, _)| *o == i) {
+                        continue;
+                    }
+                    let checks: Vec<_> = expect
+                        .iter()
+                        .filter_map(|(c, v)| rel.col_names.iter().position(|x| x == c).zip(Some(v)))
+                        .collect();
+                    if checks.is_empty() {
+                        continue;
+                    }
+                    let kept: Vec<_> = rel
+                        .data
+                        .iter()
+                        .filter(|row| checks.iter().all(|(p, v)| row[*p] == **v))
+                        .cloned()
+                        .collect();
+                    if kept.is_empty() && observed.why_not.is_none() {
+                        observed.why_not = Some(WhyNot::NoRows {
+                            input: name
+                                .clone()
+                                .unwrap_or_else(|| format!("({})", rel.col_names.join(","))),
+                            expected: checks
+                                .iter()
+                                .map(|(p, v)| (rel.col_names[*p].clone(), (*v).clone()))
+                                .collect(),
+                        });
+                    }
+                    *rel = Cow::Owned(Relation::new_with_data(rel.col_names.iter().cloned(), kept));
+                }
+            }
+            for (i, (name, rel, source)) in resolved.into_iter().enumerate() {
+                if let Some((_, kind)) = self.outer.iter().find(|(o, _)| *o == i) {
+                    outer.push((*kind, name, rel));
+                    continue;
+                }
+                planner = planner.join(Relation::new(rel.col_names.iter().cloned()));
+                inputs.push(Some((name, rel)));
+                sources.push(source);
+            }
+            if self.reduce {
+                let mut rels: Vec<_> = inputs
+                    .iter_mut()
+                    .map(|input| input.take().unwrap().1.into_owned())
+                    .collect();
+                if !yannakakis::reduce(&mut rels, &planner.query_graph, budget.nulls) {
+                    // A cyclic query is joined bag by bag first, which
+                    // leaves an acyclic query over the bags.
+                    rels = Decomposition::new(&rels).bags(
+                        &rels,
+                        budget.hashing,
+                        budget.nulls,
+                        |rows, width| budget.check(rows, width, 0, false),
+                    )?;
+                    planner = rels.iter().fold(Planner::default(), |planner, rel| {
+                        planner.join(Relation::new(rel.col_names.iter().cloned()))
+                    });
+                    yannakakis::reduce(&mut rels, &planner.query_graph, budget.nulls);
+                }
+                // What's joined now are reduced copies, not the catalog's
+                // relations.
+                sources = vec![None; rels.len()];
+                inputs = rels
+                    .into_iter()
+                    .map(|rel| Some((None, Cow::Owned(rel))))
+                    .collect();
+            }
+            let mut indexes: HashMap<(String, Vec<String>), HashIndex> = HashMap::new();
+
+            let empty = Catalog::new();
+            let strategy = match (self.strategy, catalog) {
+                (Some(strategy), _) => strategy,
+                (None, Some(_)) => Strategy::Greedy,
+                (None, None) => Strategy::Dp,
+            };
+            let estimate = |rels: &[usize]| {
+                let rels: Vec<_> = rels
+                    .iter()
+                    .map(|i| {
+                        (
+                            sources[*i].as_deref(),
+                            inputs[*i].as_ref().unwrap().1.as_ref(),
+                        )
+                    })
+                    .collect();
+                let catalog = catalog.unwrap_or(&empty);
+                match &self.estimator {
+                    Some(Estimator(estimator)) => estimator.estimate(catalog, &rels),
+                    None => catalog.estimate(&rels),
+                }
+            };
+            let width = |rels: &[usize]| {
+                let mut cols: Vec<&String> = rels
+                    .iter()
+                    .flat_map(|i| inputs[*i].as_ref().unwrap().1.col_names.iter())
+                    .collect();
+                cols.sort();
+                cols.dedup();
+                cols.len()
+            };
+            // The cost of the join that adds the last relation to the rest,
+            // with the hash table built on the one being added.
+            let join_cost = |model: &Arc<dyn CostModel>, rels: &[usize]| {
+                let (next, joined) = rels.split_last().unwrap();
+                let next = (estimate(&[*next]), width(&[*next]));
+                if joined.is_empty() {
+                    return model.scan(next.0, next.1);
+                }
+                let joined = (estimate(joined), width(joined));
+                model.hash_join(joined, next, estimate(rels), width(rels))
+            };
+            let order = match (strategy, &self.cost_model) {
+                (Strategy::Greedy, None) => planner.greedy_order(estimate),
+                (Strategy::Greedy, Some(Model(model))) => {
+                    planner.greedy_order(|rels| join_cost(model, rels))
+                }
+                (Strategy::Dp, None) => planner.dp_order(estimate),
+                (Strategy::Dp, Some(Model(model))) => {
+                    planner.dp_order(|rels| join_cost(model, rels))
+                }
+                (Strategy::Bfs, _) => planner.order(),
+                (Strategy::Bushy, _) => vec![],
+            };
+            let tree = match strategy {
+                Strategy::Bushy => Some(planner.bushy_order(estimate)),
+                Strategy::Dp if inputs.len() > MAX_DP_RELATIONS => {
+                    Some(planner.goo_order(estimate))
+                }
+                _ => None,
+            };
+            // A bushy plan is joined down its left, with each right side
+            // that's a join of its own done first, and then joined like an
+            // input that comes after the rest.
+            let mut subtrees = Vec::new();
+            let order = match &tree {
+                None => order,
+                Some(tree) => {
+                    let (first, rights) = tree.spine();
+                    let mut order = vec![first];
+                    for right in rights {
+                        match right {
+                            JoinTree::Leaf(i) => order.push(*i),
+                            join => {
+                                order.push(inputs.len() + subtrees.len());
+                                subtrees.push(join.clone());
+                            }
+                        }
+                    }
+                    order
+                }
+            };
+            // The inputs that go into each of `order`.
+            let base = |i: usize| match i.checked_sub(inputs.len()) {
+                Some(subtree) => subtrees[subtree].relations(),
+                None => vec![i],
+            };
+            let mut order = order;
+            order.sort_by_key(|i| {
+                let name = inputs
+                    .get(*i)
+                    .and_then(|input| input.as_ref().unwrap().0.as_ref());
+                let position = self.leading.iter().position(|l| Some(l) == name);
+                position.unwrap_or(self.leading.len())
+            });
+            // An empty inner input makes the result empty, so then the
+            // joins only work out the columns they'd have had, without
+            // touching any rows. Kept intermediates and the search for a
+            // missing row need what each join really made, so they still
+            // go the long way.
+            let empty = self.intermediates.is_none()
+                && self.expect.is_none()
+                && inputs
+                    .iter()
+                    .any(|input| input.as_ref().unwrap().1.data.is_empty());
+            let estimates: Vec<f64> = match (&self.intermediates, output) {
+                (Some(_), true) => (0..order.len())
+                    .map(|s| {
+                        estimate(
+                            &order[..=s]
+                                .iter()
+                                .flat_map(|i| base(*i))
+                                .collect::<Vec<_>>(),
+                        )
+                    })
+                    .collect(),
+                _ => vec![],
+            };
+            for subtree in subtrees {
+                let rel = subtree.try_execute(
+                    &mut |i| {
+                        let rel = inputs[i].take().unwrap().1;
+                        Ok(match empty {
+                            true => Relation::new(rel.col_names.iter().cloned()),
+                            false => rel.into_owned(),
+                        })
+                    },
+                    &mut |left, right| join_sides(budget, self.no_cross_products, left, right),
+                )?;
+                inputs.push(Some((None, Cow::Owned(rel))));
+                sources.push(None);
+            }
+            let mut labels = Vec::new();
+            let mut result: Option<Relation> = None;
+            // What to call the result so far in errors.
+            let mut prev_label = String::new();
+            for (step, i) in order.iter().enumerate() {
+                let top = output;
+                let output = output && outer.is_empty() && step + 1 == order.len();
+                let (name, next) = inputs[*i].take().unwrap();
+                let label = name
+                    .clone()
+                    .unwrap_or_else(|| format!("({})", next.col_names.join(",")));
+                labels.push(label.clone());
+                if top {
+                    observed.plan.steps.push(PlanStep {
+                        input: label.clone(),
+                        key: result
+                            .as_ref()
+                            .map_or(vec![], |prev| prev.common_cols(&next)),
+                    });
+                }
+                let Some(prev) = result.take() else {
+                    let first = match empty {
+                        true => Relation::new(next.col_names.iter().cloned()),
+                        false => next.into_owned(),
+                    };
+                    if output {
+                        budget.check(first.data.len(), 0, size(&first), true)?;
+                    }
+                    result = Some(first);
+                    prev_label = label;
+                    continue;
+                };
+
+                let held = size(&prev) + size(&next);
+                let key = prev.common_cols(&next);
+                if key.is_empty() && self.no_cross_products {
+                    return Err(QueryError::NoSharedColumns {
+                        suggestions: suggest_join_keys(&prev, &next)
+                            .into_iter()
+                            .map(|s| (s.left, s.right))
+                            .collect(),
+                        left: prev_label,
+                        right: label,
+                    });
+                }
+                prev_label = "result".to_string();
+                let width = prev.col_names.len() + next.col_names.len() - key.len();
+                let check = |rows| {
+                    budget.check(rows, width, held, output)?;
+                    budget.check_growth(rows, &prev, &next, &key)
+                };
+                // A spilled join only has its rows once every partition's
+                // been joined, so there's nothing to stream.
+                let streamed = output && budget.spill.is_none();
+                if let Some(stream) = stream.as_deref_mut().filter(|_| streamed) {
+                    let empty;
+                    let local;
+                    let index = match (name, &next) {
+                        // Nothing will probe it.
+                        _ if prev.data.is_empty() => {
+                            empty = Relation::new(next.col_names.iter().cloned());
+                            local = empty.index_with(&key, budget.hashing, budget.nulls);
+                            &local
+                        }
+                        (Some(name), Cow::Borrowed(rel)) => &*indexes
+                            .entry((name, key.clone()))
+                            .or_insert_with_key(|(_, key)| {
+                                rel.index_with(key, budget.hashing, budget.nulls)
+                            }),
+                        (_, next) => {
+                            local = next.index_with(&key, budget.hashing, budget.nulls);
+                            &local
+                        }
+                    };
+                    return stream.send_join(index, &prev, check);
+                }
+                let joined = match (name, &next) {
+                    // Nothing can match, so there's no need for an index,
+                    // only the columns the join would have had.
+                    (Some(_), Cow::Borrowed(rel))
+                        if budget.spill.is_none()
+                            && (prev.data.is_empty() || rel.data.is_empty()) =>
+                    {
+                        empty_join(rel, &prev)
+                    }
+                    (_, next) if prev.data.is_empty() || next.data.is_empty() => {
+                        empty_join(&prev, next)
+                    }
+                    _ if budget.spill.is_some() => {
+                        budget.join_spilled(&prev, &next, &key, width, output)?
+                    }
+                    (Some(name), Cow::Borrowed(rel)) => {
+                        let index =
+                            indexes
+                                .entry((name, key.clone()))
+                                .or_insert_with_key(|(_, key)| {
+                                    rel.index_with(key, budget.hashing, budget.nulls)
+                                });
+                        if let (Some(source), [col]) = (&sources[*i], index.key_cols.as_slice()) {
+                            observed
+                                .ndv
+                                .push((source.clone(), col.clone(), index.keys()));
+                        }
+                        index.try_join(&prev, check)?
+                    }
+                    (_, next) => prev.try_join_with(next, budget.hashing, budget.nulls, check)?,
+                };
+                if top
+                    && self.expect.is_some()
+                    && joined.data.is_empty()
+                    && observed.why_not.is_none()
+                {
+                    let keys = prev.project(key.iter().cloned()).distinct();
+                    observed.why_not = Some(WhyNot::NoMatch {
+                        step,
+                        input: labels.last().unwrap().clone(),
+                        key: key.clone(),
+                        unmatched: keys.data,
+                    });
+                }
+                // Joins that skipped their rows for an empty input didn't see
+                // how many they'd have made without it.
+                let names: Option<Vec<_>> =
+                    order[..=step].iter().map(|i| sources[*i].clone()).collect();
+                if let (Some(names), false) = (names, empty) {
+                    observed.joins.push((names, joined.data.len()));
+                }
+                if let (Some(keep), true) = (&self.intermediates, top) {
+                    let mut intermediate = Intermediate {
+                        inputs: labels.clone(),
+                        estimated_rows: estimates[step],
+                        rows: joined.data.len(),
+                        result: None,
+                        path: None,
+                    };
+                    match keep {
+                        Intermediates::Keep => intermediate.result = Some(joined.clone()),
+                        Intermediates::Dump(dir) => {
+                            let path = dir.join(format!("step_{}.nbjr", step));
+                            std::fs::create_dir_all(dir)
+                                .and_then(|()| joined.save(&path))
+                                .map_err(|e| {
+                                    QueryError::Io(format!("{}: {}", path.display(), e))
+                                })?;
+                            intermediate.path = Some(path);
+                        }
+                    }
+                    observed.intermediates.push(intermediate);
+                }
+                result = Some(joined);
+            }
+
+            let mut result = result.unwrap_or_default();
+            for (kind, name, next) in outer.iter() {
+                if output {
+                    observed.plan.steps.push(PlanStep {
+                        input: name
+                            .clone()
+                            .unwrap_or_else(|| format!("({})", next.col_names.join(","))),
+                        key: result.common_cols(next),
+                    });
+                }
+                let (left, right) = kind.keeps();
+                result = result.outer_join(next, budget.nulls, left, right);
+            }
+            if output && !outer.is_empty() {
+                budget.check(result.data.len(), result.col_names.len(), 0, true)?;
+            }
+            let result = match selected {
+                Some(select) => result.project(select),
+                None => result,
+            };
+            let lineage = lineage(&origins, &observed.lineages, &result.col_names);
+            match output {
+                true => observed.plan.lineage = lineage,
+                false => observed.lineage = lineage,
+            }
+            Ok(result)
+        });
+
+        for (name, previous) in shadowed.into_iter().rev() {
+            match previous {
+                Some(rel) => env.insert(name, rel),
+                None => env.remove(&name),
+            };
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scratch::Scratch;
+
+    fn chain() -> (Relation, Relation, Relation) {
+        let a = Relation::new(["x", "y"]).rows([[1, 2], [3, 4]]);
+        let b = Relation::new(["y", "z"]).rows([[2, 5], [4, 6]]);
+        let c = Relation::new(["z", "w"]);
+        (a, b, c)
+    }
+
+    fn tight() -> Query {
+        Query::new()
+            .join(Relation::new(["a", "b"]).rows((0..1000).map(|i| vec![i, i])))
+            .join(Relation::new(["b", "c"]).rows((0..1000).map(|i| vec![i, i])))
+            .limits(Limits {
+                max_memory: Some(150_000),
+                ..Limits::default()
+            })
+    }
+
+    fn retrying(dir: &Scratch, name: &str) -> Query {
+        tight().retry_spilling(dir.join(name), 8)
+    }
+
+    #[test]
+    fn retry_spilling_applies_however_the_query_runs() {
+        let dir = Scratch::new("retry");
+        assert!(matches!(tight().execute(), Err(QueryError::Memory { .. })));
+        assert_eq!(
+            retrying(&dir, "execute").execute().unwrap().data.len(),
+            1000
+        );
+        let rows = retrying(&dir, "with")
+            .execute_with(&mut Catalog::new())
+            .unwrap()
+            .data
+            .len();
+        assert_eq!(rows, 1000);
+        let (rel, _) = retrying(&dir, "stats").execute_with_stats(None).unwrap();
+        assert_eq!(rel.data.len(), 1000);
+        // One batch, so nothing's been handed over when it runs out.
+        let batches = retrying(&dir, "streaming").execute_streaming(1000).unwrap();
+        let rows: usize = batches.map(|b| b.unwrap().len()).sum();
+        assert_eq!(rows, 1000);
+    }
+
+    // A chain that's best joined from both ends at once, so its bushy plan
+    // joins the middle on its own first. Its middle has a null in the
+    // column it's joined on.
+    fn ends() -> Query {
+        let c = |i: i64| match i {
+            0 => Value::Null,
+            i => Value::Int(i % 2),
+        };
+        Query::new()
+            .join(Relation::new(["a", "b"]).rows((0..1000).map(|i| vec![i, i % 100])))
+            .join(Relation::new(["b", "c"]).rows((0..10).map(|i| vec![Value::Int(i), c(i)])))
+            .join(Relation::new(["c", "d"]).rows((0..10).map(|i| vec![c(i), Value::Int(i)])))
+            .join(Relation::new(["d", "e"]).rows((0..1000).map(|i| vec![i % 100, i])))
+    }
+
+    #[test]
+    fn bushy_plans_join_like_left_deep_ones() {
+        let (bushy, stats) = ends()
+            .strategy(Strategy::Bushy)
+            .execute_with_stats(None)
+            .unwrap();
+        // The first input and the join of the other three.
+        assert_eq!(stats.plan().steps.len(), 2);
+        let bfs = ends().strategy(Strategy::Bfs).execute().unwrap();
+        assert!(!bfs.data.is_empty());
+        assert_eq!(canonical(&bushy), canonical(&bfs));
+        // The nulls in the middle only match when the query says so.
+        let nulls = |strategy| {
+            ends()
+                .null_equals_null()
+                .strategy(strategy)
+                .execute()
+                .unwrap()
+        };
+        let (bushy, bfs_nulls) = (nulls(Strategy::Bushy), nulls(Strategy::Bfs));
+        assert!(bfs_nulls.data.len() > bfs.data.len());
+        assert_eq!(canonical(&bushy), canonical(&bfs_nulls));
+    }
+
+    #[test]
+    fn cyclic_queries_join_their_bags_with_the_querys_nulls() {
+        let null_or = |i: i64| match i % 4 {
+            0 => Value::Null,
+            _ => Value::from(i % 3),
+        };
+        // The nulls are in `c`, which the relations joined into the same
+        // bag share.
+        let square = || {
+            Query::new()
+                .join(Relation::new(["a", "b"]).rows((0..6).map(|i| [i % 2, i % 3])))
+                .join(
+                    Relation::new(["b", "c"])
+                        .rows((0..12).map(|i| [Value::from(i % 3), null_or(i)])),
+                )
+                .join(
+                    Relation::new(["c", "d"])
+                        .rows((0..12).map(|i| [null_or(i), Value::from(i % 2)])),
+                )
+                .join(Relation::new(["d", "a"]).rows((0..4).map(|i| [i / 2, i % 2])))
+        };
+        for equal in [false, true] {
+            let run = |query: Query| match equal {
+                true => query.null_equals_null().execute().unwrap(),
+                false => query.execute().unwrap(),
+            };
+            let reduced = run(square().semijoin_reduce());
+            let plain = run(square());
+            assert_eq!(
+                canonical(&reduced),
+                canonical(&plain),
+                "nulls equal: {}",
+                equal
+            );
+            let c = plain.col_names.iter().position(|c| c == "c").unwrap();
+            assert_eq!(plain.data.iter().any(|row| row[c].is_null()), equal);
+        }
+    }
+
+    #[test]
+    fn bushy_plans_keep_to_the_limits() {
+        let limited = |strategy| {
+            ends()
+                .strategy(strategy)
+                .limits(Limits {
+                    max_intermediate_rows: Some(20),
+                    ..Limits::default()
+                })
+                .execute()
+        };
+        assert!(matches!(
+            limited(Strategy::Bushy),
+            Err(QueryError::IntermediateRows { limit: 20, .. })
+        ));
+    }
+
+    #[test]
+    fn dp_past_its_limit_plans_bushy() {
+        let chain = (0..MAX_DP_RELATIONS + 2).fold(Query::new(), |query, i| {
+            query.join(Relation::new_with_data(
+                [format!("c{}", i), format!("c{}", i + 1)],
+                (0..4).map(|j| vec![Value::Int(j), Value::Int((j + i as i64) % 4)]),
+            ))
+        });
+        let (dp, stats) = chain
+            .clone()
+            .strategy(Strategy::Dp)
+            .execute_with_stats(None)
+            .unwrap();
+        assert!(stats.plan().steps.len() < MAX_DP_RELATIONS + 2);
+        let greedy = chain.strategy(Strategy::Greedy).execute().unwrap();
+        assert_eq!(canonical(&dp), canonical(&greedy));
+    }
+
+    #[test]
+    fn empty_input_keeps_the_join_graph() {
+        let (a, b, c) = chain();
+        let result = Query::new()
+            .join(c)
+            .join(a)
+            .join(b)
+            .forbid_cross_products()
+            .strategy(Strategy::Bfs)
+            .execute()
+            .unwrap();
+        assert!(result.data.is_empty());
+        let mut cols = result.col_names.clone();
+        cols.sort();
+        assert_eq!(cols, ["w", "x", "y", "z"]);
+    }
+
+    #[test]
+    fn empty_input_keeps_the_leading_order() {
+        let (a, b, c) = chain();
+        let mut catalog = Catalog::new();
+        catalog.insert("a", a);
+        catalog.insert("b", b);
+        catalog.insert("c", c);
+        let (result, stats) = Query::new()
+            .join_named("c")
+            .join_named("a")
+            .join_named("b")
+            .leading(&["a", "b"])
+            .execute_with_stats(Some(&mut catalog))
+            .unwrap();
+        assert!(result.data.is_empty());
+        let inputs: Vec<&str> = stats
+            .plan()
+            .steps
+            .iter()
+            .map(|s| s.input.as_str())
+            .collect();
+        assert_eq!(inputs, ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn empty_input_teaches_the_catalog_nothing() {
+        let (a, b, c) = chain();
+        let mut catalog = Catalog::new();
+        catalog.insert("a", a);
+        catalog.insert("b", b);
+        catalog.insert("c", c);
+        let query = || {
+            Query::new()
+                .join_named("a")
+                .join_named("b")
+                .join_named("c")
+                .leading(&["a", "b"])
+        };
+        query().execute_with(&mut catalog).unwrap();
+        assert_eq!(catalog.learned_rows(&["a", "b"]), None);
+        Query::new()
+            .join_named("a")
+            .join_named("b")
+            .execute_with(&mut catalog)
+            .unwrap();
+        assert_eq!(catalog.learned_rows(&["a", "b"]), Some(2));
+        query().execute_with(&mut catalog).unwrap();
+        assert_eq!(catalog.learned_rows(&["a", "b"]), Some(2));
+    }
+
+    #[test]
+    fn empty_input_is_still_checked_for_cross_products() {
+        let a = Relation::new(["x"]).row([1]);
+        let b = Relation::new(["y"]);
+        let err = Query::new()
+            .join(a)
+            .join(b)
+            .forbid_cross_products()
+            .execute()
+            .unwrap_err();
+        assert!(matches!(err, QueryError::NoSharedColumns { .. }), "{err:?}");
+    }
+
+    // Counts how often it's asked for an estimate.
+    struct Counting(Arc<std::sync::atomic::AtomicUsize>);
+
+    impl CardinalityEstimator for Counting {
+        fn name(&self) -> &str {
+            "counting"
+        }
+
+        fn estimate(&self, catalog: &Catalog, inputs: &[(Option<&str>, &Relation)]) -> f64 {
+            self.0.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            catalog.estimate(inputs)
+        }
+    }
+
+    #[test]
+    fn validate_strategies_applies_with_a_catalog() {
+        let catalog = || {
+            let (a, b, c) = chain();
+            let mut catalog = Catalog::new();
+            catalog.insert("a", a);
+            catalog.insert("b", b);
+            catalog.insert("c", c.row([5, 7]));
+            catalog
+        };
+        let estimates = |validate: bool| {
+            let count = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+            let mut query = Query::new()
+                .join_named("a")
+                .join_named("b")
+                .join_named("c")
+                .strategy(Strategy::Greedy)
+                .cardinality_estimator(Counting(count.clone()));
+            if validate {
+                query = query.validate_strategies();
+            }
+            let rel = query.clone().execute_with(&mut catalog()).unwrap();
+            assert_eq!(rel.data.len(), 1);
+            let (rel, _) = query.execute_with_stats(Some(&mut catalog())).unwrap();
+            assert_eq!(rel.data.len(), 1);
+            count.load(std::sync::atomic::Ordering::Relaxed)
+        };
+        // Validating plans the query again with every other strategy.
+        assert!(estimates(true) > estimates(false));
+    }
+
+    #[test]
+    fn streaming_a_query_that_panics_is_an_error() {
+        // Rows that are too short for their relations panic the join.
+        let err = Query::new()
+            .join(Relation::new(["a", "b"]).row([1]))
+            .join(Relation::new(["b", "c"]).row([1, 2]))
+            .execute_streaming(1)
+            .err()
+            .unwrap();
+        assert!(matches!(err, QueryError::Panicked(_)), "{err:?}");
+        // Once the columns are out, the panic ends the batches rather than
+        // looking like the last of them.
+        let batches: Vec<_> = Query::new()
+            .join(
+                Relation::new(["c", "b"])
+                    .rows((0..100).map(|i| vec![i, i]))
+                    .row([0]),
+            )
+            .join(Relation::new(["a", "b"]).rows((0..100).map(|i| vec![i, i])))
+            .strategy(Strategy::Bfs)
+            .execute_streaming(1)
+            .unwrap()
+            .collect();
+        assert_eq!(batches.len(), 101);
+        assert!(matches!(batches[100], Err(QueryError::Panicked(_))));
+    }
+}
@@ -0,0 +1,265 @@
+use std::any::Any;
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+
+use crate::query::{Query, QueryError};
+use crate::Relation;
+
+// Runs queries on a pool of threads. Each query holds a reservation
+// against a shared memory budget while it runs; once the budget (or every
+// thread) is in use, further queries wait in the queue, highest priority
+// first and in submission order among equal priorities. A query that's
+// larger than the whole budget still runs, but only on its own. A query
+// that panics gives its handle an error, and the thread goes on to the
+// next one. The pool and the budget can be changed while it runs with
+// `resize`.
+pub struct Scheduler {
+    shared: Arc<Shared>,
+    workers: Vec<JoinHandle<()>>,
+    retry_spilling: Option<(PathBuf, usize)>,
+}
+
+pub struct QueryHandle(Receiver<Result<Relation, QueryError>>);
+
+struct Shared {
+    state: Mutex<State>,
+    changed: Condvar,
+}
+
+#[derive(Default)]
+struct State {
+    queue: BinaryHeap<Job>,
+    memory_in_use: usize,
+    memory_budget: usize,
+    // How many workers there should be, and how many there are. Workers
+    // past `threads` exit once they've finished what they're running.
+    threads: usize,
+    running: usize,
+    submitted: u64,
+    shutdown: bool,
+}
+
+struct Job {
+    priority: i32,
+    seq: u64,
+    memory: usize,
+    query: Query,
+    done: Sender<Result<Relation, QueryError>>,
+}
+
+impl Scheduler {
+    pub fn new(threads: usize, memory_budget: usize) -> Self {
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State::default()),
+            changed: Condvar::new(),
+        });
+        let mut scheduler = Self {
+            shared,
+            workers: Vec::new(),
+            retry_spilling: None,
+        };
+        scheduler.resize(threads, memory_budget);
+        scheduler
+    }
+
+    // Runs queries on `threads` threads from now on, against a budget of
+    // `memory_budget` bytes. Queries already running carry on, and threads
+    // that are no longer wanted exit once theirs are done.
+    pub fn resize(&mut self, threads: usize, memory_budget: usize) {
+        let threads = threads.max(1);
+        let mut state = self.shared.state.lock().unwrap();
+        state.memory_budget = memory_budget;
+        state.threads = threads;
+        let spawn = threads.saturating_sub(state.running);
+        state.running += spawn;
+        drop(state);
+        self.shared.changed.notify_all();
+        self.workers.retain(|worker| !worker.is_finished());
+        for _ in 0..spawn {
+            let shared = self.shared.clone();
+            self.workers.push(thread::spawn(move || shared.work()));
+        }
+    }
+
+    // Has each query submitted from now on that goes over its memory limit
+    // run again with its joins spilled into `partitions` partitions, in a
+    // directory of its own under `dir`, instead of failing.
+    pub fn retry_spilling(mut self, dir: impl Into<PathBuf>, partitions: usize) -> Self {
+        self.retry_spilling = Some((dir.into(), partitions));
+        self
+    }
+
+    pub fn submit(&self, query: Query, priority: i32) -> QueryHandle {
+        let (done, result) = mpsc::channel();
+        let mut state = self.shared.state.lock().unwrap();
+        let seq = state.submitted;
+        state.submitted += 1;
+        let query = match &self.retry_spilling {
+            Some((dir, partitions)) => {
+                query.retry_spilling(dir.join(format!("query_{}", seq)), *partitions)
+            }
+            None => query,
+        };
+        state.queue.push(Job {
+            priority,
+            seq,
+            memory: query.memory_estimate(),
+            query,
+            done,
+        });
+        self.shared.changed.notify_all();
+        QueryHandle(result)
+    }
+}
+
+impl Drop for Scheduler {
+    // Queued queries still run to completion before the workers exit.
+    fn drop(&mut self) {
+        self.shared.state.lock().unwrap().shutdown = true;
+        self.shared.changed.notify_all();
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+impl QueryHandle {
+    pub fn wait(self) -> Result<Relation, QueryError> {
+        self.0.recv().unwrap_or_else(|_| {
+            Err(QueryError::Panicked(
+                "the scheduler stopped before running it".to_string(),
+            ))
+        })
+    }
+}
+
+impl Shared {
+    fn work(&self) {
+        loop {
+            let job = {
+                let mut state = self.state.lock().unwrap();
+                loop {
+                    if state.running > state.threads {
+                        state.running -= 1;
+                        return;
+                    }
+                    match state.queue.peek() {
+                        Some(next)
+                            if state.memory_in_use == 0
+                                || state.memory_in_use + next.memory <= state.memory_budget =>
+                        {
+                            let job = state.queue.pop().unwrap();
+                            state.memory_in_use += job.memory;
+                            break job;
+                        }
+                        None if state.shutdown => return,
+                        _ => state = self.changed.wait(state).unwrap(),
+                    }
+                }
+            };
+
+            let memory = job.memory;
+            let query = job.query;
+            let result = panic::catch_unwind(AssertUnwindSafe(|| query.execute()))
+                .unwrap_or_else(|payload| Err(QueryError::Panicked(panic_message(payload))));
+            // Nobody waiting for the result is fine.
+            let _ = job.done.send(result);
+
+            self.state.lock().unwrap().memory_in_use -= memory;
+            self.changed.notify_all();
+        }
+    }
+}
+
+pub fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    match payload.downcast::<String>() {
+        Ok(message) => *message,
+        Err(payload) => match payload.downcast::<&str>() {
+            Ok(message) => message.to_string(),
+            Err(_) => "no message".to_string(),
+        },
+    }
+}
+
+impl PartialEq for Job {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Job {}
+
+impl PartialOrd for Job {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Job {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.priority
+            .cmp(&other.priority)
+            .then_with(|| other.seq.cmp(&self.seq))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Its first input's rows are too short for its columns, so joining it
+    // panics.
+    fn broken() -> Query {
+        Query::new()
+            .join(Relation::new(["a", "b"]).row([1]))
+            .join(Relation::new(["b", "c"]).row([1, 2]))
+    }
+
+    fn fine() -> Query {
+        Query::new()
+            .join(Relation::new(["a", "b"]).row([1, 2]))
+            .join(Relation::new(["b", "c"]).row([2, 3]))
+    }
+
+    #[test]
+    fn a_panicking_query_fails_on_its_own() {
+        // The budget only has room for one query at a time, so a
+        // reservation that isn't given back would hold up the rest forever.
+        let scheduler = Scheduler::new(2, 1);
+        let broken = scheduler.submit(broken(), 1);
+        let after = scheduler.submit(fine(), 0);
+        assert!(matches!(broken.wait(), Err(QueryError::Panicked(_))));
+        assert_eq!(after.wait().unwrap().data.len(), 1);
+        assert_eq!(scheduler.submit(fine(), 0).wait().unwrap().data.len(), 1);
+        drop(scheduler);
+    }
+
+    #[test]
+    fn resizing_changes_the_threads_and_budget() {
+        let mut scheduler = Scheduler::new(4, 1);
+        scheduler.resize(1, 1 << 20);
+        // The workers that aren't wanted anymore see that and exit.
+        let start = std::time::Instant::now();
+        while scheduler.shared.state.lock().unwrap().running > 1 {
+            assert!(start.elapsed().as_secs() < 10, "workers didn't exit");
+            thread::yield_now();
+        }
+        assert_eq!(
+            scheduler.shared.state.lock().unwrap().memory_budget,
+            1 << 20
+        );
+        assert_eq!(scheduler.submit(fine(), 0).wait().unwrap().data.len(), 1);
+        scheduler.resize(3, 1 << 20);
+        assert_eq!(scheduler.shared.state.lock().unwrap().running, 3);
+        let handles: Vec<_> = (0..6).map(|_| scheduler.submit(fine(), 0)).collect();
+        for handle in handles {
+            assert_eq!(handle.wait().unwrap().data.len(), 1);
+        }
+        drop(scheduler);
+    }
+}
@@ -0,0 +1,116 @@
+use std::collections::HashSet;
+use std::fmt;
+
+use crate::Relation;
+
+// The narrowest type every non-null value of a column parses as.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ColumnType {
+    Bool,
+    Int,
+    Float,
+    Text,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Column {
+    pub name: String,
+    pub ty: ColumnType,
+    pub nullable: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Schema {
+    pub columns: Vec<Column>,
+    // Columns with no nulls and no repeated values in the sample, which
+    // could be keys.
+    pub keys: Vec<String>,
+}
+
+impl fmt::Display for Schema {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for col in self.columns.iter() {
+            write!(f, "{}: {:?}", col.name, col.ty)?;
+            if col.nullable {
+                write!(f, " null")?;
+            }
+            if self.keys.contains(&col.name) {
+                write!(f, " key")?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+// Values that are taken to mean there isn't one.
+pub const NULLS: [&str; 4] = ["", "NULL", "null", "NA"];
+
+impl Relation {
+    // Works out a schema for string rows from a sample of them. Rows don't
+    // have to have a value for every column; any they're missing are nulls,
+    // and any values past the last column are ignored. A column with only
+    // nulls is text.
+    pub fn infer_schema<S: AsRef<str>>(
+        col_names: &[&str],
+        rows: impl IntoIterator<Item = impl AsRef<[S]>>,
+    ) -> Schema {
+        let mut types = vec![None; col_names.len()];
+        let mut nullable = vec![false; col_names.len()];
+        let mut seen: Vec<HashSet<String>> = vec![HashSet::new(); col_names.len()];
+        let mut unique = vec![true; col_names.len()];
+        for row in rows {
+            let row = row.as_ref();
+            for c in 0..col_names.len() {
+                let value = row.get(c).map_or("", |v| v.as_ref().trim());
+                if NULLS.contains(&value) {
+                    nullable[c] = true;
+                    continue;
+                }
+                let ty = value_type(value);
+                types[c] = Some(types[c].map_or(ty, |t: ColumnType| widen(t, ty)));
+                if unique[c] && !seen[c].insert(value.to_string()) {
+                    unique[c] = false;
+                    seen[c] = HashSet::new();
+                }
+            }
+        }
+
+        let columns = col_names
+            .iter()
+            .enumerate()
+            .map(|(c, name)| Column {
+                name: name.to_string(),
+                ty: types[c].unwrap_or(ColumnType::Text),
+                nullable: nullable[c],
+            })
+            .collect();
+        let keys = (0..col_names.len())
+            .filter(|c| unique[*c] && !nullable[*c] && types[*c].is_some())
+            .map(|c| col_names[c].to_string())
+            .collect();
+        Schema { columns, keys }
+    }
+}
+
+fn value_type(value: &str) -> ColumnType {
+    if value.parse::<i64>().is_ok() {
+        ColumnType::Int
+    } else if value.parse::<f64>().is_ok() {
+        ColumnType::Float
+    } else if value.eq_ignore_ascii_case("true") || value.eq_ignore_ascii_case("false") {
+        ColumnType::Bool
+    } else {
+        ColumnType::Text
+    }
+}
+
+// The narrowest type that holds values of both types. Integers widen to
+// floats, and anything else that's mixed is text.
+fn widen(a: ColumnType, b: ColumnType) -> ColumnType {
+    match (a.min(b), a.max(b)) {
+        (x, y) if x == y => x,
+        (ColumnType::Int, ColumnType::Float) => ColumnType::Float,
+        _ => ColumnType::Text,
+    }
+}
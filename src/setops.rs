@@ -0,0 +1,185 @@
+use std::collections::HashSet;
+
+use crate::value::Value;
+use crate::Relation;
+
+impl Relation {
+    // Each distinct row whose number of copies differs between `self` and
+    // `other`, with how many more copies `self` has in a `count` column,
+    // which is negative where `other` has more. Rows are compared by column
+    // name, so `other` has to have the same columns but can have them in
+    // any order. The rows come out sorted.
+    pub fn except_with_counts(&self, other: &Relation) -> Relation {
+        self.check_compatible(other);
+        assert!(
+            !self.col_names.iter().any(|c| c == "count"),
+            "{:?} already has a count column",
+            self.col_names
+        );
+
+        let mut counts = self.key_counts(&self.col_names);
+        let mut diffs: Vec<(Vec<Value>, i64)> = Vec::new();
+        for (row, theirs) in other.key_counts(&self.col_names) {
+            let ours = counts.remove(&row).unwrap_or(0);
+            if ours != theirs {
+                diffs.push((row, ours as i64 - theirs as i64));
+            }
+        }
+        diffs.extend(counts.into_iter().map(|(row, ours)| (row, ours as i64)));
+        diffs.sort();
+
+        Relation::new_with_data(
+            self.col_names.iter().cloned().chain(["count".to_string()]),
+            diffs.into_iter().map(|(mut row, diff)| {
+                row.push(Value::Int(diff));
+                row
+            }),
+        )
+    }
+
+    // Every row of `self` and then every row of `other`, with its columns in
+    // the order `self` has them.
+    pub fn union_all(&self, other: &Relation) -> Relation {
+        let other = self.aligned(other);
+        Relation::new_with_data(
+            self.col_names.iter().cloned(),
+            self.data.iter().chain(other.data.iter()).cloned(),
+        )
+    }
+
+    // The distinct rows of either.
+    pub fn union(&self, other: &Relation) -> Relation {
+        self.union_all(other).distinct()
+    }
+
+    // The rows of `self` that `other` has a copy of, each kept as many times
+    // as the number of copies the one with fewer has.
+    pub fn intersect_all(&self, other: &Relation) -> Relation {
+        let mut counts = self.aligned(other).key_counts(&self.col_names);
+        self.keep_rows(|row| match counts.get_mut(row) {
+            Some(n) if *n > 0 => {
+                *n -= 1;
+                true
+            }
+            _ => false,
+        })
+    }
+
+    // The distinct rows of `self` that `other` has too.
+    pub fn intersect(&self, other: &Relation) -> Relation {
+        let other = self.aligned(other);
+        let theirs: HashSet<_> = other.data.iter().collect();
+        self.distinct().keep_rows(|row| theirs.contains(row))
+    }
+
+    // The rows of `self` left after taking away one copy for each copy in
+    // `other`.
+    pub fn except_all(&self, other: &Relation) -> Relation {
+        let mut counts = self.aligned(other).key_counts(&self.col_names);
+        self.keep_rows(|row| match counts.get_mut(row) {
+            Some(n) if *n > 0 => {
+                *n -= 1;
+                false
+            }
+            _ => true,
+        })
+    }
+
+    // The distinct rows of `self` that `other` doesn't have.
+    pub fn except(&self, other: &Relation) -> Relation {
+        let other = self.aligned(other);
+        let theirs: HashSet<_> = other.data.iter().collect();
+        self.distinct().keep_rows(|row| !theirs.contains(row))
+    }
+
+    fn keep_rows(&self, mut keep: impl FnMut(&Vec<Value>) -> bool) -> Relation {
+        Relation::new_with_data(
+            self.col_names.iter().cloned(),
+            self.data.iter().filter(|row| keep(row)).cloned(),
+        )
+    }
+
+    // `other` with its columns in the same order as `self`'s.
+    fn aligned(&self, other: &Relation) -> Relation {
+        self.check_compatible(other);
+        other.project(self.col_names.iter().cloned())
+    }
+
+    // Panics unless `other` has the same columns, in any order, since set
+    // operations compare rows by column name.
+    fn check_compatible(&self, other: &Relation) {
+        let mut ours = self.col_names.clone();
+        let mut theirs = other.col_names.clone();
+        ours.sort();
+        theirs.sort();
+        assert_eq!(
+            ours, theirs,
+            "can't compare rows of {:?} with rows of {:?}",
+            self.col_names, other.col_names
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn except_with_counts_reports_each_difference() {
+        let ours = Relation::new(["a", "b"]).rows([[1, 1], [1, 1], [2, 2], [3, 3]]);
+        let theirs = Relation::new(["b", "a"]).rows([[1, 1], [3, 3], [3, 3], [4, 4]]);
+        let diff = ours.except_with_counts(&theirs);
+        assert_eq!(diff.col_names, ["a", "b", "count"]);
+        assert_eq!(
+            diff.data,
+            Relation::new(["a", "b", "count"])
+                .rows([[1, 1, 1], [2, 2, 1], [3, 3, -1], [4, 4, -1]])
+                .data
+        );
+        assert!(ours.except_with_counts(&ours).data.is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "already has a count column")]
+    fn except_with_counts_needs_a_free_count_column() {
+        let r = Relation::new(["count"]).rows([[1]]);
+        r.except_with_counts(&r);
+    }
+
+    #[test]
+    fn set_operations_match_rows_by_column_name() {
+        let ours = Relation::new(["a", "b"]).rows([[1, 1], [1, 1], [2, 2], [3, 3]]);
+        let theirs = Relation::new(["b", "a"]).rows([[1, 1], [3, 3], [3, 3], [4, 4]]);
+        let expect = |data: &[[i64; 2]]| Relation::new(["a", "b"]).rows(data.iter().copied()).data;
+
+        assert_eq!(
+            ours.union_all(&theirs).data,
+            expect(&[
+                [1, 1],
+                [1, 1],
+                [2, 2],
+                [3, 3],
+                [1, 1],
+                [3, 3],
+                [3, 3],
+                [4, 4]
+            ])
+        );
+        let mut union = ours.union(&theirs).data;
+        union.sort();
+        assert_eq!(union, expect(&[[1, 1], [2, 2], [3, 3], [4, 4]]));
+        assert_eq!(ours.intersect_all(&theirs).data, expect(&[[1, 1], [3, 3]]));
+        let mut intersect = ours.intersect(&theirs).data;
+        intersect.sort();
+        assert_eq!(intersect, expect(&[[1, 1], [3, 3]]));
+        assert_eq!(ours.except_all(&theirs).data, expect(&[[1, 1], [2, 2]]));
+        assert_eq!(ours.except(&theirs).data, expect(&[[2, 2]]));
+    }
+
+    #[test]
+    #[should_panic(expected = "can't compare rows")]
+    fn set_operations_need_the_same_columns() {
+        let ours = Relation::new(["a"]).rows([[1]]);
+        ours.union(&Relation::new(["b"]).rows([[1]]));
+    }
+}
@@ -0,0 +1,80 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::BTreeSet;
+use std::hash::{Hash, Hasher};
+
+use crate::value::Value;
+
+// A summary of a column's distinct values that's enough to estimate how
+// many there are and how much two columns overlap, without keeping the
+// values. It's the `K` smallest hashes of the values: hashes are spread
+// evenly, so the smallest of a set land closer to zero the more distinct
+// values there are, and the smallest of two sets' union that are in both
+// sketches are a sample of what the sets share.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Sketch {
+    hashes: BTreeSet<u64>,
+}
+
+// How many hashes a sketch keeps. Estimates are off by around one over its
+// square root.
+pub const K: usize = 256;
+
+impl Sketch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, value: &Value) {
+        let mut hasher = DefaultHasher::new();
+        value.hash(&mut hasher);
+        let hash = mix(hasher.finish());
+        if self.hashes.len() < K {
+            self.hashes.insert(hash);
+        } else if hash < *self.hashes.last().unwrap() && self.hashes.insert(hash) {
+            self.hashes.pop_last();
+        }
+    }
+
+    // The estimated number of distinct values. It's exact below `K` of them.
+    pub fn distinct(&self) -> f64 {
+        match self.hashes.last() {
+            Some(&largest) if self.hashes.len() == K => {
+                (K - 1) as f64 / (largest as f64 / u64::MAX as f64)
+            }
+            _ => self.hashes.len() as f64,
+        }
+    }
+
+    // The estimated fraction of the distinct values of either column that
+    // are in both.
+    pub fn jaccard(&self, other: &Sketch) -> f64 {
+        let union: Vec<u64> = self.hashes.union(&other.hashes).take(K).copied().collect();
+        if union.is_empty() {
+            return 0.0;
+        }
+        let both = union
+            .iter()
+            .filter(|h| self.hashes.contains(h) && other.hashes.contains(h))
+            .count();
+        both as f64 / union.len() as f64
+    }
+
+    // The estimated fraction of this column's distinct values that `other`
+    // has too.
+    pub fn containment(&self, other: &Sketch) -> f64 {
+        if self.hashes.is_empty() {
+            return 0.0;
+        }
+        let jaccard = self.jaccard(other);
+        let shared = jaccard * (self.distinct() + other.distinct()) / (1.0 + jaccard);
+        (shared / self.distinct()).min(1.0)
+    }
+}
+
+// The finalizer from splitmix64, so that runs of close integers get hashes
+// spread over the whole range.
+fn mix(mut x: u64) -> u64 {
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
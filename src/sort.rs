@@ -0,0 +1,196 @@
+use std::cmp::Ordering;
+use std::fmt;
+
+use crate::Relation;
+
+// A column to sort on and which way. Like the rest of the engine, nulls
+// come before every other value, so they're last in descending order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SortKey {
+    pub col: String,
+    pub desc: bool,
+}
+
+impl SortKey {
+    // A column name, optionally followed by `asc` or `desc`, like in SQL.
+    pub fn parse(spec: &str) -> Result<SortKey, SortError> {
+        let bad = || SortError::Key(spec.to_string());
+        let mut words = spec.split_whitespace();
+        let col = words.next().ok_or_else(bad)?.to_string();
+        let desc = match words.next().map(|w| w.to_lowercase()).as_deref() {
+            None | Some("asc") => false,
+            Some("desc") => true,
+            Some(_) => return Err(bad()),
+        };
+        match words.next() {
+            None => Ok(SortKey { col, desc }),
+            Some(_) => Err(bad()),
+        }
+    }
+}
+
+// Why a relation couldn't be sorted the way it was asked to be.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SortError {
+    // Something other than a column name and an optional `asc` or `desc`.
+    Key(String),
+    UnknownColumn {
+        column: String,
+        columns: Vec<String>,
+    },
+}
+
+impl fmt::Display for SortError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SortError::Key(spec) => write!(
+                f,
+                "can't sort on {:?}, expected a column and then asc or desc",
+                spec
+            ),
+            SortError::UnknownColumn { column, columns } => {
+                write!(f, "no column {:?} in {:?}", column, columns)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SortError {}
+
+// A relation along with what its rows are known to be sorted on. Nothing
+// can change the rows without taking the relation out, so it can't end up
+// sorted differently from what it says.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sorted {
+    rel: Relation,
+    keys: Vec<SortKey>,
+}
+
+impl Sorted {
+    pub fn keys(&self) -> &[SortKey] {
+        &self.keys
+    }
+
+    // Whether the rows are sorted ascending on `cols` in that order, which
+    // is what merging with another relation on them needs.
+    pub fn is_sorted_on(&self, cols: &[&str]) -> bool {
+        cols.len() <= self.keys.len()
+            && cols
+                .iter()
+                .zip(&self.keys)
+                .all(|(col, key)| key.col == *col && !key.desc)
+    }
+
+    pub fn relation(&self) -> &Relation {
+        &self.rel
+    }
+
+    pub fn into_relation(self) -> Relation {
+        self.rel
+    }
+}
+
+impl Relation {
+    // The rows sorted on each of `keys` in turn, such as `["a", "b desc"]`.
+    // The sort is stable, so rows that are equal on every key stay in the
+    // order they were in. Panics where `try_sort_by` would fail.
+    pub fn sort_by(&self, keys: &[&str]) -> Sorted {
+        self.try_sort_by(keys).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    // Like `sort_by`, but failing if a key isn't a column and an optional
+    // direction, or names a column this relation doesn't have.
+    pub fn try_sort_by(&self, keys: &[&str]) -> Result<Sorted, SortError> {
+        let keys = keys
+            .iter()
+            .map(|k| SortKey::parse(k))
+            .collect::<Result<Vec<_>, _>>()?;
+        let positions = keys
+            .iter()
+            .map(|k| match self.col_names.iter().position(|c| *c == k.col) {
+                Some(i) => Ok((i, k.desc)),
+                None => Err(SortError::UnknownColumn {
+                    column: k.col.clone(),
+                    columns: self.col_names.clone(),
+                }),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut data = self.data.clone();
+        data.sort_by(|a, b| {
+            positions
+                .iter()
+                .map(|(i, desc)| match desc {
+                    true => b[*i].cmp(&a[*i]),
+                    false => a[*i].cmp(&b[*i]),
+                })
+                .find(|o| *o != Ordering::Equal)
+                .unwrap_or(Ordering::Equal)
+        });
+        Ok(Sorted {
+            rel: Relation::new_with_data(self.col_names.iter().cloned(), data),
+            keys,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::value::Value;
+
+    fn stays() -> Relation {
+        Relation::new_with_data(
+            ["name", "nights", "id"].map(String::from),
+            [
+                ("bo", Value::Int(2), 1),
+                ("al", Value::Null, 2),
+                ("bo", Value::Int(3), 3),
+                ("al", Value::Int(1), 4),
+                ("bo", Value::Int(2), 5),
+                ("al", Value::Null, 6),
+            ]
+            .map(|(name, nights, id)| vec![Value::from(name), nights, Value::from(id)]),
+        )
+    }
+
+    fn ids(sorted: &Sorted) -> Vec<Value> {
+        sorted
+            .relation()
+            .data
+            .iter()
+            .map(|row| row[2].clone())
+            .collect()
+    }
+
+    #[test]
+    fn sorts_are_stable_and_put_nulls_last_when_descending() {
+        let rel = stays();
+        let sorted = rel.sort_by(&["name", "nights DESC"]);
+        assert_eq!(ids(&sorted), [4, 2, 6, 3, 1, 5].map(Value::from));
+        assert_eq!(
+            ids(&rel.sort_by(&["nights"])),
+            [2, 6, 4, 1, 5, 3].map(Value::from)
+        );
+        assert!(sorted.is_sorted_on(&["name"]));
+        assert!(!sorted.is_sorted_on(&["name", "nights"]));
+        assert!(rel.sort_by(&["nights asc"]).is_sorted_on(&["nights"]));
+    }
+
+    #[test]
+    fn bad_keys_and_unknown_columns_are_errors() {
+        let rel = stays();
+        for bad in ["name sideways", "name desc nights", "", "  "] {
+            assert_eq!(
+                rel.try_sort_by(&[bad]),
+                Err(SortError::Key(bad.to_string()))
+            );
+        }
+        assert_eq!(
+            rel.try_sort_by(&["name", "price desc"]),
+            Err(SortError::UnknownColumn {
+                column: "price".to_string(),
+                columns: rel.col_names.clone(),
+            })
+        );
+    }
+}
@@ -0,0 +1,468 @@
+use std::cell::RefCell;
+use std::fs::{self, File};
+use std::hash::{Hash, Hasher};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::{process, vec};
+
+use crate::kernel::FastHasher;
+use crate::merge::Merge;
+use crate::persist::{read_value, write_value};
+use crate::value::Value;
+use crate::{hash_key, Relation};
+
+// Tells apart the directories of `join_partitions` calls running at once.
+static NEXT_JOIN: AtomicUsize = AtomicUsize::new(0);
+
+// Tells apart the run files of sorts sharing a directory.
+static NEXT_SORT: AtomicUsize = AtomicUsize::new(0);
+
+// A grace hash join: both inputs are hash partitioned on their common
+// columns and written to `dir`, then each pair of partitions is joined on
+// its own so only one partition needs to be in memory at a time.
+//
+// Every completed partition's output is kept in `dir`, so a job that gets
+// killed can be resumed by running the same join again with the same
+// directory, and only the unfinished partitions are redone.
+pub struct SpillJoin {
+    dir: PathBuf,
+    partitions: usize,
+}
+
+impl SpillJoin {
+    pub fn new(dir: impl Into<PathBuf>, partitions: usize) -> Self {
+        Self {
+            dir: dir.into(),
+            partitions: partitions.max(1),
+        }
+    }
+
+    pub fn join(&self, left: &Relation, right: &Relation) -> io::Result<Relation> {
+        fs::create_dir_all(&self.dir)?;
+
+        // The manifest records what was partitioned, down to a hash of every
+        // value, so we don't resume a checkpoint that belongs to some other
+        // pair of inputs, even ones with the same columns and row counts.
+        let manifest = format!(
+            "{}\n{} {} {:016x}\n{} {} {:016x}\n",
+            self.partitions,
+            left.col_names.join(","),
+            left.data.len(),
+            content_hash(left),
+            right.col_names.join(","),
+            right.data.len(),
+            content_hash(right),
+        );
+        let manifest_path = self.dir.join("manifest");
+        match fs::read_to_string(&manifest_path) {
+            Ok(existing) if existing == manifest => {}
+            Ok(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "checkpoint directory belongs to a different join",
+                ))
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                let common_cols = left.common_cols(right);
+                self.partition(left, &common_cols, "left")?;
+                self.partition(right, &common_cols, "right")?;
+                write_atomic(&manifest_path, manifest.as_bytes())?;
+            }
+            Err(e) => return Err(e),
+        }
+
+        let mut result: Option<Relation> = None;
+        for i in 0..self.partitions {
+            let out_path = self.path("out", i);
+            let out = if out_path.exists() {
+                Relation::load(&out_path)?
+            } else {
+                let out = Relation::load(self.path("left", i))?
+                    .join(&Relation::load(self.path("right", i))?);
+                let tmp = out_path.with_extension("tmp");
+                out.save(&tmp)?;
+                fs::rename(&tmp, &out_path)?;
+                out
+            };
+            match &mut result {
+                Some(result) => result.data.extend(out.data),
+                None => result = Some(out),
+            }
+        }
+
+        Ok(result.unwrap_or_default())
+    }
+
+    // Joins `left` and `right` on `key` a pair of partitions at a time like
+    // `join`, but with `join` doing the joining and nothing kept to resume
+    // from. The partitions go in a directory of their own inside `dir`,
+    // which is removed afterwards, so calls sharing `dir` don't see each
+    // other's files and nothing else in `dir` is touched.
+    pub fn join_partitions<E: From<io::Error>>(
+        &self,
+        left: &Relation,
+        right: &Relation,
+        key: &[String],
+        join: impl FnMut(&Relation, &Relation) -> Result<Relation, E>,
+    ) -> Result<Relation, E> {
+        let id = NEXT_JOIN.fetch_add(1, Ordering::Relaxed);
+        let work = SpillJoin {
+            dir: self.dir.join(format!("join_{}_{}", process::id(), id)),
+            partitions: self.partitions,
+        };
+        fs::create_dir_all(&work.dir)?;
+        let result = work.join_each(left, right, key, join);
+        match fs::remove_dir_all(&work.dir) {
+            Err(e) if result.is_ok() && e.kind() != io::ErrorKind::NotFound => Err(e.into()),
+            _ => result,
+        }
+    }
+
+    fn join_each<E: From<io::Error>>(
+        &self,
+        left: &Relation,
+        right: &Relation,
+        key: &[String],
+        mut join: impl FnMut(&Relation, &Relation) -> Result<Relation, E>,
+    ) -> Result<Relation, E> {
+        self.partition(left, key, "left")?;
+        self.partition(right, key, "right")?;
+
+        let mut result: Option<Relation> = None;
+        for i in 0..self.partitions {
+            let out = join(
+                &Relation::load(self.path("left", i))?,
+                &Relation::load(self.path("right", i))?,
+            )?;
+            match &mut result {
+                Some(result) => result.data.extend(out.data),
+                None => result = Some(out),
+            }
+        }
+        Ok(result.unwrap_or_default())
+    }
+
+    // Removes the files `join` writes to its directory, and then the
+    // directory itself if there's nothing else left in it.
+    pub fn clear(&self) -> io::Result<()> {
+        let mut files = vec![self.dir.join("manifest"), self.dir.join("manifest.tmp")];
+        for i in 0..self.partitions {
+            files.extend(["left", "right", "out"].map(|kind| self.path(kind, i)));
+            files.push(self.path("out", i).with_extension("tmp"));
+        }
+        for file in files {
+            match fs::remove_file(&file) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                _ => {}
+            }
+        }
+        match fs::read_dir(&self.dir).map(|mut entries| entries.next().is_none()) {
+            Ok(true) => fs::remove_dir(&self.dir),
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    fn partition(&self, rel: &Relation, key_cols: &[String], side: &str) -> io::Result<()> {
+        let key = rel.positions(key_cols);
+        let mut parts: Vec<_> = (0..self.partitions)
+            .map(|_| Relation::new(rel.col_names.iter().cloned()))
+            .collect();
+        for row in rel.data.iter() {
+            parts[hash_key(row, &key) as usize % self.partitions]
+                .data
+                .push(row.clone());
+        }
+        for (i, part) in parts.iter().enumerate() {
+            part.save(self.path(side, i))?;
+        }
+        Ok(())
+    }
+
+    fn path(&self, kind: &str, partition: usize) -> PathBuf {
+        self.dir.join(format!("{}_{}.bin", kind, partition))
+    }
+}
+
+// A hash of `rel`'s columns and rows. It's with the kernel's hasher rather
+// than `DefaultHasher`, whose algorithm can change between releases of Rust,
+// since a checkpoint has to be recognized by whatever build resumes it.
+fn content_hash(rel: &Relation) -> u64 {
+    let mut hasher = FastHasher::default();
+    rel.col_names.hash(&mut hasher);
+    rel.data.hash(&mut hasher);
+    hasher.finish()
+}
+
+// A merge sort for more rows than fit in memory. Rows are gathered until
+// they'd take up more than `max_memory` bytes, then sorted and written to a
+// run file in `dir`. The runs, and whatever rows are left over in memory,
+// are then merged as the sorted rows are read.
+pub struct ExternalSort {
+    dir: PathBuf,
+    max_memory: usize,
+}
+
+impl ExternalSort {
+    pub fn new(dir: impl Into<PathBuf>, max_memory: usize) -> Self {
+        Self {
+            dir: dir.into(),
+            max_memory,
+        }
+    }
+
+    // Sorts `rows`, which have the columns `col_names`, on `order_by`. Rows
+    // with equal keys keep their order.
+    pub fn sort(
+        &self,
+        col_names: &[String],
+        rows: impl IntoIterator<Item = Vec<Value>>,
+        order_by: &[&str],
+    ) -> io::Result<Sorted> {
+        let order_by: Vec<_> = order_by.iter().map(|c| c.to_string()).collect();
+        let key = Relation::new(col_names.iter().cloned()).positions(&order_by);
+        let sort = |buf: &mut Vec<Vec<Value>>| {
+            buf.sort_by(|a, b| key.iter().map(|k| &a[*k]).cmp(key.iter().map(|k| &b[*k])))
+        };
+        let row_bytes = col_names.len() * std::mem::size_of::<Value>();
+        let error = Rc::new(RefCell::new(None));
+        let id = NEXT_SORT.fetch_add(1, Ordering::Relaxed);
+
+        let mut runs = Vec::new();
+        let mut paths = Vec::new();
+        let mut buf = Vec::new();
+        for row in rows {
+            buf.push(row);
+            // Rows without columns don't take up any room, or any bytes in
+            // a run file to tell them apart.
+            if !col_names.is_empty() && buf.len() * row_bytes > self.max_memory {
+                sort(&mut buf);
+                if paths.is_empty() {
+                    fs::create_dir_all(&self.dir)?;
+                }
+                let path =
+                    self.dir
+                        .join(format!("run_{}_{}_{}.bin", process::id(), id, paths.len()));
+                paths.push(path.clone());
+                match write_run(&path, buf.drain(..)) {
+                    Ok(r) => runs.push(Run::File {
+                        r,
+                        width: col_names.len(),
+                        error: error.clone(),
+                    }),
+                    // Nothing will read the runs written so far.
+                    Err(e) => {
+                        drop(runs);
+                        for path in &paths {
+                            let _ = fs::remove_file(path);
+                        }
+                        return Err(e);
+                    }
+                }
+            }
+        }
+        sort(&mut buf);
+        runs.push(Run::Memory(buf.into_iter()));
+
+        Ok(Sorted {
+            merge: Merge::new(key, runs),
+            error,
+            paths,
+        })
+    }
+}
+
+// Writes `rows` to a run file at `path`, and opens it to be read back.
+fn write_run(path: &Path, rows: impl Iterator<Item = Vec<Value>>) -> io::Result<BufReader<File>> {
+    let mut w = BufWriter::new(File::create(path)?);
+    for row in rows {
+        for v in row {
+            write_value(&mut w, &v)?;
+        }
+    }
+    w.flush()?;
+    Ok(BufReader::new(File::open(path)?))
+}
+
+// The rows of an external sort, in order. The run files are removed once
+// this is dropped.
+pub struct Sorted {
+    merge: Merge<Run>,
+    // The first error reading any of the runs.
+    error: Rc<RefCell<Option<io::Error>>>,
+    paths: Vec<PathBuf>,
+}
+
+impl Sorted {
+    // How many runs had to be written to disk.
+    pub fn spilled_runs(&self) -> usize {
+        self.paths.len()
+    }
+}
+
+impl Iterator for Sorted {
+    type Item = io::Result<Vec<Value>>;
+
+    fn next(&mut self) -> Option<io::Result<Vec<Value>>> {
+        let row = self.merge.next();
+        // A run that failed looks like it ended, so the rest of the output
+        // would be missing its rows.
+        if let Some(e) = self.error.borrow_mut().take() {
+            self.merge = Merge::new(vec![], vec![]);
+            return Some(Err(e));
+        }
+        row.map(Ok)
+    }
+}
+
+impl Drop for Sorted {
+    fn drop(&mut self) {
+        for path in self.paths.iter() {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+enum Run {
+    Memory(vec::IntoIter<Vec<Value>>),
+    File {
+        r: BufReader<File>,
+        width: usize,
+        error: Rc<RefCell<Option<io::Error>>>,
+    },
+}
+
+impl Iterator for Run {
+    type Item = Vec<Value>;
+
+    fn next(&mut self) -> Option<Vec<Value>> {
+        let (r, width, error) = match self {
+            Run::Memory(rows) => return rows.next(),
+            Run::File { r, width, error } => (r, *width, error),
+        };
+        let mut row = Vec::with_capacity(width);
+        for i in 0..width {
+            match read_value(r) {
+                Ok(v) => row.push(v),
+                Err(e) if i == 0 && e.kind() == io::ErrorKind::UnexpectedEof => return None,
+                Err(e) => {
+                    error.borrow_mut().get_or_insert(e);
+                    return None;
+                }
+            }
+        }
+        Some(row)
+    }
+}
+
+fn write_atomic(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, contents)?;
+    fs::rename(&tmp, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scratch::Scratch;
+
+    // A directory that already has a file in it that isn't the join's.
+    fn scratch(name: &str) -> Scratch {
+        let dir = Scratch::new(name);
+        fs::write(dir.join("keep.txt"), "not the join's").unwrap();
+        dir
+    }
+
+    fn inputs() -> (Relation, Relation) {
+        let left = Relation::new(["a", "b"]).rows((0..100).map(|i| vec![i, i % 10]));
+        let right = Relation::new(["b", "c"]).rows((0..10).map(|i| vec![i, i * 2]));
+        (left, right)
+    }
+
+    #[test]
+    fn join_partitions_leaves_the_rest_of_the_directory_alone() {
+        let dir = scratch("partitions");
+        let (left, right) = inputs();
+        let key = vec!["b".to_string()];
+        let out = SpillJoin::new(dir.path(), 4)
+            .join_partitions(&left, &right, &key, |l, r| Ok::<_, io::Error>(l.join(r)))
+            .unwrap();
+        assert_eq!(out.data.len(), 100);
+        let left_over: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name())
+            .collect();
+        assert_eq!(left_over, ["keep.txt"]);
+    }
+
+    #[test]
+    fn join_partitions_cleans_up_after_an_error() {
+        let dir = scratch("partitions_error");
+        let (left, right) = inputs();
+        let key = vec!["b".to_string()];
+        let err = SpillJoin::new(dir.path(), 4)
+            .join_partitions(&left, &right, &key, |_, _| {
+                Err(io::Error::other("join failed"))
+            })
+            .unwrap_err();
+        assert_eq!(err.to_string(), "join failed");
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn join_resumes_only_a_checkpoint_of_the_same_inputs() {
+        let dir = scratch("resume");
+        let (left, right) = inputs();
+        let spill = SpillJoin::new(dir.path(), 4);
+        let expected = spill.join(&left, &right).unwrap();
+        fs::remove_file(spill.path("out", 2)).unwrap();
+        assert_eq!(spill.join(&left, &right).unwrap(), expected);
+
+        let mut changed = left.clone();
+        changed.data[0][0] = Value::from(1000);
+        let err = spill.join(&changed, &right).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        spill.clear().unwrap();
+        assert_eq!(spill.join(&changed, &right).unwrap().data.len(), 100);
+        spill.clear().unwrap();
+    }
+
+    #[test]
+    fn clear_only_removes_the_joins_files() {
+        let dir = scratch("clear");
+        let (left, right) = inputs();
+        let spill = SpillJoin::new(dir.path(), 4);
+        assert_eq!(spill.join(&left, &right).unwrap().data.len(), 100);
+        assert_eq!(spill.join(&left, &right).unwrap().data.len(), 100);
+        spill.clear().unwrap();
+        assert!(dir.join("keep.txt").exists());
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        fs::remove_file(dir.join("keep.txt")).unwrap();
+        spill.clear().unwrap();
+        assert!(!dir.path().exists());
+    }
+
+    #[test]
+    fn sorts_can_share_a_directory() {
+        let dir = scratch("sorts");
+        let cols = ["k".to_string(), "pad".to_string()];
+        let sort = ExternalSort::new(dir.path(), 200 * 2 * std::mem::size_of::<Value>());
+        // Runs bigger than a reader's buffer, so they're still being read
+        // from disk once the other sort has written its own.
+        let rows = |from: i64| {
+            (0..1000)
+                .rev()
+                .map(move |i| vec![Value::Int(from + i), Value::Str("x".repeat(100).into())])
+        };
+        let first = sort.sort(&cols, rows(0), &["k"]).unwrap();
+        let second = sort.sort(&cols, rows(5000), &["k"]).unwrap();
+        assert!(first.spilled_runs() > 1 && second.spilled_runs() > 1);
+        let first: Vec<_> = first.map(|row| row.unwrap()).collect();
+        let second: Vec<_> = second.map(|row| row.unwrap()).collect();
+        assert_eq!(first, rows(0).rev().collect::<Vec<_>>());
+        assert_eq!(second, rows(5000).rev().collect::<Vec<_>>());
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+    }
+}
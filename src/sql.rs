@@ -0,0 +1,754 @@
+use std::fmt;
+
+use crate::catalog::Catalog;
+use crate::expr::{col, lit, Expr, ExprError, Pred};
+use crate::hints::Hints;
+use crate::query::{Outer, Query, QueryError};
+use crate::value::Value;
+use crate::Relation;
+
+// A query written as SQL, over relations in a catalog:
+//
+//     SELECT a, b FROM r JOIN s USING (k) LEFT JOIN t WHERE a > 1 AND b <> 'x'
+//
+// Joins are natural joins, as everywhere else, so `USING` can only list the
+// columns the relations have in common; it's checked rather than used to
+// pick some of them. Relations after a comma or `NATURAL JOIN` are joined the
+// same way. `LEFT`, `RIGHT` and `FULL` joins have to come after the inner
+// ones, since they run last. `WHERE` takes comparisons of arithmetic over
+// columns and literals, joined by `AND`, `OR` and `NOT`, and is applied to
+// the joined rows. Hint comments like `/*+ leading(r s) */` are applied to
+// the query.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Select {
+    // The columns to output, or `None` for `*`.
+    pub columns: Option<Vec<String>>,
+    pub from: String,
+    pub joins: Vec<Join>,
+    pub filter: Option<Pred>,
+    pub hints: Hints,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Join {
+    pub outer: Option<Outer>,
+    pub relation: String,
+    pub using: Option<Vec<String>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SqlError {
+    // What was expected at a byte offset of the text, and what was there.
+    Syntax {
+        at: usize,
+        expected: String,
+        found: String,
+    },
+    // `USING` listed columns other than the ones the relations share.
+    Using {
+        relation: String,
+        using: Vec<String>,
+        common: Vec<String>,
+    },
+    InnerAfterOuter(String),
+    // Parentheses, `NOT`s or minus signs nested deeper than `MAX_DEPTH`,
+    // starting at a byte offset of the text.
+    TooDeep {
+        at: usize,
+    },
+    // A column that isn't in the joined relations, or arithmetic in `WHERE`
+    // on something that isn't a number.
+    Expr(ExprError),
+    Query(QueryError),
+}
+
+impl fmt::Display for SqlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SqlError::Syntax {
+                at,
+                expected,
+                found,
+            } => write!(f, "expected {} at {}, found {}", expected, at, found),
+            SqlError::Using {
+                relation,
+                using,
+                common,
+            } => write!(
+                f,
+                "{} is joined on every column it shares, which is ({}), not ({})",
+                relation,
+                common.join(", "),
+                using.join(", ")
+            ),
+            SqlError::InnerAfterOuter(relation) => write!(
+                f,
+                "{} is inner joined after an outer join, which isn't supported",
+                relation
+            ),
+            SqlError::TooDeep { at } => write!(
+                f,
+                "the condition at {} is nested more than {} deep",
+                at, MAX_DEPTH
+            ),
+            SqlError::Expr(e) => write!(f, "{}", e),
+            SqlError::Query(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for SqlError {}
+
+impl From<QueryError> for SqlError {
+    fn from(e: QueryError) -> SqlError {
+        SqlError::Query(e)
+    }
+}
+
+impl From<ExprError> for SqlError {
+    fn from(e: ExprError) -> SqlError {
+        SqlError::Expr(e)
+    }
+}
+
+pub fn parse(text: &str) -> Result<Select, SqlError> {
+    let mut parser = Parser {
+        tokens: tokenize(text)?,
+        pos: 0,
+        len: text.len(),
+        depth: 0,
+    };
+    let select = parser.select(Hints::parse(text))?;
+    parser.eat(&Token::Symbol(";"));
+    parser.end()?;
+    Ok(select)
+}
+
+impl Select {
+    // The joins as a query. Without a `WHERE`, it outputs the selected
+    // columns itself; with one, it outputs every column, since the filter
+    // can need them.
+    pub fn query(&self) -> Query {
+        let mut query = Query::new().join_named(&self.from);
+        for join in self.joins.iter() {
+            query = match join.outer {
+                Some(outer) => query.outer_join_named(outer, &join.relation),
+                None => query.join_named(&join.relation),
+            };
+        }
+        query = query.hints(&self.hints);
+        match (&self.columns, &self.filter) {
+            (Some(columns), None) => query.select(columns),
+            _ => query,
+        }
+    }
+
+    pub fn run(&self, catalog: &mut Catalog) -> Result<Relation, SqlError> {
+        self.check(catalog)?;
+        let joined = self.query().execute_with(catalog)?;
+        let Some(filter) = &self.filter else {
+            return Ok(joined);
+        };
+        let filtered = joined.try_filter_expr(filter)?;
+        Ok(match &self.columns {
+            Some(columns) => filtered.project(columns.iter().cloned()),
+            None => filtered,
+        })
+    }
+
+    // Checks what the query can be checked for only once the relations'
+    // columns are known, including that every column it names is one of
+    // theirs.
+    fn check(&self, catalog: &Catalog) -> Result<(), SqlError> {
+        let cols = |name: &str| {
+            catalog
+                .get(name)
+                .map(|rel| rel.col_names.clone())
+                .ok_or_else(|| QueryError::UnknownRelation(name.to_string()))
+        };
+        let mut seen = cols(&self.from)?;
+        let mut outer = false;
+        for join in self.joins.iter() {
+            if join.outer.is_none() && outer {
+                return Err(SqlError::InnerAfterOuter(join.relation.clone()));
+            }
+            outer |= join.outer.is_some();
+            let next = cols(&join.relation)?;
+            let common: Vec<String> = next.iter().filter(|c| seen.contains(c)).cloned().collect();
+            if let Some(using) = &join.using {
+                let mut sorted = using.clone();
+                let mut shared = common.clone();
+                sorted.sort();
+                shared.sort();
+                if sorted != shared {
+                    return Err(SqlError::Using {
+                        relation: join.relation.clone(),
+                        using: using.clone(),
+                        common,
+                    });
+                }
+            }
+            seen.extend(next.into_iter().filter(|c| !common.contains(c)));
+        }
+        let mut named: Vec<&str> = Vec::new();
+        if let Some(columns) = &self.columns {
+            named.extend(columns.iter().map(|c| c.as_str()));
+        }
+        if let Some(filter) = &self.filter {
+            filter.columns(&mut named);
+        }
+        match named.into_iter().find(|c| !seen.iter().any(|s| s == c)) {
+            Some(column) => Err(SqlError::Expr(ExprError::UnknownColumn {
+                column: column.to_string(),
+                columns: seen,
+            })),
+            None => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    // Identifiers and keywords, which can have dots in them for names in
+    // namespaces.
+    Word(String),
+    Number(Value),
+    Str(String),
+    Symbol(&'static str),
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Word(w) => write!(f, "{}", w),
+            Token::Number(n) => write!(f, "{}", n),
+            Token::Str(s) => write!(f, "'{}'", s),
+            Token::Symbol(s) => write!(f, "{}", s),
+        }
+    }
+}
+
+// Longer symbols first, so `<=` isn't read as `<` then `=`.
+const SYMBOLS: [&str; 16] = [
+    "<=", ">=", "<>", "!=", "(", ")", ",", "*", "=", "<", ">", "+", "-", "/", "%", ";",
+];
+
+const KEYWORDS: [&str; 17] = [
+    "select", "from", "where", "join", "inner", "natural", "left", "right", "full", "outer",
+    "using", "and", "or", "not", "true", "false", "null",
+];
+
+fn tokenize(text: &str) -> Result<Vec<(usize, Token)>, SqlError> {
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < text.len() {
+        let rest = &text[i..];
+        let c = rest.chars().next().unwrap();
+        if c.is_whitespace() {
+            i += c.len_utf8();
+        } else if rest.starts_with("--") {
+            i += rest.find('\n').unwrap_or(rest.len());
+        } else if rest.starts_with("/*") {
+            i += rest.find("*/").map_or(rest.len(), |end| end + 2);
+        } else if c.is_ascii_digit() {
+            let len = rest
+                .find(|c: char| !c.is_ascii_digit() && c != '.')
+                .unwrap_or(rest.len());
+            let number = &rest[..len];
+            let value = match number.parse::<i64>() {
+                Ok(v) => Value::Int(v),
+                Err(_) => Value::Float(number.parse().map_err(|_| SqlError::Syntax {
+                    at: i,
+                    expected: "a number".to_string(),
+                    found: number.to_string(),
+                })?),
+            };
+            tokens.push((i, Token::Number(value)));
+            i += len;
+        } else if c.is_alphabetic() || c == '_' || c == '"' {
+            let (word, len) = match c {
+                '"' => match rest[1..].find('"') {
+                    Some(end) => (rest[1..end + 1].to_string(), end + 2),
+                    None => return Err(unterminated(i, "identifier")),
+                },
+                _ => {
+                    let len = rest
+                        .find(|c: char| !c.is_alphanumeric() && c != '_' && c != '.')
+                        .unwrap_or(rest.len());
+                    (rest[..len].to_string(), len)
+                }
+            };
+            tokens.push((i, Token::Word(word)));
+            i += len;
+        } else if c == '\'' {
+            let mut value = String::new();
+            let mut chars = rest[1..].char_indices();
+            let end = loop {
+                match chars.next() {
+                    Some((j, '\'')) if rest[j + 2..].starts_with('\'') => {
+                        value.push('\'');
+                        chars.next();
+                    }
+                    Some((j, '\'')) => break j + 2,
+                    Some((_, c)) => value.push(c),
+                    None => return Err(unterminated(i, "string")),
+                }
+            };
+            tokens.push((i, Token::Str(value)));
+            i += end;
+        } else if let Some(symbol) = SYMBOLS.iter().find(|s| rest.starts_with(**s)) {
+            tokens.push((i, Token::Symbol(symbol)));
+            i += symbol.len();
+        } else {
+            return Err(SqlError::Syntax {
+                at: i,
+                expected: "a token".to_string(),
+                found: c.to_string(),
+            });
+        }
+    }
+    Ok(tokens)
+}
+
+fn unterminated(at: usize, what: &str) -> SqlError {
+    SqlError::Syntax {
+        at,
+        expected: format!("the end of the {}", what),
+        found: "the end of the query".to_string(),
+    }
+}
+
+// How deeply parentheses, `NOT`s and minus signs can nest. The parser
+// recurses once per level, so this is what keeps a hostile query from
+// overflowing the stack.
+pub const MAX_DEPTH: usize = 128;
+
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    pos: usize,
+    len: usize,
+    depth: usize,
+}
+
+// What a parenthesis holds: a condition, as in `(a = 1 OR b = 2)`, or an
+// expression, as in `(a + 1) * 2 > b`.
+enum Group {
+    Pred(Pred),
+    Expr(Expr),
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(_, t)| t)
+    }
+
+    fn error<T>(&self, expected: &str) -> Result<T, SqlError> {
+        let (at, found) = match self.tokens.get(self.pos) {
+            Some((at, token)) => (*at, token.to_string()),
+            None => (self.len, "the end of the query".to_string()),
+        };
+        Err(SqlError::Syntax {
+            at,
+            expected: expected.to_string(),
+            found,
+        })
+    }
+
+    fn eat(&mut self, token: &Token) -> bool {
+        let found = self.peek() == Some(token);
+        self.pos += found as usize;
+        found
+    }
+
+    fn keyword(&mut self, keyword: &str) -> bool {
+        let found = matches!(self.peek(), Some(Token::Word(w)) if w.eq_ignore_ascii_case(keyword));
+        self.pos += found as usize;
+        found
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> Result<(), SqlError> {
+        match self.keyword(keyword) {
+            true => Ok(()),
+            false => self.error(&keyword.to_uppercase()),
+        }
+    }
+
+    fn expect(&mut self, symbol: &'static str) -> Result<(), SqlError> {
+        match self.eat(&Token::Symbol(symbol)) {
+            true => Ok(()),
+            false => self.error(&format!("{:?}", symbol)),
+        }
+    }
+
+    fn end(&self) -> Result<(), SqlError> {
+        match self.peek() {
+            None => Ok(()),
+            Some(_) => self.error("the end of the query"),
+        }
+    }
+
+    fn name(&mut self) -> Result<String, SqlError> {
+        match self.peek() {
+            Some(Token::Word(w)) if !KEYWORDS.contains(&w.to_lowercase().as_str()) => {
+                let w = w.clone();
+                self.pos += 1;
+                Ok(w)
+            }
+            _ => self.error("a name"),
+        }
+    }
+
+    fn names(&mut self) -> Result<Vec<String>, SqlError> {
+        let mut names = vec![self.name()?];
+        while self.eat(&Token::Symbol(",")) {
+            names.push(self.name()?);
+        }
+        Ok(names)
+    }
+
+    fn select(&mut self, hints: Hints) -> Result<Select, SqlError> {
+        self.expect_keyword("select")?;
+        let columns = match self.eat(&Token::Symbol("*")) {
+            true => None,
+            false => Some(self.names()?),
+        };
+        self.expect_keyword("from")?;
+        let from = self.name()?;
+        let mut joins = Vec::new();
+        loop {
+            let outer = if self.eat(&Token::Symbol(",")) {
+                joins.push(Join {
+                    outer: None,
+                    relation: self.name()?,
+                    using: None,
+                });
+                continue;
+            } else if self.keyword("left") {
+                Some(Outer::Left)
+            } else if self.keyword("right") {
+                Some(Outer::Right)
+            } else if self.keyword("full") {
+                Some(Outer::Full)
+            } else if self.keyword("natural") || self.keyword("inner") {
+                self.keyword("inner");
+                None
+            } else if matches!(self.peek(), Some(Token::Word(w)) if w.eq_ignore_ascii_case("join"))
+            {
+                None
+            } else {
+                break;
+            };
+            if outer.is_some() {
+                self.keyword("outer");
+            }
+            self.expect_keyword("join")?;
+            let relation = self.name()?;
+            let using = match self.keyword("using") {
+                true => {
+                    self.expect("(")?;
+                    let cols = self.names()?;
+                    self.expect(")")?;
+                    Some(cols)
+                }
+                false => None,
+            };
+            joins.push(Join {
+                outer,
+                relation,
+                using,
+            });
+        }
+        let filter = match self.keyword("where") {
+            true => Some(self.or()?),
+            false => None,
+        };
+        Ok(Select {
+            columns,
+            from,
+            joins,
+            filter,
+            hints,
+        })
+    }
+
+    // Runs `parse` one level deeper, failing if that's past `MAX_DEPTH`.
+    fn nested<T>(
+        &mut self,
+        parse: impl FnOnce(&mut Self) -> Result<T, SqlError>,
+    ) -> Result<T, SqlError> {
+        if self.depth == MAX_DEPTH {
+            let at = self.tokens.get(self.pos).map_or(self.len, |(at, _)| *at);
+            return Err(SqlError::TooDeep { at });
+        }
+        self.depth += 1;
+        let result = parse(self);
+        self.depth -= 1;
+        result
+    }
+
+    fn or(&mut self) -> Result<Pred, SqlError> {
+        let first = self.not()?;
+        self.or_from(first)
+    }
+
+    // The rest of an `OR` whose first operand, `first`, is already parsed.
+    fn or_from(&mut self, first: Pred) -> Result<Pred, SqlError> {
+        let mut pred = self.and_from(first)?;
+        while self.keyword("or") {
+            pred = pred | self.and()?;
+        }
+        Ok(pred)
+    }
+
+    fn and(&mut self) -> Result<Pred, SqlError> {
+        let first = self.not()?;
+        self.and_from(first)
+    }
+
+    fn and_from(&mut self, first: Pred) -> Result<Pred, SqlError> {
+        let mut pred = first;
+        while self.keyword("and") {
+            pred = pred & self.not()?;
+        }
+        Ok(pred)
+    }
+
+    fn not(&mut self) -> Result<Pred, SqlError> {
+        if self.keyword("not") {
+            return Ok(!self.nested(Self::not)?);
+        }
+        if self.eat(&Token::Symbol("(")) {
+            return match self.nested(Self::group)? {
+                Group::Pred(pred) => Ok(pred),
+                Group::Expr(expr) => {
+                    let lhs = self.sum_from(expr)?;
+                    self.compare(lhs)
+                }
+            };
+        }
+        let lhs = self.sum()?;
+        self.compare(lhs)
+    }
+
+    // What follows a `(` that starts a condition, up to and including its
+    // `)`. It holds an expression unless a comparison, `NOT`, `AND` or `OR`
+    // turns up, so it's parsed once, whichever it turns out to be.
+    fn group(&mut self) -> Result<Group, SqlError> {
+        let first = if self.keyword("not") {
+            Group::Pred(!self.nested(Self::not)?)
+        } else if self.eat(&Token::Symbol("(")) {
+            self.nested(Self::group)?
+        } else {
+            Group::Expr(self.atom()?)
+        };
+        let pred = match first {
+            Group::Pred(pred) => pred,
+            Group::Expr(expr) => {
+                let expr = self.sum_from(expr)?;
+                if self.eat(&Token::Symbol(")")) {
+                    return Ok(Group::Expr(expr));
+                }
+                self.compare(expr)?
+            }
+        };
+        let pred = self.or_from(pred)?;
+        self.expect(")")?;
+        Ok(Group::Pred(pred))
+    }
+
+    fn compare(&mut self, lhs: Expr) -> Result<Pred, SqlError> {
+        let op: fn(Expr, Expr) -> Pred = match self.peek() {
+            Some(Token::Symbol("=")) => Expr::equals,
+            Some(Token::Symbol("<>" | "!=")) => Expr::not_equals,
+            Some(Token::Symbol("<")) => Expr::lt,
+            Some(Token::Symbol("<=")) => Expr::le,
+            Some(Token::Symbol(">")) => Expr::gt,
+            Some(Token::Symbol(">=")) => Expr::ge,
+            _ => return self.error("a comparison"),
+        };
+        self.pos += 1;
+        Ok(op(lhs, self.sum()?))
+    }
+
+    fn sum(&mut self) -> Result<Expr, SqlError> {
+        let first = self.atom()?;
+        self.sum_from(first)
+    }
+
+    // The rest of a sum whose first atom, `first`, is already parsed.
+    fn sum_from(&mut self, first: Expr) -> Result<Expr, SqlError> {
+        let mut expr = self.product_from(first)?;
+        loop {
+            if self.eat(&Token::Symbol("+")) {
+                expr = expr + self.product()?;
+            } else if self.eat(&Token::Symbol("-")) {
+                expr = expr - self.product()?;
+            } else {
+                return Ok(expr);
+            }
+        }
+    }
+
+    fn product(&mut self) -> Result<Expr, SqlError> {
+        let first = self.atom()?;
+        self.product_from(first)
+    }
+
+    fn product_from(&mut self, first: Expr) -> Result<Expr, SqlError> {
+        let mut expr = first;
+        loop {
+            if self.eat(&Token::Symbol("*")) {
+                expr = expr * self.atom()?;
+            } else if self.eat(&Token::Symbol("/")) {
+                expr = expr / self.atom()?;
+            } else if self.eat(&Token::Symbol("%")) {
+                expr = expr % self.atom()?;
+            } else {
+                return Ok(expr);
+            }
+        }
+    }
+
+    fn atom(&mut self) -> Result<Expr, SqlError> {
+        if self.eat(&Token::Symbol("(")) {
+            let expr = self.nested(Self::sum)?;
+            self.expect(")")?;
+            return Ok(expr);
+        }
+        if self.eat(&Token::Symbol("-")) {
+            return Ok(lit(0) - self.nested(Self::atom)?);
+        }
+        if self.keyword("true") {
+            return Ok(lit(true));
+        }
+        if self.keyword("false") {
+            return Ok(lit(false));
+        }
+        if self.keyword("null") {
+            return Ok(lit(Value::Null));
+        }
+        let expr = match self.peek() {
+            Some(Token::Number(v)) => lit(v.clone()),
+            Some(Token::Str(s)) => lit(Value::Str(s.as_str().into())),
+            Some(Token::Word(_)) => return self.name().map(|n| col(&n)),
+            _ => return self.error("a value"),
+        };
+        self.pos += 1;
+        Ok(expr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shop() -> Catalog {
+        let mut catalog = Catalog::new();
+        catalog.insert(
+            "users",
+            Relation::new(["user", "city"]).rows([[1, 10], [2, 20], [3, 0]]),
+        );
+        catalog.insert(
+            "purchases",
+            Relation::new(["user", "price"]).rows([[1, 5], [2, 12]]),
+        );
+        catalog
+    }
+
+    fn run(text: &str) -> Result<Relation, SqlError> {
+        parse(text)?.run(&mut shop())
+    }
+
+    fn unknown(column: &str) -> impl Fn(&SqlError) -> bool + '_ {
+        move |e| matches!(e, SqlError::Expr(ExprError::UnknownColumn { column: c, .. }) if c == column)
+    }
+
+    #[test]
+    fn runs_a_filtered_join() {
+        let rel = run("SELECT user, price FROM users JOIN purchases WHERE price * 2 > 20").unwrap();
+        assert_eq!(rel.col_names, ["user", "price"]);
+        assert_eq!(rel.data, [[Value::Int(2), Value::Int(12)]]);
+    }
+
+    #[test]
+    fn unknown_columns_are_errors() {
+        let e = run("SELECT cty FROM users").unwrap_err();
+        assert!(unknown("cty")(&e), "{e:?}");
+        let e = run("SELECT * FROM users WHERE cty = 1").unwrap_err();
+        assert!(unknown("cty")(&e), "{e:?}");
+        let e = run("SELECT user FROM users JOIN purchases WHERE NOT (prices > 1)").unwrap_err();
+        assert!(unknown("prices")(&e), "{e:?}");
+    }
+
+    #[test]
+    fn dividing_by_zero_is_null() {
+        let rel = run("SELECT user FROM users WHERE 10 / city >= 0").unwrap();
+        assert_eq!(rel.data, [[Value::Int(1)], [Value::Int(2)]]);
+        let rel = run("SELECT user FROM users WHERE NOT (10 % city = 1)").unwrap();
+        assert_eq!(rel.data, [[Value::Int(1)], [Value::Int(2)]]);
+    }
+
+    #[test]
+    fn arithmetic_on_strings_is_an_error() {
+        let e = run("SELECT * FROM users WHERE city + 'x' > 1").unwrap_err();
+        assert!(
+            matches!(e, SqlError::Expr(ExprError::Arithmetic(..))),
+            "{e:?}"
+        );
+    }
+
+    #[test]
+    fn parentheses_hold_conditions_or_expressions() {
+        let rel = run("SELECT user FROM users WHERE ((city) + 10) / 2 = 10").unwrap();
+        assert_eq!(rel.data, [[Value::Int(1)]]);
+        let rel = run("SELECT user FROM users WHERE ((city = 0 OR (user) = 1)) AND NOT (user > 2)")
+            .unwrap();
+        assert_eq!(rel.data, [[Value::Int(1)]]);
+        let rel = run("SELECT user FROM users WHERE (NOT city = 10 AND (user * 1) < 3)").unwrap();
+        assert_eq!(rel.data, [[Value::Int(2)]]);
+        let e = parse("SELECT * FROM users WHERE (city = 1) + 1 = 2").unwrap_err();
+        assert!(matches!(e, SqlError::Syntax { .. }), "{e:?}");
+    }
+
+    #[test]
+    fn deep_nesting_is_an_error() {
+        let deep = |open: &str, close: &str| {
+            let n = 100_000;
+            format!(
+                "SELECT * FROM users WHERE {}city{} = 1",
+                open.repeat(n),
+                close.repeat(n)
+            )
+        };
+        for text in [
+            deep("(", ")"),
+            deep("NOT (", ")"),
+            deep("- ", ""),
+            format!(
+                "SELECT * FROM users WHERE {}city = 1",
+                "NOT ".repeat(100_000)
+            ),
+            format!(
+                "SELECT * FROM users WHERE city = {}1{}",
+                "(".repeat(100_000),
+                ")".repeat(100_000)
+            ),
+        ] {
+            let e = parse(&text).unwrap_err();
+            assert!(matches!(e, SqlError::TooDeep { .. }), "{e:?}");
+        }
+        // Just inside the limit parses, and in time linear in its length.
+        let open = "(".repeat(MAX_DEPTH - 1);
+        let close = ")".repeat(MAX_DEPTH - 1);
+        let rel = run(&format!(
+            "SELECT user FROM users WHERE {open}city{close} = 20"
+        ))
+        .unwrap();
+        assert_eq!(rel.data, [[Value::Int(2)]]);
+        let rel = run(&format!(
+            "SELECT user FROM users WHERE {open}city = 20{close}"
+        ))
+        .unwrap();
+        assert_eq!(rel.data, [[Value::Int(2)]]);
+    }
+}
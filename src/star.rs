@@ -0,0 +1,103 @@
+use crate::catalog::KEY_COLS;
+use crate::value::Value;
+use crate::{HashIndex, Relation};
+
+// Joins `fact` against every dimension in one pass over its rows, rather
+// than materializing an intermediate result after each dimension. Each
+// dimension's index is probed with the columns of the row built so far, so
+// a dimension can also be keyed on a column brought in by an earlier one.
+pub fn star_join(fact: &Relation, dims: &[HashIndex]) -> Relation {
+    let mut col_names = fact.col_names.clone();
+    let mut steps = Vec::new();
+    for dim in dims {
+        let probe_key = dim
+            .key_cols
+            .iter()
+            .map(|col| match col_names.iter().position(|c| c == col) {
+                Some(i) => i,
+                None => panic!("no column {:?} to probe the index with", col),
+            })
+            .collect();
+        // Columns the dimension shares with the row beyond its key still have
+        // to be equal for the rows to join.
+        let mut checks = Vec::new();
+        let mut extra = Vec::new();
+        for (i, col) in dim.rel.col_names.iter().enumerate() {
+            match col_names.iter().position(|c| c == col) {
+                Some(j) if !dim.key_cols.contains(col) => checks.push((i, j)),
+                Some(_) => {}
+                None => extra.push(i),
+            }
+        }
+        col_names.extend(extra.iter().map(|i| dim.rel.col_names[*i].clone()));
+        steps.push(Step {
+            probe_key,
+            checks,
+            extra,
+        });
+    }
+
+    let mut result = Vec::new();
+    for row in fact.data.iter() {
+        expand(row.clone(), dims, &steps, &mut result);
+    }
+    Relation::new_with_data(col_names, result)
+}
+
+// Joins every dimension onto `fact`, each on the smallest of its keys that
+// the columns so far have, so a dimension can hang off another rather than
+// off `fact`. The dimension's other columns that the result already has
+// are kept too, named with the dimension's name and `_` before them, rather
+// than taken as part of the key. It's a left join, so each row of `fact`
+// is there once, with nulls from any dimension it doesn't match.
+pub fn denormalize(fact: &Relation, dims: &[(&str, &Relation)]) -> Relation {
+    let mut wide = fact.clone();
+    let mut remaining: Vec<_> = dims
+        .iter()
+        .map(|(name, dim)| (*name, *dim, dim.candidate_keys(KEY_COLS)))
+        .collect();
+    while !remaining.is_empty() {
+        let Some((i, key)) = remaining.iter().enumerate().find_map(|(i, (_, _, keys))| {
+            keys.iter()
+                .find(|key| key.iter().all(|c| wide.col_names.contains(c)))
+                .map(|key| (i, key.clone()))
+        }) else {
+            let names: Vec<_> = remaining.iter().map(|(name, _, _)| *name).collect();
+            panic!(
+                "none of {:?} has a key in {:?} to join on",
+                names, wide.col_names
+            );
+        };
+        let (name, dim, _) = remaining.remove(i);
+        let renamed =
+            dim.col_names
+                .iter()
+                .map(|c| match !key.contains(c) && wide.col_names.contains(c) {
+                    true => format!("{}_{}", name, c),
+                    false => c.clone(),
+                });
+        let dim = Relation::new_with_data(renamed, dim.data.clone());
+        wide = wide.left_join(&dim);
+    }
+    wide
+}
+
+struct Step {
+    probe_key: Vec<usize>,
+    checks: Vec<(usize, usize)>,
+    extra: Vec<usize>,
+}
+
+fn expand(row: Vec<Value>, dims: &[HashIndex], steps: &[Step], result: &mut Vec<Vec<Value>>) {
+    let (Some(dim), Some(step)) = (dims.first(), steps.first()) else {
+        result.push(row);
+        return;
+    };
+    for m in dim.matches(&row, &step.probe_key) {
+        if step.checks.iter().all(|(i, j)| m[*i] == row[*j]) {
+            let mut next = row.clone();
+            next.extend(step.extra.iter().map(|i| m[*i].clone()));
+            expand(next, &dims[1..], &steps[1..], result);
+        }
+    }
+}
@@ -0,0 +1,159 @@
+use std::convert::Infallible;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::mpsc::{self, Receiver, SyncSender, TryRecvError};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread::{self, Thread};
+
+use crate::value::Value;
+use crate::Relation;
+
+// Rows produced on a thread of their own and handed over in batches to
+// whoever is awaiting them, so an async caller never blocks one of its
+// runtime's threads on a join. At most `capacity` batches wait to be taken
+// at once; past that the producing thread stops until some are, so a slow
+// consumer holds back the join rather than letting its output pile up.
+//
+// `poll_next` has the same shape as the `Stream` trait from the futures
+// crate, so the stream works under tokio or any other executor.
+pub struct RowStream {
+    pub col_names: Vec<String>,
+    rx: Receiver<Vec<Vec<Value>>>,
+    waker: Arc<Mutex<Option<Waker>>>,
+}
+
+// The producing end of a `RowStream`.
+pub struct BatchSender {
+    // Only taken when dropped.
+    tx: Option<SyncSender<Vec<Vec<Value>>>>,
+    waker: Arc<Mutex<Option<Waker>>>,
+}
+
+impl BatchSender {
+    // Hands over a batch, waiting while the stream is full. It's false if
+    // the stream has been dropped, in which case there's no point producing
+    // any more.
+    pub fn send(&self, batch: Vec<Vec<Value>>) -> bool {
+        let sent = self.tx.as_ref().unwrap().send(batch).is_ok();
+        if let Some(waker) = self.waker.lock().unwrap().take() {
+            waker.wake();
+        }
+        sent
+    }
+}
+
+impl Drop for BatchSender {
+    fn drop(&mut self) {
+        // Whoever is waiting needs to find out there's nothing more coming,
+        // which they can only once the channel is closed.
+        self.tx = None;
+        if let Some(waker) = self.waker.lock().unwrap().take() {
+            waker.wake();
+        }
+    }
+}
+
+impl RowStream {
+    // Runs `produce` on a new thread, which sends the stream's batches.
+    pub fn spawn(
+        col_names: Vec<String>,
+        capacity: usize,
+        produce: impl FnOnce(BatchSender) + Send + 'static,
+    ) -> RowStream {
+        let (tx, rx) = mpsc::sync_channel(capacity.max(1));
+        let waker = Arc::new(Mutex::new(None));
+        let sender = BatchSender {
+            tx: Some(tx),
+            waker: waker.clone(),
+        };
+        thread::spawn(move || produce(sender));
+        RowStream {
+            col_names,
+            rx,
+            waker,
+        }
+    }
+
+    pub fn poll_next(&mut self, cx: &mut Context<'_>) -> Poll<Option<Vec<Vec<Value>>>> {
+        match self.rx.try_recv() {
+            Ok(batch) => return Poll::Ready(Some(batch)),
+            Err(TryRecvError::Disconnected) => return Poll::Ready(None),
+            Err(TryRecvError::Empty) => {}
+        }
+        // Register before looking again, so a batch sent in between isn't
+        // missed.
+        *self.waker.lock().unwrap() = Some(cx.waker().clone());
+        match self.rx.try_recv() {
+            Ok(batch) => Poll::Ready(Some(batch)),
+            Err(TryRecvError::Disconnected) => Poll::Ready(None),
+            Err(TryRecvError::Empty) => Poll::Pending,
+        }
+    }
+
+    // The next batch, or none once the producer is done.
+    pub fn next(&mut self) -> Next<'_> {
+        Next(self)
+    }
+}
+
+pub struct Next<'a>(&'a mut RowStream);
+
+impl Future for Next<'_> {
+    type Output = Option<Vec<Vec<Value>>>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.0.poll_next(cx)
+    }
+}
+
+impl Relation {
+    // Streams the join of the two relations, probing an index on this one
+    // with `batch_rows` of `other`'s rows at a time.
+    pub fn join_stream(self, other: Relation, batch_rows: usize, capacity: usize) -> RowStream {
+        let col_names = self
+            .col_names
+            .iter()
+            .chain(
+                other
+                    .col_names
+                    .iter()
+                    .filter(|c| !self.col_names.contains(c)),
+            )
+            .cloned()
+            .collect();
+        RowStream::spawn(col_names, capacity, move |sender| {
+            let index = self.index(&self.common_cols(&other));
+            for rows in other.data.chunks(batch_rows.max(1)) {
+                let joined = index
+                    .try_join_rows(&other, rows.iter(), |_| Ok::<_, Infallible>(()))
+                    .unwrap_or_else(|e| match e {});
+                if !joined.data.is_empty() && !sender.send(joined.data) {
+                    return;
+                }
+            }
+        })
+    }
+}
+
+// Runs a future to completion on the current thread, for callers that
+// don't have a runtime of their own.
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    struct Unpark(Thread);
+
+    impl Wake for Unpark {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    let waker = Waker::from(Arc::new(Unpark(thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    let mut future = std::pin::pin!(future);
+    loop {
+        match future.as_mut().poll(&mut cx) {
+            Poll::Ready(output) => return output,
+            Poll::Pending => thread::park(),
+        }
+    }
+}
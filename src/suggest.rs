@@ -0,0 +1,191 @@
+use std::collections::HashSet;
+use std::fmt;
+
+use crate::names::Normalizer;
+use crate::sketch::Sketch;
+use crate::value::Value;
+use crate::Relation;
+
+// A pair of columns that might be what two relations were meant to be
+// joined on.
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeySuggestion {
+    pub left: String,
+    pub right: String,
+    // How alike the names are, from 0 to 1.
+    pub name_similarity: f64,
+    // The fraction of the distinct values in either column that are in
+    // both, from 0 to 1.
+    pub overlap: f64,
+}
+
+impl KeySuggestion {
+    pub fn score(&self) -> f64 {
+        (self.name_similarity + self.overlap) / 2.0
+    }
+}
+
+impl fmt::Display for KeySuggestion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} = {}", self.left, self.right)
+    }
+}
+
+// How many rows of each relation are looked at to measure overlap.
+const SAMPLE_ROWS: usize = 1000;
+
+// How many suggestions are made at most.
+const SUGGESTIONS: usize = 3;
+
+// Pairs of columns of `left` and `right` that look like they could be join
+// keys, best first, judging by their names and the values they share.
+pub fn suggest_join_keys(left: &Relation, right: &Relation) -> Vec<KeySuggestion> {
+    let left_values = sample_values(left);
+    let right_values = sample_values(right);
+    let mut suggestions = Vec::new();
+    for (l, lname) in left.col_names.iter().enumerate() {
+        for (r, rname) in right.col_names.iter().enumerate() {
+            let (a, b) = (&left_values[l], &right_values[r]);
+            let shared = a.intersection(b).count();
+            let overlap = match a.len() + b.len() - shared {
+                0 => 0.0,
+                either => shared as f64 / either as f64,
+            };
+            let suggestion = KeySuggestion {
+                left: lname.clone(),
+                right: rname.clone(),
+                name_similarity: name_similarity(lname, rname),
+                overlap,
+            };
+            if suggestion.name_similarity >= 0.5 || suggestion.overlap >= 0.5 {
+                suggestions.push(suggestion);
+            }
+        }
+    }
+    suggestions.sort_by(|a, b| b.score().total_cmp(&a.score()));
+    suggestions.truncate(SUGGESTIONS);
+    suggestions
+}
+
+// A pair of columns whose values overlap enough that they might be join
+// keys, found without looking at their names.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InferredKey {
+    pub left: String,
+    pub right: String,
+    // The estimated fraction of the distinct values of whichever column has
+    // fewer of them that the other column has too, from 0 to 1. A foreign
+    // key's values are all in the key it points at, however few of them are
+    // used.
+    pub containment: f64,
+    // The estimated fraction of the distinct values in either column that
+    // are in both, from 0 to 1.
+    pub jaccard: f64,
+}
+
+impl fmt::Display for InferredKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} = {} ({:.0}% contained)",
+            self.left,
+            self.right,
+            self.containment * 100.0
+        )
+    }
+}
+
+// Columns with fewer distinct values than this are never inferred to be
+// keys, since small domains like flags overlap whatever they mean.
+const MIN_DISTINCT: f64 = 16.0;
+
+// How much of the smaller column has to be in the other.
+const MIN_CONTAINMENT: f64 = 0.8;
+
+// Pairs of columns of `left` and `right` whose values overlap the most,
+// best first. Every value is read into a sketch of each column, so unlike
+// `suggest_join_keys` this works for headers that say nothing, at the cost
+// of a pass over both relations.
+pub fn infer_join_keys(left: &Relation, right: &Relation) -> Vec<InferredKey> {
+    let left_sketches = sketch_columns(left);
+    let right_sketches = sketch_columns(right);
+    let mut keys = Vec::new();
+    for (lname, a) in left.col_names.iter().zip(&left_sketches) {
+        for (rname, b) in right.col_names.iter().zip(&right_sketches) {
+            if a.distinct().min(b.distinct()) < MIN_DISTINCT {
+                continue;
+            }
+            let (fewer, more) = if a.distinct() <= b.distinct() {
+                (a, b)
+            } else {
+                (b, a)
+            };
+            let containment = fewer.containment(more);
+            if containment >= MIN_CONTAINMENT {
+                keys.push(InferredKey {
+                    left: lname.clone(),
+                    right: rname.clone(),
+                    containment,
+                    jaccard: a.jaccard(b),
+                });
+            }
+        }
+    }
+    keys.sort_by(|a, b| {
+        (b.containment, b.jaccard)
+            .partial_cmp(&(a.containment, a.jaccard))
+            .unwrap()
+    });
+    keys
+}
+
+fn sketch_columns(rel: &Relation) -> Vec<Sketch> {
+    let mut sketches = vec![Sketch::new(); rel.col_names.len()];
+    for row in rel.data.iter() {
+        for (c, v) in row.iter().enumerate() {
+            sketches[c].insert(v);
+        }
+    }
+    sketches
+}
+
+// The distinct values of each column in an evenly spaced sample of rows.
+fn sample_values(rel: &Relation) -> Vec<HashSet<&Value>> {
+    let step = (rel.data.len() / SAMPLE_ROWS).max(1);
+    let mut values = vec![HashSet::new(); rel.col_names.len()];
+    for row in rel.data.iter().step_by(step).take(SAMPLE_ROWS) {
+        for (c, v) in row.iter().enumerate() {
+            values[c].insert(v);
+        }
+    }
+    values
+}
+
+// One minus the edit distance between the normalized names, as a fraction
+// of the longer one, ignoring underscores if that makes them closer.
+fn name_similarity(a: &str, b: &str) -> f64 {
+    let normalizer = Normalizer::all();
+    let (a, b) = (normalizer.normalize(a), normalizer.normalize(b));
+    let ratio = |a: &str, b: &str| {
+        let longest = a.chars().count().max(b.chars().count());
+        if longest == 0 {
+            return 1.0;
+        }
+        1.0 - edit_distance(a, b) as f64 / longest as f64
+    };
+    ratio(&a, &b).max(ratio(&a.replace('_', ""), &b.replace('_', "")))
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut row = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitute = prev[j] + (ca != *cb) as usize;
+            row.push(substitute.min(prev[j + 1] + 1).min(row[j] + 1));
+        }
+        prev = row;
+    }
+    prev[b.len()]
+}
@@ -0,0 +1,72 @@
+use std::convert::Infallible;
+
+use crate::expr::Pred;
+use crate::kernel;
+use crate::value::Value;
+use crate::Relation;
+
+impl Relation {
+    // Joins the rows that match on the columns `self` and `other` share and
+    // that `pred` holds for, given the row of `self` and the row of
+    // `other`. Without columns in common every pair of rows has to be
+    // tried, so that's a nested loop; otherwise the pairs come from a hash
+    // join and `pred` only sees the pairs that match on the shared columns.
+    pub fn join_where(
+        &self,
+        other: &Relation,
+        pred: impl Fn(&[Value], &[Value]) -> bool,
+    ) -> Relation {
+        let common_cols = self.common_cols(other);
+        if common_cols.is_empty() {
+            return self
+                .nested_loop_join(other, pred, |_| Ok::<_, Infallible>(()))
+                .unwrap_or_else(|e| match e {});
+        }
+
+        let extra: Vec<_> = (0..other.col_names.len())
+            .filter(|i| !self.col_names.contains(&other.col_names[*i]))
+            .collect();
+        let pairs = kernel::hash_join(
+            &other.data,
+            &other.positions(&common_cols),
+            &self.data,
+            &self.positions(&common_cols),
+            |v: &Value| !v.is_null(),
+        );
+        Relation::new_with_data(
+            self.col_names
+                .iter()
+                .chain(extra.iter().map(|i| &other.col_names[*i]))
+                .cloned(),
+            pairs
+                .into_iter()
+                .filter(|(r, l)| pred(&self.data[*l], &other.data[*r]))
+                .map(|(r, l)| {
+                    let mut row = self.data[l].clone();
+                    row.extend(extra.iter().map(|i| other.data[r][*i].clone()));
+                    row
+                }),
+        )
+    }
+
+    // Like `join_where`, with the condition an expression over the columns
+    // of both, such as `col("start").le(col("at")) & col("at").lt(col("end"))`.
+    // A column both have is the same on either side, so it's `self`'s.
+    pub fn join_where_expr(&self, other: &Relation, pred: Pred) -> Relation {
+        let extra: Vec<_> = (0..other.col_names.len())
+            .filter(|i| !self.col_names.contains(&other.col_names[*i]))
+            .collect();
+        let names: Vec<_> = self
+            .col_names
+            .iter()
+            .chain(extra.iter().map(|i| &other.col_names[*i]))
+            .cloned()
+            .collect();
+        let pred = pred.bind(&names);
+        self.join_where(other, |l, r| {
+            let mut row = l.to_vec();
+            row.extend(extra.iter().map(|i| r[*i].clone()));
+            pred.holds(&row)
+        })
+    }
+}
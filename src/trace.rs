@@ -0,0 +1,131 @@
+use std::collections::{HashMap, HashSet};
+use std::io::{self, Write};
+
+use crate::value::Value;
+use crate::{Planner, Relation};
+
+// Joins that write out what they do as they do it, for showing how a hash
+// join works rather than for running one: every key put in the hash table,
+// every probe and what it matched, and why the planner picked each input.
+
+impl Relation {
+    // Like `join`, but always with a hash table built on `other` and
+    // narrating each step to `out`.
+    pub fn join_traced(&self, other: &Relation, out: &mut impl Write) -> io::Result<Relation> {
+        let common_cols = self.common_cols(other);
+        writeln!(
+            out,
+            "join {:?} with {:?} on {:?}",
+            self.col_names, other.col_names, common_cols
+        )?;
+
+        let right_key = other.positions(&common_cols);
+        let mut table: HashMap<Vec<Value>, Vec<&Vec<Value>>> = HashMap::new();
+        for row in other.data.iter() {
+            let key: Vec<_> = right_key.iter().map(|i| row[*i].clone()).collect();
+            if key.iter().any(Value::is_null) {
+                writeln!(out, "  build {:?}: null key, left out", row)?;
+                continue;
+            }
+            writeln!(out, "  build {:?} under key {:?}", row, key)?;
+            table.entry(key).or_default().push(row);
+        }
+        writeln!(
+            out,
+            "  hash table has {} keys for {} rows",
+            table.len(),
+            other.data.len()
+        )?;
+
+        let left_key = self.positions(&common_cols);
+        let extra: Vec<_> = (0..other.col_names.len())
+            .filter(|i| !self.col_names.contains(&other.col_names[*i]))
+            .collect();
+        let mut result = Vec::new();
+        for row in self.data.iter() {
+            let key: Vec<_> = left_key.iter().map(|i| row[*i].clone()).collect();
+            let matches = table.get(&key).map_or(&[][..], |rows| rows);
+            writeln!(
+                out,
+                "  probe {:?} with key {:?}: {} matches",
+                row,
+                key,
+                matches.len()
+            )?;
+            for matched in matches {
+                let mut new_row = row.clone();
+                new_row.extend(extra.iter().map(|i| matched[*i].clone()));
+                writeln!(out, "    {:?}", new_row)?;
+                result.push(new_row);
+            }
+        }
+        writeln!(out, "  {} rows", result.len())?;
+
+        Ok(Relation::new_with_data(
+            self.col_names
+                .iter()
+                .chain(extra.iter().map(|i| &other.col_names[*i]))
+                .cloned(),
+            result,
+        ))
+    }
+}
+
+impl Planner {
+    // Works out `order`, writing to `out` why each relation comes where it
+    // does.
+    pub fn trace_order(&self, out: &mut impl Write) -> io::Result<Vec<usize>> {
+        let mut plan = vec![];
+        let mut remaining: HashSet<_> = (0..self.joined_tables.len()).collect();
+        // Grab an unjoined relation.
+        while let Some(next) = remaining.iter().min().copied() {
+            if plan.is_empty() {
+                writeln!(out, "start with {}", self.describe(next))?;
+            } else {
+                writeln!(
+                    out,
+                    "nothing left shares a column with the result, so cross with {}",
+                    self.describe(next)
+                )?;
+            }
+            // Expand outwards adding relations that are connected to the
+            // current result, along with the relation they're connected to.
+            let mut frontier = vec![(next, None)];
+            while let Some((relation, from)) = frontier.pop() {
+                // A relation can be pushed more than once if the graph has a
+                // cycle, so only take it the first time.
+                if !remaining.remove(&relation) {
+                    continue;
+                }
+                if let Some(from) = from {
+                    writeln!(
+                        out,
+                        "add {}, which shares {:?} with {}",
+                        self.describe(relation),
+                        self.joined_tables[relation].common_cols(&self.joined_tables[from]),
+                        from
+                    )?;
+                }
+                plan.push(relation);
+                frontier.extend(
+                    self.query_graph
+                        .neighbours(relation)
+                        .into_iter()
+                        .filter(|n| remaining.contains(n))
+                        .map(|n| (n, Some(relation))),
+                );
+            }
+        }
+        writeln!(out, "order {:?}", plan)?;
+        Ok(plan)
+    }
+
+    fn describe(&self, i: usize) -> String {
+        format!(
+            "{} ({}, {} rows)",
+            i,
+            self.joined_tables[i].col_names.join(", "),
+            self.joined_tables[i].data.len()
+        )
+    }
+}
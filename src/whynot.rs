@@ -0,0 +1,128 @@
+use std::fmt;
+
+use crate::value::Value;
+
+// Why a query's result doesn't have a row it was expected to. Inputs are
+// named like in plans, by their name or their columns in parentheses.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WhyNot {
+    // The row has a column that no input does.
+    UnknownColumn(String),
+    // No row of `input` has the expected values in the columns it has.
+    NoRows {
+        input: String,
+        expected: Vec<(String, Value)>,
+    },
+    // Joining `input` on `key` at this step of the plan matched none of the
+    // rows so far that could have made the row, which had these keys.
+    NoMatch {
+        step: usize,
+        input: String,
+        key: Vec<String>,
+        unmatched: Vec<Vec<Value>>,
+    },
+    // Every inner join kept the row, but an outer join or `select` didn't
+    // keep it as it was expected.
+    Later,
+}
+
+impl fmt::Display for WhyNot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WhyNot::UnknownColumn(col) => write!(f, "no input has column {:?}", col),
+            WhyNot::NoRows { input, expected } => {
+                write!(f, "no row of {} has", input)?;
+                for (i, (col, value)) in expected.iter().enumerate() {
+                    let sep = if i == 0 { "" } else { "," };
+                    write!(f, "{} {} = {}", sep, col, value)?;
+                }
+                Ok(())
+            }
+            WhyNot::NoMatch {
+                step,
+                input,
+                key,
+                unmatched,
+            } => write!(
+                f,
+                "step {} joined {} on [{}], and nothing matched {:?}",
+                step,
+                input,
+                key.join(", "),
+                unmatched
+            ),
+            WhyNot::Later => write!(f, "the joins kept the row, but the output doesn't"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::query::{Outer, Query};
+    use crate::Relation;
+
+    fn people() -> Relation {
+        Relation::new(["name", "pid"]).rows([
+            [Value::from("ada"), 1.into()],
+            ["bob".into(), 2.into()],
+            ["cy".into(), 3.into()],
+        ])
+    }
+
+    fn nights() -> Relation {
+        Relation::new(["pid", "nights"]).rows([[1, 3], [2, 5]])
+    }
+
+    fn why_not(query: Query, expected: &[(&str, Value)]) -> Option<WhyNot> {
+        query.why_not(expected).unwrap()
+    }
+
+    #[test]
+    fn explains_which_step_lost_a_row() {
+        let stays = || Query::new().join(people()).join(nights());
+        assert_eq!(
+            why_not(stays(), &[("name", "ada".into()), ("nights", 3.into())]),
+            None
+        );
+        assert_eq!(
+            why_not(stays(), &[("color", "red".into())]),
+            Some(WhyNot::UnknownColumn("color".to_string()))
+        );
+        assert_eq!(
+            why_not(stays(), &[("name", "dee".into())]),
+            Some(WhyNot::NoRows {
+                input: "(name,pid)".to_string(),
+                expected: vec![("name".to_string(), "dee".into())],
+            })
+        );
+        assert_eq!(
+            why_not(stays(), &[("name", "cy".into())]),
+            Some(WhyNot::NoMatch {
+                step: 1,
+                input: "(pid,nights)".to_string(),
+                key: vec!["pid".to_string()],
+                unmatched: vec![vec![3.into()]],
+            })
+        );
+        // Selecting the column away means no output row can have it.
+        assert_eq!(
+            why_not(stays().select(["name"]), &[("nights", 3.into())]),
+            Some(WhyNot::UnknownColumn("nights".to_string()))
+        );
+    }
+
+    #[test]
+    fn rows_an_outer_join_pads_are_lost_later() {
+        let query = Query::new()
+            .join(people())
+            .outer_join(Outer::Left, nights());
+        let expected = [("name", "cy".into()), ("nights", 3.into())];
+        let why = why_not(query, &expected);
+        assert_eq!(why, Some(WhyNot::Later));
+        assert_eq!(
+            why.unwrap().to_string(),
+            "the joins kept the row, but the output doesn't"
+        );
+    }
+}
@@ -0,0 +1,28 @@
+use crate::value::Nulls;
+use crate::{Graph, Relation};
+
+// Semi-join reduces `rels` along a join tree of `graph`, first from the
+// leaves up and then from the root back down. Afterwards every remaining
+// row takes part in the result of the join, so joining along the tree never
+// produces an intermediate result bigger than the output.
+//
+// Rows match as they would in the join, with `nulls` saying whether null
+// keys match each other. This needs the query to be acyclic. Returns false
+// and leaves `rels` alone when it isn't.
+pub fn reduce(rels: &mut [Relation], graph: &Graph, nulls: Nulls) -> bool {
+    let Some(tree) = graph.join_tree() else {
+        return false;
+    };
+
+    for (vertex, parent) in tree.iter().rev() {
+        if let Some(parent) = parent {
+            rels[*parent] = rels[*parent].reduce_by(&rels[*vertex], nulls);
+        }
+    }
+    for (vertex, parent) in tree.iter() {
+        if let Some(parent) = parent {
+            rels[*vertex] = rels[*vertex].reduce_by(&rels[*parent], nulls);
+        }
+    }
+    true
+}
@@ -0,0 +1,146 @@
+use std::convert::Infallible;
+use std::ops::RangeInclusive;
+
+use crate::value::Value;
+use crate::{HashIndex, Relation};
+
+// How many rows each zone covers.
+pub const ZONE_ROWS: usize = 1024;
+
+// The smallest and largest value of every column in each run of ZONE_ROWS
+// rows of a relation. Chunks whose ranges can't contain what a scan or join
+// is looking for can be skipped without looking at their rows, which pays
+// off most when the relation is sorted on the column being filtered.
+//
+// A zone map describes the rows of the relation at the time it was built.
+#[derive(Debug)]
+pub struct ZoneMap {
+    zones: Vec<Zone>,
+}
+
+#[derive(Debug)]
+struct Zone {
+    start: usize,
+    end: usize,
+    min: Vec<Value>,
+    max: Vec<Value>,
+}
+
+impl Relation {
+    pub fn zone_map(&self) -> ZoneMap {
+        let zones = self
+            .data
+            .chunks(ZONE_ROWS)
+            .enumerate()
+            .map(|(i, rows)| {
+                let mut min = rows[0].clone();
+                let mut max = rows[0].clone();
+                for row in rows[1..].iter() {
+                    for (c, v) in row.iter().enumerate() {
+                        if *v < min[c] {
+                            min[c] = v.clone();
+                        } else if *v > max[c] {
+                            max[c] = v.clone();
+                        }
+                    }
+                }
+                Zone {
+                    start: i * ZONE_ROWS,
+                    end: i * ZONE_ROWS + rows.len(),
+                    min,
+                    max,
+                }
+            })
+            .collect();
+        ZoneMap { zones }
+    }
+}
+
+impl ZoneMap {
+    // The rows of `rel` whose `col` is an int within `range`.
+    pub fn filter_range(&self, rel: &Relation, col: &str, range: RangeInclusive<i64>) -> Relation {
+        let c = rel.positions(&[col.to_string()])[0];
+        let bounds = ints(&[(c, *range.start(), *range.end())]);
+        Relation::new_with_data(
+            rel.col_names.iter().cloned(),
+            self.rows(rel, &bounds)
+                .filter(|row| row[c].as_int().is_some_and(|v| range.contains(&v)))
+                .cloned()
+                .collect::<Vec<_>>(),
+        )
+    }
+
+    // The number of zones that could have a row with every column in
+    // `bounds`, given as a column position and an inclusive range.
+    pub fn zones_overlapping(&self, bounds: &[(usize, i64, i64)]) -> usize {
+        self.overlapping(&ints(bounds)).count()
+    }
+
+    fn overlapping<'a>(
+        &'a self,
+        bounds: &'a [(usize, Value, Value)],
+    ) -> impl Iterator<Item = &'a Zone> {
+        self.zones.iter().filter(move |zone| {
+            bounds
+                .iter()
+                .all(|(c, lo, hi)| zone.min[*c] <= *hi && *lo <= zone.max[*c])
+        })
+    }
+
+    fn rows<'a>(
+        &'a self,
+        rel: &'a Relation,
+        bounds: &'a [(usize, Value, Value)],
+    ) -> impl Iterator<Item = &'a Vec<Value>> {
+        self.overlapping(bounds)
+            .flat_map(move |zone| rel.data[zone.start..zone.end].iter())
+    }
+}
+
+fn ints(bounds: &[(usize, i64, i64)]) -> Vec<(usize, Value, Value)> {
+    bounds
+        .iter()
+        .map(|(c, lo, hi)| (*c, Value::Int(*lo), Value::Int(*hi)))
+        .collect()
+}
+
+impl HashIndex<'_> {
+    // The range of each of the index's key columns, in key order.
+    pub fn key_bounds(&self) -> Vec<(Value, Value)> {
+        let key = self.rel.positions(&self.key_cols);
+        let mut bounds: Vec<(Value, Value)> = Vec::new();
+        for row in self.table.rows().map(|i| &self.rel.data[i]) {
+            if bounds.is_empty() {
+                bounds = key
+                    .iter()
+                    .map(|c| (row[*c].clone(), row[*c].clone()))
+                    .collect();
+            }
+            for (b, v) in bounds.iter_mut().zip(key.iter().map(|c| &row[*c])) {
+                if *v < b.0 {
+                    b.0 = v.clone();
+                } else if *v > b.1 {
+                    b.1 = v.clone();
+                }
+            }
+        }
+        bounds
+    }
+
+    // Like `join`, but skips the zones of `probe` whose keys are all outside
+    // the range of keys in the index.
+    pub fn join_pruned(&self, probe: &Relation, zones: &ZoneMap) -> Relation {
+        let positions = probe.positions(&self.key_cols);
+        let bounds: Vec<_> = positions
+            .into_iter()
+            .zip(self.key_bounds())
+            .map(|(c, (lo, hi))| (c, lo, hi))
+            .collect();
+        // An empty index has no bounds, but nothing can match it either.
+        let rows = (self.keys() > 0).then(|| zones.rows(probe, &bounds));
+        self.try_join_rows(probe, rows.into_iter().flatten(), |_| {
+            Ok::<_, Infallible>(())
+        })
+        .unwrap_or_else(|e| match e {})
+    }
+}